@@ -11,14 +11,58 @@
 //
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
-use crate::runner::{git::RepoSource, RepoName};
+use crate::{
+    runner::{git::RepoSource, ProfileName, RepoField, RepoKind, RepoName},
+    suggest,
+    units::HumanDuration,
+};
 use clap::Parser;
-use std::{ffi::OsString, path::PathBuf, process::Command, str::FromStr};
-use strum::EnumIter;
+use std::{
+    convert::Infallible, env, ffi::OsString, num::NonZeroU32, path::PathBuf, process::Command,
+    str::FromStr,
+};
 use thiserror::Error as ThisError;
 
 #[derive(Debug, Parser)]
 #[clap(about, author, version)]
+pub(crate) struct Args {
+    /// Write a trace of this invocation's operations to `TRACE_FILE`, in the Chrome trace event
+    /// format.
+    ///
+    /// Traces can be viewed with `chrome://tracing` or <https://ui.perfetto.dev>, which is handy
+    /// for debugging long-running commands and reporting performance issues.
+    #[clap(long, global = true)]
+    pub trace_file: Option<PathBuf>,
+    /// Log every Git command this tool runs, with its working directory and environment, before
+    /// running it.
+    ///
+    /// Commands are logged so that they can be copied into a shell to reproduce a problem, with
+    /// credentials in URLs redacted. The same log can be enabled with
+    /// `RUST_LOG=git_commands=debug`.
+    #[clap(long, global = true)]
+    pub show_commands: bool,
+    /// Run as if started in `PATH` instead of the current working directory, like Git's `-C`.
+    ///
+    /// This affects everything that defaults to the current directory, like which repo a command
+    /// applies to when none is named, as well as how relative paths are resolved.
+    #[clap(short = 'C', long = "directory", value_name = "PATH", global = true)]
+    pub directory: Option<PathBuf>,
+    /// Refuse to change the repo DBs, files on disk, or remotes, failing any command that might.
+    ///
+    /// Commands that only report on repos (i.e., `list`, `show`, `paths`, `stats`, and `doctor`)
+    /// still work, which makes this handy for audit scripts and unprivileged health checks. Can
+    /// also be enabled with `read_only = true` in this tool's configuration file.
+    #[clap(long, global = true)]
+    pub read_only: bool,
+    /// Use the repos, state, and configuration of the profile `NAME` for this invocation, instead
+    /// of the one chosen with `profile switch`.
+    #[clap(long, value_name = "NAME", global = true)]
+    pub profile: Option<ProfileName>,
+    #[clap(subcommand)]
+    pub command: Cli,
+}
+
+#[derive(Debug, Parser)]
 pub(crate) enum Cli {
     /// Use a starter file to quickly import or export a configuration.
     ///
@@ -39,14 +83,47 @@ pub(crate) enum Cli {
     /// dedicated folder.
     #[clap(subcommand)]
     Overlay(OverlaySubcommand),
+    /// Control the lifecycle of an unmanaged directory entry.
+    ///
+    /// `unmanaged` entries are directories that aren't Git repos, i.e., configuration created by
+    /// hooks, that should still be listed and recreated by starter files. Commands that need Git
+    /// skip them.
+    #[clap(subcommand)]
+    Unmanaged(UnmanagedSubcommand),
     /// Invoke a command against a repo.
     ///
     /// Currently, this command sets the `GIT_DIR` and `GIT_WORK_TREE` variables for the invoked
     /// command. This behavior is not stable, and may be redesigned before 1.0.0.
     Run {
-        repo_name: RepoName<'static>,
+        /// The repo to run the command against. Defaults to the repo at the current working
+        /// directory.
+        repo: Option<RepoRef>,
         #[clap(long)]
         no_cd_root: bool,
+        /// Kill the command if it runs longer than `TIMEOUT` (i.e., `30s`, `5m`, or `1h`).
+        ///
+        /// With a timeout, the command runs in its own process group, so the entire group is
+        /// killed. Note that this means it cannot read from the terminal.
+        #[clap(long)]
+        timeout: Option<HumanDuration>,
+        /// Print the working directory and the environment variables that the command would see,
+        /// instead of running it.
+        ///
+        /// Besides the variables set for the repo, this lists the `GIT_*` and `BELLBOY_*`
+        /// variables inherited from this tool's environment, since those can also change which
+        /// repo a command operates on.
+        #[clap(long, conflicts_with = "timeout")]
+        print_env: bool,
+        /// Run the command even if the repo is marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+        /// Fail if no repo is given and the current working directory isn't in one, instead of
+        /// asking which repo to use.
+        ///
+        /// The question is only asked when this tool runs in a terminal, so this is only needed
+        /// to make sure that interactive use fails like scripts do.
+        #[clap(long)]
+        no_interactive: bool,
         // #[clap(long)]
         // allow_standalone: bool,
         #[clap(flatten)]
@@ -62,11 +139,283 @@ pub(crate) enum Cli {
         /// root, for each repo entry command invocation.
         #[clap(long)]
         no_cd_root: bool,
+        /// Kill each command if it runs longer than `TIMEOUT` (i.e., `30s`, `5m`, or `1h`). See
+        /// `run --help` for details.
+        #[clap(long)]
+        timeout: Option<HumanDuration>,
+        /// Buffer the output of each command, and print it grouped under a header for its repo
+        /// once the command finishes.
+        #[clap(long)]
+        capture: bool,
+        /// Also write the captured output of each command to `<REPO>.stdout` and `<REPO>.stderr`
+        /// files in `CAPTURE_DIR`, along with a `summary.json` of exit codes and durations.
+        /// Implies `--capture`.
+        #[clap(long)]
+        capture_dir: Option<PathBuf>,
+        /// Also run against repos that have been pinned with `pin`.
+        #[clap(long)]
+        include_pinned: bool,
+        /// Also run against repos that have been marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+        /// Only run against the repos that the last `for-each` failed for, including those whose
+        /// command exited with a non-zero code, i.e., to retry them after fixing whatever went
+        /// wrong.
+        #[clap(long)]
+        failed: bool,
+        /// Only run against repos of this kind. Unmanaged directories are skipped unless this is
+        /// `unmanaged`, since commands are usually Git commands.
+        #[clap(long, value_enum)]
+        kind: Option<RepoKind>,
         #[clap(flatten)]
         cmd_and_args: CommandAndArgs,
     },
+    /// Perform maintenance on a repo's local Git data.
+    ///
+    /// Currently, the only maintenance performed is trimming the history of repos configured
+    /// with `--keep-depth`.
+    Maintain {
+        /// The repo to maintain. Defaults to the repo at the current working directory.
+        repo: Option<RepoRef>,
+        /// Keep only the most recent `KEEP_DEPTH` commits of history locally, trimming older
+        /// history every time this command or `sync` is run. The remote retains full history.
+        ///
+        /// This setting is persisted in the repo's Git configuration. Beware that shallow repos
+        /// have caveats: commands that walk history (i.e., `git log`, `git blame`, and `git
+        /// bisect`) stop at the shallow boundary, and merges or rebases involving older history
+        /// may fail.
+        #[clap(long)]
+        keep_depth: Option<NonZeroU32>,
+        /// Stop keeping this repo's history shallow, and fetch full history from the remote.
+        #[clap(long, conflicts_with = "keep_depth")]
+        no_keep_depth: bool,
+    },
+    /// Fetch repos and bring in changes from their upstream branches.
+    ///
+    /// Branches that are only behind their upstream are fast-forwarded. What happens to branches
+    /// that have diverged from their upstream is chosen by the strategy flags below, falling back
+    /// to the strategy saved for the repo with `--save`, and otherwise skipping them. Pinned repos
+    /// are skipped unless a repo is specified or `--include-pinned` is passed. Read-only repos are
+    /// skipped, or refused if specified, unless `--allow-read-only` is passed.
+    Sync {
+        /// The repo to sync. If not specified, all repos are synced.
+        repo: Option<RepoRef>,
+        /// Rebase diverged branches onto their upstream.
+        #[clap(long, group = "strategy")]
+        rebase: bool,
+        /// Merge upstream into diverged branches.
+        #[clap(long, group = "strategy")]
+        merge: bool,
+        /// Leave diverged branches alone.
+        #[clap(long, group = "strategy")]
+        skip: bool,
+        /// Like `--rebase`, but stash uncommitted changes first and restore them afterwards.
+        #[clap(long, group = "strategy")]
+        stash_and_rebase: bool,
+        /// Stash uncommitted changes before bringing in upstream changes, and restore them
+        /// afterwards, whatever the strategy.
+        ///
+        /// If restoring them conflicts with upstream changes, the repo fails to sync, the
+        /// conflicting files are listed, and the changes are kept in the stash.
+        #[clap(long)]
+        autostash: bool,
+        /// Save the strategy passed as the default for `REPO`.
+        #[clap(long, requires_all = ["repo", "strategy"])]
+        save: bool,
+        /// Also sync repos that have been pinned with `pin`.
+        #[clap(long)]
+        include_pinned: bool,
+        /// Also sync repos that have been marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+        /// Only sync the repos that failed to sync the last time all repos were synced.
+        #[clap(long, conflicts_with = "repo")]
+        failed: bool,
+        /// Only sync repos of this kind.
+        #[clap(long, value_enum, conflicts_with = "repo")]
+        kind: Option<RepoKind>,
+    },
+    /// Write an archive of a repo's tracked files to `OUTPUT`, i.e., for copying dotfiles to a
+    /// machine without Git.
+    ///
+    /// The archive format is inferred from the extension of `OUTPUT`, i.e., `.tar.gz`, `.tgz`, or
+    /// `.zip`. Paths in the archive are relative to the repo's work tree, which is the home
+    /// directory for overlay repos. Only committed files are archived; uncommitted changes are not
+    /// included.
+    Archive {
+        repo: RepoRef,
+        output: PathBuf,
+        /// The commit, branch, or tag to archive.
+        #[clap(long, default_value = "HEAD")]
+        rev: String,
+        /// A directory to prepend to every path in the archive, i.e., `dotfiles/`.
+        #[clap(long)]
+        prefix: Option<String>,
+    },
+    /// Open an interactive dashboard of all repos.
+    ///
+    /// The dashboard shows each repo's branch and number of changed files. From it, you can view
+    /// a repo's uncommitted changes, pull from its upstream, commit all changes, or remove its
+    /// entry (leaving work tree files intact).
+    #[cfg(feature = "tui")]
+    Ui,
+    /// Find directories in the overlay repos directory that don't belong to any overlay repo, and
+    /// offer to delete or quarantine each of them.
+    ///
+    /// These can be left behind by failed operations or by editing the overlay repos DB by hand.
+    /// Quarantined directories are moved to a `quarantine` directory in this tool's state
+    /// directory (see `paths`), so they can be recovered later.
+    Gc {
+        /// Only list orphaned directories, without offering to do anything with them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Delete backups that exceed the limits in the `backups` table of the configuration file.
+    ///
+    /// Backups include repo DBs from before schema upgrades and directories quarantined by `gc`.
+    /// The newest backups are kept first. Limits are also enforced after every successful command
+    /// that may make changes, so this is only needed after changing them.
+    PruneBackups {
+        /// Only list the backups that would be deleted.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Inspect the durations of past commands, recorded when `metrics = true` is set in the
+    /// configuration file.
+    ///
+    /// Records are kept in this tool's state directory, and never sent anywhere.
+    #[clap(subcommand)]
+    Metrics(MetricsSubcommand),
+    /// Manage profiles, isolated sets of repos and configuration that share a home directory.
+    ///
+    /// Each profile has its own repo DBs, overlay repos, state, and configuration file, so that,
+    /// i.e., personal and work dotfiles can be kept apart under the same user account. The
+    /// `default` profile always exists, and uses the locations this tool used before profiles
+    /// existed.
+    #[clap(subcommand)]
+    Profile(ProfileSubcommand),
+    /// Print the locations of files and directories used by this tool.
+    ///
+    /// This includes the `GIT_DIR` and `GIT_WORK_TREE` paths used for each repo entry.
+    Paths {
+        #[clap(long, default_value = "human")]
+        output: OutputFormat,
+    },
+    /// Check that files in repo work trees match what's configured for them.
+    ///
+    /// Currently, this checks that files have the modes configured with `file_mode` and
+    /// `file_modes` in repo sections of the configuration file.
+    Verify {
+        /// The repo to verify. If not specified, all repos are verified.
+        repo: Option<RepoRef>,
+        /// Fix problems found, instead of only reporting them.
+        #[clap(long)]
+        fix: bool,
+    },
+    /// Report the size of repos: how many files each tracks, how much space its Git directory
+    /// takes up, and its largest objects.
+    ///
+    /// This can help find repos bloated by large files committed at some point in their history.
+    Stats {
+        /// The repo to report on. If not specified, all repos are reported on.
+        repo: Option<RepoRef>,
+        /// The number of largest objects to list for each repo.
+        #[clap(long, default_value = "3")]
+        largest: usize,
+        #[clap(long, default_value = "human")]
+        output: OutputFormat,
+    },
+    /// Check this tool's own state for problems.
+    ///
+    /// Currently, this flags repos whose status can't be read, overlay repos whose directory was
+    /// renamed by hand, recorded sources that no longer match repos' remotes and branches, and
+    /// files and directories holding this tool's state that other users can read. Set `harden_permissions = true` in this tool's configuration file to fix the latter
+    /// automatically.
+    Doctor {
+        /// Follow overlay repos renamed by hand: rename their entries to match, and move their
+        /// excludes and attributes files along. Also update recorded sources to match repos'
+        /// remotes and branches.
+        #[clap(long)]
+        fix: bool,
+    },
     /// Remove a repo entry, attempting to remove all files associated with the repo's work tree.
-    Remove { name: RepoName<'static> },
+    ///
+    /// By default, the entry, its Git directory, and the files it tracks are all removed. For
+    /// standalone repos, this means the entire work tree is deleted. Unmanaged directories are
+    /// never deleted, since nothing could bring them back; only their entry is removed.
+    Remove {
+        repo: RepoRef,
+        /// Only remove the entry and its Git directory, leaving work tree files intact.
+        #[clap(long)]
+        keep_files: bool,
+        /// Only delete the files tracked by the repo, leaving its entry and Git directory intact.
+        #[clap(long, conflicts_with = "keep_files")]
+        files_only: bool,
+        /// Delete files even if they have changes that aren't committed.
+        #[clap(long)]
+        force: bool,
+        /// Remove the repo even if it's marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+        /// Also delete the excludes and attributes files set up for an overlay repo, i.e.,
+        /// `~/.gitignore.d/<NAME>`. See `overlay deregister --help`.
+        #[clap(long, conflicts_with = "files_only")]
+        prune_config: bool,
+    },
+    /// Pin a repo, so that commands operating on many repos at once (i.e., `for-each`) skip it
+    /// unless passed `--include-pinned`.
+    Pin { repo: RepoRef },
+    /// Unpin a repo pinned with `pin`.
+    Unpin { repo: RepoRef },
+    /// Mark a repo as read-only, so that commands that might change it (i.e., `run`, `for-each`,
+    /// `sync`, and `remove`) refuse to unless passed `--allow-read-only`.
+    ///
+    /// This is handy for repos that should never be modified locally, like configuration provided
+    /// by an employer or vendored from elsewhere.
+    Protect { repo: RepoRef },
+    /// Undo `protect`, allowing changes to a repo again.
+    Unprotect { repo: RepoRef },
+    /// Record a description, homepage, or notes for a repo, for display by `show`.
+    ///
+    /// Only the fields passed are changed. Pass an empty value to clear a field.
+    Describe {
+        repo: RepoRef,
+        #[clap(long)]
+        description: Option<String>,
+        #[clap(long)]
+        homepage: Option<String>,
+        #[clap(long)]
+        notes: Option<String>,
+    },
+    /// Read or change individual fields of repo entries, i.e., from scripts.
+    ///
+    /// Values are validated before anything is written, so this is safer than editing the repo
+    /// DBs by hand.
+    #[clap(subcommand)]
+    Db(DbSubcommand),
+    /// Print everything known about a repo: its kind, paths, source, branch, required tags, and
+    /// details recorded with `describe`, when it was last fetched, and whether it has
+    /// uncommitted changes.
+    Show { repo: RepoRef },
+    /// List a repo's local and remote-tracking branches, with how far each local branch is ahead
+    /// of or behind its upstream, and when each was last committed to.
+    #[clap(args_conflicts_with_subcommands = true)]
+    Branches {
+        /// The repo to list branches of. Defaults to the repo at the current working directory.
+        repo: Option<RepoRef>,
+        #[clap(subcommand)]
+        subcommand: Option<BranchesSubcommand>,
+    },
+    /// Print a one-line summary of the repo whose work tree contains a path, for embedding in
+    /// shell prompts: its name and kind, then `*` if it has uncommitted changes, and how many
+    /// commits it's ahead (`↑`) or behind (`↓`) its upstream, i.e., `dotfiles (overlay) *↑1`.
+    ///
+    /// Prints nothing if no repo contains the path. Statuses are cached, so this is usually fast
+    /// enough to run on every prompt.
+    PromptInfo {
+        /// Defaults to the current working directory.
+        path: Option<PathBuf>,
+    },
     // // TODO: A crazy ambitious idea to use the user's auto-magically detected shell?
     // Preposterous. :)
     // Enter {
@@ -76,116 +425,307 @@ pub(crate) enum Cli {
     // },
     /// List repo entries in the current configuration.
     ///
-    /// TODO: document repo spec and format options.
+    /// TODO: document format options.
     List {
-        #[clap(default_value = "all")]
-        repo_spec: Vec<RepoSpec>,
+        /// Only list repos of this kind.
+        #[clap(long, value_enum)]
+        kind: Option<RepoKind>,
         #[clap(long, default_value = "flat")]
         format: ListFormat,
+        /// The order to list repos in: `name`, `kind`, `path`, or `recent-activity`.
+        ///
+        /// `recent-activity` lists repos with the most recent commit on any local branch first, and
+        /// repos without commits last. Ties are always broken by name.
+        #[clap(long, default_value = "name")]
+        sort: ListSort,
+        /// Also show each repo's branch, how far it is ahead of or behind its upstream, and how
+        /// many files have uncommitted changes.
+        ///
+        /// Statuses are gathered in parallel, and cached briefly so that repeated listings are
+        /// fast.
+        #[clap(long)]
+        status: bool,
+        /// Instead of listing repos, report groups of repos that share the same remote URL.
+        ///
+        /// Remote URLs are normalized before comparison, so SSH and HTTPS URLs referring to the
+        /// same upstream are considered the same.
+        #[clap(long)]
+        duplicates: bool,
+        /// Print only the name of each repo, one per line, i.e., for shell scripts.
+        #[clap(long, conflicts_with_all = ["format", "status", "duplicates"])]
+        names_only: bool,
+        /// Print only the path of each repo's work tree, one per line, i.e., for shell scripts.
+        /// Overlay repos' work tree is the home directory.
+        #[clap(
+            long,
+            conflicts_with_all = ["format", "status", "duplicates", "names_only"]
+        )]
+        paths_only: bool,
+    },
+    /// Watch the files tracked by a repo, and report or commit changes to them once they stop
+    /// changing. Runs in the foreground until interrupted.
+    ///
+    /// Files are checked for changes every second, or every `--debounce` if that's shorter.
+    Watch {
+        repo: RepoRef,
+        /// Commit all changes to tracked files, instead of only reporting them.
+        #[clap(long)]
+        auto_commit: bool,
+        /// How long files must go unchanged before changes are acted on (i.e., `500ms`, `5s`, or
+        /// `1m`).
+        #[clap(long, default_value = "5s")]
+        debounce: HumanDuration,
+        /// The message of automatic commits. `{repo}`, `{hostname}`, and `{count}` are replaced
+        /// with the repo's name, this machine's hostname, and the number of files changed, and
+        /// `{files}` with a short list of them.
+        #[clap(
+            long,
+            default_value = "Update {files} on {hostname}",
+            requires = "auto_commit"
+        )]
+        message: String,
+        /// Print a systemd user service running this command with the same options, instead of
+        /// running it, i.e., for `~/.config/systemd/user/`.
+        #[clap(long)]
+        print_service: bool,
+        /// Commit to the repo even if it's marked read-only with `protect`.
+        #[clap(long, requires = "auto_commit")]
+        allow_read_only: bool,
     },
-    // // TODO: Might be nice to give a condensed presentation of files listed by `git status`?
-    // Status,
+    /// Print the status of every repo: its branch, how far it is ahead of or behind its upstream,
+    /// and how many files have uncommitted changes.
+    ///
+    /// Unlike `list --status`, statuses are always computed afresh.
+    Status {
+        /// Only show repos of this kind.
+        #[clap(long, value_enum)]
+        kind: Option<RepoKind>,
+        /// Keep recomputing statuses, redrawing them every `--interval`, until interrupted.
+        #[clap(long)]
+        watch: bool,
+        /// How long to wait between redraws with `--watch` (i.e., `500ms`, `2s`, or `1m`).
+        #[clap(long, default_value = "2s", requires = "watch")]
+        interval: HumanDuration,
+    },
+    /// Any other subcommand is run as an executable named `bb-<SUBCOMMAND>` found on `PATH`,
+    /// like Git and Cargo do, with the rest of the arguments.
+    #[clap(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+impl Cli {
+    /// Whether this command is guaranteed not to change anything, and so is allowed in read-only
+    /// mode.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Self::List { .. }
+            | Self::Status { .. }
+            | Self::Paths { .. }
+            | Self::Stats { .. }
+            | Self::Show { .. }
+            | Self::Branches {
+                subcommand: None,
+                ..
+            }
+            | Self::PromptInfo { .. }
+            | Self::Overlay(OverlaySubcommand::Conflicts)
+            | Self::Starter(StarterSubcommand::Verify { .. })
+            | Self::Db(DbSubcommand::Get { .. })
+            | Self::Metrics(_)
+            | Self::Profile(ProfileSubcommand::List)
+            // Plugins are told whether to run in read-only mode, and are trusted to honor it.
+            | Self::External(_) => true,
+            Self::Verify { fix, .. } | Self::Doctor { fix } => !fix,
+            Self::Gc { dry_run } | Self::PruneBackups { dry_run } => *dry_run,
+            Self::Run { print_env, .. } => *print_env,
+            Self::Watch {
+                auto_commit,
+                print_service,
+                ..
+            } => !auto_commit || *print_service,
+            Self::Starter(_)
+            | Self::Standalone(_)
+            | Self::Overlay(_)
+            | Self::Unmanaged(_)
+            | Self::Profile(_)
+            | Self::ForEach { .. }
+            | Self::Maintain { .. }
+            | Self::Sync { .. }
+            | Self::Branches { .. }
+            | Self::Archive { .. }
+            | Self::Remove { .. }
+            | Self::Pin { .. }
+            | Self::Unpin { .. }
+            | Self::Protect { .. }
+            | Self::Unprotect { .. }
+            | Self::Describe { .. }
+            | Self::Db(_) => false,
+            #[cfg(feature = "tui")]
+            Self::Ui => false,
+        }
+    }
 }
 
 #[derive(Debug, Parser)]
 pub enum StarterSubcommand {
-    /// Import a starter file from `PATH`.
+    /// Import a starter file from `PATH`, cloning every repo it lists that isn't registered yet,
+    /// and creating every unmanaged directory.
+    ///
+    /// Entries may have a `when` table of conditions (`hostname` patterns, `os` names, and `env`
+    /// variables that must be set), so that one starter file can describe several machines.
+    /// Entries whose conditions don't match this machine are skipped and reported.
     Import {
         path: PathBuf,
-        /// If specified, attempt to interpret `PATH` as a relative path into the given Git repo
-        /// source.
-        git: RepoSource<'static>,
+        /// Refuse to import `PATH` unless it has a valid SSH signature made by `KEY`, which is
+        /// either an SSH public key or the path of a file containing one.
+        ///
+        /// Signatures are made with `ssh-keygen -Y sign -n bellboy-starter -f <PRIVATE_KEY>
+        /// <PATH>`, which writes them to `<PATH>.sig`. Verification uses `ssh-keygen` too.
+        #[clap(long, value_name = "KEY")]
+        verify_key: Option<String>,
+        /// Where to read the signature checked by `--verify-key` from, instead of `<PATH>.sig`.
+        #[clap(long, value_name = "SIGNATURE_PATH", requires = "verify_key")]
+        signature: Option<PathBuf>,
+        /// Define a variable that entries' `path`s and `source`s can refer to as `{NAME}`, in
+        /// addition to the built-in `{home}`, `{hostname}`, and `{user}`. May be repeated.
+        ///
+        /// Variables defined here take precedence over built-in ones. Entries referring to
+        /// undefined variables cause the import to fail before anything is cloned.
+        #[clap(long = "var", value_name = "NAME=VALUE")]
+        vars: Vec<TemplateVar>,
+        /// Don't run the scripts listed in the starter file's `run` stage.
+        #[clap(long)]
+        skip_scripts: bool,
+        /// Run the scripts listed in the starter file's `run` stage without asking first.
+        ///
+        /// Without a terminal to ask on, scripts aren't run unless this is passed.
+        #[clap(long, conflicts_with = "skip_scripts")]
+        run_scripts: bool,
+    },
+    /// Export a starter file to `PATH`, listing every registered repo that has a remote.
+    Export {
+        path: PathBuf,
+        /// Only export these repos, and those selected with `--tag`. Defaults to all repos.
+        repos: Vec<RepoRef>,
+        /// Only export repos that require `TAG` in their metadata file, and those named as
+        /// `REPOS`. May be repeated.
+        #[clap(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        /// Replace sources that may reveal something private (local paths, hosts other than
+        /// public forges like `github.com`, and URLs with credentials) with variables named after
+        /// their repos, i.e., `{source_dots}`, to be defined with `starter import --var`.
+        #[clap(long)]
+        redact_sources: bool,
     },
-    /// Export a starter file to `PATH`.
-    Export { path: PathBuf },
+    /// Check the starter file at `PATH` for problems without cloning anything, i.e., in CI.
+    ///
+    /// This reports syntax errors, unsupported versions, invalid repo names, entries whose `kind`
+    /// and `path` disagree, duplicate names and paths, and sources that don't look clonable, each
+    /// with its line and column. Fails if any errors are found; doubtful sources are only warned
+    /// about.
+    Verify { path: PathBuf },
 }
 
 #[derive(Debug, Parser)]
 pub struct ListSubcommand {}
 
-#[derive(Clone, Debug)]
-pub enum RepoSpec {
-    All,
-    // Name(Regex),
-    Kind(CliRepoKind),
-}
-
-impl Default for RepoSpec {
-    fn default() -> Self {
-        Self::All
-    }
+#[derive(Clone, Debug, Default)]
+pub enum ListFormat {
+    #[default]
+    Flat,
+    GroupByKind,
+    /// A JSON array of repo entries, including their recorded upstream sources.
+    Json,
 }
 
 #[derive(Debug, ThisError)]
-pub enum InvalidRepoSpecError {
-    #[error(
-        "{what:?} is not a recognized repo spec; expected \"all\" \
-        or spec of the form \"<type>:<value>\""
-    )]
-    Unrecognized { what: String },
-    #[error("{what:?} is not a recognized parameterized spec type")]
-    UnrecognizedType { what: String },
-    #[error("failed to parse `kind`")]
-    ParseRepoKind { source: InvalidRepoKindError },
+#[error(
+    "invalid `by` spec; expected \"flat\", \"group-by-kind\", or \"json\", but got {actual:?}{}",
+    suggest::did_you_mean(.actual, ["flat", "group-by-kind", "json"])
+)]
+pub struct InvalidListFormatError {
+    actual: String,
 }
 
-impl FromStr for RepoSpec {
-    type Err = InvalidRepoSpecError;
+impl FromStr for ListFormat {
+    type Err = InvalidListFormatError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
-            "all" => Self::All,
-            s => {
-                if let Some((type_, value)) = s.split_once(':') {
-                    match type_ {
-                        "kind" => Self::Kind(
-                            value
-                                .parse()
-                                .map_err(|source| InvalidRepoSpecError::ParseRepoKind { source })?,
-                        ),
-                        s => {
-                            return Err(InvalidRepoSpecError::UnrecognizedType {
-                                what: s.to_string(),
-                            });
-                        }
-                    }
-                } else {
-                    return Err(InvalidRepoSpecError::Unrecognized {
-                        what: s.to_string(),
-                    });
-                }
+            "flat" => Self::Flat,
+            "group-by-kind" => Self::GroupByKind,
+            "json" => Self::Json,
+            actual => {
+                return Err(InvalidListFormatError {
+                    actual: actual.to_string(),
+                })
             }
         })
     }
 }
 
-#[derive(Clone, Debug)]
-pub enum ListFormat {
-    Flat,
-    GroupByKind,
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ListSort {
+    #[default]
+    Name,
+    Kind,
+    Path,
+    RecentActivity,
 }
 
-impl Default for ListFormat {
-    fn default() -> Self {
-        Self::Flat
+#[derive(Debug, ThisError)]
+#[error(
+    "invalid sort order; expected \"name\", \"kind\", \"path\", or \"recent-activity\", but \
+    got {actual:?}{}",
+    suggest::did_you_mean(.actual, ["name", "kind", "path", "recent-activity"])
+)]
+pub struct InvalidListSortError {
+    actual: String,
+}
+
+impl FromStr for ListSort {
+    type Err = InvalidListSortError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "name" => Self::Name,
+            "kind" => Self::Kind,
+            "path" => Self::Path,
+            "recent-activity" => Self::RecentActivity,
+            actual => {
+                return Err(InvalidListSortError {
+                    actual: actual.to_string(),
+                })
+            }
+        })
     }
 }
 
+#[derive(Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Json,
+}
+
 #[derive(Debug, ThisError)]
-#[error("invalid `by` spec; expected \"flat\", or \"group-by-kind\", but got {actual:?}")]
-pub struct InvalidListFormatError {
+#[error(
+    "invalid output format; expected \"human\" or \"json\", but got {actual:?}{}",
+    suggest::did_you_mean(.actual, ["human", "json"])
+)]
+pub struct InvalidOutputFormatError {
     actual: String,
 }
 
-impl FromStr for ListFormat {
-    type Err = InvalidListFormatError;
+impl FromStr for OutputFormat {
+    type Err = InvalidOutputFormatError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
-            "flat" => Self::Flat,
-            "group-by-kind" => Self::GroupByKind,
+            "human" => Self::Human,
+            "json" => Self::Json,
             actual => {
-                return Err(InvalidListFormatError {
+                return Err(InvalidOutputFormatError {
                     actual: actual.to_string(),
                 })
             }
@@ -207,15 +747,60 @@ pub enum StandaloneSubcommand {
     Clone {
         /// The source path or URL of the repo to clone.
         source: RepoSource<'static>,
+        /// Where to clone the repo. Defaults to a directory named after the source inside the
+        /// `projects_dir` setting of this tool's configuration file, or else the current working
+        /// directory.
         path: Option<PathBuf>,
         #[clap(flatten)]
         name: CliNewRepoName,
+        /// Clone only the most recent `KEEP_DEPTH` commits of history, and keep history trimmed
+        /// to that depth whenever it is synced, or with the `maintain` subcommand.
+        #[clap(long)]
+        keep_depth: Option<NonZeroU32>,
+        /// Apply the metadata in the repo's `.bellboy.toml`, if it has one, after asking for
+        /// confirmation.
+        ///
+        /// This file may declare a preferred repo name (used unless `--name` is given), app info,
+        /// commands to run from the work tree after cloning, and `required_tags` that this
+        /// machine should have in the `tags` setting of this tool's configuration file.
+        #[clap(long)]
+        recurse_config: bool,
     },
     /// Registers a standalone repo that already exists at `DIR`.
     Register {
         path: Option<PathBuf>,
         #[clap(flatten)]
         name: CliNewRepoName,
+        /// Apply the metadata in the repo's `.bellboy.toml`, if it has one, after asking for
+        /// confirmation. See `clone --help` for details. Post-clone commands are not run.
+        #[clap(long)]
+        recurse_config: bool,
+        /// Register every repo listed in `LIST` instead, one path per line. Use `-` to read the
+        /// list from `stdin`.
+        ///
+        /// Blank lines and lines starting with `#` are ignored. Each repo is named after the base
+        /// name of its work tree. Repos that fail to register are reported individually, and the
+        /// rest are still registered.
+        #[clap(long, value_name = "LIST", conflicts_with_all = ["path", "name", "recurse_config"])]
+        from_file: Option<PathBuf>,
+    },
+    /// Find Git repos at or below `ROOT` that aren't registered yet, and offer to register them.
+    ///
+    /// Each repo found is named after the base name of its work tree. The search depth and
+    /// directories to skip can also be set in the `[discover]` table of this tool's configuration
+    /// file.
+    Discover {
+        root: PathBuf,
+        /// How many directories deep to search below `ROOT`. Defaults to 3.
+        #[clap(long)]
+        max_depth: Option<u32>,
+        /// The name of a directory to skip while searching, in addition to those configured. May be
+        /// specified multiple times.
+        #[clap(long = "ignore", number_of_values = 1)]
+        ignore: Vec<String>,
+        /// Register every repo found without prompting.
+        #[clap(long)]
+        all: bool,
     },
     /// Deregister `REPO` without deleting files.
     ///
@@ -223,16 +808,91 @@ pub enum StandaloneSubcommand {
     /// awareness of them. If you also wish to remove all files, you may instead prefer to use the
     /// top-level `remove` subcommand.
     Deregister {
-        /// The repo to deregister. Interpreted as a path, unless `--name` is specified, in which
-        /// case this is interpreted as a repo name.
-        repo: Option<PathBuf>,
-        #[clap(long)]
-        name: bool,
+        /// The repo to deregister. Defaults to the repo at the current working directory.
+        repo: Option<RepoRef>,
     },
     // // TODO:
     // SetProjectDetails
 }
 
+#[derive(Debug, Parser)]
+pub enum UnmanagedSubcommand {
+    /// Registers the directory at `PATH`, which isn't expected to be a Git repo. Defaults to the
+    /// current working directory.
+    Register {
+        path: Option<PathBuf>,
+        #[clap(flatten)]
+        name: CliNewRepoName,
+    },
+    /// Deregister `REPO` without deleting its directory.
+    Deregister {
+        /// The entry to deregister. Defaults to the one at the current working directory.
+        repo: Option<RepoRef>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum DbSubcommand {
+    /// Print the value of `FIELD` for a repo. Fails if the field is unset.
+    ///
+    /// `FIELD` is one of `description`, `homepage`, `notes`, `pinned`, `read_only`,
+    /// `sync_strategy`, `source.url`, `source.branch`, or `depends_on`.
+    Get { repo: RepoRef, field: RepoField },
+    /// Set `FIELD` of a repo to `VALUE`. See `db get --help` for the fields available.
+    ///
+    /// `pinned` and `read_only` take `true` or `false`, and `sync_strategy` takes the same names
+    /// as `sync`'s flags, i.e., `stash-and-rebase`. `depends_on` takes a comma-separated list of
+    /// repo names, which `for-each` and `sync` handle before this repo; cycles are rejected.
+    ///
+    /// Note that `source.url` and `source.branch` are overwritten with the repo's actual remote
+    /// and branch whenever those are next inspected.
+    Set {
+        repo: RepoRef,
+        field: RepoField,
+        value: String,
+    },
+    /// Reset `FIELD` of a repo to its default, i.e., unset or `false`.
+    Unset { repo: RepoRef, field: RepoField },
+}
+
+#[derive(Debug, Parser)]
+pub enum MetricsSubcommand {
+    /// Summarize recorded runs of each command, by version of this tool: how many runs there
+    /// were, how many failed, how long they took on average and most recently, and how long the
+    /// clones, checkouts, syncs, and `for-each` runs within them took in total.
+    Show {
+        /// Only summarize runs of this subcommand, i.e., `sync` or `overlay clone`.
+        #[clap(long)]
+        command: Option<String>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum BranchesSubcommand {
+    /// Delete the local branches of a repo that are fully merged into the branch checked out,
+    /// after asking for confirmation. The branch checked out is never deleted.
+    Prune {
+        /// The repo to prune branches of. Defaults to the repo at the current working directory.
+        repo: Option<RepoRef>,
+        /// Delete them without asking first.
+        #[clap(long)]
+        yes: bool,
+        /// Prune branches even if the repo is marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum ProfileSubcommand {
+    /// Create an empty profile. It isn't used until chosen with `profile switch` or `--profile`.
+    Create { name: ProfileName },
+    /// List profiles, marking the one in use with `*`.
+    List,
+    /// Use the profile `NAME` from now on, when `--profile` isn't given.
+    Switch { name: ProfileName },
+}
+
 #[derive(Debug, Parser)]
 pub enum OverlaySubcommand {
     /// Initialize a new `overlay` repo.
@@ -242,6 +902,18 @@ pub enum OverlaySubcommand {
         ///
         /// TODO: discuss restrictions on the value provided heere
         name: RepoName<'static>,
+        /// Start the repo from a template: a directory, or the source of a Git repo containing
+        /// one. Its files are copied into your home directory and committed as the repo's first
+        /// commit.
+        ///
+        /// A `.bellboy-template.toml` file at the template's root isn't copied, and may declare Git
+        /// configuration (`git_config`, a table), patterns to seed the repo's excludes file with
+        /// (`excludes`), and the message of the first commit (`commit_message`).
+        ///
+        /// If any of the template's files already exist, this command makes no changes and exits
+        /// with an error.
+        #[clap(long, value_name = "DIR|URL")]
+        from_template: Option<String>,
     },
     /// Clone a Git repository from the specified `SOURCE`.
     ///
@@ -257,13 +929,125 @@ pub enum OverlaySubcommand {
         /// Useful for recreating your overlay repo after calling `remove-bare-repo`.
         #[clap(long)]
         no_checkout: bool,
+        /// Only check out files matching this glob pattern, relative to the home directory, i.e.,
+        /// `.config/nvim/**`. May be passed more than once.
+        ///
+        /// Useful for deploying part of a large repo onto a minimal machine. Other tracked files
+        /// will show up as deleted in `git status`.
+        #[clap(
+            long,
+            alias = "checkout-path-filter",
+            value_name = "GLOB",
+            conflicts_with = "no_checkout"
+        )]
+        only: Vec<String>,
+        /// Overwrite existing files that differ from the repo's versions without asking.
+        ///
+        /// Otherwise, a summary of the changes to existing files is shown first, and checkout is
+        /// skipped unless you confirm it.
+        #[clap(long, conflicts_with = "no_checkout")]
+        overwrite: bool,
+        /// Show the full patch of the changes to existing files, rather than just a summary.
+        #[clap(long, conflicts_with = "no_checkout")]
+        show_diff: bool,
+        /// Clone only the most recent `KEEP_DEPTH` commits of history, and keep history trimmed
+        /// to that depth whenever it is synced, or with the `maintain` subcommand.
+        ///
+        /// Useful for overlay repos with lots of churn in their history.
+        #[clap(long)]
+        keep_depth: Option<NonZeroU32>,
+    },
+    /// Register an existing bare Git repo, i.e., a `~/.cfg` set up by hand, as an `overlay` repo.
+    ///
+    /// The repo is left where it is, rather than moved into this tool's data directory. Its work
+    /// tree is the home directory, like for other overlay repos.
+    Register {
+        name: RepoName<'static>,
+        /// The bare repo's Git directory.
+        #[clap(long, value_name = "DIR")]
+        git_dir: PathBuf,
     },
     /// Remove an `overlay` repo's Git files, leaving the worktree intact.
     ///
     /// This subcommand makes no attempt to remove the work tree files associated with the
     /// specified repo; it only removes this tool's awareness of them. If you also wish to remove
     /// all files, you may instead prefer to use the top-level `remove` subcommand.
-    RemoveBareRepo { name: RepoName<'static> },
+    RemoveBareRepo {
+        name: RepoName<'static>,
+        /// Also delete the excludes and attributes files set up for the repo. See `overlay
+        /// deregister --help`.
+        #[clap(long)]
+        prune_config: bool,
+    },
+    /// Deregister an `overlay` repo without deleting anything, leaving both its Git directory and
+    /// work tree intact.
+    ///
+    /// Useful for repos added with `register`, to hand them back to whatever managed them before.
+    Deregister {
+        name: RepoName<'static>,
+        /// Also clean up what was set up for the repo outside of its Git directory: its excludes
+        /// files (`~/.gitignore.d/<NAME>`, `~/.gitignore.d/<NAME>.<HOSTNAME>` for this machine,
+        /// and the generated one), its attributes file (`~/.gitattributes.d/<NAME>`), and the
+        /// Git configuration pointing at those and at the empty hooks directory.
+        ///
+        /// Note that `~/.gitignore.d/<NAME>` is deleted even if you wrote it yourself.
+        #[clap(long)]
+        prune_config: bool,
+    },
+    /// Use a directory other than the home directory as an `overlay` repo's work tree, i.e., to
+    /// try out changes in a staging directory.
+    ///
+    /// Files aren't moved or checked out into the new work tree; use `git restore` for that. Pass
+    /// the home directory to switch back.
+    SetWorktree {
+        name: RepoName<'static>,
+        path: PathBuf,
+        /// Switch even if the current work tree has uncommitted changes, which won't be visible
+        /// from the new one.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Start tracking files that already exist in an `overlay` repo's work tree, i.e., a stray
+    /// `~/.inputrc`.
+    ///
+    /// Files are staged even if the repo's excludes would ignore them, and are left uncommitted
+    /// unless `--commit` is passed.
+    AdoptFile {
+        name: RepoName<'static>,
+        /// The files to adopt, relative to the current directory. Directories are adopted with
+        /// everything inside them that isn't ignored.
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+        /// Commit the adopted files, along with anything else already staged.
+        #[clap(long)]
+        commit: bool,
+        /// The message to commit with. Defaults to one listing the adopted files.
+        #[clap(long, short, requires = "commit")]
+        message: Option<String>,
+        /// Adopt files even if the repo is marked read-only with `protect`.
+        #[clap(long)]
+        allow_read_only: bool,
+    },
+    /// Report paths tracked by more than one overlay repo.
+    ///
+    /// Overlay repos share the home directory as their work tree, so the last repo to check out a
+    /// shared path silently wins. For each shared path, this also reports which repos' staged
+    /// content matches what's currently on disk.
+    Conflicts,
+    /// Manage the Git attributes file dedicated to an `overlay` repo.
+    ///
+    /// Each overlay repo gets its own attributes file at `~/.gitattributes.d/<NAME>`, configured
+    /// as its `core.attributesFile`.
+    #[clap(subcommand)]
+    Attributes(OverlayAttributesSubcommand),
+}
+
+#[derive(Debug, Parser)]
+pub enum OverlayAttributesSubcommand {
+    /// Open the attributes file of the overlay repo `NAME` in your editor.
+    ///
+    /// The editor is taken from the `VISUAL` or `EDITOR` environment variables, in that order.
+    Edit { name: RepoName<'static> },
 }
 
 #[derive(Debug, Parser)]
@@ -272,7 +1056,35 @@ pub struct CliExistingRepoName {
     pub name: RepoName<'static>,
 }
 
-#[derive(Debug, Parser)]
+/// A reference to an existing repo entry, either by name or by path.
+///
+/// Values containing a path separator, values that are `.` or `..`, and values that aren't valid
+/// repo names are interpreted as paths. Everything else is interpreted as a repo name. To refer to
+/// a relative path that is also a valid repo name, prefix it with `./`.
+///
+/// A path refers to the repo located exactly at that path, if any. Otherwise, it refers to the
+/// repo whose work tree contains that path, preferring the most deeply nested work tree. This
+/// means that, i.e., any path inside a standalone repo refers to that repo.
+#[derive(Clone, Debug)]
+pub enum RepoRef {
+    Name(RepoName<'static>),
+    Path(PathBuf),
+}
+
+impl FromStr for RepoRef {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let looks_like_path =
+            matches!(s, "." | "..") || s.contains(['/', std::path::MAIN_SEPARATOR]);
+        Ok(match s.parse::<RepoName<'static>>() {
+            Ok(name) if !looks_like_path => Self::Name(name),
+            _ => Self::Path(s.into()),
+        })
+    }
+}
+
+#[derive(Debug, Default, Parser)]
 pub struct CliNewRepoName {
     /// The alias by which this repo will be referred to when used later with this tool, if you
     /// wish to override what would be inferred.
@@ -287,39 +1099,62 @@ impl CliNewRepoName {
         let Self { name } = self;
         name
     }
-}
 
-pub trait NewRepoNameContainer {
-    type Output;
+    /// Whether a name was given explicitly, rather than left to be inferred.
+    pub fn is_set(&self) -> bool {
+        let Self { name } = self;
+        name.is_some()
+    }
 }
 
-#[derive(Copy, Clone, Debug, EnumIter, Eq, PartialEq)]
-pub enum CliRepoKind {
-    Standalone,
-    Overlay,
+/// A variable definition for starter file templates, written as `NAME=VALUE`.
+#[derive(Clone, Debug)]
+pub struct TemplateVar {
+    pub name: String,
+    pub value: String,
 }
 
 #[derive(Debug, ThisError)]
-#[error("unrecognized repo kind {what:?}")]
-pub struct InvalidRepoKindError {
-    what: String,
+#[error(
+    "invalid variable definition {actual:?}; expected `NAME=VALUE`, where `NAME` consists of \
+    ASCII letters, digits, `-`, and `_`"
+)]
+pub struct InvalidTemplateVarError {
+    actual: String,
 }
 
-impl FromStr for CliRepoKind {
-    type Err = InvalidRepoKindError;
+impl FromStr for TemplateVar {
+    type Err = InvalidTemplateVarError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            // TODO: How to make better diagnostics helping people with bad values?
-            "standalone" => Ok(Self::Standalone),
-            "overlay" => Ok(Self::Overlay),
-            s => Err(InvalidRepoKindError { what: s.to_owned() }),
+        match s.split_once('=') {
+            Some((name, value)) if is_template_var_name(name) => Ok(Self {
+                name: name.to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(InvalidTemplateVarError {
+                actual: s.to_owned(),
+            }),
         }
     }
 }
 
+pub fn is_template_var_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
 #[derive(Parser, Clone, Debug)]
 pub struct CommandAndArgs {
+    /// Run the command with the user's shell, so that it can use pipes, globs, and other shell
+    /// syntax, i.e., `-- 'git remote -v | grep origin'`.
+    ///
+    /// The shell is taken from the `SHELL` environment variable, falling back to `/bin/sh`. On
+    /// Windows, `cmd /C` is used instead. Multiple arguments are joined with spaces.
+    #[clap(long)]
+    shell: bool,
     #[clap(raw(true))]
     cmd_and_args: Vec<OsString>,
 }
@@ -332,12 +1167,34 @@ pub enum CommandError {
 
 impl CommandAndArgs {
     pub fn to_std(&self) -> Result<Command, CommandError> {
-        let Self { cmd_and_args } = self;
+        let Self {
+            shell,
+            cmd_and_args,
+        } = self;
         let (cmd, args) = cmd_and_args
             .split_first()
             .ok_or(CommandError::CommandNotSpecified)?;
-        let mut cmd = Command::new(cmd);
-        cmd.args(args);
+        if !*shell {
+            let mut cmd = Command::new(cmd);
+            cmd.args(args);
+            return Ok(cmd);
+        }
+
+        let mut script = cmd.clone();
+        for arg in args {
+            script.push(" ");
+            script.push(arg);
+        }
+        let mut cmd = if cfg!(windows) {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C");
+            cmd
+        } else {
+            let mut cmd = Command::new(env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into()));
+            cmd.arg("-c");
+            cmd
+        };
+        cmd.arg(script);
         Ok(cmd)
     }
 }
@@ -0,0 +1,138 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Translations of the messages this tool shows users directly, i.e., prompts and errors. Log
+//! output is left in English, so that it can be searched for and shared in bug reports.
+//!
+//! Messages are written in [Fluent](https://projectfluent.org/) syntax, with one catalog per
+//! locale in the `i18n` directory next to this file. As with gettext, the locale is taken from the
+//! first of `LC_ALL`, `LC_MESSAGES`, and `LANG` that's set. Messages missing from its catalog fall
+//! back to English.
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use std::{env, sync::OnceLock};
+use unic_langid::LanguageIdentifier;
+
+/// The catalogs built into this tool, keyed by locale. The first is the fallback for the others.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en-US", include_str!("i18n/en-US.ftl")),
+    ("fr", include_str!("i18n/fr.ftl")),
+];
+
+type Bundle = FluentBundle<FluentResource>;
+
+struct Catalogs {
+    /// The catalog for the user's locale, if it isn't the fallback.
+    selected: Option<Bundle>,
+    fallback: Bundle,
+}
+
+fn catalogs() -> &'static Catalogs {
+    static CATALOGS_CELL: OnceLock<Catalogs> = OnceLock::new();
+    CATALOGS_CELL.get_or_init(|| {
+        let (fallback_locale, fallback_source) = CATALOGS[0];
+        let selected = user_locale().and_then(|locale| {
+            let (_, source) = CATALOGS
+                .iter()
+                .skip(1)
+                .find(|(catalog_locale, _)| matches_locale(catalog_locale, &locale))?;
+            Some(bundle(locale, source))
+        });
+        Catalogs {
+            selected,
+            fallback: bundle(fallback_locale.parse().unwrap(), fallback_source),
+        }
+    })
+}
+
+fn bundle(locale: LanguageIdentifier, source: &str) -> Bundle {
+    let resource = FluentResource::try_new(source.to_owned())
+        .unwrap_or_else(|(_, errors)| panic!("message catalog is malformed: {:?}", errors));
+    let mut bundle = FluentBundle::new_concurrent(vec![locale]);
+    // Unicode isolation marks around arguments only help bidirectional text, and show up as
+    // garbage in many terminals.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .unwrap_or_else(|errors| panic!("message catalog is malformed: {:?}", errors));
+    bundle
+}
+
+/// The locale that messages should be shown in, or `None` for the default.
+fn user_locale() -> Option<LanguageIdentifier> {
+    let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    // POSIX locale names look like `fr_FR.UTF-8@euro`, while Fluent expects `fr-FR`.
+    let name = value.split(['.', '@']).next().unwrap().replace('_', "-");
+    if name == "C" || name == "POSIX" {
+        return None;
+    }
+    name.parse().ok()
+}
+
+/// Whether a catalog for `catalog_locale` should be used for `locale`, i.e., `fr` for `fr-CA`.
+fn matches_locale(catalog_locale: &str, locale: &LanguageIdentifier) -> bool {
+    let catalog_locale = catalog_locale.parse::<LanguageIdentifier>().unwrap();
+    catalog_locale.language == locale.language
+        && (catalog_locale.region.is_none() || catalog_locale.region == locale.region)
+}
+
+/// Formats the message `id` from the user's catalog, with `args` filled in. Prefer [`msg!`].
+pub(crate) fn message(id: &str, args: Option<&FluentArgs<'_>>) -> String {
+    let Catalogs { selected, fallback } = catalogs();
+    let (bundle, message) = match selected
+        .iter()
+        .chain([fallback])
+        .find_map(|bundle| Some((bundle, bundle.get_message(id)?)))
+    {
+        Some(found) => found,
+        None => {
+            tracing::debug!("no message {:?} in any catalog", id);
+            return id.to_owned();
+        }
+    };
+    let pattern = match message.value() {
+        Some(pattern) => pattern,
+        None => return id.to_owned(),
+    };
+    let mut errors = Vec::new();
+    let formatted = bundle.format_pattern(pattern, args, &mut errors);
+    if !errors.is_empty() {
+        tracing::debug!("failed to format message {:?}: {:?}", id, errors);
+    }
+    formatted.into_owned()
+}
+
+/// The answers to a prompt listed by the message `id`, separated by commas, in lowercase.
+pub(crate) fn answers(id: &str) -> Vec<String> {
+    message(id, None)
+        .split(',')
+        .map(|answer| answer.trim().to_lowercase())
+        .collect()
+}
+
+/// Formats a message from the user's catalog, i.e., `msg!("repo-name-not-found", name = "foo")`.
+/// Arguments may be anything that converts into a `FluentValue`, like strings and numbers.
+macro_rules! msg {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = ::fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+pub(crate) use msg;
@@ -0,0 +1,41 @@
+# Messages shown to users directly, in Fluent syntax: https://projectfluent.org/
+#
+# Arguments that are names and paths arrive already quoted. Only prompts and errors belong here;
+# log output isn't translated.
+
+## Suggestions
+
+did-you-mean = ; did you mean { $candidates }?
+# Joins the last of several candidates in `did-you-mean`.
+did-you-mean-or = or
+
+## Prompts
+
+confirm-choices = [y/N]
+# Answers accepted as "yes" to confirmation prompts, separated by commas.
+confirm-yes-answers = y, yes
+register-repo-prompt = register { $path } as { $name }?
+repo-metadata-contents = { $path } contains:
+repo-metadata-apply-prompt = apply it?
+repo-metadata-apply-missing-tags-prompt = this machine is missing tags required by this repo; apply it anyway?
+checkout-overwrites = checking out { $name } would overwrite existing files:
+checkout-overwrite-prompt = overwrite them?
+starter-scripts = { $path } lists scripts to run:
+starter-scripts-prompt = run them?
+gc-orphan-prompt = orphaned overlay repo directory { $desc }: [d]elete, [q]uarantine, or [S]kip?
+gc-orphan-delete-answers = d, delete
+gc-orphan-quarantine-answers = q, quarantine
+branches-prune = { $name } has branches fully merged into the branch checked out:
+branches-prune-prompt = delete them?
+add-name-conflict-prompt = a repo named { $name } already exists: [r]ename the new repo, re[p]lace the existing one (leaving its files in place), or [A]bort?
+add-name-conflict-rename-answers = r, rename
+add-name-conflict-replace-answers = p, replace
+add-name-conflict-new-name-prompt = new name (leave empty to abort):
+
+## Errors
+
+repo-name-not-found = { $name } is not a repo name in the current configuration
+repo-path-not-found = { $path } is not a path associated with any repo in the current configuration
+repo-work-tree-not-found = { $path } is not inside the work tree of any repo in the current configuration
+repo-work-tree-ambiguous = { $path } is inside the work trees of multiple repos ({ $names }); please specify one by name
+repo-entries-conflict = one or more existing repo entries conflict
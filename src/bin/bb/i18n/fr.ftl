@@ -0,0 +1,36 @@
+# Traduction française des messages de `en-US.ftl`.
+
+## Suggestions
+
+did-you-mean = ; vouliez-vous dire { $candidates } ?
+did-you-mean-or = ou
+
+## Prompts
+
+confirm-choices = [o/N]
+confirm-yes-answers = o, oui, y, yes
+register-repo-prompt = enregistrer { $path } sous le nom { $name } ?
+repo-metadata-contents = { $path } contient :
+repo-metadata-apply-prompt = l'appliquer ?
+repo-metadata-apply-missing-tags-prompt = il manque à cette machine des étiquettes requises par ce dépôt ; l'appliquer quand même ?
+checkout-overwrites = extraire { $name } écraserait des fichiers existants :
+checkout-overwrite-prompt = les écraser ?
+starter-scripts = { $path } liste des scripts à exécuter :
+starter-scripts-prompt = les exécuter ?
+gc-orphan-prompt = répertoire de dépôt overlay orphelin { $desc } : [s]upprimer, [m]ettre en quarantaine, ou [I]gnorer ?
+gc-orphan-delete-answers = s, supprimer, d, delete
+gc-orphan-quarantine-answers = m, quarantaine, q, quarantine
+branches-prune = { $name } a des branches entièrement fusionnées dans la branche extraite :
+branches-prune-prompt = les supprimer ?
+add-name-conflict-prompt = un dépôt nommé { $name } existe déjà : [r]enommer le nouveau dépôt, r[e]mplacer l'existant (en laissant ses fichiers en place), ou [A]bandonner ?
+add-name-conflict-rename-answers = r, renommer, rename
+add-name-conflict-replace-answers = e, remplacer, p, replace
+add-name-conflict-new-name-prompt = nouveau nom (laisser vide pour abandonner) :
+
+## Errors
+
+repo-name-not-found = { $name } n'est pas un nom de dépôt de la configuration actuelle
+repo-path-not-found = { $path } n'est le chemin d'aucun dépôt de la configuration actuelle
+repo-work-tree-not-found = { $path } n'est dans l'arbre de travail d'aucun dépôt de la configuration actuelle
+repo-work-tree-ambiguous = { $path } est dans les arbres de travail de plusieurs dépôts ({ $names }) ; veuillez en désigner un par son nom
+repo-entries-conflict = une ou plusieurs entrées de dépôt existantes sont en conflit
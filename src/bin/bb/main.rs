@@ -12,33 +12,130 @@
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
 use self::{
-    cli::Cli,
-    runner::{Directories, Runner}, // TODO: rename to `runner`?
+    cli::Args,
+    runner::{git::command_log, Directories, Error, MetricsLayer, Runner}, // TODO: rename to `runner`?
 };
 use anyhow::Context;
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use std::{
+    fs::File,
+    io,
+    time::{Instant, SystemTime},
+};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::{filter::LevelFilter, prelude::*, EnvFilter};
 
 mod cli;
+mod i18n;
 mod runner;
+mod suggest;
+mod units;
 
 fn main() {
-    colog::init();
-
-    let command = Cli::parse();
-    log::trace!("Parsed CLI args: {:?}", command);
+    let matches = Args::command().get_matches();
+    let command_name = subcommand_path(&matches);
+    let Args {
+        trace_file,
+        show_commands,
+        directory,
+        read_only,
+        profile,
+        command,
+    } = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // Failing to create the trace file is reported like any other error, once logging is set up.
+    let (trace_file, trace_file_err) = match trace_file
+        .map(|path| {
+            File::create(&path)
+                .with_context(|| format!("failed to create trace file {}", path.display()))
+        })
+        .transpose()
+    {
+        Ok(trace_file) => (trace_file, None),
+        Err(e) => (None, Some(e)),
+    };
+    let trace_guard = init_tracing(trace_file, show_commands);
+    tracing::trace!("Parsed CLI args: {:?}", command);
 
     let res = (|| -> anyhow::Result<_> {
+        if let Some(e) = trace_file_err {
+            return Err(e);
+        }
+        if let Some(directory) = directory {
+            std::env::set_current_dir(&directory)
+                .with_context(|| format!("failed to change directory to {:?}", directory))?;
+        }
         let dirs = Directories::new()?;
-        let mut rs = Runner::init(dirs).context("failed to initialize")?;
-        rs.run(command)?;
+        let mut rs = Runner::init(dirs, read_only, profile).context("failed to initialize")?;
+        // Commands that operate on many repos may fail partway through, so persist whatever they
+        // managed to do before reporting the failure.
+        let (started, start) = (SystemTime::now(), Instant::now());
+        let run_res = rs.run(command);
+        rs.record_metrics(
+            command_name,
+            started,
+            start.elapsed(),
+            run_res.as_ref().err().map_or(0, Error::exit_code),
+        );
 
-        log::trace!("flushing data");
+        tracing::trace!("flushing data");
         rs.flush().context("failed to flush data")?;
 
-        Ok(())
-    })();
+        Ok(run_res?)
+    })()
+    .map_err(Error::from);
     match res {
         Ok(()) => (),
-        Err(e) => log::error!("{:?}", e),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            // `exit` doesn't run destructors, so the trace needs to be flushed first.
+            drop(trace_guard);
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+/// Sets up logging to `stderr`, filtered with `RUST_LOG` (defaulting to `info`), and tracing to
+/// `trace_file` if specified. `show_commands` enables logging of Git commands on top of that.
+fn init_tracing(trace_file: Option<File>, show_commands: bool) -> Option<FlushGuard> {
+    let mut filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+    if show_commands {
+        filter = filter.add_directive(
+            format!("{}=debug", command_log::TARGET)
+                .parse()
+                .expect("directive for Git commands is invalid"),
+        );
+    }
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .without_time()
+        .with_target(false)
+        .with_filter(filter);
+    let (chrome_layer, guard) = match trace_file {
+        Some(trace_file) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .writer(trace_file)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(chrome_layer)
+        .with(MetricsLayer)
+        .init();
+    guard
+}
+
+/// The names of the (nested) subcommands in `matches`, i.e., `overlay clone`.
+fn subcommand_path(mut matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        names.push(name);
+        matches = sub_matches;
     }
+    names.join(" ")
 }
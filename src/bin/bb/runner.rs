@@ -12,65 +12,140 @@
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
 use self::{
-    dirs::current_dir,
-    git::{DynGit, GitCli, GitRepoKind, GitRepoTrait},
-    repo_db::{NewOverlayOptions, NewStandaloneOptions, RepoDb, RepoEntry},
+    backups::Backup,
+    config::Config,
+    dirs::{current_dir, HOME_OVERRIDE_VAR},
+    failed::BulkCommand,
+    git::{
+        default_remote_url, normalize_remote_url, BlobInfo, BranchInfo, DynGit, GitCli,
+        GitRepoTrait, ObjectCounts, RepoSource, RepoStatus, TransferStats, HOOKS_PATH_CONFIG_PATH,
+    },
+    hooks::{run_hooks, ConflictingField, Event, FailedSync, HookConfig, SyncedRepo},
+    machine::MachineIdentity,
+    notifications::Tally,
+    overlay_template::OverlayTemplate,
+    permissions::{ExposedStateFile, FileModeMismatch},
+    repo_db::{
+        NewOverlayOptions, NewStandaloneOptions, RepoDb, RepoDbSnapshot, RepoEntry, RepoInfo,
+        SourceInfo, SyncRecord,
+    },
+    repo_metadata::RepoMetadata,
+    ssh_config::SshConfig,
+    starter::{Machine, Severity, StarterEntry, StarterFile, StarterScript, TemplateVars},
+    sync::{SyncOutcome, SyncStrategy},
 };
 use crate::{
     cli::{
-        Cli, CliNewRepoName, CliRepoKind, ListFormat, OverlaySubcommand, RepoSpec,
-        StandaloneSubcommand,
+        BranchesSubcommand, Cli, CliNewRepoName, CommandAndArgs, DbSubcommand, ListFormat,
+        ListSort, MetricsSubcommand, OutputFormat, OverlayAttributesSubcommand, OverlaySubcommand,
+        ProfileSubcommand, RepoRef, StandaloneSubcommand, StarterSubcommand, UnmanagedSubcommand,
     },
+    i18n::{self, msg},
     runner::repo_db::{
         conflict::{
-            normalization::Normalization, NormalizedRepoNameEq, NormalizedRepoPathEq,
-            RepoConflictHandler,
+            normalization::Normalization, NameConflictResolution, NormalizedRepoNameEq,
+            NormalizedRepoPathEq, RepoConflictHandler,
         },
         NewStandaloneMethod,
     },
+    suggest,
+    units::HumanDuration,
 };
-use anyhow::{anyhow, bail, Context};
+use anyhow::{anyhow, bail, ensure, Context};
+use clap::CommandFactory;
 use format::lazy_format;
 use lifetime::{IntoStatic, ToBorrowed};
 use path_clean::PathClean;
 use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    env,
     fmt::{self, Debug, Display, Formatter},
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
     path::{Path, PathBuf},
-    process::ExitStatus,
+    process::{Command, ExitStatus},
     str::FromStr,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use strum::IntoEnumIterator;
 
+mod backups;
+mod child;
+mod config;
+mod created_dirs;
 mod dirs;
+mod discover;
+mod error;
+mod excludes;
+mod failed;
+mod gc;
 pub mod git;
+mod hooks;
+mod kind;
+mod layering;
+mod machine;
+mod metrics;
+mod notifications;
+mod ordering;
+mod overlay_files;
+mod overlay_template;
+mod permissions;
+mod picker;
+mod plugins;
+mod profiles;
+mod progress;
 mod repo_db;
+mod repo_metadata;
+mod ssh_config;
+mod starter;
+mod status;
+mod sync;
+#[cfg(feature = "tui")]
+mod ui;
+mod watch;
 
-pub(crate) use self::{dirs::Directories, repo_db::RepoName};
+pub(crate) use self::{
+    dirs::Directories,
+    error::Error,
+    kind::RepoKind,
+    metrics::MetricsLayer,
+    profiles::ProfileName,
+    repo_db::{RepoField, RepoName, RepoRecord},
+};
 
 #[derive(Debug)]
 pub struct Runner {
     dirs: Directories,
     git: DynGit,
     repos: RepoDb,
+    config: Config,
 }
 
-impl RepoSpec {
-    fn matches(&self, (_repo_name, repo): (RepoName<'_>, RepoEntry<'_>)) -> bool {
+impl RepoRef {
+    fn resolve<'a>(
+        &self,
+        dirs: &Directories,
+        repos: &'a RepoDbSnapshot,
+    ) -> anyhow::Result<(RepoName<'static>, RepoEntry<'a>)> {
         match self {
-            Self::All => true,
-            &Self::Kind(kind) => repo.kind() == kind,
+            Self::Name(name) => Ok((name.clone(), repos.get_by_name(name.to_borrowed())?)),
+            Self::Path(path) => {
+                let (name, repo) = repos
+                    .get_by_path(dirs, path)
+                    .or_else(|_e| repos.get_by_work_tree_containing(dirs, path))?;
+                Ok((name.into_static(), repo))
+            }
         }
     }
-}
 
-impl From<CliRepoKind> for GitRepoKind {
-    fn from(kind: CliRepoKind) -> Self {
-        match kind {
-            CliRepoKind::Overlay => Self::Bare,
-            CliRepoKind::Standalone => Self::Normal,
-        }
+    /// Returns `repo`, or a reference to the current working directory if `repo` is `None`.
+    fn or_cwd(repo: Option<Self>) -> anyhow::Result<Self> {
+        repo.map(Ok)
+            .unwrap_or_else(|| current_dir().map(Self::Path))
     }
 }
 
@@ -95,89 +170,414 @@ impl CliNewRepoName {
 }
 
 impl Runner {
-    pub(crate) fn init(dirs: Directories) -> anyhow::Result<Self> {
+    /// Loads the configuration and repo DBs. With `read_only`, or `read_only` set in the
+    /// configuration, nothing is written to disk from here on.
+    pub(crate) fn init(
+        mut dirs: Directories,
+        read_only: bool,
+        profile: Option<ProfileName>,
+    ) -> Result<Self, Error> {
+        profiles::select(&mut dirs, profile)?;
+        let config = Config::from_toml_on_disk(&dirs)?;
+        let home_dir = match env::var_os(HOME_OVERRIDE_VAR) {
+            Some(home_dir) => {
+                let home_dir = PathBuf::from(home_dir);
+                if !home_dir.is_absolute() {
+                    return Err(Error::Other(anyhow!(
+                        "`${}` must be an absolute path, got {:?}",
+                        HOME_OVERRIDE_VAR,
+                        home_dir
+                    )));
+                }
+                Some(home_dir)
+            }
+            None => config.home_dir().map(Path::to_owned),
+        };
+        if let Some(home_dir) = home_dir {
+            tracing::debug!("using {} as the home directory", home_dir.display());
+            dirs.set_home_dir(home_dir);
+        }
+        let git = DynGit::Cli(match config.clone_cache_dir(&dirs)? {
+            Some(dir) => GitCli::with_clone_cache(dir, config.clone_cache_max_size()),
+            None => GitCli::default(),
+        });
+        if read_only || config.read_only() {
+            dirs.set_read_only();
+        } else {
+            dirs.create()?;
+        }
         Ok(Runner {
-            repos: RepoDb::new(&dirs)?,
+            repos: RepoDb::new(&dirs, config.scan())?,
+            config,
             dirs,
-            git: DynGit::Cli(GitCli),
+            git,
         })
     }
 
-    pub(crate) fn run(&mut self, cli_args: Cli) -> anyhow::Result<()> {
-        fn print_add_res<'a, F>(op_name: &'static str, f: F) -> anyhow::Result<()>
+    pub(crate) fn run(&mut self, cli_args: Cli) -> Result<(), Error> {
+        let _span = tracing::info_span!("command", args = ?cli_args).entered();
+        if !cli_args.is_read_only() {
+            self.dirs
+                .ensure_writable("run a command that may make changes")?;
+        }
+        let is_read_only = cli_args.is_read_only();
+        self.run_subcommand(cli_args).map_err(Error::from)?;
+        if !is_read_only {
+            let num_pruned = backups::prune_automatically(&self.dirs, self.config.backups());
+            if num_pruned > 0 {
+                tracing::info!(
+                    "pruned {} backup(s) exceeding configured limits",
+                    num_pruned
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn run_subcommand(&mut self, cli_args: Cli) -> anyhow::Result<()> {
+        fn print_add_res<'a, F>(
+            hooks: &[HookConfig],
+            op_name: &'static str,
+            f: F,
+        ) -> anyhow::Result<()>
         where
             F: FnOnce(
                     &mut dyn RepoConflictHandler,
                 ) -> anyhow::Result<(RepoName<'a>, RepoEntry<'a>)>
                 + 'a,
         {
-            struct ConflictHandler;
-
-            impl RepoConflictHandler for ConflictHandler {
-                fn on_conflict_path(
-                    &mut self,
-                    matched: RepoName<'_>,
-                    partial_reason: Option<(Cow<'_, Path>, NormalizedRepoPathEq)>,
-                ) {
-                    log::error!(
-                        "a repo with the path {:?} already exists{}",
-                        matched,
-                        lazy_format!(move |f| {
-                            match &partial_reason {
-                                None => Ok(()),
-                                Some((found, match_reason)) => {
-                                    write!(f, " (")?;
-                                    match_reason.describe(found, f)?;
-                                    write!(f, ")")?;
-                                    Ok(())
+            let (name, repo) = f(&mut ConflictHandler { hooks })
+                .with_context(|| anyhow!("failed to {} repo", op_name))?;
+
+            tracing::info!("registered {:?} as {}", name, repo.short_desc());
+            run_hooks(
+                hooks,
+                &Event::RepoRegistered {
+                    name,
+                    kind: repo.kind(),
+                },
+            );
+            Ok(())
+        }
+        match cli_args {
+            Cli::Starter(subcmd) => match subcmd {
+                StarterSubcommand::Import {
+                    path,
+                    verify_key,
+                    signature,
+                    vars,
+                    skip_scripts,
+                    run_scripts,
+                } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+
+                    let mut tally = Tally::start("starter import");
+                    let contents = fs::read(&path)
+                        .with_context(|| anyhow!("failed to read starter file {:?}", path))?;
+                    if let Some(key) = verify_key {
+                        let signature_path = signature.unwrap_or_else(|| {
+                            let mut signature_path = path.clone().into_os_string();
+                            signature_path.push(".sig");
+                            signature_path.into()
+                        });
+                        starter::verify_signature(&contents, &signature_path, &key).with_context(
+                            || anyhow!("failed to verify signature of starter file {:?}", path),
+                        )?;
+                        tracing::info!("verified signature of starter file {:?}", path);
+                    }
+                    let toml = String::from_utf8(contents)
+                        .with_context(|| anyhow!("starter file {:?} is not valid UTF-8", path))?;
+                    let mut starter = StarterFile::from_toml(&toml)
+                        .with_context(|| anyhow!("failed to parse starter file {:?}", path))?;
+
+                    let machine = Machine::current();
+                    let home = dirs.home_dir_path()?;
+                    let vars = TemplateVars::new(&machine, &home, vars);
+                    let mut unresolved = BTreeSet::new();
+                    // Variables only need to be defined for entries that apply to this machine.
+                    for entry in &mut starter.repos {
+                        if entry.when.unmet_reason(&machine).is_none() {
+                            entry.expand_templates(&vars, &mut unresolved);
+                        }
+                    }
+                    for script in &mut starter.run {
+                        if script.when.unmet_reason(&machine).is_none() {
+                            script.expand_templates(&vars, &mut unresolved);
+                        }
+                    }
+                    ensure!(
+                        unresolved.is_empty(),
+                        "starter file {:?} refers to undefined variables {:?}; define them with \
+                        `--var NAME=VALUE`",
+                        path,
+                        unresolved
+                    );
+                    let (num_applied, num_skipped, num_failed) = {
+                        let mut repos = repos.transaction();
+                        let (mut num_applied, mut num_skipped, mut num_failed) = (0, 0, 0);
+                        for entry in &starter.repos {
+                            let name = entry.name.to_borrowed().into_static();
+                            if let Some(reason) = entry.when.unmet_reason(&machine) {
+                                tracing::info!("skipping {:?}: {}", name, reason);
+                                num_skipped += 1;
+                                continue;
+                            }
+                            if repos.get_by_name_opt(name.to_borrowed()).is_some() {
+                                tracing::info!("skipping {:?}: already registered", name);
+                                num_skipped += 1;
+                                continue;
+                            }
+                            let source = entry
+                                .source
+                                .as_deref()
+                                .map(|source| {
+                                    RepoSource::from_str(source)
+                                        .map_err(anyhow::Error::from)
+                                        .and_then(|source| config.rewrite_source(source))
+                                })
+                                .transpose();
+                            let source = match source {
+                                Ok(source) => source,
+                                Err(e) => {
+                                    tracing::error!("skipping {:?}: {:?}", name, e);
+                                    num_failed += 1;
+                                    continue;
+                                }
+                            };
+                            let op_name = if entry.kind.is_git_repo() {
+                                "clone"
+                            } else {
+                                "create"
+                            };
+                            // `lint` already checked that entries have the `source` and `path`
+                            // their kind needs.
+                            let res = print_add_res(config.hooks(), op_name, |handler| match entry
+                                .kind
+                            {
+                                RepoKind::Overlay => repos.new_overlay(
+                                    dirs,
+                                    git,
+                                    config.overlay(),
+                                    name.clone(),
+                                    NewOverlayOptions::Clone {
+                                        source: source.unwrap(),
+                                        no_checkout: false,
+                                        only: Vec::new(),
+                                        keep_depth: None,
+                                    },
+                                    handler,
+                                ),
+                                RepoKind::Standalone => repos.new_standalone(
+                                    dirs,
+                                    git,
+                                    NewStandaloneOptions {
+                                        name: name.clone(),
+                                        path: entry.resolved_path(&home).unwrap().into(),
+                                        app_info: None,
+                                        method: NewStandaloneMethod::Clone {
+                                            source: source.unwrap(),
+                                            keep_depth: None,
+                                        },
+                                    },
+                                    handler,
+                                ),
+                                RepoKind::Unmanaged => repos.new_unmanaged(
+                                    dirs,
+                                    name.clone(),
+                                    &entry.resolved_path(&home).unwrap(),
+                                    true,
+                                    handler,
+                                ),
+                            })
+                            .and_then(|()| {
+                                apply_configured_file_modes(dirs, git, config, &repos, name)
+                            });
+                            match res {
+                                Ok(()) => num_applied += 1,
+                                Err(e) => {
+                                    tracing::error!("{:?}", e);
+                                    num_failed += 1;
                                 }
                             }
-                        })
+                        }
+                        repos.commit();
+                        (num_applied, num_skipped, num_failed)
+                    };
+                    tracing::info!(
+                        "applied {} starter entries, skipped {}",
+                        num_applied,
+                        num_skipped
+                    );
+                    // Scripts run even if some entries failed, since they may not depend on them.
+                    let num_failed_scripts = run_starter_scripts(
+                        dirs,
+                        repos,
+                        &path,
+                        &starter,
+                        &machine,
+                        skip_scripts,
+                        run_scripts,
+                    )?;
+                    tally.succeeded = num_applied;
+                    tally.failed = num_failed + num_failed_scripts;
+                    tally.finish(config.notifications());
+                    if num_failed != 0 {
+                        bail!("failed to apply {} starter entries, see above", num_failed);
+                    }
+                    ensure!(
+                        num_failed_scripts == 0,
+                        "{} starter script(s) failed, see above",
+                        num_failed_scripts
                     );
+                    Ok(())
                 }
+                StarterSubcommand::Export {
+                    path,
+                    repos: selected,
+                    tags,
+                    redact_sources,
+                } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config: _,
+                    } = self;
 
-                fn on_conflict_name(
-                    &mut self,
-                    matched: RepoName<'_>,
-                    partial_reason: Option<NormalizedRepoNameEq>,
-                ) {
-                    log::error!(
-                        "a repo with the name {:?} already exists at the specified path{}",
-                        matched,
-                        lazy_format!(|f| {
-                            match &partial_reason {
-                                None => Ok(()),
-                                Some(match_reason) => {
-                                    write!(f, " (")?;
-                                    match_reason.describe(&matched, f)?;
-                                    write!(f, ")")?;
-                                    Ok(())
+                    let repos = repos.snapshot();
+                    let home = dirs.home_dir_path()?;
+                    let selected = selected
+                        .iter()
+                        .map(|repo| Ok(repo.resolve(dirs, &repos)?.0))
+                        .collect::<anyhow::Result<BTreeSet<_>>>()?;
+                    let is_selected = |record: &RepoRecord| {
+                        (selected.is_empty() && tags.is_empty())
+                            || selected.contains(&record.name)
+                            || record.tags.iter().any(|tag| tags.contains(tag))
+                    };
+                    let mut starter = StarterFile::default();
+                    for record in repos.records(dirs) {
+                        let RepoRecord {
+                            name,
+                            kind,
+                            path: _,
+                            work_tree,
+                            source,
+                            tags: _,
+                            app_info: _,
+                        } = match record {
+                            Ok(record) if is_selected(&record) => record,
+                            Ok(_) => continue,
+                            // One broken repo shouldn't keep the others from being exported.
+                            Err(e) => {
+                                tracing::warn!("{:?}", e.context("skipping a repo"));
+                                continue;
+                            }
+                        };
+                        let source = match source {
+                            Some(SourceInfo { url, branch: _ }) => Some(url.into_owned()),
+                            None if !kind.is_git_repo() => None,
+                            None => {
+                                let remote_url = (|| -> anyhow::Result<_> {
+                                    let opened = repos.get_by_name(name.to_borrowed())?.open(
+                                        git,
+                                        dirs,
+                                        name.to_borrowed(),
+                                    )?;
+                                    let remotes = opened.remotes()?;
+                                    Ok(default_remote_url(&remotes).map(str::to_owned))
+                                })();
+                                match remote_url {
+                                    Ok(Some(url)) => Some(url),
+                                    Ok(None) => {
+                                        tracing::warn!("skipping {:?}: it has no remotes", name);
+                                        continue;
+                                    }
+                                    Err(e) => {
+                                        tracing::warn!(
+                                            "{:?}",
+                                            e.context(anyhow!("skipping {:?}", name))
+                                        );
+                                        continue;
+                                    }
                                 }
                             }
-                        })
-                    );
+                        };
+                        let path = match kind {
+                            RepoKind::Overlay => None,
+                            RepoKind::Standalone | RepoKind::Unmanaged => Some(
+                                work_tree
+                                    .strip_prefix(&home)
+                                    .map_or_else(|_e| work_tree.clone(), Path::to_owned),
+                            ),
+                        };
+                        let source =
+                            source.map(|source| match starter::redact_source(&name, &source) {
+                                Some(placeholder) if redact_sources => {
+                                    tracing::info!("redacted source of {:?}", name);
+                                    placeholder
+                                }
+                                _ => source,
+                            });
+                        starter.repos.push(StarterEntry {
+                            name,
+                            kind,
+                            source: source.map(Into::into),
+                            path,
+                            when: Default::default(),
+                        });
+                    }
+                    fs::write(&path, starter.to_toml()?)
+                        .with_context(|| anyhow!("failed to write starter file to {:?}", path))?;
+                    tracing::info!("exported {} repo(s) to {:?}", starter.repos.len(), path);
+                    Ok(())
                 }
-
-                fn on_iteration_err(&mut self, err: anyhow::Error) {
-                    log::error!("failed to enumerate existing repo entry: {:?}", err);
+                StarterSubcommand::Verify { path } => {
+                    let toml = fs::read_to_string(&path)
+                        .with_context(|| anyhow!("failed to read starter file {:?}", path))?;
+                    let problems = starter::lint(&toml, true);
+                    for problem in &problems {
+                        // Formatted like compiler diagnostics, so editors and CI can link to them.
+                        let separator = if problem.position.is_some() {
+                            ":"
+                        } else {
+                            ": "
+                        };
+                        println!("{}{}{}", path.display(), separator, problem);
+                    }
+                    let num_errors = problems
+                        .iter()
+                        .filter(|problem| problem.severity == Severity::Error)
+                        .count();
+                    ensure!(
+                        num_errors == 0,
+                        "found {} error(s) in starter file {:?}",
+                        num_errors,
+                        path
+                    );
+                    if problems.is_empty() {
+                        tracing::info!("no problems found in starter file {:?}", path);
+                    }
+                    Ok(())
                 }
-            }
-            let (name, repo) =
-                f(&mut ConflictHandler).with_context(|| anyhow!("failed to {} repo", op_name))?;
-
-            log::info!("registered {:?} as {}", name, repo.short_desc());
-            Ok(())
-        }
-        match cli_args {
-            Cli::Starter(_subcmd) => {
-                bail!("`starter` commands are not implemented yet, stay tuned!")
-            }
+            },
             Cli::Standalone(subcmd) => match subcmd {
                 StandaloneSubcommand::Init { path, name } => {
-                    let Self { dirs, git, repos } = self;
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
                     let path = path.map(Ok).unwrap_or_else(current_dir)?;
                     let name = name.unwrap_or_base_name(&path)?;
-                    print_add_res("initialize", |handler| {
+                    let mut repos = repos.transaction();
+                    print_add_res(config.hooks(), "initialize", |handler| {
                         repos.new_standalone(
                             dirs,
                             git,
@@ -189,277 +589,2606 @@ impl Runner {
                             },
                             handler,
                         )
-                    })
-                }
-                StandaloneSubcommand::Clone { name, path, source } => {
-                    let Self { dirs, git, repos } = self;
-                    #[allow(clippy::diverging_sub_expression)]
-                    let path = path.map(Ok).unwrap_or_else(|| -> anyhow::Result<_> {
-                        let mut cwd = current_dir()?;
-                        #[allow(unreachable_code)]
-                        cwd.push::<&Path>(todo!(
-                            "still haven't implemented getting a base name from the repo source"
-                        ));
-                        Ok(cwd)
                     })?;
+                    repos.commit();
+                    Ok(())
+                }
+                StandaloneSubcommand::Clone {
+                    name,
+                    path,
+                    source,
+                    keep_depth,
+                    recurse_config,
+                } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+                    let path = match path {
+                        Some(path) => path,
+                        None => default_clone_path(dirs, config, &source)?,
+                    };
+                    let name_is_set = name.is_set();
                     let name = name.unwrap_or_base_name(&path)?;
+                    let source = config.rewrite_source(source)?;
 
-                    print_add_res("clone", |handler| {
-                        repos.new_standalone(
-                            dirs,
-                            git,
-                            NewStandaloneOptions {
-                                name,
-                                path: path.into(),
-                                app_info: None,
-                                method: NewStandaloneMethod::Clone { source },
-                            },
-                            handler,
-                        )
-                    })
+                    {
+                        let mut repos = repos.transaction();
+                        print_add_res(config.hooks(), "clone", |handler| {
+                            repos.new_standalone(
+                                dirs,
+                                git,
+                                NewStandaloneOptions {
+                                    name: name.clone(),
+                                    path: path.clone().into(),
+                                    app_info: None,
+                                    method: NewStandaloneMethod::Clone { source, keep_depth },
+                                },
+                                handler,
+                            )
+                        })?;
+                        repos.commit();
+                    }
+
+                    let metadata_toml = read_repo_metadata(&path, recurse_config)?;
+                    let metadata = metadata_toml
+                        .as_deref()
+                        .map(RepoMetadata::from_toml)
+                        .transpose()
+                        .with_context(|| anyhow!("{:?} is invalid", RepoMetadata::path(&path)))?;
+                    let name = match metadata {
+                        Some(metadata)
+                            if confirm_repo_metadata(&path, &metadata, config.tags())? =>
+                        {
+                            let RepoMetadata {
+                                name: preferred_name,
+                                app_info,
+                                post_clone: _,
+                                required_tags: _,
+                            } = &metadata;
+                            let new_name = match preferred_name {
+                                Some(preferred_name) if !name_is_set => {
+                                    preferred_name.to_borrowed().into_static()
+                                }
+                                _ => name.clone(),
+                            };
+                            let mut tx = repos.transaction();
+                            let name = tx
+                                .update_standalone(
+                                    dirs,
+                                    name.to_borrowed(),
+                                    new_name,
+                                    app_info.to_borrowed().into_static(),
+                                    &mut ConflictHandler {
+                                        hooks: config.hooks(),
+                                    },
+                                )?
+                                .0
+                                .into_static();
+                            tx.commit();
+                            tracing::info!("applied metadata to {:?}", name);
+                            metadata.run_post_clone(&path)?;
+                            name
+                        }
+                        _ => name,
+                    };
+                    apply_configured_file_modes(dirs, git, config, &repos.snapshot(), name)
                 }
-                StandaloneSubcommand::Register { path, name } => {
-                    let Self { repos, dirs, git } = self;
+                StandaloneSubcommand::Register {
+                    path,
+                    name,
+                    recurse_config,
+                    from_file,
+                } => {
+                    let Self {
+                        repos,
+                        dirs,
+                        git,
+                        config,
+                    } = self;
+
+                    let mut repos = repos.transaction();
+                    if let Some(list_path) = from_file {
+                        let list = if list_path == Path::new("-") {
+                            io::read_to_string(io::stdin()).context("failed to read `stdin`")?
+                        } else {
+                            fs::read_to_string(&list_path)
+                                .with_context(|| anyhow!("failed to read {:?}", list_path))?
+                        };
+                        let paths = list
+                            .lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(PathBuf::from);
+
+                        let mut num_registered = 0;
+                        let mut num_failed = 0;
+                        for path in paths {
+                            let res = CliNewRepoName::default()
+                                .unwrap_or_base_name(&path)
+                                .and_then(|name| {
+                                    print_add_res(config.hooks(), "register", |handler| {
+                                        repos.new_standalone(
+                                            dirs,
+                                            git,
+                                            NewStandaloneOptions {
+                                                name,
+                                                path: path.as_path().into(),
+                                                app_info: None,
+                                                method: NewStandaloneMethod::Register,
+                                            },
+                                            handler,
+                                        )
+                                    })
+                                });
+                            match res {
+                                Ok(()) => num_registered += 1,
+                                Err(e) => {
+                                    tracing::error!("skipping {:?}: {:?}", path, e);
+                                    num_failed += 1;
+                                }
+                            }
+                        }
+                        repos.commit();
+                        tracing::info!("registered {} repo(s)", num_registered);
+                        if num_failed != 0 {
+                            bail!("failed to register {} repo(s), see above", num_failed);
+                        }
+                        return Ok(());
+                    }
 
                     let path = path.map(Ok).unwrap_or_else(current_dir)?;
-                    let name = name.unwrap_or_base_name(&path)?;
 
-                    print_add_res("register", |handler| {
+                    let metadata_toml = read_repo_metadata(&path, recurse_config)?;
+                    let metadata = metadata_toml
+                        .as_deref()
+                        .map(RepoMetadata::from_toml)
+                        .transpose()
+                        .with_context(|| anyhow!("{:?} is invalid", RepoMetadata::path(&path)))?;
+                    let (preferred_name, app_info) = match metadata {
+                        Some(metadata)
+                            if confirm_repo_metadata(&path, &metadata, config.tags())? =>
+                        {
+                            let RepoMetadata {
+                                name,
+                                app_info,
+                                post_clone,
+                                required_tags: _,
+                            } = metadata;
+                            if !post_clone.is_empty() {
+                                tracing::info!(
+                                    "not running post-clone commands, since this repo wasn't \
+                                    cloned"
+                                );
+                            }
+                            (name, app_info)
+                        }
+                        _ => (None, None),
+                    };
+                    let name = match preferred_name {
+                        Some(preferred_name) if !name.is_set() => preferred_name.into_static(),
+                        _ => name.unwrap_or_base_name(&path)?,
+                    };
+
+                    print_add_res(config.hooks(), "register", |handler| {
                         repos.new_standalone(
                             dirs,
                             git,
                             NewStandaloneOptions {
                                 name,
                                 path: path.into(),
-                                app_info: None,
+                                app_info: app_info.map(IntoStatic::into_static),
                                 method: NewStandaloneMethod::Register,
                             },
                             handler,
                         )
-                    })
+                    })?;
+                    repos.commit();
+                    Ok(())
+                }
+                StandaloneSubcommand::Discover {
+                    root,
+                    max_depth,
+                    ignore,
+                    all,
+                } => {
+                    let Self {
+                        repos,
+                        dirs,
+                        git,
+                        config,
+                    } = self;
+
+                    let discover_config = config.discover();
+                    let max_depth = max_depth.unwrap_or_else(|| discover_config.max_depth());
+                    let ignore = discover_config
+                        .ignore()
+                        .iter()
+                        .cloned()
+                        .chain(ignore)
+                        .collect::<Vec<_>>();
+
+                    let found = discover::find_repos(&root, max_depth, &ignore)?;
+                    let mut repos = repos.transaction();
+                    let unregistered = found
+                        .into_iter()
+                        .filter(|path| repos.get_by_path(dirs, path).is_err())
+                        .collect::<Vec<_>>();
+                    if unregistered.is_empty() {
+                        tracing::info!("no unregistered repos found under {:?}", root);
+                        return Ok(());
+                    }
+
+                    let mut num_registered = 0;
+                    let mut num_failed = 0;
+                    for path in unregistered {
+                        let name = match CliNewRepoName::default().unwrap_or_base_name(&path) {
+                            Ok(name) => name,
+                            Err(e) => {
+                                tracing::warn!("skipping {:?}: {:?}", path, e);
+                                num_failed += 1;
+                                continue;
+                            }
+                        };
+                        let question = msg!(
+                            "register-repo-prompt",
+                            path = format!("{:?}", path),
+                            name = format!("{:?}", name),
+                        );
+                        if !all && !confirm(question)? {
+                            continue;
+                        }
+                        let res = print_add_res(config.hooks(), "register", |handler| {
+                            repos.new_standalone(
+                                dirs,
+                                git,
+                                NewStandaloneOptions {
+                                    name,
+                                    path: path.into(),
+                                    app_info: None,
+                                    method: NewStandaloneMethod::Register,
+                                },
+                                handler,
+                            )
+                        });
+                        match res {
+                            Ok(()) => num_registered += 1,
+                            Err(e) => {
+                                tracing::error!("{:?}", e);
+                                num_failed += 1;
+                            }
+                        }
+                    }
+                    repos.commit();
+                    tracing::info!("registered {} repo(s)", num_registered);
+                    if num_failed != 0 {
+                        bail!("failed to register {} repo(s), see above", num_failed);
+                    }
+                    Ok(())
                 }
-                StandaloneSubcommand::Deregister { repo, name } => {
+                StandaloneSubcommand::Deregister { repo } => {
                     let Self {
                         repos,
                         git: _,
                         dirs,
+                        config,
                     } = self;
 
-                    // TODO: ensure `repo` is after `--name` for forwards compatibility
-                    let name = if name {
-                        repo.context("`--name` was specified without a value")?
-                            .to_str()
-                            .context("name was not UTF-8")?
-                            .parse::<RepoName<'static>>()?
-                    } else {
-                        let path = repo.map(Ok).unwrap_or_else(current_dir)?;
-                        let (name, _repo) = repos.get_by_path(dirs, &path)?;
-                        name.into_static()
-                    };
+                    let mut repos = repos.transaction();
+                    let name = RepoRef::or_cwd(repo)?.resolve(dirs, &repos)?.0;
 
                     let repo = repos.deregister_standalone(name.to_borrowed())?;
-                    log::info!(
+                    repos.commit();
+                    tracing::info!(
                         "deregistered {}; your files have been left intact",
                         repo.short_desc()
                     );
+                    run_hooks(config.hooks(), &Event::RepoDeregistered { name });
                     Ok(())
                 }
             },
             Cli::Overlay(subcmd) => match subcmd {
-                OverlaySubcommand::Init { name } => {
-                    let Self { dirs, git, repos } = self;
-                    print_add_res("initialize", |handler| {
-                        repos.new_overlay(dirs, git, name, NewOverlayOptions::Init, handler)
-                    })
+                OverlaySubcommand::Init {
+                    name,
+                    from_template,
+                } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+                    let template = from_template
+                        .map(|location| OverlayTemplate::fetch(git, &location))
+                        .transpose()?;
+                    if let Some(template) = &template {
+                        template.ensure_no_conflicts(&dirs.home_dir_path()?, &name)?;
+                    }
+                    {
+                        let mut repos = repos.transaction();
+                        print_add_res(config.hooks(), "initialize", |handler| {
+                            repos.new_overlay(
+                                dirs,
+                                git,
+                                config.overlay(),
+                                name.clone(),
+                                NewOverlayOptions::Init,
+                                handler,
+                            )
+                        })?;
+                        repos.commit();
+                    }
+                    if let Some(template) = template {
+                        let snapshot = repos.snapshot();
+                        let repo = snapshot.get_by_name(name.to_borrowed())?;
+                        repo.open(git, dirs, name.to_borrowed())
+                            .and_then(|mut repo| {
+                                template.apply(
+                                    dirs,
+                                    &mut repo,
+                                    &name,
+                                    config.overlay().allow_git_hooks(),
+                                )
+                            })
+                            .context("failed to apply template")?;
+                    }
+                    Ok(())
                 }
                 OverlaySubcommand::Clone {
                     name,
                     no_checkout,
+                    only,
+                    overwrite,
+                    show_diff,
                     source,
+                    keep_depth,
                 } => {
-                    let Self { dirs, git, repos } = self;
-                    let name = name.into_opt().map(Ok).unwrap_or_else(|| -> anyhow::Result<_> {
-                        todo!("still haven't implemented getting a base name from the repo source")
-                    })?;
-                    print_add_res("clone", |handler| {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+                    let name = match name.into_opt() {
+                        Some(name) => name,
+                        None => {
+                            let base_name = source.base_name().with_context(|| {
+                                anyhow!(
+                                    "failed to infer a repo name from {:?}; specify one with \
+                                    `--name`",
+                                    source.as_str()
+                                )
+                            })?;
+                            RepoName::from_str(base_name).with_context(|| {
+                                anyhow!(
+                                    "{:?}, inferred from {:?}, is not a valid repo name; specify \
+                                    one with `--name`",
+                                    base_name,
+                                    source.as_str()
+                                )
+                            })?
+                        }
+                    };
+                    let source = config.rewrite_source(source)?;
+                    {
+                        let mut repos = repos.transaction();
+                        print_add_res(config.hooks(), "clone", |handler| {
+                            repos.new_overlay(
+                                dirs,
+                                git,
+                                config.overlay(),
+                                name.clone(),
+                                // Checkout happens below, once overwriting files is confirmed.
+                                NewOverlayOptions::Clone {
+                                    source,
+                                    no_checkout: true,
+                                    only: Vec::new(),
+                                    keep_depth,
+                                },
+                                handler,
+                            )
+                        })?;
+                        repos.commit();
+                    }
+                    if no_checkout {
+                        return Ok(());
+                    }
+                    let snapshot = repos.snapshot();
+                    let mut repo = snapshot.get_by_name(name.to_borrowed())?.open(
+                        git,
+                        dirs,
+                        name.to_borrowed(),
+                    )?;
+                    let overwritten = repo.restore_preview(&only, false)?;
+                    if !overwritten.is_empty() {
+                        eprint!(
+                            "{}\n{}",
+                            msg!("checkout-overwrites", name = format!("{:?}", name)),
+                            overwritten
+                        );
+                        if show_diff {
+                            eprint!("{}", repo.restore_preview(&only, true)?);
+                        }
+                        if !overwrite && !confirm(msg!("checkout-overwrite-prompt"))? {
+                            tracing::warn!(
+                                "left the work tree of {:?} as it was; files from the repo will \
+                                show up as changed or deleted in `git status`",
+                                name
+                            );
+                            return Ok(());
+                        }
+                    }
+                    if let Err(e) = tracing::info_span!("restore", repo = ?name)
+                        .in_scope(|| created_dirs::restore(&mut repo, &only))
+                        .context("failed to populate work tree")
+                    {
+                        tracing::warn!("{}", e);
+                    }
+                    apply_configured_file_modes(dirs, git, config, &snapshot, name)
+                }
+                OverlaySubcommand::Register { name, git_dir } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+                    let mut repos = repos.transaction();
+                    print_add_res(config.hooks(), "register", |handler| {
                         repos.new_overlay(
                             dirs,
                             git,
+                            config.overlay(),
                             name,
-                            NewOverlayOptions::Clone {
-                                source,
-                                no_checkout,
+                            NewOverlayOptions::Register {
+                                git_dir: git_dir.into(),
                             },
                             handler,
                         )
-                    })
+                    })?;
+                    repos.commit();
+                    Ok(())
                 }
-                OverlaySubcommand::RemoveBareRepo { name } => {
+                OverlaySubcommand::RemoveBareRepo { name, prune_config } => {
                     let Self {
                         dirs,
                         git: _,
                         repos,
+                        config,
                     } = self;
+                    let mut repos = repos.transaction();
                     repos.remove_overlay_bare_repo(dirs, name.to_borrowed())?;
-                    log::info!("removed bare Git repo for {:?}; your work tree files have been left intact", name);
+                    repos.commit();
+                    tracing::info!("removed bare Git repo for {:?}; your work tree files have been left intact", name);
+                    if prune_config {
+                        overlay_files::prune(dirs, &name)?;
+                    }
+                    run_hooks(
+                        config.hooks(),
+                        &Event::RepoRemoved {
+                            name,
+                            files_deleted: false,
+                        },
+                    );
                     Ok(())
                 }
-            },
-            Cli::Run {
-                repo_name,
-                no_cd_root,
-                cmd_and_args,
-            } => {
-                let Self { dirs, git, repos } = self;
-
-                let mut cmd = cmd_and_args.to_std()?;
-
-                let repo = repos
-                    .get_by_name(repo_name.to_borrowed())
-                    .with_context(|| {
-                        anyhow!(
-                            concat!(
-                                "no repo configured with the name {:?} -- do you need to `",
-                                env!("CARGO_BIN_NAME"),
-                                " repo add`?",
-                            ),
-                            repo_name,
-                        )
-                    })?;
-
-                let repo = {
-                    if !no_cd_root {
-                        cmd.current_dir(repo.work_tree_path(dirs)?);
+                OverlaySubcommand::Deregister { name, prune_config } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config,
+                    } = self;
+                    let mut repos = repos.transaction();
+                    if prune_config {
+                        let mut repo = repos.get_by_name(name.to_borrowed())?.open(
+                            git,
+                            dirs,
+                            name.to_borrowed(),
+                        )?;
+                        overlay_files::unset_config(dirs, &mut repo, &name)?;
                     }
-                    repo.open(git, dirs, repo_name)?
-                };
-
-                let cmd_status = repo.run_cmd(cmd, |mut cmd| {
-                    log::debug!("running command {:?}", cmd);
-                    cmd.status().context("failed to spawn command")
-                })?;
-
-                let _our_exit_code = match cmd_status.code() {
-                    Some(code) => {
-                        let display_exit_code =
-                            lazy_format!(|f| { write!(f, "command returned exit code {}", code) });
-                        if code == 0 {
-                            log::debug!("{}", display_exit_code);
-                        } else {
-                            log::warn!("{}", display_exit_code);
-                        }
-                        code
+                    let repo = repos.deregister_overlay(name.to_borrowed())?;
+                    repos.commit();
+                    tracing::info!(
+                        "deregistered {:?}; its Git directory at {} and your files have been left \
+                        intact",
+                        name,
+                        repo.path(dirs, name.to_borrowed())?.display()
+                    );
+                    if prune_config {
+                        overlay_files::prune(dirs, &name)?;
                     }
-                    None => {
-                        log::warn!("command was terminated by a signal");
-                        201 // TODO: actually design error codes for this command
+                    run_hooks(config.hooks(), &Event::RepoDeregistered { name });
+                    Ok(())
+                }
+                OverlaySubcommand::SetWorktree { name, path, force } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config: _,
+                    } = self;
+                    let mut repos = repos.transaction();
+                    let entry = repos.get_by_name(name.to_borrowed())?;
+                    ensure!(
+                        entry.kind() == RepoKind::Overlay,
+                        "{:?} is not an overlay repo",
+                        name
+                    );
+                    let path = canonicalize_path(&path)?;
+                    ensure!(path.is_dir(), "{} is not a directory", path.display());
+                    for (what, own_dir) in [
+                        ("data", dirs.data_dir_path()),
+                        ("state", dirs.state_dir_path()),
+                    ] {
+                        ensure!(
+                            !path.starts_with(&own_dir),
+                            "{} is inside this tool's {} directory",
+                            path.display(),
+                            what
+                        );
                     }
-                };
 
-                // TODO: Return with exit code
+                    let mut repo = entry.open(git, dirs, name.to_borrowed())?;
+                    if !force {
+                        let status = repo.status()?;
+                        ensure!(
+                            !status.has_uncommitted_changes(),
+                            "{:?} has uncommitted changes in {} ({}); commit them, or pass \
+                            `--force` to switch anyway",
+                            name,
+                            repo.work_tree_path().display(),
+                            status
+                        );
+                    }
+                    // Keep `core.worktree` in step, if it was set for this repo.
+                    if repo.get_config("core.worktree")?.is_some() {
+                        repo.set_config("core.worktree", Some(path.as_os_str()))?;
+                    }
 
-                Ok(())
-            }
-            // TODO: This `allow` is necessary, but `clippy` throws a false positive. We need
-            // to `collect` first in order to avoid borrowing `self` while iterating.
-            #[allow(clippy::needless_collect)]
-            Cli::ForEach {
-                no_cd_root,
-                cmd_and_args,
-            } => {
-                let mut err_happened = false;
-                let names = self
-                    .repos
-                    .iter()
-                    .map(|(name, repo)| (name.clone().into_static(), repo.short_desc().to_string()))
-                    .collect::<Vec<_>>();
-                names.into_iter().for_each(|(repo_name, repo_short_desc)| {
-                    log::info!(
-                        "running command against {:?} ({})",
-                        repo_name,
-                        repo_short_desc
+                    let home = dirs.home_dir_path()?;
+                    let work_tree = (path != home).then_some(path);
+                    repos.set_overlay_work_tree(name.to_borrowed(), work_tree)?;
+                    tracing::info!(
+                        "{:?} now uses {} as its work tree",
+                        name,
+                        repos
+                            .get_by_name(name.to_borrowed())?
+                            .work_tree_path(dirs)?
+                            .display()
                     );
-                    match self
-                        .run(Cli::Run {
-                            repo_name: repo_name.clone(),
-                            no_cd_root,
-                            cmd_and_args: cmd_and_args.clone(),
-                        })
-                        .with_context(|| anyhow!("failed to run command for repo {:?}", repo_name))
-                    {
-                        Ok(()) => (),
-                        Err(e) => {
-                            err_happened = true;
-                            log::error!("{}", e);
-                        }
-                    }
-                });
-                if err_happened {
-                    Err(anyhow!(
-                        "one or more errors occurred, see above output for more details"
-                    ))
-                } else {
+                    repos.commit();
                     Ok(())
                 }
+                OverlaySubcommand::AdoptFile {
+                    name,
+                    paths,
+                    commit,
+                    message,
+                    allow_read_only,
+                } => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config: _,
+                    } = self;
+                    let repos = repos.snapshot();
+                    let entry = repos.get_by_name(name.to_borrowed())?;
+                    ensure!(
+                        entry.kind() == RepoKind::Overlay,
+                        "{:?} is not an overlay repo",
+                        name
+                    );
+                    entry.ensure_writable(&name, "adopt files into", allow_read_only)?;
+                    let mut repo = entry.open(git, dirs, name.to_borrowed())?;
+
+                    let cwd = current_dir()?;
+                    let work_tree = repo.work_tree_path().to_owned();
+                    let own_dirs = [dirs.data_dir_path(), dirs.state_dir_path()];
+                    let paths = paths
+                        .iter()
+                        .map(|path| {
+                            // Don't resolve symlinks, so that a symlinked file is adopted as a link.
+                            let path = cwd.join(path).clean();
+                            let relative = path.strip_prefix(&work_tree).with_context(|| {
+                                anyhow!(
+                                    "{} is outside of {:?}'s work tree, {}",
+                                    path.display(),
+                                    name,
+                                    work_tree.display()
+                                )
+                            })?;
+                            ensure!(
+                                relative != Path::new(""),
+                                "refusing to adopt the whole work tree of {:?}",
+                                name
+                            );
+                            ensure!(
+                                !own_dirs.iter().any(|own_dir| path.starts_with(own_dir)),
+                                "{} is inside this tool's own directories",
+                                path.display()
+                            );
+                            ensure!(
+                                path.symlink_metadata().is_ok(),
+                                "{} does not exist",
+                                path.display()
+                            );
+                            Ok(relative.to_owned())
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    repo.add(&paths)?;
+                    let adopted = paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    if commit {
+                        let message = message.unwrap_or_else(|| format!("Adopt {}", adopted));
+                        repo.commit(&message)?;
+                        tracing::info!("adopted and committed {} into {:?}", adopted, name);
+                    } else {
+                        tracing::info!("adopted {} into {:?}; commit when ready", adopted, name);
+                    }
+                    Ok(())
+                }
+                OverlaySubcommand::Conflicts => {
+                    let Self {
+                        dirs,
+                        git,
+                        repos,
+                        config: _,
+                    } = self;
+                    let repos = repos.snapshot();
+                    let shared = layering::find_shared_paths(dirs, git, repos.iter())?;
+                    if shared.is_empty() {
+                        tracing::info!("no paths are tracked by more than one overlay repo");
+                    }
+                    for shared_path in shared {
+                        println!("{}", shared_path);
+                    }
+                    Ok(())
+                }
+                OverlaySubcommand::Attributes(subcmd) => match subcmd {
+                    OverlayAttributesSubcommand::Edit { name } => {
+                        let Self {
+                            dirs,
+                            git,
+                            repos,
+                            config: _,
+                        } = self;
+                        let repos = repos.snapshot();
+                        let repo = repos.get_by_name(name.to_borrowed())?;
+                        if repo.kind() != RepoKind::Overlay {
+                            bail!("{:?} is not an overlay repo", name);
+                        }
+                        let path = RepoDb::ensure_overlay_attributes_file(dirs, &name)?;
+                        // Repos created before attributes files were supported won't have this
+                        // set yet.
+                        repo.open(git, dirs, name.to_borrowed())?
+                            .set_attributes_file(Some(&path))?;
+                        edit_file(&path)
+                    }
+                },
+            },
+            Cli::Unmanaged(subcmd) => match subcmd {
+                UnmanagedSubcommand::Register { path, name } => {
+                    let Self {
+                        dirs,
+                        git: _,
+                        repos,
+                        config,
+                    } = self;
+                    let path = path.map(Ok).unwrap_or_else(current_dir)?;
+                    let name = name.unwrap_or_base_name(&path)?;
+                    let mut repos = repos.transaction();
+                    print_add_res(config.hooks(), "register", |handler| {
+                        repos.new_unmanaged(dirs, name, &path, false, handler)
+                    })?;
+                    repos.commit();
+                    Ok(())
+                }
+                UnmanagedSubcommand::Deregister { repo } => {
+                    let Self {
+                        dirs,
+                        git: _,
+                        repos,
+                        config,
+                    } = self;
+
+                    let mut repos = repos.transaction();
+                    let name = RepoRef::or_cwd(repo)?.resolve(dirs, &repos)?.0;
+
+                    let repo = repos.deregister_unmanaged(name.to_borrowed())?;
+                    repos.commit();
+                    tracing::info!(
+                        "deregistered {}; your files have been left intact",
+                        repo.short_desc()
+                    );
+                    run_hooks(config.hooks(), &Event::RepoDeregistered { name });
+                    Ok(())
+                }
+            },
+            Cli::Run {
+                repo,
+                no_cd_root,
+                timeout,
+                print_env,
+                allow_read_only,
+                no_interactive,
+                cmd_and_args,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+
+                let repo_ref = match repo {
+                    Some(repo) => repo,
+                    None => {
+                        let cwd = RepoRef::or_cwd(None)?;
+                        let snapshot = repos.snapshot();
+                        match cwd.resolve(dirs, &snapshot) {
+                            Ok(_) => cwd,
+                            Err(e) if no_interactive || !picker::is_available() => return Err(e),
+                            Err(_) => match pick_repo(dirs, &snapshot)? {
+                                Some(name) => RepoRef::Name(name),
+                                None => {
+                                    tracing::info!("no repo picked; not running anything");
+                                    return Ok(());
+                                }
+                            },
+                        }
+                    }
+                };
+                if print_env {
+                    let cmd = prep_cmd_in_repo(
+                        dirs,
+                        git,
+                        &repos.snapshot(),
+                        &repo_ref,
+                        no_cd_root,
+                        Command::new("git"),
+                    )?;
+                    return print_cmd_env(&cmd);
+                }
+                {
+                    let snapshot = repos.snapshot();
+                    let (name, entry) = repo_ref.resolve(dirs, &snapshot)?;
+                    entry.ensure_writable(&name, "run a command against", allow_read_only)?;
+                }
+                let report = run_in_repo(
+                    dirs,
+                    git,
+                    &repos.snapshot(),
+                    &repo_ref,
+                    no_cd_root,
+                    &cmd_and_args,
+                    child::Options {
+                        timeout: timeout.map(|HumanDuration(timeout)| timeout),
+                        capture: false,
+                    },
+                )?;
+                let _our_exit_code = check_cmd_outcome(&report.outcome)?;
+
+                // TODO: Return with exit code
+
+                Ok(())
+            }
+            Cli::ForEach {
+                no_cd_root,
+                timeout,
+                capture,
+                capture_dir,
+                include_pinned,
+                allow_read_only,
+                failed: only_failed,
+                kind,
+                cmd_and_args,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let retry = only_failed
+                    .then(|| failed::last_failed(dirs, BulkCommand::ForEach))
+                    .transpose()?;
+                if matches!(&retry, Some(retry) if retry.is_empty()) {
+                    tracing::info!("no repos failed in the last `for-each`");
+                    return Ok(());
+                }
+
+                let options = child::Options {
+                    timeout: timeout.map(|HumanDuration(timeout)| timeout),
+                    capture: capture || capture_dir.is_some(),
+                };
+                if let Some(capture_dir) = &capture_dir {
+                    fs::create_dir_all(capture_dir).with_context(|| {
+                        anyhow!("failed to create capture directory {:?}", capture_dir)
+                    })?;
+                }
+
+                let mut tally = Tally::start("for-each");
+                let mut err_happened = false;
+                let mut failures = Vec::new();
+                let mut summary = Vec::new();
+                let snapshot = repos.snapshot();
+                for (repo_name, repo) in ordering::dependency_order(snapshot.iter().collect())? {
+                    let _span = tracing::info_span!("for_each", repo = ?repo_name).entered();
+                    if let Some(retry) = &retry {
+                        if !retry.contains(&*repo_name) {
+                            continue;
+                        }
+                    }
+                    if kind.is_some_and(|kind| repo.kind() != kind) {
+                        continue;
+                    }
+                    // Commands are usually Git commands, so unmanaged directories have to be
+                    // asked for.
+                    if kind.is_none() && !is_git_repo_logged(&repo_name, &repo) {
+                        continue;
+                    }
+                    if repo.pinned() && !include_pinned {
+                        tracing::info!("skipping pinned repo {:?}", repo_name);
+                        continue;
+                    }
+                    if repo.read_only() && !allow_read_only {
+                        tracing::info!("skipping read-only repo {:?}", repo_name);
+                        continue;
+                    }
+                    let repo_short_desc = repo.short_desc();
+                    if !options.capture {
+                        tracing::info!(
+                            "running command against {:?} ({})",
+                            repo_name,
+                            repo_short_desc
+                        );
+                    }
+                    let res = run_in_repo(
+                        dirs,
+                        git,
+                        &snapshot,
+                        &RepoRef::Name(repo_name.to_borrowed().into_static()),
+                        no_cd_root,
+                        &cmd_and_args,
+                        options,
+                    );
+                    let report = match res {
+                        Ok(report) => report,
+                        // Stop early, since the user probably wants to stop everything.
+                        Err(e) if e.is::<child::Interrupted>() => {
+                            return Err(e.context(anyhow!(
+                                "stopped running commands while running against {:?}",
+                                repo_name
+                            )))
+                        }
+                        Err(e) => {
+                            err_happened = true;
+                            failures.push(repo_name.to_borrowed().into_static());
+                            tracing::error!(
+                                "{:?}",
+                                e.context(anyhow!(
+                                    "failed to run command for repo {:?}",
+                                    repo_name
+                                ))
+                            );
+                            continue;
+                        }
+                    };
+
+                    if let Some(output) = &report.output {
+                        println!(
+                            "==> {} ({}) [{:.2?}]",
+                            repo_name, repo_short_desc, report.duration
+                        );
+                        write_captured_output(output).context("failed to print captured output")?;
+                        if let Some(capture_dir) = &capture_dir {
+                            save_captured_output(capture_dir, repo_name.to_borrowed(), output)?;
+                        }
+                    } else {
+                        tracing::debug!("command finished in {:.2?}", report.duration);
+                    }
+
+                    match check_cmd_outcome(&report.outcome) {
+                        Ok(0) => tally.succeeded += 1,
+                        // Non-zero exit codes are only reported, but can still be retried.
+                        Ok(_code) => failures.push(repo_name.to_borrowed().into_static()),
+                        Err(e) => {
+                            err_happened = true;
+                            failures.push(repo_name.to_borrowed().into_static());
+                            tracing::error!(
+                                "{:?}",
+                                e.context(anyhow!(
+                                    "failed to run command for repo {:?}",
+                                    repo_name
+                                ))
+                            );
+                        }
+                    }
+                    summary.push(CapturedRunSummary {
+                        repo: repo_name.to_borrowed().into_static(),
+                        exit_code: match report.outcome {
+                            child::Outcome::Exited(status) => status.code(),
+                            child::Outcome::TimedOut => None,
+                        },
+                        timed_out: matches!(report.outcome, child::Outcome::TimedOut),
+                        duration_secs: report.duration.as_secs_f64(),
+                    });
+                }
+
+                if let Some(capture_dir) = &capture_dir {
+                    let summary_path = capture_dir.join("summary.json");
+                    let json = serde_json::to_string_pretty(&summary)
+                        .context("failed to serialize run summary")?;
+                    fs::write(&summary_path, json)
+                        .with_context(|| anyhow!("failed to write {:?}", summary_path))?;
+                    tracing::info!("wrote captured output to {:?}", capture_dir);
+                }
+
+                failed::record_failed(dirs, BulkCommand::ForEach, &failures);
+                tally.failed = failures.len();
+                tally.finish(config.notifications());
+                if !failures.is_empty() {
+                    tracing::info!(
+                        "pass `--failed` to retry only the {} repo(s) that failed",
+                        failures.len()
+                    );
+                }
+                if err_happened {
+                    Err(anyhow!(
+                        "one or more errors occurred, see above output for more details"
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            Cli::Maintain {
+                repo,
+                keep_depth,
+                no_keep_depth,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let (repo_name, repo) = RepoRef::or_cwd(repo)?.resolve(dirs, &repos)?;
+                let mut repo = repo.open(git, dirs, repo_name.to_borrowed())?;
+
+                if no_keep_depth {
+                    repo.set_keep_depth(None)?;
+                    repo.unshallow()?;
+                    tracing::info!("{:?} now has full history", repo_name);
+                    return Ok(());
+                }
+
+                if let Some(depth) = keep_depth {
+                    repo.set_keep_depth(Some(depth))?;
+                    tracing::warn!(
+                        "only the last {} commit(s) of history for {:?} will be kept locally; \
+                        history-walking commands like `git log` and `git blame` will stop there, \
+                        and merges or rebases involving older history may fail",
+                        depth,
+                        repo_name,
+                    );
+                }
+
+                match repo.keep_depth()? {
+                    Some(depth) => {
+                        repo.reshallow(depth)?;
+                        tracing::info!(
+                            "trimmed local history of {:?} to {} commit(s)",
+                            repo_name,
+                            depth
+                        );
+                    }
+                    None => tracing::info!(
+                        "{:?} is not configured with `--keep-depth`, nothing to do",
+                        repo_name
+                    ),
+                }
+                Ok(())
+            }
+            Cli::Sync {
+                repo,
+                rebase,
+                merge,
+                skip,
+                stash_and_rebase,
+                autostash,
+                save,
+                include_pinned,
+                allow_read_only,
+                failed: only_failed,
+                kind,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let mut tally = Tally::start("sync");
+                let strategy = [
+                    (rebase, SyncStrategy::Rebase),
+                    (merge, SyncStrategy::Merge),
+                    (skip, SyncStrategy::Skip),
+                    (stash_and_rebase, SyncStrategy::StashAndRebase),
+                ]
+                .into_iter()
+                .find_map(|(passed, strategy)| passed.then_some(strategy));
+
+                let repos_to_sync = match &repo {
+                    Some(repo) => {
+                        let mut repos = repos.transaction();
+                        let (name, entry) = repo.resolve(dirs, &repos)?;
+                        entry.ensure_writable(&name, "sync", allow_read_only)?;
+                        let name = name.into_static();
+                        if save {
+                            repos.set_sync_strategy(name.to_borrowed(), strategy)?;
+                            repos.commit();
+                        }
+                        vec![name]
+                    }
+                    None if only_failed => {
+                        let retry = failed::last_failed(dirs, BulkCommand::Sync)?;
+                        if retry.is_empty() {
+                            tracing::info!("no repos failed in the last `sync`");
+                            return Ok(());
+                        }
+                        repos
+                            .snapshot()
+                            .iter()
+                            .filter(|(name, _repo)| retry.contains(&**name))
+                            .filter(|(_name, repo)| kind.map_or(true, |kind| repo.kind() == kind))
+                            .map(|(name, _repo)| name.into_static())
+                            .collect()
+                    }
+                    None => repos
+                        .snapshot()
+                        .iter()
+                        .filter(|(_name, repo)| kind.map_or(true, |kind| repo.kind() == kind))
+                        .filter(|(name, repo)| {
+                            let skip = repo.pinned() && !include_pinned;
+                            if skip {
+                                tracing::info!("skipping pinned repo {:?}", name);
+                            }
+                            !skip
+                        })
+                        .map(|(name, _repo)| name.into_static())
+                        .collect(),
+                };
+
+                let snapshot = repos.snapshot();
+                let repos_to_sync = repos_to_sync
+                    .iter()
+                    .map(|name| {
+                        Ok((
+                            name.to_borrowed(),
+                            snapshot.get_by_name(name.to_borrowed())?,
+                        ))
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                let repos_to_sync = ordering::dependency_order(repos_to_sync)?
+                    .into_iter()
+                    .map(|(name, _repo)| name.into_static())
+                    .collect::<Vec<_>>();
+                let mut skipped = Vec::new();
+                let mut failures = Vec::new();
+                let mut transfers = Vec::new();
+                let mut synced = Vec::new();
+                let mut outcomes = Vec::new();
+                for name in repos_to_sync {
+                    let _span = tracing::info_span!("sync", repo = ?name).entered();
+                    let repo = snapshot.get_by_name(name.to_borrowed())?;
+                    if !is_git_repo_logged(&name, &repo) {
+                        continue;
+                    }
+                    // A repo given by name was already refused above instead.
+                    if repo.read_only() && !allow_read_only {
+                        tracing::info!("skipping read-only repo {:?}", name);
+                        continue;
+                    }
+                    let strategy = strategy.or(repo.sync_strategy()).unwrap_or_default();
+                    let res = repo
+                        .open(git, dirs, name.to_borrowed())
+                        .and_then(|mut repo| sync::sync_repo(&mut repo, strategy, autostash));
+                    match res {
+                        Ok((outcome, transfer)) => {
+                            tracing::info!("{}: {}", name, outcome);
+                            if let SyncOutcome::Skipped { .. } = outcome {
+                                skipped.push(name.clone());
+                            } else {
+                                synced.push(name.clone());
+                            }
+                            outcomes.push(SyncedRepo {
+                                name: name.clone(),
+                                outcome,
+                            });
+                            transfers.push((name, transfer));
+                        }
+                        Err(e) => {
+                            let error = format!("{:#}", e);
+                            tracing::error!(
+                                "{:?}",
+                                e.context(anyhow!("failed to sync {:?}", name))
+                            );
+                            failures.push(FailedSync { name, error });
+                        }
+                    }
+                }
+
+                tally.succeeded = transfers.len();
+                tally.failed = failures.len();
+                print_transfer_summary(&mut transfers);
+                if !synced.is_empty() {
+                    match MachineIdentity::load_or_create(dirs) {
+                        Ok(machine) => {
+                            let mut repos = repos.transaction();
+                            for name in synced {
+                                repos.record_sync(name, machine.sync_record())?;
+                            }
+                            repos.commit();
+                        }
+                        Err(e) => tracing::warn!(
+                            "{:?}",
+                            e.context("failed to identify this machine, not recording syncs")
+                        ),
+                    }
+                }
+                if !skipped.is_empty() {
+                    tracing::warn!(
+                        "skipped {} diverged repo(s): {}; pass `--rebase`, `--merge`, or \
+                        `--stash-and-rebase` to reconcile them",
+                        skipped.len(),
+                        lazy_format!(|f| {
+                            let mut names = skipped.iter();
+                            if let Some(first) = names.next() {
+                                write!(f, "{}", first)?;
+                            }
+                            names.try_for_each(|name| write!(f, ", {}", name))
+                        }),
+                    );
+                }
+                // Failures syncing a single repo don't replace what's recorded for syncing all of
+                // them.
+                if repo.is_none() {
+                    let failed_names = failures
+                        .iter()
+                        .map(|failure| failure.name.clone())
+                        .collect::<Vec<_>>();
+                    failed::record_failed(dirs, BulkCommand::Sync, &failed_names);
+                }
+                tally.finish(config.notifications());
+                let num_failures = failures.len();
+                run_hooks(
+                    config.hooks(),
+                    &Event::SyncFinished {
+                        synced: outcomes,
+                        failed: failures,
+                    },
+                );
+                if num_failures != 0 {
+                    bail!(
+                        "failed to sync {} repo(s); pass `--failed` to retry only those",
+                        num_failures
+                    );
+                }
+                Ok(())
+            }
+            Cli::Archive {
+                repo,
+                output,
+                rev,
+                prefix,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let (repo_name, repo) = repo.resolve(dirs, &repos)?;
+                let repo = repo.open(git, dirs, repo_name.to_borrowed())?;
+                // Relative paths need to stay relative to our working directory, regardless of
+                // where Git runs.
+                let output = current_dir()?.join(output);
+                repo.archive(&rev, &output, prefix.as_deref())?;
+                tracing::info!("archived {:?} at {:?} to {:?}", repo_name, rev, output);
+                Ok(())
+            }
+            #[cfg(feature = "tui")]
+            Cli::Ui => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                ui::run(dirs, git, repos, config.hooks())
+            }
+            Cli::Gc { dry_run } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let orphans = gc::find_orphans(&dirs.overlay_repos_dir_path()?, |file_name| {
+                    file_name
+                        .to_str()
+                        .and_then(|name| name.parse::<RepoName<'_>>().ok())
+                        .and_then(|name| repos.get_by_name_opt(name))
+                        .is_some_and(|repo| repo.kind() == RepoKind::Overlay)
+                })?;
+                if orphans.is_empty() {
+                    tracing::info!("no orphaned overlay repo directories found");
+                    return Ok(());
+                }
+
+                let quarantine_dir = dirs.quarantine_dir_path();
+                for gc::Orphan {
+                    path,
+                    size,
+                    modified,
+                } in orphans
+                {
+                    let desc = format!(
+                        "{:?} ({}, last modified {})",
+                        path,
+                        gc::display_size(size),
+                        gc::display_age(modified),
+                    );
+                    if dry_run {
+                        println!("{}", desc);
+                        continue;
+                    }
+                    let answer = prompt(msg!("gc-orphan-prompt", desc = desc))?.to_lowercase();
+                    if i18n::answers("gc-orphan-delete-answers").contains(&answer) {
+                        remove_dir_all::remove_dir_all(&path)
+                            .with_context(|| anyhow!("failed to delete {:?}", path))?;
+                        tracing::info!("deleted {:?}", path);
+                    } else if i18n::answers("gc-orphan-quarantine-answers").contains(&answer) {
+                        let new_path = gc::quarantine(&path, &quarantine_dir)?;
+                        tracing::info!("quarantined {:?} to {:?}", path, new_path);
+                    } else {
+                        tracing::info!("skipped {:?}", path);
+                    }
+                }
+                Ok(())
+            }
+            Cli::PruneBackups { dry_run } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos: _,
+                    config,
+                } = self;
+                let retention = config.backups();
+                if !retention.is_enabled() {
+                    tracing::info!(
+                        "no backup limits are configured; set them in the `backups` table of {}",
+                        dirs.config_file_path().display()
+                    );
+                    return Ok(());
+                }
+                let backups = backups::find_backups(dirs)?;
+                let (_kept, pruned) = retention.select(backups, SystemTime::now());
+                if pruned.is_empty() {
+                    tracing::info!("no backups exceed the configured limits");
+                    return Ok(());
+                }
+                for backup in &pruned {
+                    let Backup {
+                        path,
+                        size,
+                        modified,
+                    } = backup;
+                    let desc = lazy_format!(
+                        "{:?} ({}, last modified {})",
+                        path,
+                        gc::display_size(*size),
+                        gc::display_age(*modified),
+                    );
+                    if dry_run {
+                        println!("{}", desc);
+                        continue;
+                    }
+                    backups::remove(backup)?;
+                    tracing::info!("deleted {}", desc);
+                }
+                Ok(())
+            }
+            Cli::Metrics(MetricsSubcommand::Show { command }) => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos: _,
+                    config,
+                } = self;
+                let mut records = metrics::read_records(dirs)?;
+                if let Some(command) = &command {
+                    records.retain(|record| record.command == *command);
+                }
+                if records.is_empty() {
+                    if config.metrics() {
+                        tracing::info!("no runs recorded yet");
+                    } else {
+                        tracing::info!(
+                            "no runs recorded; set `metrics = true` in {:?} to start recording",
+                            dirs.config_file_path()
+                        );
+                    }
+                }
+                for ((command, version), summary) in metrics::summarize(&records) {
+                    let metrics::CommandSummary {
+                        runs,
+                        failed,
+                        total_ms,
+                        last_ms,
+                        operations,
+                    } = summary;
+                    println!(
+                        "{} (version {}): {} run(s), {} failed, {} on average, {} last",
+                        command,
+                        version,
+                        runs,
+                        failed,
+                        metrics::display_duration(total_ms / runs),
+                        metrics::display_duration(last_ms),
+                    );
+                    for (operation, stats) in operations {
+                        println!(
+                            "  {}: {} in {} total",
+                            operation,
+                            stats.count,
+                            metrics::display_duration(stats.total_ms)
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Cli::Profile(subcommand) => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos: _,
+                    config: _,
+                } = self;
+                match subcommand {
+                    ProfileSubcommand::Create { name } => {
+                        profiles::create(dirs, &name)?;
+                        tracing::info!(
+                            "created profile {:?}; use it with `profile switch {}`",
+                            name.as_str(),
+                            name
+                        );
+                    }
+                    ProfileSubcommand::List => {
+                        let current = dirs
+                            .profile()
+                            .map_or(profiles::DEFAULT_PROFILE, |p| p.as_str());
+                        for name in profiles::list(dirs)? {
+                            let marker = if name.as_str() == current { '*' } else { ' ' };
+                            println!("{} {}", marker, name);
+                        }
+                    }
+                    ProfileSubcommand::Switch { name } => {
+                        profiles::switch(dirs, &name)?;
+                        tracing::info!("switched to profile {:?}", name.as_str());
+                    }
+                }
+                Ok(())
+            }
+            Cli::Paths { output } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let snapshot = repos.snapshot();
+                let repos = snapshot
+                    .iter()
+                    .filter(|(name, repo)| is_git_repo_logged(name, repo))
+                    .filter_map(
+                        |(name, repo)| match repo.open(git, dirs, name.to_borrowed()) {
+                            Ok(repo) => Some((
+                                name,
+                                RepoPathsReport {
+                                    git_dir: repo.git_dir_path().to_owned(),
+                                    work_tree: repo.work_tree_path().to_owned(),
+                                },
+                            )),
+                            Err(e) => {
+                                tracing::warn!("skipping paths for {:?}: {:?}", name, e);
+                                None
+                            }
+                        },
+                    )
+                    .collect();
+                let report = PathsReport {
+                    data_dir: dirs.data_dir_path(),
+                    state_dir: dirs.state_dir_path(),
+                    config_file: dirs.config_file_path(),
+                    overlay_repos_dir: dirs.overlay_repos_dir_path()?,
+                    standalone_repo_db: dirs.standalone_repo_db_path()?,
+                    repos,
+                };
+                match output {
+                    OutputFormat::Human => {
+                        let PathsReport {
+                            data_dir,
+                            state_dir,
+                            config_file,
+                            overlay_repos_dir,
+                            standalone_repo_db,
+                            repos,
+                        } = &report;
+                        println!("data directory: {}", data_dir.display());
+                        println!("state directory: {}", state_dir.display());
+                        println!("configuration file: {}", config_file.display());
+                        println!("overlay repos directory: {}", overlay_repos_dir.display());
+                        println!("standalone repos DB: {}", standalone_repo_db.display());
+                        println!("repos:");
+                        for (name, RepoPathsReport { git_dir, work_tree }) in repos {
+                            println!("  {}:", name);
+                            println!("    GIT_DIR: {}", git_dir.display());
+                            println!("    GIT_WORK_TREE: {}", work_tree.display());
+                        }
+                    }
+                    OutputFormat::Json => {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&report)
+                                .context("failed to serialize paths as JSON")?
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Cli::Verify { repo, fix } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let repos = repos.snapshot();
+                let repos_to_verify = match &repo {
+                    Some(repo) => vec![repo.resolve(dirs, &repos)?],
+                    None => repos
+                        .iter()
+                        .map(|(name, repo)| (name.into_static(), repo))
+                        .collect(),
+                };
+
+                let mut problems_found = false;
+                for (name, repo) in repos_to_verify {
+                    let repo_config = match config.repo(&name) {
+                        Some(repo_config) if repo_config.has_file_modes() => repo_config,
+                        _ => continue,
+                    };
+                    if !is_git_repo_logged(&name, &repo) {
+                        continue;
+                    }
+                    let repo = repo.open(git, dirs, name.to_borrowed())?;
+                    let mismatches = permissions::check_file_modes(&repo, repo_config)
+                        .with_context(|| anyhow!("failed to check file modes of {:?}", name))?;
+                    if mismatches.is_empty() {
+                        continue;
+                    }
+                    for FileModeMismatch {
+                        path,
+                        expected,
+                        actual,
+                    } in &mismatches
+                    {
+                        tracing::warn!(
+                            "{}: {} has mode {}, but {} is configured",
+                            name,
+                            path.display(),
+                            actual,
+                            expected,
+                        );
+                    }
+                    if fix {
+                        permissions::apply_file_modes(&repo, repo_config)
+                            .with_context(|| anyhow!("failed to fix file modes of {:?}", name))?;
+                        tracing::info!("fixed file modes of {:?}", name);
+                    } else {
+                        problems_found = true;
+                    }
+                }
+
+                if problems_found {
+                    bail!(concat!(
+                        "one or more problems were found; run `",
+                        env!("CARGO_BIN_NAME"),
+                        " verify --fix` to fix them",
+                    ));
+                }
+                Ok(())
+            }
+            Cli::Stats {
+                repo,
+                largest,
+                output,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let repos_to_report = match &repo {
+                    Some(repo) => vec![repo.resolve(dirs, &repos)?],
+                    None => repos
+                        .iter()
+                        .map(|(name, repo)| (name.into_static(), repo))
+                        .collect(),
+                };
+
+                let mut report = StatsReport {
+                    repos: BTreeMap::new(),
+                    total: StatsTotals::default(),
+                };
+                for (name, repo) in repos_to_report {
+                    if !is_git_repo_logged(&name, &repo) {
+                        continue;
+                    }
+                    let repo = repo.open(git, dirs, name.to_borrowed())?;
+                    let stats = (|| -> anyhow::Result<_> {
+                        let tracked_files = repo.list_files()?.count();
+                        let ObjectCounts {
+                            objects,
+                            size_on_disk,
+                        } = repo.count_objects()?;
+                        let largest_blobs = repo
+                            .largest_blobs(largest)?
+                            .into_iter()
+                            .map(|BlobInfo { id, size, path }| BlobReport { id, size, path })
+                            .collect();
+                        Ok(RepoStats {
+                            tracked_files,
+                            objects,
+                            size_on_disk,
+                            largest_blobs,
+                        })
+                    })()
+                    .with_context(|| anyhow!("failed to gather stats for {:?}", name))?;
+                    let StatsTotals {
+                        tracked_files,
+                        objects,
+                        size_on_disk,
+                    } = &mut report.total;
+                    *tracked_files += stats.tracked_files;
+                    *objects += stats.objects;
+                    *size_on_disk += stats.size_on_disk;
+                    report.repos.insert(name, stats);
+                }
+
+                match output {
+                    OutputFormat::Human => {
+                        let StatsReport { repos, total } = &report;
+                        for (
+                            name,
+                            RepoStats {
+                                tracked_files,
+                                objects,
+                                size_on_disk,
+                                largest_blobs,
+                            },
+                        ) in repos
+                        {
+                            println!("{}:", name);
+                            println!("  tracked files: {}", tracked_files);
+                            println!(
+                                "  objects: {} ({} on disk)",
+                                objects,
+                                gc::display_size(*size_on_disk)
+                            );
+                            if !largest_blobs.is_empty() {
+                                println!("  largest objects:");
+                            }
+                            for BlobReport { id, size, path } in largest_blobs {
+                                let size = gc::display_size(*size);
+                                let id = &id[..id.len().min(12)];
+                                match path {
+                                    Some(path) => {
+                                        println!("    {} {} {}", size, id, path.display())
+                                    }
+                                    None => println!("    {} {} (unreachable)", size, id),
+                                }
+                            }
+                        }
+                        if repos.len() > 1 {
+                            let StatsTotals {
+                                tracked_files,
+                                objects,
+                                size_on_disk,
+                            } = total;
+                            println!(
+                                "total: {} tracked files, {} objects ({} on disk)",
+                                tracked_files,
+                                objects,
+                                gc::display_size(*size_on_disk),
+                            );
+                        }
+                    }
+                    OutputFormat::Json => {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&report)
+                                .context("failed to serialize stats as JSON")?
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Cli::Doctor { fix } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let mut renamed_repos = 0;
+                let renames = repos.snapshot().find_renamed_overlays(dirs, git)?;
+                for (old_name, new_name) in &renames {
+                    tracing::warn!(
+                        "overlay repo {:?} was renamed to {:?} by hand, but its entry wasn't",
+                        old_name,
+                        new_name
+                    );
+                    if fix {
+                        let mut txn = repos.transaction();
+                        txn.adopt_renamed_overlay(old_name.to_borrowed(), new_name.clone())?;
+                        txn.commit();
+                        tracing::info!("renamed entry {:?} to {:?}", old_name, new_name);
+                    } else {
+                        renamed_repos += 1;
+                    }
+                }
+
+                let mut broken_repos = 0;
+                let mut hooked_repos = 0;
+                let mut stale_sources = Vec::new();
+                let empty_hooks_dir = dirs.empty_hooks_dir_path();
+                let snapshot = repos.snapshot();
+                for (name, repo) in snapshot.iter() {
+                    if renames.iter().any(|(old_name, _)| *old_name == name) {
+                        continue;
+                    }
+                    let kind = repo.kind();
+                    if !kind.is_git_repo() {
+                        let path = repo.work_tree_path(dirs)?;
+                        if !path.is_dir() {
+                            broken_repos += 1;
+                            tracing::warn!(
+                                "{:?} is broken: unmanaged directory {:?} is missing",
+                                name,
+                                path
+                            );
+                        }
+                        continue;
+                    }
+                    let status = repo
+                        .open(git, dirs, name.to_borrowed())
+                        .and_then(|mut repo| {
+                            let (hooks_path, previous_name) = match kind {
+                                RepoKind::Overlay => (
+                                    repo.get_config(HOOKS_PATH_CONFIG_PATH)?,
+                                    overlay_files::previous_name(dirs, &repo, &name)?,
+                                ),
+                                RepoKind::Standalone | RepoKind::Unmanaged => (None, None),
+                            };
+                            let renamed = match previous_name {
+                                Some(previous_name) if fix => {
+                                    overlay_files::follow_rename(
+                                        dirs,
+                                        &mut repo,
+                                        &previous_name,
+                                        &name,
+                                    )?;
+                                    tracing::info!(
+                                    "moved excludes and attributes files of {:?} over from {:?}",
+                                    name,
+                                    previous_name
+                                );
+                                    false
+                                }
+                                Some(previous_name) => {
+                                    tracing::warn!(
+                                    "{:?} still uses the excludes or attributes files of {:?}, \
+                                    which it was probably renamed from",
+                                    name,
+                                    previous_name
+                                );
+                                    true
+                                }
+                                None => false,
+                            };
+                            let stale_source =
+                                snapshot.has_stale_source(name.to_borrowed(), &repo)?;
+                            Ok((repo.status()?, hooks_path, renamed, stale_source))
+                        });
+                    match status {
+                        Ok((status, hooks_path, renamed, stale_source)) => {
+                            if renamed {
+                                renamed_repos += 1;
+                            }
+                            if stale_source {
+                                tracing::warn!(
+                                    "the recorded source of {:?} doesn't match its remotes and \
+                                    branch",
+                                    name
+                                );
+                                stale_sources.push(name.to_borrowed().into_static());
+                            }
+                            if status.detached_head {
+                                tracing::info!("{:?} has a detached `HEAD`", name);
+                            }
+                            let hooks_isolated =
+                                hooks_path.is_some_and(|path| Path::new(&path) == empty_hooks_dir);
+                            if kind == RepoKind::Overlay
+                                && !config.overlay().allow_git_hooks()
+                                && !hooks_isolated
+                            {
+                                hooked_repos += 1;
+                                tracing::warn!(
+                                    "{:?} may run Git hooks against your home directory; set \
+                                    `{}` to {:?} in it to prevent that",
+                                    name,
+                                    HOOKS_PATH_CONFIG_PATH,
+                                    empty_hooks_dir
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            broken_repos += 1;
+                            tracing::warn!("{:?}", e.context(anyhow!("{:?} is broken", name)));
+                        }
+                    }
+                }
+                drop(snapshot);
+                if fix && !stale_sources.is_empty() {
+                    let mut txn = repos.transaction();
+                    for name in stale_sources.drain(..) {
+                        txn.sync_source(git, dirs, name.to_borrowed())?;
+                    }
+                    txn.commit();
+                }
+                ensure!(
+                    broken_repos == 0,
+                    "{} repo(s) are broken; see above for details",
+                    broken_repos
+                );
+                ensure!(
+                    stale_sources.is_empty(),
+                    "{} repo(s) have out-of-date recorded sources; see above for details, and \
+                    pass `--fix` to update them",
+                    stale_sources.len()
+                );
+                ensure!(
+                    renamed_repos == 0,
+                    "{} overlay repo(s) were renamed by hand; see above for details, and pass \
+                    `--fix` to follow the renames",
+                    renamed_repos
+                );
+
+                let exposed = permissions::find_exposed_state(dirs)?;
+                for ExposedStateFile { path, actual } in &exposed {
+                    tracing::warn!(
+                        "{} is readable by other users (mode {})",
+                        path.display(),
+                        actual
+                    );
+                }
+                if !exposed.is_empty() {
+                    if config.harden_permissions() {
+                        tracing::info!("these will be fixed when this command exits");
+                    } else {
+                        bail!(
+                            "one or more problems were found; set `harden_permissions = true` in \
+                            {} to fix them",
+                            dirs.config_file_path().display()
+                        );
+                    }
+                }
+                ensure!(
+                    hooked_repos == 0,
+                    "{} overlay repo(s) may run Git hooks; see above for details, or set \
+                    `overlay.allow_git_hooks = true` in {} to allow them",
+                    hooked_repos,
+                    dirs.config_file_path().display()
+                );
+                if exposed.is_empty() {
+                    tracing::info!("no problems found");
+                }
+                Ok(())
+            }
+            Cli::Remove {
+                repo,
+                keep_files,
+                files_only,
+                force,
+                allow_read_only,
+                prune_config,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let mut repos = repos.transaction();
+                let (name, entry) = repo.resolve(dirs, &repos)?;
+                let name = name.into_static();
+                entry.ensure_writable(&name, "remove", allow_read_only)?;
+                // Nothing could bring an unmanaged directory back, so only its entry is removed.
+                if !entry.kind().is_git_repo() {
+                    ensure!(
+                        !files_only,
+                        "{:?} is an unmanaged directory, and has no tracked files",
+                        name
+                    );
+                    let entry = repos.deregister_unmanaged(name.to_borrowed())?;
+                    repos.commit();
+                    tracing::info!(
+                        "deregistered {}; its files have been left intact",
+                        entry.short_desc()
+                    );
+                    run_hooks(config.hooks(), &Event::RepoDeregistered { name });
+                    return Ok(());
+                }
+                // Nothing is set up outside of standalone repos' Git directories.
+                let prune_config = prune_config && entry.kind() == RepoKind::Overlay;
+                if !keep_files && !force {
+                    let status = entry.open(git, dirs, name.to_borrowed())?.status()?;
+                    // Only removing the entire repo deletes untracked files.
+                    let would_lose_changes =
+                        status.has_uncommitted_changes() || (!files_only && status.untracked > 0);
+                    ensure!(
+                        !would_lose_changes,
+                        "{:?} has changes that would be lost ({}); commit them, or pass `--force` \
+                        to remove it anyway",
+                        name,
+                        status
+                    );
+                }
+                if files_only {
+                    return repos.remove_tracked_files(dirs, git, name.to_borrowed());
+                }
+                // The entry is gone even if deleting files from disk fails partway through.
+                let res = if keep_files {
+                    repos.remove_git_dir(dirs, name.to_borrowed())
+                } else {
+                    repos.try_remove_entire_repo(dirs, git, name.to_borrowed())
+                };
+                repos.commit();
+                res?;
+                if prune_config {
+                    overlay_files::prune(dirs, &name)?;
+                }
+                run_hooks(
+                    config.hooks(),
+                    &Event::RepoRemoved {
+                        name,
+                        files_deleted: !keep_files,
+                    },
+                );
+                Ok(())
+            }
+            Cli::Pin { repo } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let mut repos = repos.transaction();
+                let name = repo.resolve(dirs, &repos)?.0.into_static();
+                if repos.set_pinned(name.to_borrowed(), true)? {
+                    tracing::info!("{:?} is already pinned", name);
+                }
+                repos.commit();
+                Ok(())
+            }
+            Cli::Unpin { repo } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let mut repos = repos.transaction();
+                let name = repo.resolve(dirs, &repos)?.0.into_static();
+                if !repos.set_pinned(name.to_borrowed(), false)? {
+                    tracing::info!("{:?} is not pinned", name);
+                }
+                repos.commit();
+                Ok(())
+            }
+            Cli::Protect { repo } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let mut repos = repos.transaction();
+                let name = repo.resolve(dirs, &repos)?.0.into_static();
+                if repos.set_read_only(name.to_borrowed(), true)? {
+                    tracing::info!("{:?} is already read-only", name);
+                }
+                repos.commit();
+                Ok(())
+            }
+            Cli::Unprotect { repo } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let mut repos = repos.transaction();
+                let name = repo.resolve(dirs, &repos)?.0.into_static();
+                if !repos.set_read_only(name.to_borrowed(), false)? {
+                    tracing::info!("{:?} is not read-only", name);
+                }
+                repos.commit();
+                Ok(())
+            }
+            Cli::Describe {
+                repo,
+                description,
+                homepage,
+                notes,
+            } => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                ensure!(
+                    description.is_some() || homepage.is_some() || notes.is_some(),
+                    "nothing to describe; pass at least one of `--description`, `--homepage`, or \
+                    `--notes`"
+                );
+                let mut repos = repos.transaction();
+                let name = repo.resolve(dirs, &repos)?.0.into_static();
+                repos.update_info(name, |info| {
+                    let RepoInfo {
+                        description: old_description,
+                        homepage: old_homepage,
+                        notes: old_notes,
+                    } = info;
+                    for (old, new) in [
+                        (old_description, description),
+                        (old_homepage, homepage),
+                        (old_notes, notes),
+                    ] {
+                        if let Some(new) = new {
+                            *old = (!new.is_empty()).then(|| new.into());
+                        }
+                    }
+                })?;
+                repos.commit();
+                Ok(())
+            }
+            Cli::Db(subcmd) => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                match subcmd {
+                    DbSubcommand::Get { repo, field } => {
+                        let repos = repos.snapshot();
+                        let (name, entry) = repo.resolve(dirs, &repos)?;
+                        match entry.field(field) {
+                            Some(value) => println!("{}", value),
+                            None => bail!("{} is not set for {:?}", field, name),
+                        }
+                    }
+                    DbSubcommand::Set { repo, field, value } => {
+                        let mut repos = repos.transaction();
+                        let name = repo.resolve(dirs, &repos)?.0.into_static();
+                        repos.set_field(name, field, Some(&value))?;
+                        repos.commit();
+                    }
+                    DbSubcommand::Unset { repo, field } => {
+                        let mut repos = repos.transaction();
+                        let name = repo.resolve(dirs, &repos)?.0.into_static();
+                        repos.set_field(name, field, None)?;
+                        repos.commit();
+                    }
+                }
+                Ok(())
+            }
+            Cli::External(args) => {
+                let Self {
+                    dirs,
+                    git: _,
+                    repos,
+                    config: _,
+                } = self;
+                let (subcmd, args) = args.split_first().context("no subcommand given")?;
+                let subcmd = subcmd.to_string_lossy();
+                let path = plugins::find(&subcmd).with_context(|| {
+                    let builtins = Cli::command()
+                        .get_subcommands()
+                        .map(|subcmd| subcmd.get_name().to_owned())
+                        .collect::<Vec<_>>();
+                    anyhow!(
+                        "unrecognized subcommand {:?}, and no `{}-{}` plugin was found on \
+                        `PATH`{}",
+                        subcmd,
+                        env!("CARGO_BIN_NAME"),
+                        subcmd,
+                        suggest::did_you_mean(&subcmd, &builtins)
+                    )
+                })?;
+                let repo = RepoRef::or_cwd(None)?
+                    .resolve(dirs, &repos.snapshot())
+                    .ok()
+                    .map(|(name, _repo)| name.to_string());
+                let context = plugins::PluginContext::new(dirs, repo)?;
+                let status = plugins::run(&path, args, &context)?;
+                cmd_failure_res(status).with_context(|| anyhow!("plugin {:?} failed", path))
+            }
+            Cli::Show { repo } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config,
+                } = self;
+                let repos = repos.snapshot();
+                let (name, repo) = repo.resolve(dirs, &repos)?;
+                let opened = repo
+                    .kind()
+                    .is_git_repo()
+                    .then(|| repo.open(git, dirs, name.to_borrowed()))
+                    .transpose()?;
+
+                println!("{}: {}", name, repo.short_desc());
+                if repo.pinned() {
+                    println!("  pinned");
+                }
+                if repo.read_only() {
+                    println!("  read-only");
+                }
+                if !repo.depends_on().is_empty() {
+                    println!(
+                        "  depends on: {}",
+                        repo.depends_on()
+                            .iter()
+                            .map(|name| name.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                if let Some(record) = repo.last_sync() {
+                    let machine = MachineIdentity::load_or_create(dirs)
+                        .map_err(|e| tracing::warn!("{:?}", e))
+                        .ok();
+                    println!(
+                        "  last synced: {}",
+                        machine::display_sync_record(record, machine.as_ref())
+                    );
+                }
+                if let Some(opened) = &opened {
+                    println!("  GIT_DIR: {}", opened.git_dir_path().display());
+                    println!("  GIT_WORK_TREE: {}", opened.work_tree_path().display());
+                    if let Some(SourceInfo { url, branch }) = repo.source() {
+                        print!("  source: {}", url);
+                        match branch {
+                            Some(branch) => println!(" ({})", branch),
+                            None => println!(),
+                        }
+                    }
+
+                    let statuses =
+                        status::gather(dirs, git, [(name.to_borrowed(), repo.to_borrowed())]);
+                    match statuses.into_values().next() {
+                        Some(Ok(status)) => println!("  status: {}", status),
+                        Some(Err(e)) => {
+                            tracing::warn!("failed to get status of {:?}: {:?}", name, e)
+                        }
+                        None => (),
+                    }
+                    match opened.last_commit() {
+                        Ok(Some(commit)) => println!("  last commit: {}", commit),
+                        Ok(None) => println!("  last commit: none yet"),
+                        Err(e) => {
+                            tracing::warn!("failed to find last commit of {:?}: {:?}", name, e)
+                        }
+                    }
+                    // `FETCH_HEAD` is rewritten by every fetch.
+                    let last_fetched = fs::metadata(opened.git_dir_path().join("FETCH_HEAD"))
+                        .and_then(|metadata| metadata.modified())
+                        .ok();
+                    if last_fetched.is_some() {
+                        println!("  last fetched: {}", gc::display_age(last_fetched));
+                    }
+                }
+
+                let work_tree = repo.work_tree_path(dirs)?;
+                if let Some(toml) = RepoMetadata::read_toml(&work_tree)? {
+                    let metadata = RepoMetadata::from_toml(&toml).with_context(|| {
+                        anyhow!("{:?} is invalid", RepoMetadata::path(&work_tree))
+                    })?;
+                    let machine_tags = config.tags();
+                    if !metadata.required_tags.is_empty() {
+                        print!("  required tags: {}", metadata.required_tags.join(", "));
+                        match &*metadata.missing_tags(machine_tags) {
+                            [] => println!(),
+                            missing => {
+                                println!(" (this machine is missing {})", missing.join(", "))
+                            }
+                        }
+                    }
+                }
+
+                let RepoInfo {
+                    description,
+                    homepage,
+                    notes,
+                } = repo.info();
+                if let Some(description) = description {
+                    println!("  description: {}", description);
+                }
+                if let Some(homepage) = homepage {
+                    println!("  homepage: {}", homepage);
+                }
+                if let Some(notes) = notes {
+                    println!("  notes:");
+                    for line in notes.lines() {
+                        println!("    {}", line);
+                    }
+                }
+                Ok(())
+            }
+            Cli::Branches {
+                repo,
+                subcommand: None,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let (name, repo) = RepoRef::or_cwd(repo)?.resolve(dirs, &repos)?;
+                let opened = repo.open(git, dirs, name.to_borrowed())?;
+                let branches = opened.branches()?;
+                let tracking = branches
+                    .iter()
+                    .map(|branch| {
+                        let BranchInfo {
+                            name: _,
+                            remote: _,
+                            current: _,
+                            upstream,
+                            divergence,
+                            last_commit_time: _,
+                        } = branch;
+                        let Some(upstream) = upstream else {
+                            return String::new();
+                        };
+                        match divergence {
+                            None => format!("{}, gone", upstream),
+                            Some((0, 0)) => format!("{}, up to date", upstream),
+                            Some((ahead, 0)) => format!("{}, ahead {}", upstream, ahead),
+                            Some((0, behind)) => format!("{}, behind {}", upstream, behind),
+                            Some((ahead, behind)) => {
+                                format!("{}, ahead {}, behind {}", upstream, ahead, behind)
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let name_width = branches.iter().map(|b| b.name.len()).max().unwrap_or(0);
+                let tracking_width = tracking.iter().map(String::len).max().unwrap_or(0);
+                for (branch, tracking) in branches.iter().zip(&tracking) {
+                    println!(
+                        "{} {:name_width$}  {:tracking_width$}  {}",
+                        if branch.current { '*' } else { ' ' },
+                        branch.name,
+                        tracking,
+                        gc::display_age(Some(branch.last_commit_time)),
+                    );
+                }
+                Ok(())
+            }
+            Cli::Branches {
+                repo: _,
+                subcommand:
+                    Some(BranchesSubcommand::Prune {
+                        repo,
+                        yes,
+                        allow_read_only,
+                    }),
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let (name, repo) = RepoRef::or_cwd(repo)?.resolve(dirs, &repos)?;
+                repo.ensure_writable(&name, "prune branches of", allow_read_only)?;
+                let mut opened = repo.open(git, dirs, name.to_borrowed())?;
+                let merged = opened.merged_branches()?;
+                if merged.is_empty() {
+                    tracing::info!("{:?} has no merged branches to prune", name);
+                    return Ok(());
+                }
+                if !yes {
+                    eprintln!("{}", msg!("branches-prune", name = format!("{:?}", name)));
+                    for branch in &merged {
+                        eprintln!("  {}", branch);
+                    }
+                    if !confirm(msg!("branches-prune-prompt"))? {
+                        tracing::info!("not pruning branches of {:?}", name);
+                        return Ok(());
+                    }
+                }
+                let mut num_failed = 0;
+                for branch in &merged {
+                    match opened.delete_branch(branch) {
+                        Ok(()) => tracing::info!("deleted branch {:?} of {:?}", branch, name),
+                        Err(e) => {
+                            tracing::error!("{:?}", anyhow::Error::new(e));
+                            num_failed += 1;
+                        }
+                    }
+                }
+                ensure!(
+                    num_failed == 0,
+                    "failed to delete {} branch(es) of {:?}, see above",
+                    num_failed,
+                    name
+                );
+                Ok(())
+            }
+            Cli::PromptInfo { path } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let start = Instant::now();
+                let path = path.map(Ok).unwrap_or_else(current_dir)?;
+                let repos = repos.snapshot();
+                let (name, repo) = match repos
+                    .get_by_path(dirs, &path)
+                    .or_else(|_e| repos.get_by_work_tree_containing(dirs, &path))
+                {
+                    Ok(found) => found,
+                    // Prompts are shown everywhere, so being outside of repos isn't an error.
+                    Err(Error::NotFound(_)) => return Ok(()),
+                    Err(e) => return Err(e.into()),
+                };
+                let state =
+                    match status::gather(dirs, git, [(name.to_borrowed(), repo.to_borrowed())])
+                        .into_values()
+                        .next()
+                    {
+                        Some(Ok(status)) => status::display_for_prompt(&status).to_string(),
+                        Some(Err(e)) => {
+                            tracing::debug!("failed to get status of {:?}: {:?}", name, e);
+                            String::new()
+                        }
+                        None => String::new(),
+                    };
+                if state.is_empty() {
+                    println!("{} ({})", name, repo.kind());
+                } else {
+                    println!("{} ({}) {}", name, repo.kind(), state);
+                }
+                let elapsed = start.elapsed();
+                if elapsed > status::PROMPT_INFO_BUDGET {
+                    tracing::debug!(
+                        "`prompt-info` took {:.2?}, more than its budget of {:?}",
+                        elapsed,
+                        status::PROMPT_INFO_BUDGET
+                    );
+                }
+                Ok(())
+            }
+            Cli::Watch {
+                repo,
+                auto_commit,
+                debounce: HumanDuration(debounce),
+                message,
+                print_service,
+                allow_read_only,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let snapshot = repos.snapshot();
+                let (name, entry) = repo.resolve(dirs, &snapshot)?;
+                if print_service {
+                    let mut args = Vec::new();
+                    if let Some(profile) = dirs.profile() {
+                        args.extend(["--profile".to_owned(), profile.as_str().to_owned()]);
+                    }
+                    args.extend([
+                        "watch".to_owned(),
+                        name.to_string(),
+                        "--debounce".to_owned(),
+                        format!("{}ms", debounce.as_millis()),
+                    ]);
+                    if auto_commit {
+                        args.extend(["--auto-commit".to_owned(), "--message".to_owned(), message]);
+                    }
+                    if allow_read_only {
+                        args.push("--allow-read-only".to_owned());
+                    }
+                    print!("{}", watch::systemd_service(&name, &args)?);
+                    return Ok(());
+                }
+                let on_change = if auto_commit {
+                    entry.ensure_writable(&name, "commit to", allow_read_only)?;
+                    watch::OnChange::Commit(&message)
+                } else {
+                    watch::OnChange::Report
+                };
+                let mut repo = entry.open(git, dirs, name.to_borrowed())?;
+                Ok(watch::watch(&mut repo, &name, debounce, on_change)?)
             }
-            Cli::Remove { name } => {
-                let Self { dirs, git, repos } = self;
-                repos.try_remove_entire_repo(dirs, git, name)?;
-                Ok(())
-            }
-            Cli::List { repo_spec, format } => {
+            Cli::Status {
+                kind,
+                watch,
+                interval: HumanDuration(interval),
+            } => {
                 let Self {
                     dirs,
-                    git: _, // TODO: diagnostics for broken stuff? :D
+                    git,
                     repos,
+                    config: _,
                 } = self;
+                let repos = repos.snapshot();
                 let matching_repos_iter = || {
-                    repos.iter().filter(|(name, repo)| {
-                        repo_spec
-                            .iter()
-                            .all(|spec| spec.matches((name.to_borrowed(), repo.to_borrowed())))
+                    repos
+                        .iter()
+                        .filter(|(_name, repo)| kind.map_or(true, |kind| repo.kind() == kind))
+                };
+                // Off a terminal, frames are printed one after another instead.
+                let redraw = watch && io::stdout().is_terminal();
+                loop {
+                    let mut statuses = status::gather_fresh(dirs, git, matching_repos_iter());
+                    let mut frame = String::new();
+                    if watch {
+                        frame += &format!("Every {:?}; press Ctrl-C to stop.\n\n", interval);
+                    }
+                    for (name, repo) in matching_repos_iter() {
+                        let status = match statuses.remove(&*name) {
+                            Some(Ok(status)) => status.to_string(),
+                            Some(Err(e)) => format!("failed to get status: {:#}", e),
+                            None => continue,
+                        };
+                        frame +=
+                            &format!("{:?} ({}): {}\n", name, repo.kind().human_name(), status);
+                    }
+                    if redraw {
+                        // Clear the screen, and move the cursor to its top left.
+                        frame.insert_str(0, "\x1b[2J\x1b[H");
+                    }
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(frame.as_bytes())?;
+                    stdout.flush()?;
+                    drop(stdout);
+                    if !watch {
+                        return Ok(());
+                    }
+                    thread::sleep(interval);
+                    if !redraw {
+                        println!();
+                    }
+                }
+            }
+            Cli::List {
+                kind,
+                format,
+                sort,
+                status: show_status,
+                duplicates,
+                names_only,
+                paths_only,
+            } => {
+                let Self {
+                    dirs,
+                    git,
+                    repos,
+                    config: _,
+                } = self;
+                let repos = repos.snapshot();
+                let matching_repos_iter = || {
+                    repos
+                        .iter()
+                        .filter(|(_name, repo)| kind.map_or(true, |kind| repo.kind() == kind))
+                };
+                if duplicates {
+                    return print_duplicate_remotes(dirs, git, matching_repos_iter());
+                }
+                let listed = sort_listed_repos(dirs, git, matching_repos_iter().collect(), sort)?;
+                if names_only || paths_only {
+                    for (name, repo) in &listed {
+                        if paths_only {
+                            println!("{}", repo.work_tree_path(dirs)?.display());
+                        } else {
+                            println!("{}", name);
+                        }
+                    }
+                    return Ok(());
+                }
+                let matching_repos_iter = || {
+                    listed
+                        .iter()
+                        .map(|(name, repo)| (name.to_borrowed(), repo.to_borrowed()))
+                };
+                let mut statuses = if show_status {
+                    status::gather(dirs, git, matching_repos_iter())
+                } else {
+                    Default::default()
+                };
+                let mut take_status = |name: &RepoName<'_>| match statuses.remove(&**name)? {
+                    Ok(status) => Some(status),
+                    Err(e) => {
+                        tracing::warn!("{:?}", e);
+                        None
+                    }
+                };
+                let machine = show_status
+                    .then(|| MachineIdentity::load_or_create(dirs))
+                    .transpose()
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("{:?}", e);
+                        None
+                    });
+                let display_status = |status: Option<RepoStatus>, repo: &RepoEntry<'_>| {
+                    let last_sync = repo.last_sync().filter(|_| show_status).map(|record| {
+                        machine::display_sync_record(record, machine.as_ref()).to_string()
+                    });
+                    lazy_format!(move |f| {
+                        if let Some(status) = &status {
+                            write!(f, " [{}]", status)?;
+                        }
+                        if let Some(last_sync) = &last_sync {
+                            write!(f, " (synced {})", last_sync)?;
+                        }
+                        Ok(())
                     })
                 };
                 match format {
                     ListFormat::Flat => {
                         matching_repos_iter().for_each(|(name, repo)| {
                             // TODO: Finalize this?
-                            println!("{:?}: {}", name, repo.short_desc());
+                            println!(
+                                "{:?}: {}{}",
+                                name,
+                                repo.short_desc(),
+                                display_status(take_status(&name), &repo)
+                            );
                         });
                     }
                     ListFormat::GroupByKind => {
-                        CliRepoKind::iter().for_each(|repo_kind| {
-                            // TODO: get casing right
-                            println!("{:?}", repo_kind);
+                        RepoKind::iter().for_each(|repo_kind| {
+                            println!("{}", repo_kind.display_plural_human_name());
                             matching_repos_iter()
                                 .filter(|(_name, repo)| repo.kind() == repo_kind)
                                 .for_each(|(name, repo)| match repo_kind {
-                                    CliRepoKind::Overlay => {
-                                        println!("  {}", name);
+                                    RepoKind::Overlay => {
+                                        println!(
+                                            "  {}{}",
+                                            name,
+                                            display_status(take_status(&name), &repo)
+                                        );
                                     }
-                                    CliRepoKind::Standalone => {
+                                    RepoKind::Standalone | RepoKind::Unmanaged => {
                                         println!(
-                                            "  {}: {}",
+                                            "  {}: {}{}",
                                             name,
-                                            repo.path(dirs, name.to_borrowed()).unwrap().display()
+                                            repo.path(dirs, name.to_borrowed()).unwrap().display(),
+                                            display_status(take_status(&name), &repo)
                                         );
                                     }
                                 })
                         });
                     }
+                    ListFormat::Json => {
+                        let entries = matching_repos_iter()
+                            .map(|(name, repo)| -> anyhow::Result<_> {
+                                Ok(ListedRepo {
+                                    record: repo.record(dirs, name.to_borrowed())?,
+                                    status: take_status(&name),
+                                    last_sync: repo
+                                        .last_sync()
+                                        .filter(|_| show_status)
+                                        .map(|record| record.clone().into_static()),
+                                })
+                            })
+                            .collect::<anyhow::Result<Vec<_>>>()?;
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&entries)
+                                .context("failed to serialize repo list")?
+                        );
+                    }
                 };
                 Ok(())
             }
         }
     }
 
-    pub fn flush(&mut self) -> anyhow::Result<()> {
+    /// Appends a record of the command `command` to the metrics file, if enabled. Failures are only
+    /// logged, since they shouldn't fail the command.
+    pub(crate) fn record_metrics(
+        &self,
+        command: String,
+        started: SystemTime,
+        duration: Duration,
+        exit_code: i32,
+    ) {
+        let Self {
+            dirs,
+            git: _,
+            repos: _,
+            config,
+        } = self;
+        // Looking at the metrics shouldn't add to them.
+        if !config.metrics() || dirs.is_read_only() || command.starts_with("metrics") {
+            return;
+        }
+        let started = started
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        let record = metrics::RunRecord::new(command, started, duration, exit_code);
+        if let Err(e) = record.append(dirs) {
+            tracing::warn!("{:?}", e.context("failed to record metrics"));
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
         let Self {
             repos,
             git: _,
             dirs,
+            config,
         } = self;
-        repos.flush(dirs)
+        if dirs.is_read_only() {
+            tracing::trace!("in read-only mode, not flushing");
+            return Ok(());
+        }
+        repos.flush(dirs)?;
+        if config.harden_permissions() {
+            permissions::harden_state(dirs).context("failed to harden permissions")?;
+        }
+        Ok(())
     }
 }
 
@@ -476,6 +3205,665 @@ impl Display for RemoteName<'_> {
     }
 }
 
+/// Runs the scripts of `starter`, read from `path`, whose conditions `machine` meets, after
+/// asking for confirmation unless `confirmed`. Returns how many failed.
+fn run_starter_scripts(
+    dirs: &Directories,
+    repos: &RepoDb,
+    path: &Path,
+    starter: &StarterFile<'_>,
+    machine: &Machine,
+    skip: bool,
+    confirmed: bool,
+) -> anyhow::Result<usize> {
+    let scripts = starter
+        .run
+        .iter()
+        .filter(|script| match script.when.unmet_reason(machine) {
+            Some(reason) => {
+                tracing::info!("skipping script {:?}: {}", script.script, reason);
+                false
+            }
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    if scripts.is_empty() {
+        return Ok(0);
+    }
+    if skip {
+        tracing::info!("skipping {} starter script(s)", scripts.len());
+        return Ok(0);
+    }
+    let describe = |script: &StarterScript<'_>| {
+        let mut desc = format!("{}: {}", script.repo, script.script.display());
+        for arg in &script.args {
+            desc.push_str(&format!(" {:?}", arg));
+        }
+        desc
+    };
+    if !confirmed {
+        eprintln!("{}", msg!("starter-scripts", path = format!("{:?}", path)));
+        for script in &scripts {
+            eprintln!("  {}", describe(script));
+        }
+        if !confirm(msg!("starter-scripts-prompt"))? {
+            tracing::info!("not running starter scripts");
+            return Ok(0);
+        }
+    }
+
+    let snapshot = repos.snapshot();
+    let mut num_failed = 0;
+    for script in scripts {
+        let res = (|| -> anyhow::Result<()> {
+            let repo = snapshot.get_by_name(script.repo.to_borrowed())?;
+            let work_tree = repo.work_tree_path(dirs)?;
+            let script_path = work_tree.join(&script.script);
+            // Paths leading outside of the work tree were already rejected by `lint`.
+            ensure!(
+                script_path.is_file(),
+                "{:?} is not a file in repo {:?}",
+                script.script,
+                script.repo
+            );
+            tracing::info!("running {}", describe(script));
+            let status = Command::new(&script_path)
+                .args(&script.args)
+                .current_dir(&*work_tree)
+                .env("BELLBOY_REPO", script.repo.to_string())
+                .status()
+                .with_context(|| anyhow!("failed to spawn {:?}", script_path))?;
+            cmd_failure_res(status)
+        })();
+        if let Err(e) = res {
+            tracing::error!("{:?}", e.context(anyhow!("script {}", describe(script))));
+            num_failed += 1;
+        }
+    }
+    Ok(num_failed)
+}
+
+fn apply_configured_file_modes(
+    dirs: &Directories,
+    git: &DynGit,
+    config: &Config,
+    repos: &RepoDbSnapshot,
+    name: RepoName<'_>,
+) -> anyhow::Result<()> {
+    let repo_config = match config.repo(&name) {
+        Some(repo_config) if repo_config.has_file_modes() => repo_config,
+        _ => return Ok(()),
+    };
+    let repo = repos.get_by_name(name.to_borrowed())?;
+    if !repo.kind().is_git_repo() {
+        tracing::warn!(
+            "not applying configured file modes to {:?}, since it isn't a Git repo",
+            name
+        );
+        return Ok(());
+    }
+    let repo = repo.open(git, dirs, name.to_borrowed())?;
+    permissions::apply_file_modes(&repo, repo_config)
+        .with_context(|| anyhow!("failed to apply configured file modes to {:?}", name))
+}
+
+/// Whether `repo` is a Git repo, logging that it's skipped if it's an unmanaged directory instead,
+/// for commands that need Git.
+fn is_git_repo_logged(name: &RepoName<'_>, repo: &RepoEntry<'_>) -> bool {
+    let is_git_repo = repo.kind().is_git_repo();
+    if !is_git_repo {
+        tracing::info!("skipping unmanaged directory {:?}", name);
+    }
+    is_git_repo
+}
+
+#[derive(Debug, Serialize)]
+struct PathsReport<'a> {
+    data_dir: PathBuf,
+    state_dir: PathBuf,
+    config_file: PathBuf,
+    overlay_repos_dir: PathBuf,
+    standalone_repo_db: PathBuf,
+    repos: BTreeMap<RepoName<'a>, RepoPathsReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoPathsReport {
+    git_dir: PathBuf,
+    work_tree: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsReport<'a> {
+    repos: BTreeMap<RepoName<'a>, RepoStats>,
+    total: StatsTotals,
+}
+
+#[derive(Debug, Serialize)]
+struct RepoStats {
+    tracked_files: usize,
+    objects: u64,
+    /// In bytes.
+    size_on_disk: u64,
+    largest_blobs: Vec<BlobReport>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct StatsTotals {
+    tracked_files: usize,
+    objects: u64,
+    size_on_disk: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct BlobReport {
+    id: String,
+    size: u64,
+    path: Option<PathBuf>,
+}
+
+fn print_duplicate_remotes<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: impl Iterator<Item = (RepoName<'a>, RepoEntry<'a>)>,
+) -> anyhow::Result<()> {
+    let ssh = SshConfig::load(&dirs.home_dir_path()?);
+    let mut repos_by_url = BTreeMap::<String, Vec<(RepoName<'a>, RemoteName<'static>)>>::new();
+    for (name, repo) in repos.filter(|(name, repo)| is_git_repo_logged(name, repo)) {
+        let remotes = match repo
+            .open(git, dirs, name.to_borrowed())
+            .and_then(|repo| Ok(repo.remotes()?))
+        {
+            Ok(remotes) => remotes,
+            Err(e) => {
+                tracing::warn!("skipping {:?} in search for duplicates: {:?}", name, e);
+                continue;
+            }
+        };
+        for (remote_name, url) in remotes {
+            repos_by_url
+                .entry(normalize_remote_url(&url, &ssh).into_owned())
+                .or_default()
+                .push((name.clone(), remote_name));
+        }
+    }
+
+    let mut found_duplicates = false;
+    for (url, entries) in repos_by_url {
+        let distinct_repos = entries
+            .iter()
+            .map(|(name, _remote_name)| name)
+            .collect::<BTreeSet<_>>();
+        if distinct_repos.len() < 2 {
+            continue;
+        }
+        found_duplicates = true;
+        println!("{}", url);
+        for (name, remote_name) in &entries {
+            println!("  {} ({})", name, remote_name);
+        }
+    }
+
+    if found_duplicates {
+        tracing::info!(concat!(
+            "repos sharing a remote are likely redundant; consider merging their work, and ",
+            "then using `",
+            env!("CARGO_BIN_NAME"),
+            " standalone deregister <NAME>` or `",
+            env!("CARGO_BIN_NAME"),
+            " remove <NAME>` on the copy you no longer need",
+        ));
+    } else {
+        tracing::info!("no repos sharing a remote were found");
+    }
+    Ok(())
+}
+
+/// Prints how much was fetched for each repo and how long it took, slowest first, so that slow
+/// repos stand out.
+fn print_transfer_summary(transfers: &mut [(RepoName<'static>, TransferStats)]) {
+    if transfers.is_empty() {
+        return;
+    }
+    transfers.sort_by_key(|(_name, transfer)| Reverse(transfer.duration));
+    let name_width = transfers
+        .iter()
+        .map(|(name, _transfer)| name.len())
+        .max()
+        .unwrap_or_default();
+    println!("fetched:");
+    for (name, transfer) in transfers.iter() {
+        let TransferStats {
+            objects,
+            bytes,
+            duration,
+        } = transfer;
+        println!(
+            "  {:name_width$}  {:>10}  {:>7} object(s)  {:.2?}",
+            name.to_string(),
+            gc::display_size(*bytes).to_string(),
+            objects,
+            duration,
+            name_width = name_width,
+        );
+    }
+}
+
+/// Orders `repos`, which are expected to already be sorted by name, by `sort`. The sort is stable,
+/// so ties stay ordered by name.
+fn sort_listed_repos<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    mut repos: Vec<(RepoName<'a>, RepoEntry<'a>)>,
+    sort: ListSort,
+) -> anyhow::Result<Vec<(RepoName<'a>, RepoEntry<'a>)>> {
+    match sort {
+        ListSort::Name => (),
+        ListSort::Kind => repos.sort_by_key(|(_name, repo)| repo.kind()),
+        ListSort::Path => {
+            let mut keyed = repos
+                .into_iter()
+                .map(|(name, repo)| {
+                    let path = repo.path(dirs, name.to_borrowed())?.into_owned();
+                    Ok((path, (name, repo)))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+            repos = keyed.into_iter().map(|(_path, entry)| entry).collect();
+        }
+        ListSort::RecentActivity => {
+            let mut keyed = repos
+                .into_iter()
+                .map(|(name, repo)| {
+                    if !repo.kind().is_git_repo() {
+                        return (None, (name, repo));
+                    }
+                    let last_commit_time = repo
+                        .open(git, dirs, name.to_borrowed())
+                        .and_then(|repo| Ok(repo.last_commit()?.map(|commit| commit.time)))
+                        .with_context(|| anyhow!("failed to find most recent commit of {:?}", name))
+                        .unwrap_or_else(|e| {
+                            tracing::warn!("{:?}", e);
+                            None
+                        });
+                    (last_commit_time, (name, repo))
+                })
+                .collect::<Vec<_>>();
+            // `None` sorts before `Some`, so reversing this also puts repos without commits, and
+            // unmanaged directories, last.
+            keyed.sort_by_key(|(last_commit_time, _)| Reverse(*last_commit_time));
+            repos = keyed.into_iter().map(|(_time, entry)| entry).collect();
+        }
+    }
+    Ok(repos)
+}
+
+/// Reads the [`RepoMetadata`] TOML of the repo whose work tree is at `work_tree`, if
+/// `recurse_config` is set. Otherwise, only points out that the repo has metadata.
+fn read_repo_metadata(work_tree: &Path, recurse_config: bool) -> anyhow::Result<Option<String>> {
+    let toml = RepoMetadata::read_toml(work_tree)?;
+    if toml.is_some() && !recurse_config {
+        tracing::info!(
+            "found {:?}; pass `--recurse-config` to apply it",
+            RepoMetadata::path(work_tree)
+        );
+        return Ok(None);
+    }
+    Ok(toml)
+}
+
+/// Shows what applying `metadata` would do, and asks the user whether to apply it.
+fn confirm_repo_metadata(
+    work_tree: &Path,
+    metadata: &RepoMetadata<'_>,
+    machine_tags: &[String],
+) -> anyhow::Result<bool> {
+    eprintln!(
+        "{}\n{}",
+        msg!(
+            "repo-metadata-contents",
+            path = format!("{:?}", RepoMetadata::path(work_tree))
+        ),
+        metadata.describe(machine_tags)
+    );
+    confirm(if metadata.missing_tags(machine_tags).is_empty() {
+        msg!("repo-metadata-apply-prompt")
+    } else {
+        msg!("repo-metadata-apply-missing-tags-prompt")
+    })
+}
+
+/// Reports conflicts found while adding a repo entry, both in logs and to hooks.
+struct ConflictHandler<'h> {
+    hooks: &'h [HookConfig],
+}
+
+impl RepoConflictHandler for ConflictHandler<'_> {
+    fn on_conflict_path(
+        &mut self,
+        matched: RepoName<'_>,
+        partial_reason: Option<(Cow<'_, Path>, NormalizedRepoPathEq)>,
+    ) {
+        let Self { hooks } = self;
+        run_hooks(
+            hooks,
+            &Event::ConflictDetected {
+                existing_name: matched.to_borrowed(),
+                conflicting_field: ConflictingField::Path,
+            },
+        );
+        tracing::error!(
+            "a repo with the path {:?} already exists{}",
+            matched,
+            lazy_format!(move |f| {
+                match &partial_reason {
+                    None => Ok(()),
+                    Some((found, match_reason)) => {
+                        write!(f, " (")?;
+                        match_reason.describe(found, f)?;
+                        write!(f, ")")?;
+                        Ok(())
+                    }
+                }
+            })
+        );
+    }
+
+    fn on_conflict_name(
+        &mut self,
+        matched: RepoName<'_>,
+        partial_reason: Option<NormalizedRepoNameEq>,
+    ) {
+        let Self { hooks } = self;
+        run_hooks(
+            hooks,
+            &Event::ConflictDetected {
+                existing_name: matched.to_borrowed(),
+                conflicting_field: ConflictingField::Name,
+            },
+        );
+        tracing::error!(
+            "a repo with the name {:?} already exists at the specified path{}",
+            matched,
+            lazy_format!(|f| {
+                match &partial_reason {
+                    None => Ok(()),
+                    Some(match_reason) => {
+                        write!(f, " (")?;
+                        match_reason.describe(&matched, f)?;
+                        write!(f, ")")?;
+                        Ok(())
+                    }
+                }
+            })
+        );
+    }
+
+    fn on_iteration_err(&mut self, err: anyhow::Error) {
+        tracing::error!("failed to enumerate existing repo entry: {:?}", err);
+    }
+
+    fn resolve_name_conflict(
+        &mut self,
+        existing: RepoName<'_>,
+    ) -> anyhow::Result<NameConflictResolution> {
+        let answer = prompt(msg!(
+            "add-name-conflict-prompt",
+            name = format!("{:?}", existing)
+        ))?
+        .to_lowercase();
+        if i18n::answers("add-name-conflict-replace-answers").contains(&answer) {
+            return Ok(NameConflictResolution::Replace);
+        }
+        if !i18n::answers("add-name-conflict-rename-answers").contains(&answer) {
+            return Ok(NameConflictResolution::Abort);
+        }
+        loop {
+            let new_name = prompt(msg!("add-name-conflict-new-name-prompt"))?;
+            if new_name.is_empty() {
+                return Ok(NameConflictResolution::Abort);
+            }
+            match new_name.parse() {
+                Ok(new_name) => return Ok(NameConflictResolution::Rename(new_name)),
+                Err(e) => tracing::error!("{}", e),
+            }
+        }
+    }
+}
+
+/// Where `standalone clone` puts `source` when it isn't given a path: a directory named after the
+/// source in the configured `projects_dir`, or else the current working directory.
+fn default_clone_path(
+    dirs: &Directories,
+    config: &Config,
+    source: &RepoSource<'_>,
+) -> anyhow::Result<PathBuf> {
+    let base_name = source.base_name().with_context(|| {
+        anyhow!(
+            "failed to infer a directory name from {:?}; specify a path",
+            source.as_str()
+        )
+    })?;
+    let parent = match config.projects_dir(&dirs.home_dir_path()?) {
+        Some(projects_dir) => {
+            fs::create_dir_all(&projects_dir).with_context(|| {
+                anyhow!(
+                    "failed to create projects directory {}",
+                    projects_dir.display()
+                )
+            })?;
+            projects_dir
+        }
+        None => current_dir()?,
+    };
+    Ok(parent.join(base_name))
+}
+
+/// Runs the command in `cmd_and_args` against `repo`.
+#[allow(clippy::too_many_arguments)]
+fn run_in_repo(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: &RepoDbSnapshot,
+    repo: &RepoRef,
+    no_cd_root: bool,
+    cmd_and_args: &CommandAndArgs,
+    options: child::Options,
+) -> anyhow::Result<child::Report> {
+    let cmd = prep_cmd_in_repo(dirs, git, repos, repo, no_cd_root, cmd_and_args.to_std()?)?;
+    tracing::debug!("running command {:?}", cmd);
+    child::run(cmd, options)
+}
+
+/// Sets up `cmd` to run against `repo`, the same way for [`run_in_repo`] and `run --print-env`.
+fn prep_cmd_in_repo(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: &RepoDbSnapshot,
+    repo: &RepoRef,
+    no_cd_root: bool,
+    mut cmd: Command,
+) -> anyhow::Result<Command> {
+    let (repo_name, repo) = repo.resolve(dirs, repos)?;
+
+    if !no_cd_root {
+        cmd.current_dir(repo.work_tree_path(dirs)?);
+    }
+    // There's no Git repo to point the command at.
+    if !repo.kind().is_git_repo() {
+        return Ok(cmd);
+    }
+    let repo = repo.open(git, dirs, repo_name)?;
+
+    Ok(repo.run_cmd(cmd, |cmd| cmd))
+}
+
+/// Prints the working directory and environment that `cmd` would run with, followed by the
+/// variables it would inherit that could affect which repo it operates on.
+fn print_cmd_env(cmd: &Command) -> anyhow::Result<()> {
+    let cwd = match cmd.get_current_dir() {
+        Some(cwd) => cwd.to_owned(),
+        None => current_dir()?,
+    };
+    println!("working directory: {}", cwd.display());
+
+    println!("set:");
+    let set = cmd
+        .get_envs()
+        .map(|(key, value)| (key.to_owned(), value.map(|value| value.to_owned())))
+        .collect::<BTreeMap<_, _>>();
+    for (key, value) in &set {
+        match value {
+            Some(value) => println!("  {}={}", key.to_string_lossy(), value.to_string_lossy()),
+            None => println!("  {} (removed)", key.to_string_lossy()),
+        }
+    }
+
+    println!("inherited:");
+    let inherited = std::env::vars_os()
+        .filter(|(key, _value)| !set.contains_key(key))
+        .filter(|(key, _value)| {
+            let key = key.to_string_lossy();
+            key.starts_with("GIT_") || key.starts_with("BELLBOY_")
+        })
+        .collect::<BTreeMap<_, _>>();
+    if inherited.is_empty() {
+        println!("  (none)");
+    }
+    for (key, value) in inherited {
+        println!("  {}={}", key.to_string_lossy(), value.to_string_lossy());
+    }
+    Ok(())
+}
+
+/// Logs how a command run with [`run_in_repo`] finished, returning its exit code. Fails if the
+/// command didn't exit on its own.
+fn check_cmd_outcome(outcome: &child::Outcome) -> anyhow::Result<i32> {
+    let cmd_status = match outcome {
+        child::Outcome::Exited(status) => status,
+        child::Outcome::TimedOut => bail!("command was killed after timing out"),
+    };
+    Ok(match cmd_status.code() {
+        Some(code) => {
+            let display_exit_code =
+                lazy_format!(|f| { write!(f, "command returned exit code {}", code) });
+            if code == 0 {
+                tracing::debug!("{}", display_exit_code);
+            } else {
+                tracing::warn!("{}", display_exit_code);
+            }
+            code
+        }
+        None => {
+            tracing::warn!("command was terminated by a signal");
+            201 // TODO: actually design error codes for this command
+        }
+    })
+}
+
+fn write_captured_output(output: &child::CapturedOutput) -> io::Result<()> {
+    let child::CapturedOutput { stdout, stderr } = output;
+    io::stdout().write_all(stdout)?;
+    io::stdout().flush()?;
+    io::stderr().write_all(stderr)?;
+    io::stderr().flush()
+}
+
+fn save_captured_output(
+    capture_dir: &Path,
+    repo_name: RepoName<'_>,
+    output: &child::CapturedOutput,
+) -> anyhow::Result<()> {
+    let child::CapturedOutput { stdout, stderr } = output;
+    for (extension, contents) in [("stdout", stdout), ("stderr", stderr)] {
+        let path = capture_dir.join(format!("{}.{}", repo_name, extension));
+        fs::write(&path, contents).with_context(|| anyhow!("failed to write {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// An entry in the `summary.json` written by `for-each --capture-dir`.
+#[derive(Debug, Serialize)]
+struct CapturedRunSummary {
+    repo: RepoName<'static>,
+    /// `null` if the command was terminated by a signal or timed out.
+    exit_code: Option<i32>,
+    timed_out: bool,
+    duration_secs: f64,
+}
+
+/// An entry in the output of `list --format json`.
+#[derive(Debug, Serialize)]
+struct ListedRepo {
+    #[serde(flatten)]
+    record: RepoRecord,
+    /// Only present with `--status`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<RepoStatus>,
+    /// Only present with `--status`, and if the repo has been synced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_sync: Option<SyncRecord<'static>>,
+}
+
+/// Opens `path` in the user's preferred editor, and waits for it to exit.
+fn edit_file(path: &Path) -> anyhow::Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|e| !e.trim().is_empty()))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    // Editors are commonly configured with arguments, i.e., `code --wait`.
+    let mut editor_and_args = editor.split_whitespace();
+    let mut cmd = Command::new(editor_and_args.next().unwrap());
+    cmd.args(editor_and_args).arg(path);
+    let status = cmd
+        .status()
+        .with_context(|| anyhow!("failed to spawn editor {:?}", editor))?;
+    cmd_failure_res(status).with_context(|| anyhow!("editor {:?} failed", editor))
+}
+
+/// Asks the user to pick one of `repos`, returning its name, or `None` if they cancelled.
+fn pick_repo(
+    dirs: &Directories,
+    repos: &RepoDbSnapshot,
+) -> anyhow::Result<Option<RepoName<'static>>> {
+    let listed = repos.iter().collect::<Vec<_>>();
+    if listed.is_empty() {
+        bail!("no repos are registered to pick from");
+    }
+    let items = listed
+        .iter()
+        .map(|(name, repo)| picker::Item {
+            label: name.to_string(),
+            detail: match repo.path(dirs, name.to_borrowed()) {
+                Ok(path) if repo.kind() == RepoKind::Standalone => path.display().to_string(),
+                _ => repo.kind().human_name().to_owned(),
+            },
+        })
+        .collect::<Vec<_>>();
+    let picked = picker::pick("not in a repo; pick one to run in", &items)?;
+    Ok(picked.map(|idx| listed[idx].0.to_borrowed().into_static()))
+}
+
+/// Asks the user a yes-or-no question on `stdin`, defaulting to "no".
+fn confirm(question: impl Display) -> anyhow::Result<bool> {
+    let answer = prompt(lazy_format!("{} {}", question, msg!("confirm-choices")))?;
+    Ok(i18n::answers("confirm-yes-answers").contains(&answer.to_lowercase()))
+}
+
+/// Asks the user `question` on `stderr`, and returns their trimmed answer.
+fn prompt(question: impl Display) -> anyhow::Result<String> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{} ", question)
+        .and_then(|()| stderr.flush())
+        .context("failed to write prompt")?;
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read answer from stdin")?;
+    Ok(answer.trim().to_owned())
+}
+
 fn canonicalize_path(path: &Path) -> anyhow::Result<PathBuf> {
     dunce::canonicalize(path)
         .with_context(|| anyhow!("failed to canonicalize relative path {:?}", path))
@@ -0,0 +1,220 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::{
+    runner::{dirs::Directories, gc},
+    units::{ByteSize, HumanDuration},
+};
+use anyhow::{anyhow, ensure, Context};
+use serde::Deserialize;
+use std::{
+    cmp::Reverse,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// How long, how many, and how much of the backups this tool leaves behind to keep. Unset limits
+/// aren't enforced, so nothing is pruned by default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RetentionConfig {
+    /// Prune backups last modified longer ago than this, i.e., `"30d"`.
+    max_age: Option<HumanDuration>,
+    /// Like `max_age`, in days. Kept for configuration written before `max_age` existed.
+    max_age_days: Option<u64>,
+    /// Keep at most this many backups, pruning the oldest first.
+    max_count: Option<usize>,
+    /// Keep at most this much of backups, i.e., `"500MB"`, pruning the oldest first.
+    max_total_size: Option<ByteSize>,
+}
+
+impl RetentionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let Self {
+            max_age,
+            max_age_days,
+            max_count: _,
+            max_total_size: _,
+        } = self;
+        ensure!(
+            max_age.is_none() || max_age_days.is_none(),
+            "`max_age` and `max_age_days` can't both be set; use `max_age`"
+        );
+        Ok(())
+    }
+
+    /// Whether any limit is set, i.e., whether pruning could ever remove anything.
+    pub fn is_enabled(&self) -> bool {
+        let Self {
+            max_age,
+            max_age_days,
+            max_count,
+            max_total_size,
+        } = self;
+        max_age.is_some()
+            || max_age_days.is_some()
+            || max_count.is_some()
+            || max_total_size.is_some()
+    }
+
+    /// Splits `backups` into those to keep and those to prune, in that order. Newer backups are
+    /// kept in preference to older ones; backups with an unknown modification time are treated as
+    /// the oldest.
+    pub fn select(&self, mut backups: Vec<Backup>, now: SystemTime) -> (Vec<Backup>, Vec<Backup>) {
+        let Self {
+            max_age,
+            max_age_days,
+            max_count,
+            max_total_size,
+        } = self;
+        let max_age = max_age
+            .map(|HumanDuration(max_age)| max_age)
+            .or_else(|| max_age_days.map(|days| Duration::from_secs(days.saturating_mul(86_400))));
+
+        backups.sort_by(|a, b| {
+            Reverse(a.modified)
+                .cmp(&Reverse(b.modified))
+                .then_with(|| b.path.cmp(&a.path))
+        });
+        let mut kept = Vec::new();
+        let mut pruned = Vec::new();
+        let mut kept_size = 0u64;
+        for backup in backups {
+            let too_old = max_age.is_some_and(|max_age| match backup.modified {
+                Some(modified) => now.duration_since(modified).is_ok_and(|age| age > max_age),
+                None => true,
+            });
+            let too_many = max_count.is_some_and(|max_count| kept.len() >= max_count);
+            let too_big = max_total_size.is_some_and(|ByteSize(max_total_size)| {
+                kept_size.saturating_add(backup.size) > max_total_size
+            });
+            if too_old || too_many || too_big {
+                pruned.push(backup);
+            } else {
+                kept_size += backup.size;
+                kept.push(backup);
+            }
+        }
+        (kept, pruned)
+    }
+}
+
+/// A file or directory this tool set aside in case it's needed again, i.e., a repo DB from before
+/// a schema upgrade or a directory quarantined by `gc`.
+#[derive(Debug)]
+pub(crate) struct Backup {
+    pub path: PathBuf,
+    /// The total size of the files in this backup, in bytes.
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Finds the backups of repo DBs and the quarantined directories this tool has left behind.
+pub(crate) fn find_backups(dirs: &Directories) -> anyhow::Result<Vec<Backup>> {
+    let db_paths = [dirs.standalone_repo_db_path()?, dirs.overlay_repo_db_path()];
+    let mut backups = Vec::new();
+    for entry in read_dir_if_exists(&dirs.data_dir_path())? {
+        let (path, metadata) = entry?;
+        let is_db_backup = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                name.ends_with(".bak")
+                    && db_paths
+                        .iter()
+                        .filter_map(|db_path| db_path.file_name()?.to_str())
+                        .any(|db_name| name.starts_with(&format!("{}.v", db_name)))
+            });
+        if is_db_backup && metadata.is_file() {
+            backups.push(Backup {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+    }
+    for entry in read_dir_if_exists(&dirs.quarantine_dir_path())? {
+        let (path, metadata) = entry?;
+        let size = if metadata.is_dir() {
+            gc::dir_size(&path).unwrap_or_else(|e| {
+                tracing::warn!("failed to compute size of {:?}: {}", path, e);
+                0
+            })
+        } else {
+            metadata.len()
+        };
+        backups.push(Backup {
+            path,
+            size,
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(backups)
+}
+
+fn read_dir_if_exists(
+    dir: &Path,
+) -> anyhow::Result<impl Iterator<Item = anyhow::Result<(PathBuf, fs::Metadata)>> + '_> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => Some(entries),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| anyhow!("failed to read directory {:?}", dir)),
+    };
+    Ok(entries.into_iter().flatten().map(move |entry| {
+        let entry = entry.with_context(|| anyhow!("failed to read entry of {:?}", dir))?;
+        let path = entry.path();
+        // Don't follow symlinks, since they may point outside of this tool's directories.
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|| anyhow!("failed to get metadata of {:?}", path))?;
+        Ok((path, metadata))
+    }))
+}
+
+/// Deletes `backup` from disk.
+pub(crate) fn remove(backup: &Backup) -> anyhow::Result<()> {
+    let Backup { path, .. } = backup;
+    let res = if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+        remove_dir_all::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    res.with_context(|| anyhow!("failed to delete {:?}", path))
+}
+
+/// Deletes the backups that `retention` doesn't keep, returning how many were deleted. Called
+/// after every successful command that may make changes, so failures are only logged.
+pub(crate) fn prune_automatically(dirs: &Directories, retention: &RetentionConfig) -> usize {
+    if !retention.is_enabled() || dirs.is_read_only() {
+        return 0;
+    }
+    let backups = match find_backups(dirs) {
+        Ok(backups) => backups,
+        Err(e) => {
+            tracing::warn!("failed to find backups to prune: {:?}", e);
+            return 0;
+        }
+    };
+    let (_kept, pruned) = retention.select(backups, SystemTime::now());
+    let mut num_pruned = 0;
+    for backup in pruned {
+        match remove(&backup) {
+            Ok(()) => {
+                tracing::debug!("pruned backup {:?}", backup.path);
+                num_pruned += 1;
+            }
+            Err(e) => tracing::warn!("{:?}", e),
+        }
+    }
+    num_pruned
+}
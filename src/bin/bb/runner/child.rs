@@ -0,0 +1,259 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use anyhow::{anyhow, Context};
+use std::{
+    io::{self, Read},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Options for [`run`].
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Options {
+    /// Kill the command if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Buffer the command's `stdout` and `stderr` instead of letting it inherit ours.
+    pub capture: bool,
+}
+
+/// How a supervised command finished.
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    Exited(ExitStatus),
+    /// The command was killed because it ran longer than its timeout.
+    TimedOut,
+}
+
+#[derive(Debug)]
+pub(crate) struct Report {
+    pub outcome: Outcome,
+    /// The command's output, if [`Options::capture`] was set.
+    pub output: Option<CapturedOutput>,
+    /// How long the command ran for.
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// This tool received a signal asking it to stop while supervising a command. The signal was
+/// forwarded to the command, and the command has exited.
+#[derive(Debug, ThisError)]
+#[error("interrupted by signal {signal}")]
+pub(crate) struct Interrupted {
+    pub signal: i32,
+}
+
+/// Runs `cmd` to completion, killing it if it runs longer than [`Options::timeout`].
+///
+/// While the command runs, `SIGINT` and `SIGTERM` received by this process are forwarded to the
+/// command rather than terminating this process. If a timeout is set, the command is started in
+/// its own process group, so that forwarded signals and timeouts reach everything it spawns.
+/// Otherwise, the command stays in this process' group so that it can still use the terminal
+/// interactively; in that case, `SIGINT` isn't forwarded, since a terminal already sends it to
+/// the whole foreground group.
+///
+/// If a signal was received, this returns an [`Interrupted`] error once the command has exited.
+pub(crate) fn run(mut cmd: Command, options: Options) -> anyhow::Result<Report> {
+    let Options { timeout, capture } = options;
+
+    let own_process_group = timeout.is_some();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        if own_process_group {
+            cmd.process_group(0);
+        }
+    }
+    if capture {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+
+    let received_signal = Arc::new(AtomicUsize::new(0));
+    let _handlers =
+        SignalHandlers::install(&received_signal).context("failed to install signal handlers")?;
+
+    let start = Instant::now();
+    let mut child = cmd.spawn().context("failed to spawn command")?;
+    // Read output concurrently, so that a command writing lots of output doesn't block on a full
+    // pipe.
+    let readers = capture.then(|| {
+        (
+            read_to_end(child.stdout.take()),
+            read_to_end(child.stderr.take()),
+        )
+    });
+
+    let deadline = timeout.map(|timeout| start + timeout);
+    let mut interrupted_by = None;
+    let outcome = loop {
+        if let Some(status) = child.try_wait().context("failed to wait for command")? {
+            break Outcome::Exited(status);
+        }
+
+        match received_signal.swap(0, Ordering::SeqCst) {
+            0 => (),
+            signal => {
+                let signal = signal as i32;
+                tracing::debug!("forwarding signal {} to command", signal);
+                forward_signal(&child, signal, own_process_group);
+                interrupted_by = Some(signal);
+            }
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            tracing::debug!("command timed out, killing it");
+            kill(&mut child, own_process_group).context("failed to kill timed out command")?;
+            child
+                .wait()
+                .context("failed to wait for timed out command")?;
+            break Outcome::TimedOut;
+        }
+
+        sleep(POLL_INTERVAL);
+    };
+    let duration = start.elapsed();
+
+    if let Some(signal) = interrupted_by {
+        return Err(Interrupted { signal }.into());
+    }
+
+    let output = readers
+        .map(|(stdout, stderr)| -> anyhow::Result<_> {
+            let join = |reader: JoinHandle<io::Result<Vec<u8>>>, name: &str| {
+                reader
+                    .join()
+                    .expect("output reader thread panicked")
+                    .with_context(|| anyhow!("failed to read command's {}", name))
+            };
+            Ok(CapturedOutput {
+                stdout: join(stdout, "stdout")?,
+                stderr: join(stderr, "stderr")?,
+            })
+        })
+        .transpose()?;
+
+    Ok(Report {
+        outcome,
+        output,
+        duration,
+    })
+}
+
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+#[cfg(unix)]
+struct SignalHandlers(Vec<signal_hook::SigId>);
+
+#[cfg(unix)]
+impl SignalHandlers {
+    fn install(received_signal: &Arc<AtomicUsize>) -> io::Result<Self> {
+        use signal_hook::consts::{SIGINT, SIGTERM};
+
+        let mut ids = Vec::new();
+        for signal in [SIGINT, SIGTERM] {
+            let id = signal_hook::flag::register_usize(
+                signal,
+                Arc::clone(received_signal),
+                signal as usize,
+            )?;
+            ids.push(id);
+        }
+        Ok(Self(ids))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalHandlers {
+    fn drop(&mut self) {
+        let Self(ids) = self;
+        for id in ids.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn forward_signal(child: &Child, signal: i32, own_process_group: bool) {
+    let pid = child.id() as libc::pid_t;
+    let target = if own_process_group {
+        -pid
+    } else if signal == libc::SIGINT {
+        // The terminal has already delivered this to the command.
+        return;
+    } else {
+        pid
+    };
+    // SAFETY: `kill` has no memory safety preconditions.
+    if unsafe { libc::kill(target, signal) } != 0 {
+        tracing::warn!(
+            "failed to forward signal {} to command: {}",
+            signal,
+            io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(unix)]
+fn kill(child: &mut Child, own_process_group: bool) -> io::Result<()> {
+    if !own_process_group {
+        return child.kill();
+    }
+    // SAFETY: `kill` has no memory safety preconditions.
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// On other platforms, console interrupts are already delivered to every process attached to the
+// console, so there's nothing to forward.
+
+#[cfg(not(unix))]
+struct SignalHandlers;
+
+#[cfg(not(unix))]
+impl SignalHandlers {
+    fn install(_received_signal: &Arc<AtomicUsize>) -> io::Result<Self> {
+        Ok(Self)
+    }
+}
+
+#[cfg(not(unix))]
+fn forward_signal(_child: &Child, _signal: i32, _own_process_group: bool) {}
+
+#[cfg(not(unix))]
+fn kill(child: &mut Child, _own_process_group: bool) -> io::Result<()> {
+    child.kill()
+}
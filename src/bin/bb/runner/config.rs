@@ -0,0 +1,664 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::{
+    runner::{
+        backups::RetentionConfig,
+        dirs::{expand_home_path, Directories},
+        discover::DiscoverConfig,
+        git::{RepoSource, HOOKS_PATH_CONFIG_PATH},
+        hooks::HookConfig,
+        notifications::NotificationConfig,
+        permissions::FileMode,
+        repo_db::{RepoName, ScanPolicy},
+    },
+    units::ByteSize,
+};
+use anyhow::{anyhow, ensure, Context};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// User configuration for this tool, read from [`Directories::config_file_path`].
+///
+/// Unlike the repo DBs, this file is never written to by this tool.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Per-repo settings, keyed by repo name.
+    #[serde(default)]
+    repos: BTreeMap<String, RepoConfig>,
+    /// Programs to invoke on lifecycle events of this tool.
+    #[serde(default)]
+    hooks: Vec<HookConfig>,
+    /// Settings for `standalone discover`.
+    #[serde(default)]
+    discover: DiscoverConfig,
+    /// Settings applied to overlay repos when they're created.
+    #[serde(default)]
+    overlay: OverlayConfig,
+    /// Tags describing this machine, i.e., `work` or `laptop`. Repos may require tags with their
+    /// `.bellboy.toml`.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Whether to restrict the directories and files holding this tool's state (i.e., the repo
+    /// DBs and overlay repos) to the current user, with modes `0700` and `0600`. Dotfiles often
+    /// include sensitive material.
+    #[serde(default)]
+    harden_permissions: bool,
+    /// Prefixes of repo sources to replace before cloning, i.e., `"git@github.com:" =
+    /// "https://github.com/"` on machines where SSH is blocked. When several prefixes match, the
+    /// longest wins.
+    #[serde(default, rename = "url-rewrites")]
+    url_rewrites: BTreeMap<String, String>,
+    /// The directory that `standalone clone` clones into when it isn't given a path, i.e.,
+    /// `"~/code"`. A leading `~` stands for the home directory. Defaults to the current working
+    /// directory.
+    projects_dir: Option<PathBuf>,
+    /// Whether to always run in read-only mode, as with `--read-only`.
+    #[serde(default)]
+    read_only: bool,
+    /// Limits on the backups this tool keeps, i.e., repo DBs from before schema upgrades and
+    /// directories quarantined by `gc`. Enforced by `prune-backups`, and after every successful
+    /// command that may make changes.
+    #[serde(default)]
+    backups: RetentionConfig,
+    /// Whether to record how long each command and the operations within it take, for `metrics
+    /// show`. Records are only kept locally.
+    #[serde(default)]
+    metrics: bool,
+    /// Settings for keeping mirrors of cloned sources, to speed up cloning them again.
+    #[serde(default)]
+    clone_cache: CloneCacheConfig,
+    /// Settings for desktop notifications when commands against many repos finish.
+    #[serde(default)]
+    notifications: NotificationConfig,
+    /// A directory to use in place of the home directory, i.e., as the work tree of overlay repos
+    /// and what `~` stands for in stored paths, so that overlay setups can be tried out in a
+    /// sandbox first. Must be absolute. Overridden by `$BELLBOY_HOME`.
+    home_dir: Option<PathBuf>,
+    /// How to react to each kind of problem found while loading the repo DBs: `"error"`,
+    /// `"warn"`, or `"ignore"`.
+    #[serde(default)]
+    scan: ScanPolicy,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OverlayConfig {
+    /// Whether `git status` should list untracked files. Since an overlay's work tree is an entire
+    /// home directory, this is off by default.
+    #[serde(default)]
+    show_untracked_files: bool,
+    /// Whether to set `core.worktree` to the home directory, so that Git commands work against an
+    /// overlay repo with only `GIT_DIR` set.
+    #[serde(default)]
+    set_core_worktree: bool,
+    /// Whether Git may run hooks in overlay repos. Since hooks can come from anywhere a repo's
+    /// configuration does (i.e., a template), and run against the entire home directory, this is
+    /// off by default: `core.hooksPath` is pointed at an empty directory instead.
+    #[serde(default)]
+    allow_git_hooks: bool,
+    /// Additional Git configuration to set on new overlay repos, keyed by name. These take
+    /// precedence over the settings above.
+    #[serde(default)]
+    git_config: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CloneCacheConfig {
+    /// Whether to keep a mirror of each remote source cloned on this machine, and clone from it
+    /// (with `--reference-if-able`) when the source is cloned again, fetching only what's new.
+    /// Clones don't depend on the mirrors afterwards, so they may be deleted at any time.
+    #[serde(default)]
+    enabled: bool,
+    /// Where to keep mirrors, i.e., a directory shared between CI runs. A leading `~` stands for
+    /// the home directory. Defaults to a directory in the data directory, shared by all profiles.
+    dir: Option<PathBuf>,
+    /// How much space mirrors may take up, i.e., `"2GB"`. Once past it, the least recently
+    /// updated mirrors are deleted after each clone. Unlimited by default.
+    #[serde(default)]
+    max_size: Option<ByteSize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RepoConfig {
+    /// The mode to give all files restored into this repo's work tree, unless overridden in
+    /// `file_modes`.
+    file_mode: Option<FileMode>,
+    /// Modes to give specific files restored into this repo's work tree, keyed by path relative
+    /// to the work tree root.
+    #[serde(default)]
+    file_modes: BTreeMap<PathBuf, FileMode>,
+}
+
+impl Config {
+    pub fn from_toml_on_disk(dirs: &Directories) -> anyhow::Result<Self> {
+        let config_path = dirs.config_file_path();
+        tracing::trace!("reading configuration at {}", config_path.display());
+        let config_toml = match fs::read_to_string(&config_path) {
+            Ok(toml) => toml,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                tracing::trace!("no configuration file found, using defaults");
+                return Ok(Self::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    anyhow!("failed to read configuration at {}", config_path.display())
+                })
+            }
+        };
+        let config = toml::from_str::<Self>(&config_toml).with_context(|| {
+            anyhow!(
+                "failed to deserialize TOML from configuration at {}",
+                config_path.display()
+            )
+        })?;
+        config
+            .validate()
+            .with_context(|| anyhow!("configuration at {} is invalid", config_path.display()))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let Self {
+            repos,
+            hooks,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites,
+            projects_dir: _,
+            read_only: _,
+            backups,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir,
+            scan: _,
+        } = self;
+        for name in repos.keys() {
+            RepoName::from_str(name)
+                .with_context(|| anyhow!("{:?} is not a valid repo name", name))?;
+        }
+        for (idx, hook) in hooks.iter().enumerate() {
+            hook.validate()
+                .with_context(|| anyhow!("hook at index {} is invalid", idx))?;
+        }
+        ensure!(
+            !url_rewrites.contains_key(""),
+            "URL rewrite prefixes must not be empty"
+        );
+        backups
+            .validate()
+            .context("`backups` settings are invalid")?;
+        if let Some(home_dir) = home_dir {
+            ensure!(
+                home_dir.is_absolute(),
+                "`home_dir` must be an absolute path, got {:?}",
+                home_dir
+            );
+        }
+        Ok(())
+    }
+
+    pub fn repo(&self, name: &RepoName<'_>) -> Option<&RepoConfig> {
+        let Self {
+            repos,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        repos.get(&**name)
+    }
+
+    pub fn hooks(&self) -> &[HookConfig] {
+        let Self {
+            repos: _,
+            hooks,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        hooks
+    }
+
+    pub fn discover(&self) -> &DiscoverConfig {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        discover
+    }
+
+    pub fn overlay(&self) -> &OverlayConfig {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        overlay
+    }
+
+    pub fn tags(&self) -> &[String] {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        tags
+    }
+
+    pub fn harden_permissions(&self) -> bool {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        *harden_permissions
+    }
+
+    pub fn read_only(&self) -> bool {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        *read_only
+    }
+
+    pub fn backups(&self) -> &RetentionConfig {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        backups
+    }
+
+    pub fn metrics(&self) -> bool {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        *metrics
+    }
+
+    /// Where mirrors of cloned sources are kept, or `None` if the clone cache is disabled.
+    pub fn clone_cache_dir(&self, dirs: &Directories) -> anyhow::Result<Option<PathBuf>> {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache:
+                CloneCacheConfig {
+                    enabled,
+                    dir,
+                    max_size: _,
+                },
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        if !enabled {
+            return Ok(None);
+        }
+        Ok(Some(match dir {
+            Some(dir) => {
+                expand_home_path(dir, &dirs.home_dir_path()?).unwrap_or_else(|| dir.to_owned())
+            }
+            None => dirs.clone_cache_dir_path(),
+        }))
+    }
+
+    pub fn home_dir(&self) -> Option<&Path> {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir,
+            scan: _,
+        } = self;
+        home_dir.as_deref()
+    }
+
+    pub fn scan(&self) -> &ScanPolicy {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan,
+        } = self;
+        scan
+    }
+
+    pub fn notifications(&self) -> &NotificationConfig {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications,
+            home_dir: _,
+            scan: _,
+        } = self;
+        notifications
+    }
+
+    /// How many bytes mirrors in the clone cache may take up, if limited.
+    pub fn clone_cache_max_size(&self) -> Option<u64> {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache:
+                CloneCacheConfig {
+                    enabled: _,
+                    dir: _,
+                    max_size,
+                },
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        max_size.map(|ByteSize(bytes)| bytes)
+    }
+
+    /// The configured `projects_dir`, with a leading `~` expanded to `home`.
+    pub fn projects_dir(&self, home: &Path) -> Option<PathBuf> {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites: _,
+            projects_dir,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        projects_dir
+            .as_deref()
+            .map(|path| expand_home_path(path, home).unwrap_or_else(|| path.to_owned()))
+    }
+
+    /// Applies the rewrite in `url-rewrites` with the longest prefix of `source`, if any. Fails if
+    /// the rewritten source isn't valid.
+    pub fn rewrite_source<'a>(&self, source: RepoSource<'a>) -> anyhow::Result<RepoSource<'a>> {
+        let Self {
+            repos: _,
+            hooks: _,
+            discover: _,
+            overlay: _,
+            tags: _,
+            harden_permissions: _,
+            url_rewrites,
+            projects_dir: _,
+            read_only: _,
+            backups: _,
+            metrics: _,
+            clone_cache: _,
+            notifications: _,
+            home_dir: _,
+            scan: _,
+        } = self;
+        let best_match = url_rewrites
+            .iter()
+            .filter(|(prefix, _replacement)| source.as_str().starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _replacement)| prefix.len());
+        match best_match {
+            Some((prefix, replacement)) => {
+                let rewritten = format!("{}{}", replacement, &source.as_str()[prefix.len()..]);
+                tracing::debug!("rewrote {:?} to {:?}", source.as_str(), rewritten);
+                RepoSource::from_str(&rewritten).with_context(|| {
+                    anyhow!(
+                        "rewriting {:?} with `url-rewrites` gave an invalid source",
+                        source.as_str()
+                    )
+                })
+            }
+            None => Ok(source),
+        }
+    }
+}
+
+impl OverlayConfig {
+    /// Returns the Git configuration to set on a new overlay repo whose work tree is `home`.
+    /// `empty_hooks_dir` is where hooks are looked for, unless they're allowed.
+    pub fn git_config<'a>(
+        &'a self,
+        home: &'a Path,
+        empty_hooks_dir: &'a Path,
+    ) -> BTreeMap<&'a str, &'a OsStr> {
+        let Self {
+            show_untracked_files,
+            set_core_worktree,
+            allow_git_hooks,
+            git_config,
+        } = self;
+        let mut config = BTreeMap::new();
+        if !show_untracked_files {
+            config.insert("status.showUntrackedFiles", OsStr::new("no"));
+        }
+        if *set_core_worktree {
+            // Git refuses to use `core.worktree` with a bare repo.
+            config.insert("core.bare", OsStr::new("false"));
+            config.insert("core.worktree", home.as_os_str());
+        }
+        if !allow_git_hooks {
+            config.insert(HOOKS_PATH_CONFIG_PATH, empty_hooks_dir.as_os_str());
+        }
+        config.extend(
+            git_config
+                .iter()
+                .map(|(key, value)| (key.as_str(), OsStr::new(value))),
+        );
+        config
+    }
+
+    pub fn allow_git_hooks(&self) -> bool {
+        let Self {
+            show_untracked_files: _,
+            set_core_worktree: _,
+            allow_git_hooks,
+            git_config: _,
+        } = self;
+        *allow_git_hooks
+    }
+}
+
+impl RepoConfig {
+    pub fn has_file_modes(&self) -> bool {
+        let Self {
+            file_mode,
+            file_modes,
+        } = self;
+        file_mode.is_some() || !file_modes.is_empty()
+    }
+
+    /// Returns the mode that the file at `relative_path` in the work tree should have, if any is
+    /// configured.
+    pub fn file_mode_for(&self, relative_path: &Path) -> Option<FileMode> {
+        let Self {
+            file_mode,
+            file_modes,
+        } = self;
+        file_modes.get(relative_path).copied().or(*file_mode)
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Keeping track of the directories that checking out an overlay repo creates in its work tree,
+//! so that deleting its files later on only deletes directories that weren't there before it.
+//!
+//! The record lives in the repo's Git directory, so that it follows the repo when it's renamed
+//! and goes away along with it. Paths in it are relative to the work tree.
+
+use crate::runner::{
+    canonicalize_path,
+    git::{DynGitRepo, GitRepoTrait},
+};
+use anyhow::{anyhow, Context};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = "bellboy-created-dirs.json";
+
+/// Checks out the files of `repo` matching `only` (see [`GitRepoTrait::restore`]), and adds the
+/// directories that creates to those recorded for it. Failing to record them is logged, rather
+/// than failing the checkout.
+pub(crate) fn restore(repo: &mut DynGitRepo, only: &[String]) -> anyhow::Result<()> {
+    let missing = missing_dirs(repo)
+        .map_err(|e| {
+            tracing::warn!(
+                "failed to find the directories that checking out files creates: {:#}",
+                e
+            )
+        })
+        .ok();
+    repo.restore(only)?;
+    let (work_tree_path, missing) = match missing {
+        Some(missing) => missing,
+        None => return Ok(()),
+    };
+    let created = missing
+        .into_iter()
+        .filter(|dir| work_tree_path.join(dir).is_dir())
+        .collect::<BTreeSet<_>>();
+    if created.is_empty() {
+        return Ok(());
+    }
+    tracing::debug!("checking out files created {} directories", created.len());
+    if let Err(e) = add(repo.git_dir_path(), created) {
+        tracing::warn!("{:#}", e);
+    }
+    Ok(())
+}
+
+/// The directories of the files tracked by `repo` that don't exist yet, relative to its work
+/// tree, along with the canonical path of the work tree.
+fn missing_dirs(repo: &DynGitRepo) -> anyhow::Result<(PathBuf, BTreeSet<PathBuf>)> {
+    let work_tree_path = canonicalize_path(repo.work_tree_path())?;
+    let mut missing = BTreeSet::new();
+    for file in repo.list_files().context("failed to list files")? {
+        for dir in file.ancestors().skip(1) {
+            // Directories that exist already have existing parents too.
+            if dir.symlink_metadata().is_ok() {
+                break;
+            }
+            let dir = match dir.strip_prefix(&work_tree_path) {
+                Ok(dir) => dir,
+                Err(_) => break,
+            };
+            if !missing.insert(dir.to_owned()) {
+                break;
+            }
+        }
+    }
+    Ok((work_tree_path, missing))
+}
+
+/// The directories recorded as created by checking out the repo whose Git directory is at
+/// `git_dir`, relative to its work tree.
+pub(crate) fn read(git_dir: &Path) -> anyhow::Result<BTreeSet<PathBuf>> {
+    let path = git_dir.join(FILE_NAME);
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(e).with_context(|| anyhow!("failed to read {:?}", path)),
+    };
+    serde_json::from_str(&json).with_context(|| anyhow!("failed to deserialize {:?}", path))
+}
+
+fn add(git_dir: &Path, created: BTreeSet<PathBuf>) -> anyhow::Result<()> {
+    let mut dirs = read(git_dir)?;
+    dirs.extend(created);
+    let path = git_dir.join(FILE_NAME);
+    let json = serde_json::to_string_pretty(&dirs)
+        .with_context(|| anyhow!("failed to serialize {:?}", path))?;
+    fs::write(&path, json).with_context(|| anyhow!("failed to write {:?}", path))
+}
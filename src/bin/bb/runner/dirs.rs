@@ -11,63 +11,303 @@
 //
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
-use anyhow::Context;
+use crate::runner::{profiles::ProfileName, Error};
+use anyhow::{anyhow, bail, Context};
 use directories::{BaseDirs, ProjectDirs};
 use std::{
     env,
-    fs::create_dir_all,
+    fmt::Display,
+    fs::{self, create_dir_all},
     path::{Path, PathBuf},
 };
 
+/// The environment variable that overrides the home directory, i.e., for trying out overlay
+/// setups in a sandbox. Takes precedence over `home_dir` in the configuration.
+pub(crate) const HOME_OVERRIDE_VAR: &str = "BELLBOY_HOME";
+
+/// The environment variables that override where this tool keeps its data, state, and
+/// configuration, i.e., for keeping tests away from the current user's files. The `XDG_*`
+/// variables do the same, but only on Linux.
+const DATA_DIR_OVERRIDE_VAR: &str = "BELLBOY_DATA_DIR";
+const STATE_DIR_OVERRIDE_VAR: &str = "BELLBOY_STATE_DIR";
+const CONFIG_DIR_OVERRIDE_VAR: &str = "BELLBOY_CONFIG_DIR";
+
 #[derive(Debug)]
 pub(crate) struct Directories {
     base_dirs: BaseDirs,
-    project_dirs: ProjectDirs,
+    /// The data directory of the default profile, which also holds data shared by all profiles.
+    data_dir: PathBuf,
+    /// The state directory of the default profile, which also holds state shared by all profiles.
+    state_dir: PathBuf,
+    /// The configuration directory of the default profile.
+    config_dir: PathBuf,
+    /// Whether this tool must not change anything on disk. See [`Self::set_read_only`].
+    read_only: bool,
+    /// The profile whose files are used, or `None` for the default profile. See
+    /// [`profiles`](crate::runner::profiles).
+    profile: Option<ProfileName>,
+    /// The directory standing in for the home directory, if any. See [`Self::set_home_dir`].
+    home_dir: Option<PathBuf>,
 }
 
 impl Directories {
     pub(crate) fn new() -> anyhow::Result<Self> {
-        // TODO: make this mockable
+        let project_dirs = ProjectDirs::from(
+            "", // TODO: Is this right?
+            "bellboy-dotfiles",
+            env!("CARGO_PKG_NAME"),
+        )
+        .unwrap();
         let this = Self {
             base_dirs: BaseDirs::new().context("no home directory found for current user")?, // error message based on documented error cases for `BaseDirs::new`
-            project_dirs: ProjectDirs::from(
-                "", // TODO: Is this right?
-                "bellboy-dotfiles",
-                env!("CARGO_PKG_NAME"),
-            )
-            .unwrap(),
+            data_dir: dir_override(DATA_DIR_OVERRIDE_VAR)?
+                .unwrap_or_else(|| project_dirs.data_local_dir().to_owned()),
+            state_dir: dir_override(STATE_DIR_OVERRIDE_VAR)?.unwrap_or_else(|| {
+                project_dirs
+                    .state_dir()
+                    .unwrap_or_else(|| project_dirs.data_local_dir())
+                    .to_owned()
+            }),
+            config_dir: dir_override(CONFIG_DIR_OVERRIDE_VAR)?
+                .unwrap_or_else(|| project_dirs.config_dir().to_owned()),
+            read_only: false,
+            profile: None,
+            home_dir: None,
         };
+        Ok(this)
+    }
+
+    /// Creates the directories this tool keeps its data in, and migrates state left behind by
+    /// older versions. Not needed in read-only mode, where nothing is written to them.
+    pub(crate) fn create(&self) -> anyhow::Result<()> {
         create_dir_all(
-            this.overlay_repos_dir_path()
+            self.overlay_repos_dir_path()
                 .context("failed to get overlay repos directory path")?,
         )
         .context("failed to create overlay repos directory path")?;
-        Ok(this)
+        create_dir_all(self.state_dir_path()).context("failed to create state directory")?;
+        self.migrate_state();
+        Ok(())
+    }
+
+    /// Moves state that older versions of this tool kept in the data directory into the state
+    /// directory, unless something is already there. Failures are logged, since the state can
+    /// always be recreated.
+    fn migrate_state(&self) {
+        let data_dir = self.data_dir_path();
+        let moves = [
+            (data_dir.join("status_cache.json"), self.status_cache_path()),
+            (data_dir.join("failed_repos.json"), self.failed_repos_path()),
+            (data_dir.join("quarantine/"), self.quarantine_dir_path()),
+            (
+                self.data_dir.join("machine.json"),
+                self.machine_identity_path(),
+            ),
+        ];
+        for (old, new) in moves {
+            if old == new || !old.exists() || new.exists() {
+                continue;
+            }
+            tracing::info!("moving {} to {}", old.display(), new.display());
+            if let Err(e) = fs::rename(&old, &new) {
+                tracing::warn!(
+                    "failed to move {} to {}: {}",
+                    old.display(),
+                    new.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Forbids changes to the repo DBs, files on disk, and remotes for the rest of this
+    /// invocation. Operations that would make them fail with [`Error::ReadOnly`], and incidental
+    /// writes like caches are skipped.
+    pub(crate) fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Points the paths of the repo DBs, overlay repos, state, and configuration at those of the
+    /// profile `profile`.
+    pub(crate) fn set_profile(&mut self, profile: ProfileName) {
+        self.profile = Some(profile);
+    }
+
+    /// The profile in use, or `None` for the default profile.
+    pub(crate) fn profile(&self) -> Option<&ProfileName> {
+        self.profile.as_ref()
+    }
+
+    /// Points [`Self::home_dir_path`], and with it the work trees of overlay repos and what `~`
+    /// stands for in stored paths, at `home_dir` instead of the current user's home directory.
+    /// The configuration and repo DBs stay where they are; use a profile to keep those apart too.
+    pub(crate) fn set_home_dir(&mut self, home_dir: PathBuf) {
+        self.home_dir = Some(home_dir);
+    }
+
+    /// Fails with [`Error::ReadOnly`] if this tool is in read-only mode. `what` describes the
+    /// operation that was refused, i.e., "write the repo DB".
+    pub(crate) fn ensure_writable(&self, what: impl Display) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnly(anyhow!(
+                "refusing to {} in read-only mode",
+                what
+            )));
+        }
+        Ok(())
+    }
+
+    /// Joins the subdirectory of the profile in use to `dir`, if it isn't the default profile.
+    fn in_profile(&self, dir: &Path) -> PathBuf {
+        match &self.profile {
+            Some(profile) => dir.join("profiles").join(profile.as_str()),
+            None => dir.to_path_buf(),
+        }
+    }
+
+    /// Where the repo DBs and overlay repos of the profile in use are kept.
+    pub(crate) fn data_dir_path(&self) -> PathBuf {
+        self.in_profile(&self.data_dir)
+    }
+
+    /// Where state that can be recreated or discarded is kept, i.e., caches and quarantined
+    /// files. Follows `$XDG_STATE_HOME` on Linux, and is the same as the data directory elsewhere.
+    pub(crate) fn state_dir_path(&self) -> PathBuf {
+        self.in_profile(self.shared_state_dir_path())
+    }
+
+    /// The state directory of the default profile, which also holds state shared by all profiles.
+    fn shared_state_dir_path(&self) -> &Path {
+        &self.state_dir
+    }
+
+    pub(crate) fn config_file_path(&self) -> PathBuf {
+        self.in_profile(&self.config_dir).join("config.toml")
+    }
+
+    /// Where profiles other than the default one keep their data.
+    pub(crate) fn profiles_dir_path(&self) -> PathBuf {
+        self.data_dir.join("profiles/")
+    }
+
+    /// Where the name of the profile chosen with `profile switch` is kept.
+    pub(crate) fn active_profile_path(&self) -> PathBuf {
+        self.data_dir.join("active_profile")
+    }
+
+    /// Where mirrors of cloned sources are kept by default, if the clone cache is enabled. Shared
+    /// by all profiles.
+    pub(crate) fn clone_cache_dir_path(&self) -> PathBuf {
+        self.data_dir.join("clone_cache/")
     }
 
     pub(crate) fn home_dir_path(&self) -> anyhow::Result<PathBuf> {
         // TODO: Remove `Result`, return a reference
-        Ok(self.base_dirs.home_dir().to_path_buf())
+        Ok(self.home_dir().to_path_buf())
+    }
+
+    fn home_dir(&self) -> &Path {
+        self.home_dir
+            .as_deref()
+            .unwrap_or_else(|| self.base_dirs.home_dir())
+    }
+
+    /// The path of the Git attributes file dedicated to the overlay repo named `name`.
+    pub(crate) fn overlay_attributes_file_path(&self, name: &str) -> PathBuf {
+        self.home_dir().join(".gitattributes.d").join(name)
+    }
+
+    /// The path of the excludes file generated for the overlay repo named `name`. See
+    /// [`refresh_overlay_excludes`](crate::runner::excludes::refresh_overlay_excludes).
+    pub(crate) fn overlay_excludes_file_path(&self, name: &str) -> PathBuf {
+        self.state_dir_path().join("excludes").join(name)
     }
 
     pub(crate) fn overlay_repos_dir_path(&self) -> anyhow::Result<PathBuf> {
         // TODO: Remove `Result`
-        Ok(self.project_dirs.data_local_dir().join("overlay_repos/"))
+        Ok(self.data_dir_path().join("overlay_repos/"))
+    }
+
+    /// An empty directory that overlay repos' `core.hooksPath` points to, so that Git finds no
+    /// hooks to run in them. See [`OverlayConfig`](crate::runner::config::OverlayConfig).
+    pub(crate) fn empty_hooks_dir_path(&self) -> PathBuf {
+        self.data_dir_path().join("empty_hooks/")
     }
 
     pub(crate) fn standalone_repo_db_path(&self) -> anyhow::Result<PathBuf> {
         // TODO: Remove `Result`
-        Ok(self
-            .project_dirs
-            .data_local_dir()
-            .join("standalone_repos.toml"))
+        Ok(self.data_dir_path().join("standalone_repos.toml"))
+    }
+
+    /// Where `gc` moves orphaned overlay repo directories that the user chose to quarantine.
+    pub(crate) fn quarantine_dir_path(&self) -> PathBuf {
+        self.state_dir_path().join("quarantine/")
     }
+
+    /// Where the repos that failed in the last run of `for-each` and `sync` are recorded.
+    pub(crate) fn failed_repos_path(&self) -> PathBuf {
+        self.state_dir_path().join("failed_repos.json")
+    }
+
+    /// Where this machine's identity, used to record which machine last synced each repo, is
+    /// kept. Shared by all profiles.
+    pub(crate) fn machine_identity_path(&self) -> PathBuf {
+        self.shared_state_dir_path().join("machine.json")
+    }
+
+    /// Where records of each command's duration and operations are appended, if enabled with
+    /// `metrics = true` in the configuration file. Shared by all profiles.
+    pub(crate) fn metrics_path(&self) -> PathBuf {
+        self.shared_state_dir_path().join("metrics.jsonl")
+    }
+
+    /// Where recently gathered repo statuses are cached.
+    pub(crate) fn status_cache_path(&self) -> PathBuf {
+        self.state_dir_path().join("status_cache.json")
+    }
+
+    pub(crate) fn overlay_repo_db_path(&self) -> PathBuf {
+        self.data_dir_path().join("overlay_repos.toml")
+    }
+}
+
+/// The directory that the environment variable `var` is set to, if any.
+fn dir_override(var: &str) -> anyhow::Result<Option<PathBuf>> {
+    let Some(dir) = env::var_os(var).map(PathBuf::from) else {
+        return Ok(None);
+    };
+    if !dir.is_absolute() {
+        bail!("`${}` must be an absolute path, got {:?}", var, dir);
+    }
+    Ok(Some(dir))
 }
 
 pub(crate) fn current_dir() -> anyhow::Result<PathBuf> {
     env::current_dir().context("failed to get current working directory path")
 }
 
-pub(crate) fn set_current_dir(path: &Path) -> anyhow::Result<()> {
-    env::set_current_dir(path).context("failed to set current working directory path")
+/// The path component standing in for the home directory in stored paths, so that they survive
+/// being copied to a machine where the home directory is elsewhere.
+const HOME_COMPONENT: &str = "~";
+
+/// Replaces a leading `~` component in `path` with `home`. Returns `None` if `path` doesn't start
+/// with `~`.
+pub(crate) fn expand_home_path(path: &Path, home: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(HOME_COMPONENT).ok()?;
+    Some(if rest.as_os_str().is_empty() {
+        home.to_owned()
+    } else {
+        home.join(rest)
+    })
+}
+
+/// The inverse of [`expand_home_path`]: replaces a leading `home` in `path` with `~`. Returns
+/// `None` if `path` isn't inside `home`.
+pub(crate) fn contract_home_path(path: &Path, home: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(home).ok()?;
+    Some(Path::new(HOME_COMPONENT).join(rest))
 }
@@ -0,0 +1,109 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The default for [`DiscoverConfig::max_depth`].
+const DEFAULT_MAX_DEPTH: u32 = 3;
+
+/// Settings for `standalone discover`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DiscoverConfig {
+    /// How many directories deep to search below the root. `0` only checks the root itself.
+    max_depth: Option<u32>,
+    /// Names of directories that should never be searched, i.e., `node_modules`.
+    #[serde(default)]
+    ignore: Vec<String>,
+}
+
+impl DiscoverConfig {
+    pub fn max_depth(&self) -> u32 {
+        let Self {
+            max_depth,
+            ignore: _,
+        } = self;
+        max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+
+    pub fn ignore(&self) -> &[String] {
+        let Self {
+            max_depth: _,
+            ignore,
+        } = self;
+        ignore
+    }
+}
+
+/// Finds the work trees of Git repos at or below `root`, searching at most `max_depth`
+/// directories deep.
+///
+/// Directories named in `ignore` are skipped, as are symlinks. The contents of a repo's work tree
+/// aren't searched, so nested repos (i.e., submodules) aren't reported.
+pub(crate) fn find_repos(
+    root: &Path,
+    max_depth: u32,
+    ignore: &[String],
+) -> anyhow::Result<Vec<PathBuf>> {
+    fn search(
+        dir: &Path,
+        depth_left: u32,
+        ignore: &[String],
+        found: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        // Both `.git` directories and `.git` files (as used by worktrees and submodules) mark a
+        // work tree.
+        if dir.join(".git").exists() {
+            found.push(dir.to_owned());
+            return Ok(());
+        }
+        if depth_left == 0 {
+            return Ok(());
+        }
+        let entries =
+            fs::read_dir(dir).with_context(|| anyhow!("failed to read directory {:?}", dir))?;
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let entry = entry.with_context(|| anyhow!("failed to read entry of {:?}", dir))?;
+            let file_type = entry
+                .file_type()
+                .with_context(|| anyhow!("failed to get file type of {:?}", entry.path()))?;
+            if !file_type.is_dir() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            if ignore.iter().any(|i| OsStr::new(i) == file_name) {
+                tracing::trace!("ignoring {:?}", entry.path());
+                continue;
+            }
+            subdirs.push(entry.path());
+        }
+        subdirs.sort();
+        for subdir in subdirs {
+            if let Err(e) = search(&subdir, depth_left - 1, ignore, found) {
+                tracing::warn!("skipping {:?}: {:?}", subdir, e);
+            }
+        }
+        Ok(())
+    }
+
+    let mut found = Vec::new();
+    search(root, max_depth, ignore, &mut found)?;
+    Ok(found)
+}
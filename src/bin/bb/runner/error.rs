@@ -0,0 +1,123 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::git;
+use std::{
+    fmt::{self, Debug, Formatter},
+    io,
+};
+use thiserror::Error as ThisError;
+
+/// An error returned at the boundary of [`Runner`](crate::runner::Runner) and
+/// [`RepoDb`](crate::runner::repo_db::RepoDb), categorized so that callers can react to it.
+///
+/// Internally, `anyhow` is still used for attaching context. Errors are categorized either
+/// explicitly at the site where the category is known (i.e., `Error::NotFound(anyhow!(...))`), or
+/// after the fact by [`From<anyhow::Error>`], which searches the error's chain for a known cause.
+#[derive(ThisError)]
+pub(crate) enum Error {
+    /// A requested repo entry doesn't exist.
+    #[error(transparent)]
+    NotFound(anyhow::Error),
+    /// A new repo entry conflicts with an existing one.
+    #[error(transparent)]
+    Conflict(anyhow::Error),
+    /// An invocation of Git failed.
+    #[error(transparent)]
+    GitFailure(anyhow::Error),
+    /// An I/O operation failed.
+    #[error(transparent)]
+    Io(anyhow::Error),
+    /// Data persisted by this tool is malformed.
+    #[error(transparent)]
+    Corruption(anyhow::Error),
+    /// An operation would have made changes, but this tool is running in read-only mode.
+    #[error(transparent)]
+    ReadOnly(anyhow::Error),
+    /// Any other failure.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// The process exit code corresponding to this error.
+    ///
+    /// `2` is skipped, since that's what `clap` uses for command-line usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Other(_) => 1,
+            Self::NotFound(_) => 3,
+            Self::Conflict(_) => 4,
+            Self::GitFailure(_) => 5,
+            Self::Io(_) => 6,
+            Self::Corruption(_) => 7,
+            Self::ReadOnly(_) => 8,
+        }
+    }
+
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Self::NotFound(e)
+            | Self::Conflict(e)
+            | Self::GitFailure(e)
+            | Self::Io(e)
+            | Self::Corruption(e)
+            | Self::ReadOnly(e)
+            | Self::Other(e) => e,
+        }
+    }
+
+    fn constructor(&self) -> fn(anyhow::Error) -> Self {
+        match self {
+            Self::NotFound(_) => Self::NotFound,
+            Self::Conflict(_) => Self::Conflict,
+            Self::GitFailure(_) => Self::GitFailure,
+            Self::Io(_) => Self::Io,
+            Self::Corruption(_) => Self::Corruption,
+            Self::ReadOnly(_) => Self::ReadOnly,
+            Self::Other(_) => Self::Other,
+        }
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.inner(), f)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Self>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        // Prefer the outermost categorized cause, since it's the most specific to what we were
+        // doing.
+        let constructor = e
+            .chain()
+            .find_map(|cause| -> Option<fn(anyhow::Error) -> Self> {
+                if let Some(categorized) = cause.downcast_ref::<Self>() {
+                    Some(categorized.constructor())
+                } else if git::is_git_error(cause) {
+                    Some(Self::GitFailure)
+                } else if cause.is::<io::Error>() {
+                    Some(Self::Io)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self::Other);
+        constructor(e)
+    }
+}
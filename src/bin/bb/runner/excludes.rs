@@ -0,0 +1,124 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{
+    dirs::Directories,
+    git::{DynGitRepo, GitRepoTrait, EXCLUDES_FILE_CONFIG_PATH},
+};
+use anyhow::{anyhow, Context};
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Regenerates the excludes file of the overlay repo `name` from its source files, and points the
+/// repo at it if it isn't already.
+///
+/// An overlay repo's ignore patterns come from `~/.gitignore.d/<NAME>`, shared by every machine,
+/// and `~/.gitignore.d/<NAME>.<HOSTNAME>`, specific to this one. Git only reads a single
+/// `core.excludesFile`, so the two are concatenated into a generated file at
+/// [`Directories::overlay_excludes_file_path`].
+///
+/// Does nothing in read-only mode, leaving whatever was last generated in place.
+pub(crate) fn refresh_overlay_excludes(
+    dirs: &Directories,
+    repo: &mut DynGitRepo,
+    name: &str,
+) -> anyhow::Result<()> {
+    if dirs.is_read_only() {
+        return Ok(());
+    }
+    let home = dirs.home_dir_path()?;
+    let shared_path = shared_excludes_file_path(&home, name);
+    let mut sources = vec![shared_path.clone()];
+    match gethostname::gethostname().into_string() {
+        Ok(hostname) => sources.push(host_excludes_file_path(&home, name, &hostname)),
+        Err(hostname) => tracing::debug!(
+            "hostname {:?} is not valid UTF-8, not looking for host-specific excludes",
+            hostname
+        ),
+    }
+
+    let mut generated =
+        String::from("# Generated by Bellboy; edit the files named below instead.\n");
+    for source in &sources {
+        let contents = match fs::read_to_string(source) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(anyhow!("failed to read excludes file {:?}", source)))
+            }
+        };
+        writeln!(generated, "\n# {}", source.display()).unwrap();
+        generated.push_str(&contents);
+        if !contents.ends_with('\n') {
+            generated.push('\n');
+        }
+    }
+
+    let generated_path = dirs.overlay_excludes_file_path(name);
+    let previous = match fs::read_to_string(&generated_path) {
+        Ok(previous) => Some(previous),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(anyhow::Error::new(e).context(anyhow!(
+                "failed to read generated excludes file {:?}",
+                generated_path
+            )))
+        }
+    };
+    if previous.as_deref() != Some(&generated) {
+        let parent = generated_path.parent().unwrap();
+        fs::create_dir_all(parent)
+            .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+        fs::write(&generated_path, &generated).with_context(|| {
+            anyhow!(
+                "failed to write generated excludes file {:?}",
+                generated_path
+            )
+        })?;
+    }
+    // Only check the repo's configuration when the file is first generated, since that takes a
+    // call to Git. Repos created by older versions point at the shared file directly.
+    if previous.is_none() {
+        let configured = repo.get_config(EXCLUDES_FILE_CONFIG_PATH)?;
+        let points_elsewhere = matches!(
+            configured.as_deref().map(Path::new),
+            Some(configured) if configured != generated_path && configured != shared_path
+        );
+        if points_elsewhere {
+            tracing::warn!(
+                "overlay repo {:?} has a custom `{}`, leaving it alone; host-specific excludes \
+                won't apply",
+                name,
+                EXCLUDES_FILE_CONFIG_PATH
+            );
+        } else {
+            repo.set_excludes_file(Some(&generated_path))?;
+        }
+    }
+    Ok(())
+}
+
+/// The excludes file shared by every machine for the overlay repo `name`.
+pub(crate) fn shared_excludes_file_path(home: &Path, name: &str) -> PathBuf {
+    home.join(".gitignore.d").join(name)
+}
+
+/// The excludes file for the overlay repo `name` that only applies on the machine `hostname`.
+pub(crate) fn host_excludes_file_path(home: &Path, name: &str, hostname: &str) -> PathBuf {
+    home.join(".gitignore.d")
+        .join(format!("{}.{}", name, hostname))
+}
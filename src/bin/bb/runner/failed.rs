@@ -0,0 +1,101 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{dirs::Directories, repo_db::RepoName};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+
+/// A command that runs against many repos, whose failures can be retried with `--failed`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum BulkCommand {
+    ForEach,
+    Sync,
+}
+
+impl BulkCommand {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ForEach => "for-each",
+            Self::Sync => "sync",
+        }
+    }
+}
+
+/// The names of the repos that failed in the last run of each [`BulkCommand`], stored at
+/// [`Directories::failed_repos_path`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct FailedRepos {
+    commands: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl FailedRepos {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(anyhow::Error::new(e).context("failed to read failed repos list")),
+        };
+        serde_json::from_str(&json).context("failed to deserialize failed repos list")
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("failed to serialize failed repos list")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+        }
+        fs::write(path, json).with_context(|| anyhow!("failed to write {:?}", path))
+    }
+}
+
+/// The names of the repos that failed the last time `command` was run.
+pub(crate) fn last_failed(
+    dirs: &Directories,
+    command: BulkCommand,
+) -> anyhow::Result<BTreeSet<String>> {
+    let FailedRepos { mut commands } = FailedRepos::read(&dirs.failed_repos_path())?;
+    Ok(commands.remove(command.as_str()).unwrap_or_default())
+}
+
+/// Records `failed` as the repos that failed in this run of `command`, replacing what was
+/// recorded for its last run. Errors are logged, rather than failing the command.
+pub(crate) fn record_failed<'a>(
+    dirs: &Directories,
+    command: BulkCommand,
+    failed: impl IntoIterator<Item = &'a RepoName<'a>>,
+) {
+    let res = (|| -> anyhow::Result<_> {
+        let path = dirs.failed_repos_path();
+        let mut failed_repos = FailedRepos::read(&path)?;
+        failed_repos.commands.insert(
+            command.as_str().to_owned(),
+            failed.into_iter().map(|name| name.to_string()).collect(),
+        );
+        failed_repos.write(&path)
+    })();
+    if let Err(e) = res {
+        tracing::warn!(
+            "{:?}",
+            e.context(anyhow!(
+                "failed to record which repos failed, so `{} --failed` may not work as expected",
+                command.as_str()
+            ))
+        );
+    }
+}
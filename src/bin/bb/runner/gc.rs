@@ -0,0 +1,140 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use anyhow::{anyhow, Context};
+use format::lazy_format;
+use std::{
+    ffi::OsStr,
+    fmt::Display,
+    fs::{self, create_dir_all},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A directory in the overlay repos directory that doesn't belong to any overlay repo entry.
+#[derive(Debug)]
+pub(crate) struct Orphan {
+    pub path: PathBuf,
+    /// The total size of the files in this directory, in bytes.
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// Finds directories directly inside `dir` whose names `is_known` rejects.
+pub(crate) fn find_orphans(
+    dir: &Path,
+    is_known: impl Fn(&OsStr) -> bool,
+) -> anyhow::Result<Vec<Orphan>> {
+    let entries =
+        fs::read_dir(dir).with_context(|| anyhow!("failed to read directory {:?}", dir))?;
+    let mut orphans = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| anyhow!("failed to read entry of {:?}", dir))?;
+        let path = entry.path();
+        let metadata = entry
+            .metadata()
+            .with_context(|| anyhow!("failed to get metadata of {:?}", path))?;
+        if !metadata.is_dir() || is_known(&entry.file_name()) {
+            continue;
+        }
+        let size = dir_size(&path).unwrap_or_else(|e| {
+            tracing::warn!("failed to compute size of {:?}: {}", path, e);
+            0
+        });
+        orphans.push(Orphan {
+            path,
+            size,
+            modified: metadata.modified().ok(),
+        });
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(orphans)
+}
+
+pub(crate) fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        // Don't follow symlinks, since they may point outside of this directory.
+        let metadata = fs::symlink_metadata(entry.path())?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Moves `path` into `quarantine_dir`, suffixing its name with the current time so that repeated
+/// quarantines of the same name don't collide. Returns the new path.
+pub(crate) fn quarantine(path: &Path, quarantine_dir: &Path) -> anyhow::Result<PathBuf> {
+    create_dir_all(quarantine_dir)
+        .with_context(|| anyhow!("failed to create quarantine directory {:?}", quarantine_dir))?;
+    let file_name = path
+        .file_name()
+        .with_context(|| anyhow!("{:?} has no file name", path))?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let mut new_file_name = file_name.to_owned();
+    new_file_name.push(format!(".{}", timestamp));
+    let new_path = quarantine_dir.join(new_file_name);
+    fs::rename(path, &new_path)
+        .with_context(|| anyhow!("failed to move {:?} to {:?}", path, new_path))?;
+    Ok(new_path)
+}
+
+/// Formats `bytes` with a binary unit, i.e., `12.3 MiB`.
+pub(crate) fn display_size(bytes: u64) -> impl Display {
+    lazy_format!(move |f| {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+        if bytes < 1024 {
+            return write!(f, "{} B", bytes);
+        }
+        let mut size = bytes as f64 / 1024.;
+        let mut unit = UNITS[0];
+        for next_unit in &UNITS[1..] {
+            if size < 1024. {
+                break;
+            }
+            size /= 1024.;
+            unit = next_unit;
+        }
+        write!(f, "{:.1} {}", size, unit)
+    })
+}
+
+/// Formats how long ago `time` was, i.e., `3 days ago`.
+pub(crate) fn display_age(time: Option<SystemTime>) -> impl Display {
+    lazy_format!(move |f| {
+        let secs = match time.and_then(|time| SystemTime::now().duration_since(time).ok()) {
+            Some(age) => age.as_secs(),
+            None => return write!(f, "unknown time"),
+        };
+        let (amount, unit) = match secs {
+            0..=59 => (secs, "second"),
+            60..=3599 => (secs / 60, "minute"),
+            3600..=86_399 => (secs / 3600, "hour"),
+            _ => (secs / 86_400, "day"),
+        };
+        write!(
+            f,
+            "{} {}{} ago",
+            amount,
+            unit,
+            if amount == 1 { "" } else { "s" }
+        )
+    })
+}
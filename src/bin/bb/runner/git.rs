@@ -11,19 +11,32 @@
 //
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
-use lifetime::{IntoStatic, ToBorrowed};
+use crate::runner::{ssh_config::SshConfig, RemoteName};
+use anyhow::{anyhow, Context};
+use format::lazy_format;
+use path_clean::PathClean;
+use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
-    convert::Infallible,
+    collections::BTreeMap,
     ffi::OsStr,
     fmt::Debug,
+    num::NonZeroU32,
     path::{Path, PathBuf},
     process::Command,
     str::FromStr,
+    time::{Duration, SystemTime},
 };
 use thiserror::Error as ThisError;
 
-pub use cli::GitCli;
+pub use self::{
+    cli::GitCli,
+    source::{ParsedRepoSource, RepoSource},
+};
+
+pub(crate) mod command_log;
+mod source;
+mod work_tree;
 
 pub trait GitTrait
 where
@@ -44,6 +57,7 @@ where
         path: &Path,
         source: RepoSource<'_>,
         repo_kind: GitRepoKind,
+        depth: Option<NonZeroU32>,
     ) -> Result<(), GitCloneError>;
 
     fn open_repo(&self, options: OpenRepoOptions<'_>) -> Result<Self::Repo, OpenRepoError>;
@@ -53,12 +67,254 @@ pub trait GitRepoTrait {
     type ListFilesIter: Iterator<Item = PathBuf>;
 
     fn run_cmd<T>(&self, cmd: Command, f: impl FnOnce(Command) -> T) -> T;
+    /// The path that `GIT_DIR` is set to for commands run against this repo.
+    fn git_dir_path(&self) -> &Path;
+    /// The path that `GIT_WORK_TREE` is set to for commands run against this repo.
+    fn work_tree_path(&self) -> &Path;
+    /// Gets the Git configuration value at `key`, if it's set.
+    fn get_config(&self, key: &str) -> Result<Option<String>, GitGetConfigError>;
+    /// Sets the Git configuration value at `key` in this repo's local configuration, or unsets it
+    /// if `value` is `None`.
+    fn set_config(&mut self, key: &str, value: Option<&OsStr>) -> Result<(), GitSetConfigError>;
     fn set_excludes_file(&mut self, path: Option<&Path>) -> Result<(), GitSetExcludeFileError>;
     fn set_attributes_file(&mut self, path: Option<&Path>)
         -> Result<(), GitSetAttributesFileError>;
     fn list_files(&self) -> Result<Self::ListFilesIter, GitListFilesError>;
+    /// The object IDs of the blobs staged for each tracked file, keyed by path relative to the
+    /// work tree.
+    fn staged_blob_ids(&self) -> Result<BTreeMap<PathBuf, String>, GitListFilesError>;
+    /// The object IDs that the files at `paths` would have if they were added to this repo, in
+    /// the same order as `paths`.
+    fn hash_files(&self, paths: &[PathBuf]) -> Result<Vec<String>, GitHashObjectError>;
+    fn remotes(&self) -> Result<Vec<(RemoteName<'static>, String)>, GitListRemotesError>;
+    /// Counts the objects in this repo's object database, and the space they take on disk.
+    fn count_objects(&self) -> Result<ObjectCounts, GitObjectStatsError>;
+    /// The `limit` largest blobs in this repo's object database, largest first, including those
+    /// only found in history.
+    fn largest_blobs(&self, limit: usize) -> Result<Vec<BlobInfo>, GitObjectStatsError>;
+    /// The short name of the branch checked out at `HEAD`, or `None` if `HEAD` is detached.
+    fn current_branch(&self) -> Result<Option<String>, GitCurrentBranchError>;
+    fn keep_depth(&self) -> Result<Option<NonZeroU32>, GitKeepDepthConfigError>;
+    fn set_keep_depth(&mut self, depth: Option<NonZeroU32>) -> Result<(), GitKeepDepthConfigError>;
+    /// Discards local history older than `depth` commits, after fetching from the default remote.
+    fn reshallow(&mut self, depth: NonZeroU32) -> Result<(), GitShallowError>;
+    /// Fetches all history missing from a shallow repo. Does nothing if the repo isn't shallow.
+    fn unshallow(&mut self) -> Result<(), GitShallowError>;
+    /// Fetches from the default remote, and reports what was transferred.
+    fn fetch(&mut self) -> Result<TransferStats, GitFetchError>;
+    /// How many commits the branch checked out is ahead and behind its upstream branch, or `None`
+    /// if `HEAD` is detached or the branch has no upstream.
+    fn upstream_divergence(&self) -> Result<Option<(u32, u32)>, GitUpstreamError>;
+    /// The local and remote-tracking branches of this repo, sorted by name, with local branches
+    /// first.
+    fn branches(&self) -> Result<Vec<BranchInfo>, GitBranchesError>;
+    /// The local branches, other than the one checked out, whose commits are all reachable from
+    /// `HEAD`, sorted by name.
+    fn merged_branches(&self) -> Result<Vec<String>, GitBranchesError>;
+    /// Deletes the local branch `name`. Git refuses if it isn't fully merged.
+    fn delete_branch(&mut self, name: &str) -> Result<(), GitDeleteBranchError>;
+    /// The most recent commit on any local branch, or `None` if there are no commits yet.
+    fn last_commit(&self) -> Result<Option<CommitInfo>, GitLastCommitError>;
+    /// Brings changes from the upstream branch into the branch checked out. If this fails partway,
+    /// i.e., because of conflicts, the merge or rebase is aborted.
+    fn integrate_upstream(&mut self, how: Integration) -> Result<(), GitIntegrateError>;
+    /// Stashes uncommitted changes to tracked files, staged or not, with `message`. Untracked files
+    /// are left alone. Returns whether a stash entry was made, which it isn't if Git finds nothing
+    /// it can stash (i.e., when only submodules changed).
+    fn stash(&mut self, message: &str) -> Result<bool, GitStashError>;
+    /// Restores the changes stashed most recently, and drops them from the stash. If they conflict
+    /// with the work tree, they're kept in the stash, and the conflicting paths are reported.
+    fn stash_pop(&mut self) -> Result<(), GitStashPopError>;
     fn reset(&mut self) -> Result<(), GitResetError>;
-    fn restore(&mut self) -> Result<(), GitRestoreError>;
+    /// Restores files in the work tree from the index. If `only` is non-empty, only files matching
+    /// one of its glob patterns, relative to the work tree root, are restored.
+    fn restore(&mut self, only: &[String]) -> Result<(), GitRestoreError>;
+    /// What [`Self::restore`] would change in files that already exist in the work tree, as a
+    /// diffstat, or as a full patch if `patch` is set. Empty if nothing would be overwritten.
+    fn restore_preview(&self, only: &[String], patch: bool) -> Result<String, GitDiffError>;
+    /// Stages the files at `paths`, relative to the work tree root, even if they're ignored.
+    fn add(&mut self, paths: &[PathBuf]) -> Result<(), GitAddError>;
+    /// Stages the changes to every tracked file, including deletions, and returns the paths of
+    /// all files with staged changes, relative to the work tree root.
+    fn stage_tracked_changes(&mut self) -> Result<Vec<PathBuf>, GitAddError>;
+    /// Commits the changes staged in the index with `message`.
+    fn commit(&mut self, message: &str) -> Result<(), GitCommitError>;
+    fn status(&self) -> Result<RepoStatus, GitStatusError>;
+    /// The uncommitted changes to tracked files in the work tree, as a patch.
+    #[cfg(feature = "tui")]
+    fn diff(&self) -> Result<String, GitDiffError>;
+    /// Writes an archive of the tree at `rev` to `output`, with every path prefixed by `prefix`.
+    /// The archive format is inferred from `output`'s extension.
+    fn archive(
+        &self,
+        rev: &str,
+        output: &Path,
+        prefix: Option<&str>,
+    ) -> Result<(), GitArchiveError>;
+}
+
+/// A summary of a repo's working state. See [`GitRepoTrait::status`].
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepoStatus {
+    /// The branch checked out, or `None` if `HEAD` is detached.
+    pub current_branch: Option<String>,
+    pub detached_head: bool,
+    /// The upstream branch of `current_branch`, if it has one.
+    pub upstream: Option<String>,
+    /// Commits on `current_branch` that aren't on `upstream`.
+    pub ahead: u32,
+    /// Commits on `upstream` that aren't on `current_branch`.
+    pub behind: u32,
+    /// Files with changes staged in the index.
+    pub staged: u32,
+    /// Tracked files with changes in the work tree that aren't staged, including files with merge
+    /// conflicts.
+    pub unstaged: u32,
+    /// Untracked files that aren't ignored. These are only counted if the repo is configured to
+    /// show them, which overlay repos aren't by default.
+    pub untracked: u32,
+}
+
+impl RepoStatus {
+    /// Whether tracked files have uncommitted changes, staged or not.
+    pub fn has_uncommitted_changes(&self) -> bool {
+        self.staged > 0 || self.unstaged > 0
+    }
+
+    /// Parses the output of `git status --porcelain=v2 --branch`.
+    fn parse_porcelain_v2(output: &str) -> anyhow::Result<Self> {
+        let mut status = Self {
+            current_branch: None,
+            detached_head: false,
+            upstream: None,
+            ahead: 0,
+            behind: 0,
+            staged: 0,
+            unstaged: 0,
+            untracked: 0,
+        };
+        for line in output.lines() {
+            let malformed = || anyhow!("malformed status line {:?}", line);
+            let (kind, rest) = line.split_once(' ').with_context(malformed)?;
+            match kind {
+                "#" => {
+                    let (header, value) = rest.split_once(' ').with_context(malformed)?;
+                    match header {
+                        "branch.head" if value == "(detached)" => status.detached_head = true,
+                        "branch.head" => status.current_branch = Some(value.to_owned()),
+                        "branch.upstream" => status.upstream = Some(value.to_owned()),
+                        "branch.ab" => {
+                            let (ahead, behind) = value.split_once(' ').with_context(malformed)?;
+                            let parse = |count: Option<&str>| {
+                                count
+                                    .and_then(|count| count.parse::<u32>().ok())
+                                    .with_context(malformed)
+                            };
+                            status.ahead = parse(ahead.strip_prefix('+'))?;
+                            status.behind = parse(behind.strip_prefix('-'))?;
+                        }
+                        _ => (),
+                    }
+                }
+                // Ordinary and renamed or copied entries start with two characters for the index
+                // and work tree statuses, where `.` means unchanged.
+                "1" | "2" => {
+                    let mut xy = rest.chars();
+                    let (x, y) = (xy.next(), xy.next());
+                    if x.is_none() || y.is_none() {
+                        return Err(malformed());
+                    }
+                    if x != Some('.') {
+                        status.staged += 1;
+                    }
+                    if y != Some('.') {
+                        status.unstaged += 1;
+                    }
+                }
+                "u" => status.unstaged += 1,
+                "?" => status.untracked += 1,
+                _ => (),
+            }
+        }
+        Ok(status)
+    }
+}
+
+/// See [`GitRepoTrait::count_objects`].
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectCounts {
+    /// The number of loose and packed objects.
+    pub objects: u64,
+    /// The space taken by loose objects and packs, in bytes.
+    pub size_on_disk: u64,
+}
+
+/// What a fetch transferred. See [`GitRepoTrait::fetch`].
+///
+/// The CLI backend can't observe the transfer itself, so it estimates these from how much the
+/// object database grew.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TransferStats {
+    pub objects: u64,
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+/// See [`GitRepoTrait::largest_blobs`].
+#[derive(Clone, Debug)]
+pub struct BlobInfo {
+    pub id: String,
+    /// The uncompressed size of the blob, in bytes.
+    pub size: u64,
+    /// A path this blob was committed at, if it's reachable from any ref.
+    pub path: Option<PathBuf>,
+}
+
+/// See [`GitRepoTrait::branches`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BranchInfo {
+    /// The branch's name, i.e., `main`, or `origin/main` for a remote-tracking branch.
+    pub name: String,
+    pub remote: bool,
+    /// Whether this is the branch checked out.
+    pub current: bool,
+    /// The upstream branch of a local branch, named like `name`, if it has one.
+    pub upstream: Option<String>,
+    /// How many commits this branch is ahead of and behind `upstream`, unless `upstream` is
+    /// missing or gone.
+    pub divergence: Option<(u32, u32)>,
+    /// When the commit this branch points at was made, according to its committer.
+    pub last_commit_time: SystemTime,
+}
+
+/// See [`GitRepoTrait::last_commit`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommitInfo {
+    /// The full object name of the commit.
+    pub hash: String,
+    pub author: String,
+    /// When the commit was made, according to its committer.
+    pub time: SystemTime,
+    /// The first line of the commit message.
+    pub subject: String,
+}
+
+impl CommitInfo {
+    /// An abbreviation of `hash`, for display.
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+}
+
+/// See [`GitRepoTrait::integrate_upstream`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Integration {
+    FastForward,
+    Merge,
+    Rebase {
+        /// Stash uncommitted changes before rebasing, and restore them afterwards.
+        autostash: bool,
+    },
 }
 
 pub enum OpenRepoOptions<'a> {
@@ -105,9 +361,10 @@ impl GitTrait for DynGit {
         path: &Path,
         source: RepoSource<'_>,
         repo_kind: GitRepoKind,
+        depth: Option<NonZeroU32>,
     ) -> Result<(), GitCloneError> {
         match self {
-            Self::Cli(cli) => cli.clone(path, source, repo_kind),
+            Self::Cli(cli) => cli.clone(path, source, repo_kind, depth),
         }
     }
 
@@ -127,6 +384,30 @@ impl GitRepoTrait for DynGitRepo {
         }
     }
 
+    fn git_dir_path(&self) -> &Path {
+        match self {
+            Self::Cli(cli) => cli.git_dir_path(),
+        }
+    }
+
+    fn work_tree_path(&self) -> &Path {
+        match self {
+            Self::Cli(cli) => cli.work_tree_path(),
+        }
+    }
+
+    fn get_config(&self, key: &str) -> Result<Option<String>, GitGetConfigError> {
+        match self {
+            Self::Cli(cli) => cli.get_config(key),
+        }
+    }
+
+    fn set_config(&mut self, key: &str, value: Option<&OsStr>) -> Result<(), GitSetConfigError> {
+        match self {
+            Self::Cli(cli) => cli.set_config(key, value),
+        }
+    }
+
     fn set_excludes_file(&mut self, path: Option<&Path>) -> Result<(), GitSetExcludeFileError> {
         match self {
             Self::Cli(cli) => cli.set_excludes_file(path),
@@ -148,34 +429,178 @@ impl GitRepoTrait for DynGitRepo {
         }
     }
 
+    fn staged_blob_ids(&self) -> Result<BTreeMap<PathBuf, String>, GitListFilesError> {
+        match self {
+            Self::Cli(cli) => cli.staged_blob_ids(),
+        }
+    }
+
+    fn hash_files(&self, paths: &[PathBuf]) -> Result<Vec<String>, GitHashObjectError> {
+        match self {
+            Self::Cli(cli) => cli.hash_files(paths),
+        }
+    }
+
+    fn current_branch(&self) -> Result<Option<String>, GitCurrentBranchError> {
+        match self {
+            Self::Cli(cli) => cli.current_branch(),
+        }
+    }
+
+    fn remotes(&self) -> Result<Vec<(RemoteName<'static>, String)>, GitListRemotesError> {
+        match self {
+            Self::Cli(cli) => cli.remotes(),
+        }
+    }
+
+    fn count_objects(&self) -> Result<ObjectCounts, GitObjectStatsError> {
+        match self {
+            Self::Cli(cli) => cli.count_objects(),
+        }
+    }
+
+    fn largest_blobs(&self, limit: usize) -> Result<Vec<BlobInfo>, GitObjectStatsError> {
+        match self {
+            Self::Cli(cli) => cli.largest_blobs(limit),
+        }
+    }
+
+    fn keep_depth(&self) -> Result<Option<NonZeroU32>, GitKeepDepthConfigError> {
+        match self {
+            Self::Cli(cli) => cli.keep_depth(),
+        }
+    }
+
+    fn set_keep_depth(&mut self, depth: Option<NonZeroU32>) -> Result<(), GitKeepDepthConfigError> {
+        match self {
+            Self::Cli(cli) => cli.set_keep_depth(depth),
+        }
+    }
+
+    fn reshallow(&mut self, depth: NonZeroU32) -> Result<(), GitShallowError> {
+        match self {
+            Self::Cli(cli) => cli.reshallow(depth),
+        }
+    }
+
+    fn unshallow(&mut self) -> Result<(), GitShallowError> {
+        match self {
+            Self::Cli(cli) => cli.unshallow(),
+        }
+    }
+
+    fn fetch(&mut self) -> Result<TransferStats, GitFetchError> {
+        match self {
+            Self::Cli(cli) => cli.fetch(),
+        }
+    }
+
+    fn upstream_divergence(&self) -> Result<Option<(u32, u32)>, GitUpstreamError> {
+        match self {
+            Self::Cli(cli) => cli.upstream_divergence(),
+        }
+    }
+
+    fn branches(&self) -> Result<Vec<BranchInfo>, GitBranchesError> {
+        match self {
+            Self::Cli(cli) => cli.branches(),
+        }
+    }
+
+    fn merged_branches(&self) -> Result<Vec<String>, GitBranchesError> {
+        match self {
+            Self::Cli(cli) => cli.merged_branches(),
+        }
+    }
+
+    fn delete_branch(&mut self, name: &str) -> Result<(), GitDeleteBranchError> {
+        match self {
+            Self::Cli(cli) => cli.delete_branch(name),
+        }
+    }
+
+    fn last_commit(&self) -> Result<Option<CommitInfo>, GitLastCommitError> {
+        match self {
+            Self::Cli(cli) => cli.last_commit(),
+        }
+    }
+
+    fn integrate_upstream(&mut self, how: Integration) -> Result<(), GitIntegrateError> {
+        match self {
+            Self::Cli(cli) => cli.integrate_upstream(how),
+        }
+    }
+
+    fn stash(&mut self, message: &str) -> Result<bool, GitStashError> {
+        match self {
+            Self::Cli(cli) => cli.stash(message),
+        }
+    }
+
+    fn stash_pop(&mut self) -> Result<(), GitStashPopError> {
+        match self {
+            Self::Cli(cli) => cli.stash_pop(),
+        }
+    }
+
     fn reset(&mut self) -> Result<(), GitResetError> {
         match self {
             Self::Cli(cli) => cli.reset(),
         }
     }
 
-    fn restore(&mut self) -> Result<(), GitRestoreError> {
+    fn restore(&mut self, only: &[String]) -> Result<(), GitRestoreError> {
         match self {
-            Self::Cli(cli) => cli.restore(),
+            Self::Cli(cli) => cli.restore(only),
         }
     }
-}
 
-#[derive(Clone, Debug, ToBorrowed, IntoStatic)]
-pub struct RepoSource<'a>(Cow<'a, str>);
+    fn restore_preview(&self, only: &[String], patch: bool) -> Result<String, GitDiffError> {
+        match self {
+            Self::Cli(cli) => cli.restore_preview(only, patch),
+        }
+    }
 
-impl AsRef<OsStr> for RepoSource<'_> {
-    fn as_ref(&self) -> &OsStr {
-        let Self(inner) = self;
-        OsStr::new(inner.as_ref())
+    fn add(&mut self, paths: &[PathBuf]) -> Result<(), GitAddError> {
+        match self {
+            Self::Cli(cli) => cli.add(paths),
+        }
     }
-}
 
-impl FromStr for RepoSource<'static> {
-    type Err = Infallible;
+    fn stage_tracked_changes(&mut self) -> Result<Vec<PathBuf>, GitAddError> {
+        match self {
+            Self::Cli(cli) => cli.stage_tracked_changes(),
+        }
+    }
+
+    fn commit(&mut self, message: &str) -> Result<(), GitCommitError> {
+        match self {
+            Self::Cli(cli) => cli.commit(message),
+        }
+    }
+
+    fn status(&self) -> Result<RepoStatus, GitStatusError> {
+        match self {
+            Self::Cli(cli) => cli.status(),
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    fn diff(&self) -> Result<String, GitDiffError> {
+        match self {
+            Self::Cli(cli) => cli.diff(),
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(Cow::Owned(s.to_string())))
+    fn archive(
+        &self,
+        rev: &str,
+        output: &Path,
+        prefix: Option<&str>,
+    ) -> Result<(), GitArchiveError> {
+        match self {
+            Self::Cli(cli) => cli.archive(rev, output, prefix),
+        }
     }
 }
 
@@ -216,18 +641,98 @@ pub struct GitCloneError {
     source: Option<anyhow::Error>,
 }
 
-const EXCLUDES_FILE_CONFIG_PATH: &str = "core.excludesFile";
+#[derive(Debug, ThisError)]
+#[error("failed to get `{key}` config")]
+pub struct GitGetConfigError {
+    key: String,
+    source: anyhow::Error,
+}
+
+#[derive(Debug, ThisError)]
+#[error("failed to get current branch")]
+pub struct GitCurrentBranchError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to set `{key}` config")]
+pub struct GitSetConfigError {
+    key: String,
+    source: anyhow::Error,
+}
+
+pub(crate) const EXCLUDES_FILE_CONFIG_PATH: &str = "core.excludesFile";
 
 #[derive(Debug, ThisError)]
 #[error("failed to set `{}` config", EXCLUDES_FILE_CONFIG_PATH)]
 pub struct GitSetExcludeFileError(#[from] anyhow::Error);
 
-const ATTRIBUTES_FILE_CONFIG_PATH: &str = "core.attributesFile";
+pub(crate) const ATTRIBUTES_FILE_CONFIG_PATH: &str = "core.attributesFile";
 
 #[derive(Debug, ThisError)]
 #[error("failed to set `{}` config", ATTRIBUTES_FILE_CONFIG_PATH)]
 pub struct GitSetAttributesFileError(#[from] anyhow::Error);
 
+/// Where Git looks for hooks, instead of the Git directory's `hooks` directory.
+pub(crate) const HOOKS_PATH_CONFIG_PATH: &str = "core.hooksPath";
+
+const KEEP_DEPTH_CONFIG_PATH: &str = "bellboy.keepDepth";
+
+#[derive(Debug, ThisError)]
+#[error("failed to access `{}` config", KEEP_DEPTH_CONFIG_PATH)]
+pub struct GitKeepDepthConfigError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to change depth of shallow history")]
+pub struct GitShallowError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to fetch")]
+pub struct GitFetchError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to compare with upstream branch")]
+pub struct GitUpstreamError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to find most recent commit")]
+pub struct GitLastCommitError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to list branches")]
+pub struct GitBranchesError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to delete branch {name:?}")]
+pub struct GitDeleteBranchError {
+    name: String,
+    source: anyhow::Error,
+}
+
+#[derive(Debug, ThisError)]
+#[error("failed to integrate changes from upstream branch")]
+pub struct GitIntegrateError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to stash uncommitted changes")]
+pub struct GitStashError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+pub enum GitStashPopError {
+    #[error(
+        "restoring stashed changes conflicted in {}; they're kept in the stash until the \
+        conflicts are resolved and `git stash drop` is run",
+        lazy_format!(|f| {
+            let mut paths = conflicts.iter();
+            if let Some(first) = paths.next() {
+                write!(f, "{}", first.display())?;
+            }
+            paths.try_for_each(|path| write!(f, ", {}", path.display()))
+        })
+    )]
+    Conflicts { conflicts: Vec<PathBuf> },
+    #[error("failed to restore stashed changes; they're kept in the stash")]
+    Other(#[from] anyhow::Error),
+}
+
 #[derive(Debug, ThisError)]
 #[error("failed to open repo at {}", path.display())]
 pub struct OpenRepoError {
@@ -241,6 +746,24 @@ pub struct GitListFilesError {
     source: anyhow::Error,
 }
 
+#[derive(Debug, ThisError)]
+#[error("failed to hash files")]
+pub struct GitHashObjectError {
+    source: anyhow::Error,
+}
+
+#[derive(Debug, ThisError)]
+#[error("failed to gather object statistics")]
+pub struct GitObjectStatsError {
+    source: anyhow::Error,
+}
+
+#[derive(Debug, ThisError)]
+#[error("failed to list remotes")]
+pub struct GitListRemotesError {
+    source: anyhow::Error,
+}
+
 #[derive(Debug, ThisError)]
 #[error("failed to discard staged changes")]
 pub struct GitResetError {
@@ -255,6 +778,124 @@ pub struct GitRestoreError {
     source: anyhow::Error,
 }
 
+#[derive(Debug, ThisError)]
+#[error("failed to stage files")]
+pub struct GitAddError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to commit")]
+pub struct GitCommitError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to get status")]
+pub struct GitStatusError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to get diff")]
+pub struct GitDiffError(#[from] anyhow::Error);
+
+#[derive(Debug, ThisError)]
+#[error("failed to archive {rev:?} to {}", output.display())]
+pub struct GitArchiveError {
+    rev: String,
+    output: PathBuf,
+    source: anyhow::Error,
+}
+
+/// Picks the URL of the remote most likely to be a repo's upstream from `remotes`: `origin` if it
+/// exists, or else the first remote.
+pub fn default_remote_url<'a>(remotes: &'a [(RemoteName<'_>, String)]) -> Option<&'a str> {
+    remotes
+        .iter()
+        .find(|(RemoteName(name), _url)| name == "origin")
+        .or_else(|| remotes.first())
+        .map(|(_name, url)| url.as_str())
+}
+
+/// Returns whether `e` is one of the errors produced by this module's operations.
+pub(crate) fn is_git_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.is::<GitExistError>()
+        || e.is::<GitInitError>()
+        || e.is::<GitCloneError>()
+        || e.is::<GitGetConfigError>()
+        || e.is::<GitCurrentBranchError>()
+        || e.is::<GitSetConfigError>()
+        || e.is::<GitSetExcludeFileError>()
+        || e.is::<GitSetAttributesFileError>()
+        || e.is::<GitKeepDepthConfigError>()
+        || e.is::<GitShallowError>()
+        || e.is::<OpenRepoError>()
+        || e.is::<GitListFilesError>()
+        || e.is::<GitHashObjectError>()
+        || e.is::<GitObjectStatsError>()
+        || e.is::<GitFetchError>()
+        || e.is::<GitUpstreamError>()
+        || e.is::<GitLastCommitError>()
+        || e.is::<GitBranchesError>()
+        || e.is::<GitDeleteBranchError>()
+        || e.is::<GitIntegrateError>()
+        || e.is::<GitListRemotesError>()
+        || e.is::<GitResetError>()
+        || e.is::<GitRestoreError>()
+        || e.is::<GitStatusError>()
+        || e.is::<GitDiffError>()
+        || e.is::<GitArchiveError>()
+}
+
+/// Normalizes a remote URL so that different spellings of the same remote compare equal.
+///
+/// For example, `git@github.com:foo/bar.git`, `ssh://git@github.com/foo/bar`, and
+/// `https://github.com/foo/bar/` all normalize to `github.com/foo/bar`, as does `gh:foo/bar` if
+/// `gh` is an alias for `github.com` in `ssh`. Ports are kept unless they're the default for the
+/// URL's scheme, since self-hosted servers may serve different instances on different ports, so
+/// `ssh://git@host:2222/foo/bar` normalizes to `host:2222/foo/bar`. Anything without a host (i.e.,
+/// a local path) is returned with only trailing separators trimmed.
+pub(crate) fn normalize_remote_url<'a>(url: &'a str, ssh: &SshConfig) -> Cow<'a, str> {
+    let url = url.trim();
+    let trimmed = || url.trim_end_matches(['/', '\\']).into();
+    let source = match RepoSource::from_str(url) {
+        Ok(source) => source,
+        Err(_e) => return trimmed(),
+    };
+    let (host, path) = match (source.resolved_host(ssh), source.parsed()) {
+        (Some(host), ParsedRepoSource::Url { path, .. } | ParsedRepoSource::Scp { path, .. }) => {
+            (host, path)
+        }
+        _ => return trimmed(),
+    };
+    let path = path.trim_matches('/');
+    let path = path
+        .strip_suffix(".git")
+        .unwrap_or(path)
+        .trim_end_matches('/');
+    let host = host.to_lowercase();
+    match source.non_default_port() {
+        Some(port) => format!("{}:{}/{}", host, port, path).into(),
+        None => format!("{}/{}", host, path).into(),
+    }
+}
+
+/// Finds the Git directory of the non-bare repo whose work tree is at `work_tree_path`.
+///
+/// This is usually `.git` in the work tree, but linked work trees (made with `git worktree add`)
+/// and submodules have a `.git` file instead, containing a `gitdir: <PATH>` line that points to
+/// the real Git directory. Relative paths in it are relative to the work tree.
+fn resolve_git_dir(work_tree_path: &Path) -> anyhow::Result<PathBuf> {
+    let dot_git = work_tree_path.join(".git");
+    if !dot_git.is_file() {
+        return Ok(dot_git);
+    }
+    let contents = std::fs::read_to_string(&dot_git)
+        .with_context(|| anyhow!("failed to read Git directory redirect {:?}", dot_git))?;
+    let git_dir = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))
+        .map(str::trim)
+        .filter(|git_dir| !git_dir.is_empty())
+        .with_context(|| anyhow!("{:?} has no `gitdir:` line", dot_git))?;
+    Ok(work_tree_path.join(git_dir).clean())
+}
+
 fn prep_cmd(cmd: &mut Command, git_work_tree_path: &Path, git_dir_path: &Path) {
     cmd.envs([
         ("GIT_WORK_TREE", git_work_tree_path.as_os_str()),
@@ -264,27 +905,193 @@ fn prep_cmd(cmd: &mut Command, git_work_tree_path: &Path, git_dir_path: &Path) {
 
 mod cli {
     use super::{
-        prep_cmd, GitCloneError, GitExistCheckFailure, GitExistError, GitInitError,
-        GitListFilesError, GitRepoKind, GitRepoTrait, GitResetError, GitRestoreError,
-        GitSetExcludeFileError, GitTrait, OpenRepoError, OpenRepoOptions, RepoSource,
-        ATTRIBUTES_FILE_CONFIG_PATH, EXCLUDES_FILE_CONFIG_PATH,
-    };
-    use crate::runner::{
-        canonicalize_path, cmd_failure_err, cmd_failure_res,
-        dirs::{current_dir, set_current_dir},
+        command_log, prep_cmd, resolve_git_dir, work_tree, BlobInfo, BranchInfo, CommitInfo,
+        GitAddError, GitArchiveError, GitBranchesError, GitCloneError, GitCommitError,
+        GitCurrentBranchError, GitDeleteBranchError, GitDiffError, GitExistCheckFailure,
+        GitExistError, GitFetchError, GitGetConfigError, GitHashObjectError, GitInitError,
+        GitIntegrateError, GitKeepDepthConfigError, GitLastCommitError, GitListFilesError,
+        GitListRemotesError, GitObjectStatsError, GitRepoKind, GitRepoTrait, GitResetError,
+        GitRestoreError, GitSetConfigError, GitSetExcludeFileError, GitShallowError, GitStashError,
+        GitStashPopError, GitStatusError, GitTrait, GitUpstreamError, Integration, ObjectCounts,
+        OpenRepoError, OpenRepoOptions, ParsedRepoSource, RepoSource, RepoStatus, TransferStats,
+        ATTRIBUTES_FILE_CONFIG_PATH, EXCLUDES_FILE_CONFIG_PATH, KEEP_DEPTH_CONFIG_PATH,
     };
-    use anyhow::{anyhow, ensure, Context};
+    use crate::runner::{cmd_failure_err, cmd_failure_res, gc::dir_size, RemoteName};
+    use anyhow::{anyhow, bail, ensure, Context};
     use std::{
-        ffi::OsStr,
-        io::{BufRead, Cursor},
+        cmp::Reverse,
+        collections::BTreeMap,
+        ffi::{OsStr, OsString},
+        fs,
+        num::NonZeroU32,
         path::{Path, PathBuf},
         process::{Command, Output, Stdio},
+        time::{Duration, Instant, SystemTime},
     };
 
     // TODO: use `GIT_REFLOG_ACTION` for logging niceness
 
-    #[derive(Debug)]
-    pub struct GitCli;
+    /// The pathspecs selecting the files that `restore` touches, given its `only` patterns.
+    fn restore_pathspecs(only: &[String]) -> Vec<String> {
+        // Pathspecs are otherwise relative to the current directory, and don't expand `**`.
+        if only.is_empty() {
+            vec![":(top)".to_owned()]
+        } else {
+            only.iter()
+                .map(|pattern| format!(":(top,glob){}", pattern))
+                .collect()
+        }
+    }
+
+    /// Rejects a `value` passed to Git that it could mistake for an option. Arguments should be
+    /// placed after `--` or `--end-of-options` where possible; this is for when that isn't enough.
+    fn ensure_not_option(what: &str, value: &OsStr) -> anyhow::Result<()> {
+        ensure!(
+            !value.to_string_lossy().starts_with('-'),
+            "{} {:?} looks like a command-line option",
+            what,
+            value
+        );
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    pub struct GitCli {
+        /// Where mirrors of cloned sources are kept, if anywhere; see [`Self::with_clone_cache`].
+        clone_cache_dir: Option<PathBuf>,
+        /// How many bytes the mirrors in `clone_cache_dir` may take up, if limited.
+        clone_cache_max_size: Option<u64>,
+    }
+
+    impl GitCli {
+        /// Keeps a mirror of each remote source cloned in `dir`, and borrows objects from it
+        /// when the same source is cloned again. If `max_size` is set, the least recently updated
+        /// mirrors are deleted whenever the cache grows past it.
+        pub fn with_clone_cache(dir: PathBuf, max_size: Option<u64>) -> Self {
+            Self {
+                clone_cache_dir: Some(dir),
+                clone_cache_max_size: max_size,
+            }
+        }
+
+        /// Brings the mirror of `source` in the clone cache at `cache_dir` up to date, creating it
+        /// if there's none yet, and returns its path.
+        fn update_mirror(cache_dir: &Path, source: &RepoSource<'_>) -> anyhow::Result<PathBuf> {
+            let file_name = source
+                .as_ref()
+                .to_string_lossy()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || matches!(c, '.' | '-') {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>();
+            let mirror_path = cache_dir.join(format!("{}.git", file_name));
+            let run = |mut cmd: Command| -> anyhow::Result<()> {
+                cmd.env_remove("GIT_DIR")
+                    .env_remove("GIT_WORK_TREE")
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::inherit());
+                command_log::log(&cmd);
+                cmd.status()
+                    .context("failed to spawn command")
+                    .and_then(cmd_failure_res)
+            };
+
+            if mirror_path.join("HEAD").is_file() {
+                // Different sources may map to the same name, so check that this is ours.
+                let mut cmd = Command::new("git");
+                cmd.arg("--git-dir").arg(&mirror_path).args([
+                    "config",
+                    "--get",
+                    "remote.origin.url",
+                ]);
+                command_log::log(&cmd);
+                let output = cmd.output().context("failed to spawn command")?;
+                let url = String::from_utf8_lossy(&output.stdout);
+                ensure!(
+                    url.trim_end_matches(['\r', '\n']) == source.as_ref().to_string_lossy(),
+                    "mirror {:?} is of another source, {:?}",
+                    mirror_path,
+                    url.trim_end()
+                );
+                tracing::debug!("updating mirror {:?}", mirror_path);
+                let mut cmd = Command::new("git");
+                cmd.arg("--git-dir")
+                    .arg(&mirror_path)
+                    .args(["remote", "update", "--prune"]);
+                run(cmd).context("failed to update mirror")?;
+            } else {
+                tracing::debug!("creating mirror {:?}", mirror_path);
+                fs::create_dir_all(cache_dir)
+                    .with_context(|| anyhow!("failed to create directory {:?}", cache_dir))?;
+                // Clone next to the mirror and move it into place, so that an interrupted clone
+                // isn't mistaken for a mirror, including by other processes sharing the cache.
+                let temp_path = cache_dir.join(format!("{}.tmp-{}", file_name, std::process::id()));
+                let mut cmd = Command::new("git");
+                cmd.args(["clone", "--mirror", "--quiet", "--"])
+                    .arg(source)
+                    .arg(&temp_path);
+                let res = run(cmd).context("failed to create mirror").and_then(|()| {
+                    fs::rename(&temp_path, &mirror_path)
+                        .with_context(|| anyhow!("failed to move mirror to {:?}", mirror_path))
+                });
+                if let Err(e) = res {
+                    let _ = remove_dir_all::remove_dir_all(&temp_path);
+                    // Another process sharing the cache may have created the mirror first.
+                    if !mirror_path.join("HEAD").is_file() {
+                        return Err(e);
+                    }
+                }
+            }
+            Ok(mirror_path)
+        }
+
+        /// Deletes mirrors in the clone cache at `cache_dir`, least recently updated first, until
+        /// it takes up at most `max_size` bytes. The mirror at `keep` is never deleted.
+        fn prune_mirrors(cache_dir: &Path, keep: &Path, max_size: u64) -> anyhow::Result<()> {
+            let mut mirrors = Vec::new();
+            let mut total_size = 0;
+            let entries = fs::read_dir(cache_dir)
+                .with_context(|| anyhow!("failed to read directory {:?}", cache_dir))?;
+            for entry in entries {
+                let path = entry.context("failed to read directory entry")?.path();
+                if !path.is_dir() {
+                    continue;
+                }
+                let size =
+                    dir_size(&path).with_context(|| anyhow!("failed to measure {:?}", path))?;
+                total_size += size;
+                if path == keep {
+                    continue;
+                }
+                // Fetches touch `FETCH_HEAD`, but a mirror that was never updated won't have one.
+                let updated = ["FETCH_HEAD", "HEAD"]
+                    .iter()
+                    .find_map(|file| {
+                        fs::metadata(path.join(file))
+                            .and_then(|m| m.modified())
+                            .ok()
+                    })
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                mirrors.push((updated, size, path));
+            }
+            mirrors.sort();
+            for (_updated, size, path) in mirrors {
+                if total_size <= max_size {
+                    break;
+                }
+                tracing::info!("deleting mirror {:?} to keep clone cache small", path);
+                remove_dir_all::remove_dir_all(&path)
+                    .with_context(|| anyhow!("failed to delete mirror {:?}", path))?;
+                total_size -= size;
+            }
+            Ok(())
+        }
+    }
 
     #[derive(Debug)]
     pub struct GitCliRepo {
@@ -306,61 +1113,66 @@ mod cli {
                 source,
             };
 
+            // Only ask Git about directories that look like repos. Git has no dedicated exit code
+            // for "not a repo", and would otherwise find repos in parent directories.
+            if !path.join(".git").exists() && !path.join("HEAD").is_file() {
+                return Ok(Err(GitExistCheckFailure {
+                    expected: expected_repo_kind,
+                    actual: None,
+                }));
+            }
+
+            // The ceiling keeps Git from looking above `path` if `path` turns out not to be a repo
+            // after all, i.e., a directory that happens to contain a `HEAD` file.
+            let mut cmd = Command::new("git");
+            cmd.arg("-C")
+                .arg(path)
+                .args(["rev-parse", "--is-bare-repository"])
+                .env_remove("GIT_DIR")
+                .env_remove("GIT_WORK_TREE");
+            if let Some(parent) = path.parent() {
+                cmd.env("GIT_CEILING_DIRECTORIES", parent);
+            }
+            command_log::log(&cmd);
             let Output {
                 stdout,
                 stderr,
                 status,
-            } = Command::new("git")
-                .args::<_, &OsStr>([
-                    "-C".as_ref(),
-                    path.as_ref(),
-                    "rev-parse".as_ref(),
-                    "--is-bare-repository".as_ref(),
-                ])
-                .output()
-                .map_err(|e| {
-                    err(
-                        "unable to spawn command".into(),
-                        Some(anyhow::Error::new(e)),
-                    )
-                })?;
-
-            let parse_std = |channel_name, channel| {
-                String::from_utf8(channel).map_err(|e| {
-                    err(
-                        format!("failed to parse `rev-parse`'s `{}` as UTF-8", channel_name,)
-                            .into(),
-                        Some(anyhow::Error::new(e)),
-                    )
-                })
-            };
-
-            let stderr = parse_std("stderr", stderr)?;
+            } = cmd.output().map_err(|e| {
+                err(
+                    "unable to spawn command".into(),
+                    Some(anyhow::Error::new(e)),
+                )
+            })?;
+            if !status.success() {
+                tracing::debug!(
+                    "{} is not a repo: {}",
+                    path.display(),
+                    String::from_utf8_lossy(&stderr).trim_end()
+                );
+                return Ok(Err(GitExistCheckFailure {
+                    expected: expected_repo_kind,
+                    actual: None,
+                }));
+            }
 
-            let actual = if status.code() == Some(128) && stderr.contains("not a git repository") {
-                // TODO: how to make this `None` check more stable?
-                None
-            } else if let Some(err_msg) = cmd_failure_err(status) {
-                return Err(err(err_msg, None));
-            } else {
-                let found = parse_std("stdout", stdout)?
-                    .trim()
-                    .parse::<bool>()
-                    .map(|b| {
-                        if b {
-                            GitRepoKind::Bare
-                        } else {
-                            GitRepoKind::Normal
-                        }
-                    })
-                    .map_err(|e| {
-                        err(
-                            "failed to parse `rev-parse` response as a boolean literal".into(),
-                            Some(anyhow::Error::new(e)),
-                        )
-                    })?;
-                Some(found)
+            let stdout = String::from_utf8(stdout).map_err(|e| {
+                err(
+                    "failed to parse `rev-parse`'s output as UTF-8".into(),
+                    Some(anyhow::Error::new(e)),
+                )
+            })?;
+            let actual = match stdout.trim_end_matches(['\r', '\n']).parse::<bool>() {
+                Ok(true) => GitRepoKind::Bare,
+                Ok(false) => GitRepoKind::Normal,
+                Err(e) => {
+                    return Err(err(
+                        "failed to parse `rev-parse` response as a boolean literal".into(),
+                        Some(anyhow::Error::new(e)),
+                    ))
+                }
             };
+            let actual = Some(actual);
 
             Ok(if Some(expected_repo_kind) == actual {
                 Ok(())
@@ -379,14 +1191,16 @@ mod cli {
                 source,
             };
             let mut git_cmd = Command::new("git");
-            git_cmd.args::<_, &OsStr>(["init".as_ref(), path.as_ref()]);
+            git_cmd.arg("init");
             match repo_kind {
                 GitRepoKind::Normal => (),
                 GitRepoKind::Bare => {
                     git_cmd.arg("--bare");
                 }
             }
+            git_cmd.arg("--").arg(path);
 
+            command_log::log(&git_cmd);
             let status = git_cmd
                 .status()
                 .map_err(|e| err("spawn command".into(), Some(anyhow::Error::new(e))))?;
@@ -403,6 +1217,7 @@ mod cli {
             path: &Path,
             source: RepoSource<'_>,
             repo_kind: GitRepoKind,
+            depth: Option<NonZeroU32>,
         ) -> Result<(), GitCloneError> {
             let err = |op, source| GitCloneError {
                 op,
@@ -410,15 +1225,52 @@ mod cli {
                 source,
             };
 
+            // `--` below already keeps Git from taking `source` as an option, but a source that
+            // looks like one is almost certainly a mistake.
+            ensure_not_option("repo source", source.as_ref())
+                .and_then(|()| source.ensure_local_path_exists())
+                .map_err(|e| err("validate source".into(), Some(e)))?;
+
             let mut git_cmd = Command::new("git");
-            git_cmd.args::<_, &OsStr>(["clone".as_ref(), source.as_ref(), path.as_ref()]);
+            git_cmd.arg("clone");
             match repo_kind {
                 GitRepoKind::Normal => (),
                 GitRepoKind::Bare => {
                     git_cmd.arg("--bare");
                 }
             }
+            if let Some(depth) = depth {
+                git_cmd.arg(format!("--depth={}", depth));
+            }
+            // Local sources are cloned with hard links already, so a mirror wouldn't help.
+            let cacheable = !matches!(source.parsed(), ParsedRepoSource::Local(_));
+            if let Some(cache_dir) = self.clone_cache_dir.as_deref().filter(|_| cacheable) {
+                match Self::update_mirror(cache_dir, &source) {
+                    Ok(mirror_path) => {
+                        if let Some(max_size) = self.clone_cache_max_size {
+                            if let Err(e) = Self::prune_mirrors(cache_dir, &mirror_path, max_size) {
+                                tracing::warn!(
+                                    "{:?}",
+                                    e.context("failed to keep clone cache within its size limit")
+                                );
+                            }
+                        }
+                        // Copy the objects borrowed from the mirror, so that the clone doesn't
+                        // depend on it.
+                        git_cmd
+                            .arg("--reference-if-able")
+                            .arg(mirror_path)
+                            .arg("--dissociate");
+                    }
+                    Err(e) => tracing::warn!(
+                        "{:?}",
+                        e.context(anyhow!("not using clone cache for {:?}", source))
+                    ),
+                }
+            }
+            git_cmd.arg("--").arg(&source).arg(path);
 
+            command_log::log(&git_cmd);
             let status = git_cmd
                 .status()
                 .map_err(|e| err("spawn command".into(), Some(anyhow::Error::new(e))))?;
@@ -452,8 +1304,14 @@ mod cli {
                     work_tree_path: work_tree_path.to_owned(),
                 }),
                 OpenRepoOptions::Normal { work_tree_path } => {
-                    exists(work_tree_path, GitRepoKind::Normal).map(|()| GitCliRepo {
-                        repo_path: work_tree_path.join(".git"),
+                    exists(work_tree_path, GitRepoKind::Normal)?;
+                    let repo_path =
+                        resolve_git_dir(work_tree_path).map_err(|source| OpenRepoError {
+                            path: work_tree_path.to_owned(),
+                            source,
+                        })?;
+                    Ok(GitCliRepo {
+                        repo_path,
                         work_tree_path: work_tree_path.to_owned(),
                     })
                 }
@@ -466,24 +1324,94 @@ mod cli {
             Command::new("git")
         }
 
+        /// Like [`GitRepoTrait::run_cmd`], but logs `cmd` first; see [`command_log`].
+        fn run_git<T>(&self, mut cmd: Command, f: impl FnOnce(Command) -> T) -> T {
+            let Self {
+                work_tree_path,
+                repo_path,
+            } = self;
+            prep_cmd(&mut cmd, work_tree_path, repo_path);
+            command_log::log(&cmd);
+            f(cmd)
+        }
+
         fn config_set(&self, path: &str, value: Option<impl AsRef<OsStr>>) -> anyhow::Result<()> {
+            ensure_not_option("configuration key", path.as_ref())?;
             let mut cmd = Self::git_cmd();
-            cmd.args(["config", path]);
-            if let Some(value) = value {
-                // TODO: How to prevent something dumb like an option injection here?
-                cmd.arg(value);
-            } else {
-                cmd.arg("--unset-all");
-            }
+            cmd.arg("config");
+            let unsetting = value.is_none();
+            // Everything after `--` is taken literally, so values like `--unset-all` are set as-is.
+            match value {
+                Some(value) => cmd.args(["--", path]).arg(value),
+                None => cmd.args(["--unset-all", "--", path]),
+            };
 
             let exit_status = self
-                .run_cmd(cmd, |mut cmd| cmd.status())
+                .run_git(cmd, |mut cmd| cmd.status())
                 .context("failed to spawn command")?;
-            if !exit_status.success() {
+            // `git config --unset-all` exits with 5 if there was nothing to unset, which is fine.
+            let nothing_to_unset = unsetting && exit_status.code() == Some(5);
+            if !exit_status.success() && !nothing_to_unset {
                 return Err(anyhow!("command did not exit successfully"));
             }
             Ok(())
         }
+
+        fn config_get(&self, path: &str) -> anyhow::Result<Option<String>> {
+            ensure_not_option("configuration key", path.as_ref())?;
+            let mut cmd = Self::git_cmd();
+            cmd.args(["config", "--get", "--", path])
+                .stderr(Stdio::inherit());
+            let Output {
+                status,
+                stdout,
+                stderr: _,
+            } = self
+                .run_git(cmd, |mut cmd| cmd.output())
+                .context("failed to spawn command")?;
+            // `git config --get` exits with 1 if the key isn't set.
+            if status.code() == Some(1) {
+                return Ok(None);
+            }
+            cmd_failure_res(status)?;
+            let value = String::from_utf8(stdout).context("config value was not UTF-8")?;
+            Ok(Some(value.trim_end_matches(['\r', '\n']).to_owned()))
+        }
+
+        fn run_git_output(&self, args: &[&str]) -> anyhow::Result<String> {
+            let mut cmd = Self::git_cmd();
+            cmd.args(args).stderr(Stdio::inherit());
+            let Output {
+                status,
+                stdout,
+                stderr: _,
+            } = self
+                .run_git(cmd, |mut cmd| cmd.output())
+                .with_context(|| anyhow!("failed to spawn `git {}`", args.join(" ")))?;
+            cmd_failure_res(status).with_context(|| anyhow!("`git {}` failed", args.join(" ")))?;
+            String::from_utf8(stdout)
+                .with_context(|| anyhow!("output of `git {}` was not UTF-8", args.join(" ")))
+        }
+
+        /// How many commits `left` has that `right` doesn't, and the other way around.
+        fn divergence(&self, left: &str, right: &str) -> anyhow::Result<(u32, u32)> {
+            let range = format!("{}...{}", left, right);
+            let counts = self.run_git_output(&["rev-list", "--left-right", "--count", &range])?;
+            let parse_counts = || -> Option<(u32, u32)> {
+                let (ahead, behind) = counts.trim().split_once('\t')?;
+                Some((ahead.parse().ok()?, behind.parse().ok()?))
+            };
+            parse_counts().with_context(|| anyhow!("malformed commit counts {:?}", counts))
+        }
+
+        fn run_git_status(&self, args: &[&str]) -> anyhow::Result<()> {
+            let mut cmd = Self::git_cmd();
+            cmd.args(args);
+            self.run_git(cmd, |mut cmd| cmd.status())
+                .with_context(|| anyhow!("failed to spawn `git {}`", args.join(" ")))
+                .and_then(cmd_failure_res)
+                .with_context(|| anyhow!("`git {}` failed", args.join(" ")))
+        }
     }
 
     impl GitRepoTrait for GitCliRepo {
@@ -498,6 +1426,14 @@ mod cli {
             f(cmd)
         }
 
+        fn git_dir_path(&self) -> &Path {
+            &self.repo_path
+        }
+
+        fn work_tree_path(&self) -> &Path {
+            &self.work_tree_path
+        }
+
         fn set_excludes_file(&mut self, path: Option<&Path>) -> Result<(), GitSetExcludeFileError> {
             Ok(self.config_set(EXCLUDES_FILE_CONFIG_PATH, path)?)
         }
@@ -509,59 +1445,651 @@ mod cli {
             Ok(self.config_set(ATTRIBUTES_FILE_CONFIG_PATH, path)?)
         }
 
+        fn get_config(&self, key: &str) -> Result<Option<String>, GitGetConfigError> {
+            self.config_get(key).map_err(|source| GitGetConfigError {
+                key: key.to_owned(),
+                source,
+            })
+        }
+
+        fn current_branch(&self) -> Result<Option<String>, GitCurrentBranchError> {
+            let mut cmd = Self::git_cmd();
+            cmd.args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+                .stderr(Stdio::inherit());
+            let Output {
+                status,
+                stdout,
+                stderr: _,
+            } = self
+                .run_git(cmd, |mut cmd| cmd.output())
+                .context("failed to spawn command")?;
+            // `git symbolic-ref --quiet` exits with 1 if `HEAD` is detached.
+            if status.code() == Some(1) {
+                return Ok(None);
+            }
+            cmd_failure_res(status)?;
+            let branch = String::from_utf8(stdout).context("branch name was not UTF-8")?;
+            Ok(Some(branch.trim_end_matches(['\r', '\n']).to_owned()))
+        }
+
+        fn set_config(
+            &mut self,
+            key: &str,
+            value: Option<&OsStr>,
+        ) -> Result<(), GitSetConfigError> {
+            self.config_set(key, value)
+                .map_err(|source| GitSetConfigError {
+                    key: key.to_owned(),
+                    source,
+                })
+        }
+
         fn list_files(&self) -> Result<Self::ListFilesIter, GitListFilesError> {
-            let mut cmd = Command::new("git");
-            cmd.arg("ls-files").stderr(Stdio::inherit());
             (|| {
-                let cwd = current_dir()?;
+                let output = self.run_git_output(&["ls-files", "-z", "--full-name"])?;
+                let paths = output.split_terminator('\0').map(PathBuf::from);
+                work_tree::resolve_tracked_paths(&self.work_tree_path, paths)
+            })()
+            .map(|files| -> Box<dyn Iterator<Item = PathBuf>> { Box::new(files.into_iter()) })
+            .map_err(|source| GitListFilesError { source })
+        }
 
-                set_current_dir(&self.work_tree_path)
-                    .context("failed to change working directory to work tree")?;
+        fn staged_blob_ids(&self) -> Result<BTreeMap<PathBuf, String>, GitListFilesError> {
+            (|| -> anyhow::Result<_> {
+                let output = self.run_git_output(&["ls-files", "--stage", "-z", "--full-name"])?;
+                output
+                    .split_terminator('\0')
+                    .map(|entry| {
+                        // Each entry is `<mode> <object> <stage>\t<path>`.
+                        let (info, path) = entry
+                            .split_once('\t')
+                            .with_context(|| anyhow!("malformed index entry {:?}", entry))?;
+                        let object = info
+                            .split(' ')
+                            .nth(1)
+                            .with_context(|| anyhow!("malformed index entry {:?}", entry))?;
+                        Ok((PathBuf::from(path), object.to_owned()))
+                    })
+                    .collect()
+            })()
+            .map_err(|source| GitListFilesError { source })
+        }
 
+        fn hash_files(&self, paths: &[PathBuf]) -> Result<Vec<String>, GitHashObjectError> {
+            if paths.is_empty() {
+                return Ok(Vec::new());
+            }
+            let mut cmd = Self::git_cmd();
+            cmd.args(["hash-object", "--"])
+                .args(paths)
+                .stderr(Stdio::inherit());
+            (|| -> anyhow::Result<_> {
                 let Output {
                     status,
                     stdout,
                     stderr: _,
                 } = self
-                    .run_cmd(cmd, |mut cmd| cmd.output())
-                    .context("failed to spawn file listing command")
-                    .map_err(|source| GitListFilesError { source })?;
-                ensure!(status.success(), "command did not exit with 0");
-
-                let files = BufRead::lines(Cursor::new(stdout))
-                    .map(|l| {
-                        l.context("failed to read line from output")
-                            .and_then(|l| canonicalize_path(Path::new(&l)))
+                    .run_git(cmd, |mut cmd| cmd.output())
+                    .context("failed to spawn hashing command")?;
+                cmd_failure_res(status)?;
+                let stdout = String::from_utf8(stdout).context("output was not UTF-8")?;
+                Ok(stdout.lines().map(ToOwned::to_owned).collect())
+            })()
+            .map_err(|source| GitHashObjectError { source })
+        }
+
+        fn count_objects(&self) -> Result<ObjectCounts, GitObjectStatsError> {
+            (|| -> anyhow::Result<_> {
+                let output = self.run_git_output(&["count-objects", "-v"])?;
+                let mut counts = BTreeMap::new();
+                for line in output.lines() {
+                    let (key, value) = line
+                        .split_once(": ")
+                        .with_context(|| anyhow!("malformed object count line {:?}", line))?;
+                    let value = value
+                        .parse::<u64>()
+                        .with_context(|| anyhow!("malformed object count line {:?}", line))?;
+                    counts.insert(key, value);
+                }
+                let count = |key| counts.get(key).copied().unwrap_or(0);
+                Ok(ObjectCounts {
+                    objects: count("count") + count("in-pack"),
+                    // Sizes are reported in KiB.
+                    size_on_disk: (count("size") + count("size-pack")) * 1024,
+                })
+            })()
+            .map_err(|source| GitObjectStatsError { source })
+        }
+
+        fn largest_blobs(&self, limit: usize) -> Result<Vec<BlobInfo>, GitObjectStatsError> {
+            (|| -> anyhow::Result<_> {
+                let objects = self.run_git_output(&[
+                    "cat-file",
+                    "--batch-all-objects",
+                    "--batch-check=%(objecttype) %(objectname) %(objectsize)",
+                ])?;
+                let mut blobs = Vec::new();
+                for line in objects.lines() {
+                    let mut fields = line.split(' ');
+                    let (Some(kind), Some(id), Some(size)) =
+                        (fields.next(), fields.next(), fields.next())
+                    else {
+                        return Err(anyhow!("malformed object entry {:?}", line));
+                    };
+                    if kind != "blob" {
+                        continue;
+                    }
+                    let size = size
+                        .parse()
+                        .with_context(|| anyhow!("malformed object entry {:?}", line))?;
+                    blobs.push(BlobInfo {
+                        id: id.to_owned(),
+                        size,
+                        path: None,
+                    });
+                }
+                blobs.sort_by_key(|blob| Reverse(blob.size));
+                blobs.truncate(limit);
+                if blobs.is_empty() {
+                    return Ok(blobs);
+                }
+
+                // Objects reachable from a ref are listed along with the path they were first seen
+                // at.
+                let reachable = self.run_git_output(&["rev-list", "--all", "--objects"])?;
+                let mut paths = reachable
+                    .lines()
+                    .filter_map(|line| line.split_once(' '))
+                    .collect::<BTreeMap<_, _>>();
+                for blob in &mut blobs {
+                    blob.path = paths.remove(blob.id.as_str()).map(PathBuf::from);
+                }
+                Ok(blobs)
+            })()
+            .map_err(|source| GitObjectStatsError { source })
+        }
+
+        fn remotes(&self) -> Result<Vec<(RemoteName<'static>, String)>, GitListRemotesError> {
+            let mut cmd = Self::git_cmd();
+            cmd.args(["config", "--get-regexp", r"^remote\..*\.url$"])
+                .stderr(Stdio::inherit());
+            (|| -> anyhow::Result<_> {
+                let Output {
+                    status,
+                    stdout,
+                    stderr: _,
+                } = self
+                    .run_git(cmd, |mut cmd| cmd.output())
+                    .context("failed to spawn remote listing command")?;
+                // `git config --get-regexp` exits with 1 when no keys matched, i.e., there are no
+                // remotes.
+                if status.code() == Some(1) {
+                    return Ok(Vec::new());
+                }
+                cmd_failure_res(status)?;
+
+                let stdout = String::from_utf8(stdout).context("remote listing was not UTF-8")?;
+                stdout
+                    .lines()
+                    .map(|line| {
+                        let (key, url) = line
+                            .split_once(' ')
+                            .with_context(|| anyhow!("malformed remote config line {:?}", line))?;
+                        let name = key
+                            .strip_prefix("remote.")
+                            .and_then(|key| key.strip_suffix(".url"))
+                            .with_context(|| anyhow!("unexpected remote config key {:?}", key))?;
+                        Ok((RemoteName(name.to_owned().into()), url.to_owned()))
                     })
-                    .collect::<Result<Vec<_>, _>>()?
-                    .into_iter();
+                    .collect()
+            })()
+            .map_err(|source| GitListRemotesError { source })
+        }
 
-                set_current_dir(&cwd)
-                    .context("failed to switch back to original working directory path")
-                    .unwrap(); // there's nothing sensible a client could do here, so get outta here
+        fn keep_depth(&self) -> Result<Option<NonZeroU32>, GitKeepDepthConfigError> {
+            Ok(self
+                .config_get(KEEP_DEPTH_CONFIG_PATH)?
+                .map(|depth| {
+                    depth
+                        .parse()
+                        .with_context(|| anyhow!("{:?} is not a valid depth", depth))
+                })
+                .transpose()?)
+        }
+
+        fn set_keep_depth(
+            &mut self,
+            depth: Option<NonZeroU32>,
+        ) -> Result<(), GitKeepDepthConfigError> {
+            Ok(self.config_set(KEEP_DEPTH_CONFIG_PATH, depth.map(|d| d.to_string()))?)
+        }
 
-                Ok(files)
+        fn reshallow(&mut self, depth: NonZeroU32) -> Result<(), GitShallowError> {
+            (|| -> anyhow::Result<_> {
+                let depth_arg = format!("--depth={}", depth);
+                self.run_git_status(&["fetch", depth_arg.as_str()])?;
+                // Commits beyond the new shallow boundary are only actually dropped once nothing
+                // refers to them anymore, so clear out the reflog before collecting garbage.
+                self.run_git_status(&["reflog", "expire", "--expire=now", "--all"])?;
+                self.run_git_status(&["gc", "--prune=now", "--quiet"])?;
+                Ok(())
             })()
-            .map(|i| -> Box<dyn Iterator<Item = PathBuf>> { Box::new(i) })
-            .map_err(|source| GitListFilesError { source })
+            .map_err(GitShallowError)
+        }
+
+        fn unshallow(&mut self) -> Result<(), GitShallowError> {
+            (|| -> anyhow::Result<_> {
+                let mut cmd = Self::git_cmd();
+                cmd.args(["rev-parse", "--is-shallow-repository"])
+                    .stderr(Stdio::inherit());
+                let Output {
+                    status,
+                    stdout,
+                    stderr: _,
+                } = self
+                    .run_git(cmd, |mut cmd| cmd.output())
+                    .context("failed to spawn shallow check command")?;
+                cmd_failure_res(status)?;
+                let is_shallow = String::from_utf8(stdout)
+                    .context("shallow check output was not UTF-8")?
+                    .trim()
+                    .parse::<bool>()
+                    .context("failed to parse shallow check output as a boolean literal")?;
+                if is_shallow {
+                    self.run_git_status(&["fetch", "--unshallow"])?;
+                }
+                Ok(())
+            })()
+            .map_err(GitShallowError)
+        }
+
+        fn fetch(&mut self) -> Result<TransferStats, GitFetchError> {
+            let before = self.count_objects().map_err(anyhow::Error::from)?;
+            let start = Instant::now();
+            self.run_git_status(&["fetch"])?;
+            let duration = start.elapsed();
+            let after = self.count_objects().map_err(anyhow::Error::from)?;
+            Ok(TransferStats {
+                objects: after.objects.saturating_sub(before.objects),
+                bytes: after.size_on_disk.saturating_sub(before.size_on_disk),
+                duration,
+            })
+        }
+
+        fn upstream_divergence(&self) -> Result<Option<(u32, u32)>, GitUpstreamError> {
+            (|| -> anyhow::Result<_> {
+                let branch = match self.current_branch()? {
+                    Some(branch) => branch,
+                    None => return Ok(None),
+                };
+                // Unlike `@{upstream}`, this doesn't fail when there's no upstream.
+                let branch_ref = format!("refs/heads/{}", branch);
+                let upstream =
+                    self.run_git_output(&["for-each-ref", "--format=%(upstream)", &branch_ref])?;
+                let upstream = upstream.trim();
+                if upstream.is_empty() {
+                    return Ok(None);
+                }
+                self.divergence("HEAD", upstream).map(Some)
+            })()
+            .map_err(GitUpstreamError)
+        }
+
+        fn branches(&self) -> Result<Vec<BranchInfo>, GitBranchesError> {
+            (|| -> anyhow::Result<_> {
+                let output = self.run_git_output(&[
+                    "for-each-ref",
+                    "--format=%(refname)%00%(refname:lstrip=2)%00%(symref)%00%(HEAD)%00\
+                    %(upstream)%00%(upstream:lstrip=2)%00%(upstream:track)%00\
+                    %(committerdate:unix)",
+                    "refs/heads/",
+                    "refs/remotes/",
+                ])?;
+                let mut branches = Vec::new();
+                for line in output.lines() {
+                    let fields = line.split('\0').collect::<Vec<_>>();
+                    let [refname, name, symref, head, upstream_ref, upstream, track, timestamp] =
+                        fields[..]
+                    else {
+                        bail!("malformed branch description {:?}", line);
+                    };
+                    // Skip `refs/remotes/<REMOTE>/HEAD`, which only points at another branch.
+                    if !symref.is_empty() {
+                        continue;
+                    }
+                    let secs = timestamp
+                        .parse()
+                        .with_context(|| anyhow!("malformed commit timestamp {:?}", timestamp))?;
+                    let divergence = if upstream_ref.is_empty() || track == "[gone]" {
+                        None
+                    } else {
+                        Some(self.divergence(refname, upstream_ref)?)
+                    };
+                    branches.push(BranchInfo {
+                        name: name.to_owned(),
+                        remote: refname.starts_with("refs/remotes/"),
+                        current: head == "*",
+                        upstream: (!upstream.is_empty()).then(|| upstream.to_owned()),
+                        divergence,
+                        last_commit_time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                    });
+                }
+                Ok(branches)
+            })()
+            .map_err(GitBranchesError)
+        }
+
+        fn merged_branches(&self) -> Result<Vec<String>, GitBranchesError> {
+            (|| -> anyhow::Result<_> {
+                // `--merged HEAD` fails if nothing has been committed yet.
+                if self
+                    .run_git_output(&["rev-parse", "--verify", "--quiet", "HEAD^{commit}"])
+                    .is_err()
+                {
+                    return Ok(Vec::new());
+                }
+                let output = self.run_git_output(&[
+                    "for-each-ref",
+                    "--merged=HEAD",
+                    "--format=%(HEAD)%(refname:lstrip=2)",
+                    "refs/heads/",
+                ])?;
+                Ok(output
+                    .lines()
+                    .filter_map(|line| line.strip_prefix(' '))
+                    .map(ToOwned::to_owned)
+                    .collect())
+            })()
+            .map_err(GitBranchesError)
+        }
+
+        fn delete_branch(&mut self, name: &str) -> Result<(), GitDeleteBranchError> {
+            (|| -> anyhow::Result<_> {
+                ensure_not_option("branch name", OsStr::new(name))?;
+                self.run_git_status(&["branch", "--delete", "--quiet", name])
+            })()
+            .map_err(|source| GitDeleteBranchError {
+                name: name.to_owned(),
+                source,
+            })
+        }
+
+        fn last_commit(&self) -> Result<Option<CommitInfo>, GitLastCommitError> {
+            (|| -> anyhow::Result<_> {
+                // Unlike `log`, this doesn't fail on a repo without commits. Fields are separated
+                // by NULs, which can't appear in any of them.
+                let output = self.run_git_output(&[
+                    "for-each-ref",
+                    "--count=1",
+                    "--sort=-committerdate",
+                    "--format=%(objectname)%00%(authorname)%00%(committerdate:unix)%00\
+                    %(contents:subject)",
+                    "refs/heads/",
+                ])?;
+                let line = output.trim_end_matches(['\r', '\n']);
+                if line.is_empty() {
+                    return Ok(None);
+                }
+                let parse = || -> Option<CommitInfo> {
+                    let mut fields = line.split('\0');
+                    let hash = fields.next()?.to_owned();
+                    let author = fields.next()?.to_owned();
+                    let secs = fields.next()?.parse().ok()?;
+                    let subject = fields.next()?.to_owned();
+                    Some(CommitInfo {
+                        hash,
+                        author,
+                        time: SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                        subject,
+                    })
+                };
+                parse()
+                    .with_context(|| anyhow!("malformed commit description {:?}", line))
+                    .map(Some)
+            })()
+            .map_err(GitLastCommitError)
+        }
+
+        fn integrate_upstream(&mut self, how: Integration) -> Result<(), GitIntegrateError> {
+            let (args, in_progress_marker, abort_args): (&[&str], _, &[&str]) = match how {
+                Integration::FastForward => (&["merge", "--ff-only", "@{upstream}"], None, &[]),
+                Integration::Merge => (
+                    &["merge", "--no-edit", "@{upstream}"],
+                    Some("MERGE_HEAD"),
+                    &["merge", "--abort"],
+                ),
+                Integration::Rebase { autostash } => (
+                    if autostash {
+                        &["rebase", "--autostash", "@{upstream}"]
+                    } else {
+                        &["rebase", "@{upstream}"]
+                    },
+                    Some("rebase-merge"),
+                    &["rebase", "--abort"],
+                ),
+            };
+            self.run_git_status(args).map_err(|e| {
+                let left_in_progress = in_progress_marker
+                    .is_some_and(|marker| self.git_dir_path().join(marker).exists());
+                if left_in_progress {
+                    if let Err(abort_err) = self.run_git_status(abort_args) {
+                        tracing::warn!("failed to abort: {:?}", abort_err);
+                    }
+                }
+                GitIntegrateError(e)
+            })
+        }
+
+        fn stash(&mut self, message: &str) -> Result<bool, GitStashError> {
+            // `git stash push` succeeds without making an entry if there's nothing it can stash,
+            // so compare the newest entry before and after.
+            let newest_stash =
+                || self.run_git_output(&["for-each-ref", "--format=%(objectname)", "refs/stash"]);
+            let before = newest_stash()?;
+            self.run_git_status(&["stash", "push", "--quiet", "--message", message])?;
+            Ok(newest_stash()? != before)
+        }
+
+        fn stash_pop(&mut self) -> Result<(), GitStashPopError> {
+            let Err(e) = self.run_git_status(&["stash", "pop", "--quiet"]) else {
+                return Ok(());
+            };
+            let conflicts =
+                match self.run_git_output(&["diff", "--name-only", "--diff-filter=U", "-z"]) {
+                    Ok(conflicts) => conflicts,
+                    Err(list_err) => {
+                        tracing::warn!("failed to list conflicts: {:?}", list_err);
+                        return Err(e.into());
+                    }
+                };
+            if conflicts.is_empty() {
+                return Err(e.into());
+            }
+            Err(GitStashPopError::Conflicts {
+                conflicts: conflicts
+                    .split_terminator('\0')
+                    .map(PathBuf::from)
+                    .collect(),
+            })
         }
 
         fn reset(&mut self) -> Result<(), GitResetError> {
             let mut cmd = Command::new("git");
             cmd.arg("reset");
             Ok(self
-                .run_cmd(cmd, |mut cmd| cmd.status())
+                .run_git(cmd, |mut cmd| cmd.status())
                 .map_err(anyhow::Error::new)
                 .and_then(cmd_failure_res)?)
         }
 
-        fn restore(&mut self) -> Result<(), GitRestoreError> {
+        fn restore(&mut self, only: &[String]) -> Result<(), GitRestoreError> {
+            // Refuse before anything is written, rather than partway through.
+            let _files = self.list_files().map_err(anyhow::Error::new)?;
             let mut cmd = Command::new("git");
-            cmd.arg("restore");
+            cmd.args(["restore", "--"]).args(restore_pathspecs(only));
             Ok(self
-                .run_cmd(cmd, |mut cmd| cmd.status())
+                .run_git(cmd, |mut cmd| cmd.status())
                 .map_err(anyhow::Error::new)
                 .and_then(cmd_failure_res)?)
         }
+
+        fn restore_preview(&self, only: &[String], patch: bool) -> Result<String, GitDiffError> {
+            // Reversed, the index's differences from the work tree are what restoring would do.
+            // Files missing from the work tree are only created, so they're filtered out.
+            let mut args = vec!["diff", "--no-color", "-R", "--diff-filter=MT"];
+            args.push(if patch { "--patch" } else { "--stat" });
+            args.push("--");
+            let pathspecs = restore_pathspecs(only);
+            args.extend(pathspecs.iter().map(String::as_str));
+            Ok(self.run_git_output(&args)?)
+        }
+
+        fn add(&mut self, paths: &[PathBuf]) -> Result<(), GitAddError> {
+            let mut cmd = Self::git_cmd();
+            cmd.args(["add", "--force", "--"]);
+            // Pathspecs are otherwise relative to the current directory, and may contain globs.
+            cmd.args(paths.iter().map(|path| {
+                let mut pathspec = OsString::from(":(top,literal)");
+                pathspec.push(path);
+                pathspec
+            }));
+            Ok(self
+                .run_git(cmd, |mut cmd| cmd.status())
+                .map_err(anyhow::Error::new)
+                .and_then(cmd_failure_res)?)
+        }
+
+        fn stage_tracked_changes(&mut self) -> Result<Vec<PathBuf>, GitAddError> {
+            self.run_git_status(&["add", "--update", "--", ":(top)"])?;
+            let output = self.run_git_output(&["diff", "--cached", "--name-only", "-z"])?;
+            Ok(output.split_terminator('\0').map(PathBuf::from).collect())
+        }
+
+        fn commit(&mut self, message: &str) -> Result<(), GitCommitError> {
+            Ok(self.run_git_status(&["commit", "--quiet", "--message", message])?)
+        }
+
+        fn archive(
+            &self,
+            rev: &str,
+            output: &Path,
+            prefix: Option<&str>,
+        ) -> Result<(), GitArchiveError> {
+            let mut cmd = Self::git_cmd();
+            cmd.arg("archive").arg("--output").arg(output);
+            if let Some(prefix) = prefix {
+                cmd.arg(format!("--prefix={}", prefix));
+            }
+            // Prevent `rev` from being interpreted as an option.
+            cmd.arg("--end-of-options").arg(rev);
+            self.run_git(cmd, |mut cmd| cmd.status())
+                .map_err(anyhow::Error::new)
+                .and_then(cmd_failure_res)
+                .map_err(|source| GitArchiveError {
+                    rev: rev.to_owned(),
+                    output: output.to_owned(),
+                    source,
+                })
+        }
+
+        fn status(&self) -> Result<RepoStatus, GitStatusError> {
+            let output = self.run_git_output(&["status", "--porcelain=v2", "--branch"])?;
+            Ok(RepoStatus::parse_porcelain_v2(&output)?)
+        }
+
+        #[cfg(feature = "tui")]
+        fn diff(&self) -> Result<String, GitDiffError> {
+            Ok(self.run_git_output(&["diff", "--no-color", "HEAD"])?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GitCli, GitRepoKind, GitTrait};
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    /// A scratch directory, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("bb-git-{}-{}", name, std::process::id()));
+            if path.exists() {
+                fs::remove_dir_all(&path).unwrap();
+            }
+            fs::create_dir_all(&path).unwrap();
+            Self(dunce::canonicalize(path).unwrap())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn git_init(path: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("init")
+            .arg("--quiet")
+            .args(args)
+            .arg(path)
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// The kind of repo `GitCli::exists` finds at `path`, if any.
+    fn found_kind(path: &Path) -> Option<GitRepoKind> {
+        match GitCli::default().exists(path, GitRepoKind::Normal).unwrap() {
+            Ok(()) => Some(GitRepoKind::Normal),
+            Err(failure) => failure.actual,
+        }
+    }
+
+    #[test]
+    fn exists_finds_normal_repo() {
+        let dir = TempDir::new("exists-normal");
+        git_init(&dir.0, &[]);
+        assert_eq!(found_kind(&dir.0), Some(GitRepoKind::Normal));
+    }
+
+    #[test]
+    fn exists_finds_bare_repo() {
+        let dir = TempDir::new("exists-bare");
+        git_init(&dir.0, &["--bare"]);
+        assert_eq!(found_kind(&dir.0), Some(GitRepoKind::Bare));
+    }
+
+    #[test]
+    fn exists_finds_nothing_in_plain_directory() {
+        let dir = TempDir::new("exists-plain");
+        assert_eq!(found_kind(&dir.0), None);
+
+        // Only looking like a bare repo isn't enough.
+        fs::write(dir.0.join("HEAD"), "").unwrap();
+        assert_eq!(found_kind(&dir.0), None);
+    }
+
+    #[test]
+    fn exists_ignores_enclosing_repo() {
+        let dir = TempDir::new("exists-nested");
+        git_init(&dir.0, &[]);
+        let sub = dir.0.join("sub");
+        fs::create_dir(&sub).unwrap();
+        assert_eq!(found_kind(&sub), None);
+        fs::write(sub.join("HEAD"), "").unwrap();
+        assert_eq!(found_kind(&sub), None);
+
+        let inner = dir.0.join("inner");
+        git_init(&inner, &[]);
+        assert_eq!(found_kind(&inner), Some(GitRepoKind::Normal));
     }
 }
@@ -0,0 +1,312 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Where repos are cloned from, and the forms of source that Git understands.
+
+use super::command_log;
+use crate::{runner::ssh_config::SshConfig, suggest};
+use anyhow::{anyhow, bail};
+use lifetime::{IntoStatic, ToBorrowed};
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    fmt::{self, Debug, Formatter},
+    path::Path,
+    str::FromStr,
+};
+use thiserror::Error as ThisError;
+
+/// URL schemes that Git supports without a remote helper. Other schemes are passed on to
+/// `git-remote-<SCHEME>` helpers, unless they look like a typo of one of these.
+const KNOWN_SCHEMES: [&str; 8] = [
+    "http", "https", "ssh", "git", "file", "ftp", "ftps", "git+ssh",
+];
+
+/// The port that Git connects to for each scheme in [`KNOWN_SCHEMES`] that takes one, when a URL
+/// doesn't give any.
+const DEFAULT_PORTS: [(&str, &str); 7] = [
+    ("http", "80"),
+    ("https", "443"),
+    ("ssh", "22"),
+    ("git+ssh", "22"),
+    ("git", "9418"),
+    ("ftp", "21"),
+    ("ftps", "990"),
+];
+
+/// A source to clone a repo from: a URL, an `scp`-like SSH address, or a local path. Validated
+/// when parsed; see [`ParsedRepoSource`] for its parts.
+#[derive(Clone, ToBorrowed, IntoStatic)]
+pub struct RepoSource<'a>(Cow<'a, str>);
+
+// Sources end up in logs through `tracing` fields, so keep credentials in URLs out of them.
+impl Debug for RepoSource<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self(inner) = self;
+        f.debug_tuple("RepoSource")
+            .field(&command_log::redact_url_credentials(inner))
+            .finish()
+    }
+}
+
+/// The parts of a [`RepoSource`], according to the form it's written in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParsedRepoSource<'a> {
+    /// A URL with a scheme, i.e., `https://host/path` or `ssh://user@host:port/path`. `host`
+    /// excludes any user and port. It's empty for remote helper addresses
+    /// (`<TRANSPORT>::<ADDRESS>`) and URLs with schemes that Git doesn't know itself, whose
+    /// `path` is everything after the scheme.
+    Url {
+        scheme: &'a str,
+        host: &'a str,
+        path: &'a str,
+    },
+    /// Git's `scp`-like SSH syntax, `[user@]host:path`. `host` may be an alias from the SSH
+    /// client configuration.
+    Scp { host: &'a str, path: &'a str },
+    /// A path to a repo on this machine.
+    Local(&'a Path),
+}
+
+#[derive(Debug, ThisError)]
+pub enum InvalidRepoSourceError {
+    #[error("repo source is empty")]
+    Empty,
+    #[error(
+        "{actual:?} has an unsupported URL scheme {scheme:?}{}",
+        suggest::did_you_mean(.scheme, KNOWN_SCHEMES.iter())
+    )]
+    UnknownScheme { actual: String, scheme: String },
+    #[error("{actual:?} has no host")]
+    MissingHost { actual: String },
+    #[error(
+        "{actual:?} has an invalid host {host:?}; expected letters, digits, \"-\", \".\", or \
+        \"_\""
+    )]
+    InvalidHost { actual: String, host: String },
+    #[error("{actual:?} has no repo path after its host")]
+    MissingPath { actual: String },
+}
+
+impl AsRef<OsStr> for RepoSource<'_> {
+    fn as_ref(&self) -> &OsStr {
+        let Self(inner) = self;
+        OsStr::new(inner.as_ref())
+    }
+}
+
+impl RepoSource<'_> {
+    pub fn as_str(&self) -> &str {
+        let Self(inner) = self;
+        inner
+    }
+
+    /// The parts of this source. Can't fail, since sources are validated when created.
+    pub fn parsed(&self) -> ParsedRepoSource<'_> {
+        parse(self.as_str()).expect("repo source was validated when created")
+    }
+
+    /// The host this source is fetched from, if it's remote. For SSH sources, this may be an
+    /// alias; see [`Self::resolved_host`].
+    pub fn host(&self) -> Option<&str> {
+        match self.parsed() {
+            ParsedRepoSource::Url { host, .. } | ParsedRepoSource::Scp { host, .. } => {
+                (!host.is_empty()).then_some(host)
+            }
+            ParsedRepoSource::Local(_path) => None,
+        }
+    }
+
+    /// [`Self::host`], with SSH host aliases resolved to the host names they stand for in `ssh`.
+    pub(crate) fn resolved_host<'a>(&'a self, ssh: &'a SshConfig) -> Option<&'a str> {
+        let host = self.host()?;
+        Some(match self.parsed() {
+            ParsedRepoSource::Scp { .. } => ssh.host_name(host),
+            ParsedRepoSource::Url { scheme, .. } if scheme.contains("ssh") => ssh.host_name(host),
+            _ => host,
+        })
+    }
+
+    /// The port of a URL source, unless it's the default for its scheme, i.e., `2222` for
+    /// `ssh://git@host:2222/path`, but nothing for `ssh://git@host:22/path`.
+    pub(crate) fn non_default_port(&self) -> Option<&str> {
+        let scheme = match self.parsed() {
+            ParsedRepoSource::Url { scheme, host, .. } if !host.is_empty() => scheme,
+            _ => return None,
+        };
+        let (_default_scheme, default_port) = DEFAULT_PORTS
+            .into_iter()
+            .find(|(default_scheme, _port)| *default_scheme == scheme)?;
+        let (_scheme, rest) = self.as_str().split_once("://")?;
+        let authority = rest
+            .split_once('/')
+            .map_or(rest, |(authority, _path)| authority);
+        let host_and_port = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_user, host)| host);
+        let port = match host_and_port.strip_prefix('[') {
+            // An IPv6 address, i.e., `[::1]:22`.
+            Some(rest) => rest.split_once("]:")?.1,
+            None => host_and_port.split_once(':')?.1,
+        };
+        (!port.is_empty() && port != default_port).then_some(port)
+    }
+
+    /// The name that `git clone` would give this source's work tree, i.e., `repo` for
+    /// `git@host:user/repo.git`. Returns `None` if nothing usable is left.
+    pub fn base_name(&self) -> Option<&str> {
+        let path = match self.parsed() {
+            ParsedRepoSource::Url { path, .. } | ParsedRepoSource::Scp { path, .. } => path,
+            ParsedRepoSource::Local(path) => path.to_str()?,
+        };
+        let trimmed = path.trim_end_matches(['/', '\\']);
+        let last = trimmed.rsplit(['/', '\\', ':']).next().unwrap_or(trimmed);
+        let name = last.strip_suffix(".git").unwrap_or(last);
+        (!name.is_empty() && name != "." && name != "..").then_some(name)
+    }
+
+    /// Fails if this is a local path relative to the current working directory, and nothing
+    /// exists there, with a hint if it looks like a URL missing its scheme.
+    pub fn ensure_local_path_exists(&self) -> anyhow::Result<()> {
+        let path = match self.parsed() {
+            ParsedRepoSource::Local(path) => path,
+            _ => return Ok(()),
+        };
+        if path.exists() {
+            return Ok(());
+        }
+        let first = path.iter().next().and_then(|first| first.to_str());
+        if path.is_relative() && first.is_some_and(|first| first.contains('.')) {
+            bail!(
+                "{:?} looks like a local path, but nothing exists there; did you mean \
+                \"https://{}\"?",
+                self.as_str(),
+                self.as_str()
+            );
+        }
+        Err(anyhow!(
+            "{:?} looks like a local path, but nothing exists there",
+            self.as_str()
+        ))
+    }
+}
+
+impl FromStr for RepoSource<'static> {
+    type Err = InvalidRepoSourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s).map(|_parsed| Self(Cow::Owned(s.to_string())))
+    }
+}
+
+fn parse(source: &str) -> Result<ParsedRepoSource<'_>, InvalidRepoSourceError> {
+    let owned = || source.to_owned();
+    if source.trim().is_empty() {
+        return Err(InvalidRepoSourceError::Empty);
+    }
+    let first_slash = source.find('/').unwrap_or(source.len());
+
+    // `<TRANSPORT>::<ADDRESS>`, handled by a remote helper.
+    if let Some((transport, address)) = source.split_once("::") {
+        let is_transport = !transport.is_empty()
+            && transport
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+        if is_transport && transport.len() < first_slash {
+            return Ok(ParsedRepoSource::Url {
+                scheme: transport,
+                host: "",
+                path: address,
+            });
+        }
+    }
+
+    if let Some((scheme, rest)) = source.split_once("://") {
+        let is_known = KNOWN_SCHEMES.contains(&scheme);
+        if !is_known && !suggest::closest(scheme, KNOWN_SCHEMES.iter()).is_empty() {
+            return Err(InvalidRepoSourceError::UnknownScheme {
+                actual: owned(),
+                scheme: scheme.to_owned(),
+            });
+        }
+        if !is_known {
+            return Ok(ParsedRepoSource::Url {
+                scheme,
+                host: "",
+                path: rest,
+            });
+        }
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if scheme == "file" {
+            return Ok(ParsedRepoSource::Url {
+                scheme,
+                host: authority,
+                path,
+            });
+        }
+        let host = validate_host(source, authority, true)?;
+        if path.trim_matches('/').is_empty() {
+            return Err(InvalidRepoSourceError::MissingPath { actual: owned() });
+        }
+        return Ok(ParsedRepoSource::Url { scheme, host, path });
+    }
+
+    // Git only takes `host:path` as SSH if there's no slash before the colon. A single letter
+    // before the colon is more likely a Windows drive.
+    if let Some(colon) = source[..first_slash].find(':') {
+        let (user_and_host, path) = (&source[..colon], &source[colon + 1..]);
+        let is_drive = user_and_host.len() == 1 && !source.contains('@');
+        if !is_drive && !user_and_host.contains('\\') {
+            let host = validate_host(source, user_and_host, false)?;
+            if path.trim_matches('/').is_empty() {
+                return Err(InvalidRepoSourceError::MissingPath { actual: owned() });
+            }
+            return Ok(ParsedRepoSource::Scp { host, path });
+        }
+    }
+
+    Ok(ParsedRepoSource::Local(Path::new(source)))
+}
+
+/// Strips any user from `authority`, and any port too if `with_port` is set, and checks that
+/// what's left is a plausible host name, IP address, or SSH alias.
+fn validate_host<'a>(
+    source: &str,
+    authority: &'a str,
+    with_port: bool,
+) -> Result<&'a str, InvalidRepoSourceError> {
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_user, host)| host);
+    let host = match host.strip_prefix('[') {
+        // An IPv6 address, i.e., `[::1]:22`.
+        Some(rest) => rest.split_once(']').map_or(host, |(addr, _port)| addr),
+        None if with_port => host.split_once(':').map_or(host, |(host, _port)| host),
+        None => host,
+    };
+    if host.is_empty() {
+        return Err(InvalidRepoSourceError::MissingHost {
+            actual: source.to_owned(),
+        });
+    }
+    let valid = host
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | ':'));
+    if !valid {
+        return Err(InvalidRepoSourceError::InvalidHost {
+            actual: source.to_owned(),
+            host: host.to_owned(),
+        });
+    }
+    Ok(host)
+}
@@ -0,0 +1,104 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Keeping operations on tracked files inside of the work tree they're tracked in.
+//!
+//! Index entries are relative paths, but they can still lead elsewhere: a directory in the work
+//! tree may be a symlink to somewhere outside of it, and a crafted index may contain absolute
+//! paths or `..` components. Deleting or overwriting such a "tracked file" would touch a file that
+//! isn't ours, so the whole operation is refused instead.
+
+use crate::runner::canonicalize_path;
+use anyhow::Context;
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Component, Path, PathBuf},
+};
+use thiserror::Error as ThisError;
+
+/// Tracked paths that resolve outside of the work tree they're tracked in.
+#[derive(Debug, ThisError)]
+#[error(
+    "refusing to operate on tracked files outside of the work tree {:?}:{}",
+    work_tree,
+    DisplayEscapes(escapes)
+)]
+pub struct OutsideWorkTreeError {
+    work_tree: PathBuf,
+    /// Each offending path, as tracked, and where it resolves to, if it's known.
+    escapes: Vec<(PathBuf, Option<PathBuf>)>,
+}
+
+struct DisplayEscapes<'a>(&'a [(PathBuf, Option<PathBuf>)]);
+
+impl Display for DisplayEscapes<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (tracked, resolved) in self.0 {
+            write!(f, "\n  {}", tracked.display())?;
+            if let Some(resolved) = resolved {
+                write!(f, " -> {}", resolved.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Resolves the tracked `paths`, relative to `work_tree`, to absolute paths, failing with an
+/// [`OutsideWorkTreeError`] listing every path that leads outside of it.
+///
+/// Symlinks among the directories leading to each file are resolved, but a tracked file that is
+/// itself a symlink isn't, since operating on it means operating on the link. Files and
+/// directories that don't exist (i.e., ones deleted from the work tree) are taken as they are.
+pub(crate) fn resolve_tracked_paths(
+    work_tree: &Path,
+    paths: impl IntoIterator<Item = PathBuf>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let work_tree = canonicalize_path(work_tree)?;
+    let mut resolved = Vec::new();
+    let mut escapes = Vec::new();
+    for path in paths {
+        let is_relative = path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !is_relative {
+            escapes.push((path, None));
+            continue;
+        }
+        let full_path = resolve_parent(&work_tree.join(&path))
+            .with_context(|| format!("failed to resolve tracked path {:?}", path))?;
+        if full_path.starts_with(&work_tree) && full_path != work_tree {
+            resolved.push(full_path);
+        } else {
+            escapes.push((path, Some(full_path)));
+        }
+    }
+    if escapes.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(OutsideWorkTreeError { work_tree, escapes }.into())
+    }
+}
+
+/// Canonicalizes the deepest existing ancestor of `path`, and appends the rest of `path` to it.
+fn resolve_parent(path: &Path) -> anyhow::Result<PathBuf> {
+    let (parent, file_name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => (parent, file_name),
+        _ => return Ok(path.to_owned()),
+    };
+    let parent = if parent.symlink_metadata().is_ok() {
+        canonicalize_path(parent)?
+    } else {
+        resolve_parent(parent)?
+    };
+    Ok(parent.join(file_name))
+}
@@ -0,0 +1,175 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{kind::RepoKind, repo_db::RepoName, sync::SyncOutcome};
+use anyhow::{anyhow, ensure, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+const PAYLOAD_VERSION: u32 = 1;
+
+/// A program for external automation, invoked on lifecycle events of this tool.
+///
+/// These are distinct from Git hooks: they're configured in this tool's configuration file, and
+/// are invoked with a JSON payload describing the event on `stdin`. The payload's schema is
+/// versioned via its `version` field; new fields may be added without bumping the version, but
+/// existing fields will not be removed or changed without bumping it.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HookConfig {
+    /// The events that trigger this hook. If empty, every event triggers this hook.
+    #[serde(default)]
+    events: Vec<EventKind>,
+    /// The program to invoke, followed by its arguments.
+    command: Vec<String>,
+}
+
+impl HookConfig {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        let Self { events: _, command } = self;
+        ensure!(!command.is_empty(), "hook `command` must not be empty");
+        Ok(())
+    }
+
+    fn triggered_by(&self, kind: EventKind) -> bool {
+        let Self { events, command: _ } = self;
+        events.is_empty() || events.contains(&kind)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum EventKind {
+    RepoRegistered,
+    RepoDeregistered,
+    RepoRemoved,
+    ConflictDetected,
+    SyncFinished,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event<'a> {
+    RepoRegistered {
+        name: RepoName<'a>,
+        kind: RepoKind,
+    },
+    RepoDeregistered {
+        name: RepoName<'a>,
+    },
+    /// The repo's entry was removed along with its Git directory.
+    RepoRemoved {
+        name: RepoName<'a>,
+        /// Whether the files of the repo's work tree were deleted too.
+        files_deleted: bool,
+    },
+    /// A new repo entry was rejected because it conflicted with `existing_name`.
+    ConflictDetected {
+        existing_name: RepoName<'a>,
+        conflicting_field: ConflictingField,
+    },
+    /// `sync` finished, whether or not every repo synced successfully.
+    SyncFinished {
+        synced: Vec<SyncedRepo<'a>>,
+        failed: Vec<FailedSync<'a>>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SyncedRepo<'a> {
+    pub name: RepoName<'a>,
+    pub outcome: SyncOutcome,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct FailedSync<'a> {
+    pub name: RepoName<'a>,
+    /// The error that the repo failed to sync with, formatted for humans.
+    pub error: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ConflictingField {
+    Name,
+    Path,
+}
+
+impl Event<'_> {
+    fn kind(&self) -> EventKind {
+        match self {
+            Self::RepoRegistered { .. } => EventKind::RepoRegistered,
+            Self::RepoDeregistered { .. } => EventKind::RepoDeregistered,
+            Self::RepoRemoved { .. } => EventKind::RepoRemoved,
+            Self::ConflictDetected { .. } => EventKind::ConflictDetected,
+            Self::SyncFinished { .. } => EventKind::SyncFinished,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Payload<'a, 'e> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event<'e>,
+}
+
+/// Invokes every hook in `hooks` triggered by `event`.
+///
+/// Hook failures are logged, but otherwise ignored; a broken hook shouldn't prevent the operation
+/// that triggered it.
+pub(crate) fn run_hooks(hooks: &[HookConfig], event: &Event<'_>) {
+    let kind = event.kind();
+    for hook in hooks.iter().filter(|hook| hook.triggered_by(kind)) {
+        if let Err(e) = run_hook(hook, event) {
+            tracing::warn!("failed to run hook {:?}: {:?}", hook.command, e);
+        }
+    }
+}
+
+fn run_hook(hook: &HookConfig, event: &Event<'_>) -> anyhow::Result<()> {
+    let HookConfig { events: _, command } = hook;
+    let (program, args) = command.split_first().context("hook command is empty")?;
+
+    let payload = serde_json::to_vec(&Payload {
+        version: PAYLOAD_VERSION,
+        event,
+    })
+    .context("failed to serialize event payload")?;
+
+    tracing::debug!("running hook {:?} for {:?}", command, event.kind());
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to spawn hook")?;
+    let written = {
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&payload)
+    };
+    // The hook is waited on even if writing its payload failed, so that it isn't left a zombie.
+    let status = child.wait().context("failed to wait for hook to exit")?;
+    match written {
+        // The hook may legitimately ignore its input and exit early, so don't treat a broken pipe
+        // as an error.
+        Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => {
+            return Err(anyhow!("failed to write event payload to hook: {}", e));
+        }
+        _ => (),
+    }
+    ensure!(status.success(), "hook exited unsuccessfully ({})", status);
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! The kinds of repo entries, and how they're parsed from the command line and displayed.
+
+use crate::runner::git::GitRepoKind;
+use anyhow::anyhow;
+use clap::ValueEnum;
+use format::lazy_format;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    io::{self, IsTerminal},
+};
+use strum::EnumIter;
+
+#[derive(
+    Copy, Clone, Debug, Deserialize, EnumIter, Eq, Ord, PartialEq, PartialOrd, Serialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RepoKind {
+    Standalone,
+    Overlay,
+    /// A directory that isn't a Git repo, tracked so that it's listed and recreated by starter
+    /// files along with everything else.
+    Unmanaged,
+}
+
+impl RepoKind {
+    /// The name used for this kind on the command line and in files, i.e., `overlay`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Standalone => "standalone",
+            Self::Overlay => "overlay",
+            Self::Unmanaged => "unmanaged",
+        }
+    }
+
+    /// What repos of this kind are called in output meant for people, i.e., `overlay repo`.
+    pub fn human_name(self) -> &'static str {
+        match self {
+            Self::Standalone => "standalone repo",
+            Self::Overlay => "overlay repo",
+            Self::Unmanaged => "unmanaged directory",
+        }
+    }
+
+    /// The plural of [`Self::human_name`], i.e., `overlay repos`.
+    pub fn plural_human_name(self) -> &'static str {
+        match self {
+            Self::Standalone => "standalone repos",
+            Self::Overlay => "overlay repos",
+            Self::Unmanaged => "unmanaged directories",
+        }
+    }
+
+    /// Whether entries of this kind are Git repos, which commands that need Git can work with.
+    pub fn is_git_repo(self) -> bool {
+        match self {
+            Self::Standalone | Self::Overlay => true,
+            Self::Unmanaged => false,
+        }
+    }
+
+    /// [`Self::plural_human_name`], colored by kind when `stdout` is a terminal and `NO_COLOR`
+    /// isn't set.
+    pub fn display_plural_human_name(self) -> impl Display {
+        lazy_format!(move |f| {
+            if !use_color() {
+                return f.write_str(self.plural_human_name());
+            }
+            let color = match self {
+                Self::Standalone => 36, // cyan
+                Self::Overlay => 35,    // magenta
+                Self::Unmanaged => 33,  // yellow
+            };
+            write!(f, "\x1b[{}m{}\x1b[0m", color, self.plural_human_name())
+        })
+    }
+}
+
+impl Display for RepoKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl TryFrom<RepoKind> for GitRepoKind {
+    type Error = anyhow::Error;
+
+    fn try_from(kind: RepoKind) -> anyhow::Result<Self> {
+        match kind {
+            RepoKind::Overlay => Ok(Self::Bare),
+            RepoKind::Standalone => Ok(Self::Normal),
+            RepoKind::Unmanaged => Err(anyhow!("unmanaged directories aren't Git repos")),
+        }
+    }
+}
+
+/// Whether to color output on `stdout`, following <https://no-color.org>.
+fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").map_or(true, |v| v.is_empty())
+}
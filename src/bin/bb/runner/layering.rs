@@ -0,0 +1,155 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{
+    dirs::Directories,
+    git::{DynGit, GitRepoTrait},
+    kind::RepoKind,
+    repo_db::{RepoEntry, RepoName},
+};
+use anyhow::{anyhow, Context};
+use lifetime::{IntoStatic, ToBorrowed};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+/// A path in the home directory that more than one overlay repo tracks.
+///
+/// Since overlay repos share the home directory as their work tree, whichever of them last
+/// checked out a shared path determines its content.
+#[derive(Debug)]
+pub(crate) struct SharedPath {
+    /// The path, relative to the home directory if it's inside it.
+    pub path: PathBuf,
+    /// The overlay repos tracking this path.
+    pub claimants: Vec<RepoName<'static>>,
+    pub on_disk: OnDisk,
+}
+
+/// What's currently in the home directory at a [`SharedPath`].
+#[derive(Debug)]
+pub(crate) enum OnDisk {
+    Missing,
+    /// The file exists, and its content matches what these claimants have staged. If this is
+    /// empty, the file matches none of them, i.e., because it was modified after checkout.
+    MatchesRepos(Vec<RepoName<'static>>),
+}
+
+impl Display for SharedPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self {
+            path,
+            claimants,
+            on_disk,
+        } = self;
+        write!(f, "{}: tracked by ", path.display())?;
+        write_names(f, claimants)?;
+        match on_disk {
+            OnDisk::Missing => write!(f, "; missing from disk"),
+            OnDisk::MatchesRepos(owners) if owners.is_empty() => {
+                write!(f, "; on-disk content matches none of them")
+            }
+            OnDisk::MatchesRepos(owners) => {
+                write!(f, "; on-disk content matches ")?;
+                write_names(f, owners)
+            }
+        }
+    }
+}
+
+fn write_names(f: &mut Formatter<'_>, names: &[RepoName<'_>]) -> fmt::Result {
+    for (idx, name) in names.iter().enumerate() {
+        if idx != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{:?}", name)?;
+    }
+    Ok(())
+}
+
+/// Finds every path tracked by more than one of the overlay repos in `repos`, and determines
+/// which of them the content currently on disk belongs to by comparing blob IDs.
+///
+/// Repos that can't be inspected are skipped with a warning, since the report is still useful
+/// for the rest.
+pub(crate) fn find_shared_paths<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: impl IntoIterator<Item = (RepoName<'a>, RepoEntry<'a>)>,
+) -> anyhow::Result<Vec<SharedPath>> {
+    let mut claims = BTreeMap::<PathBuf, Vec<(RepoName<'static>, String)>>::new();
+    let mut opened = BTreeMap::new();
+    for (name, repo) in repos {
+        if repo.kind() != RepoKind::Overlay {
+            continue;
+        }
+        let name = name.into_static();
+        let res = repo
+            .open(git, dirs, name.to_borrowed())
+            .and_then(|repo| Ok((repo.staged_blob_ids()?, repo)));
+        let (blob_ids, repo) = match res {
+            Ok(res) => res,
+            Err(e) => {
+                tracing::warn!("skipping {:?}: {:?}", name, e);
+                continue;
+            }
+        };
+        // Overlay repos may have work trees other than the home directory, so key claims by where
+        // files are checked out to.
+        let work_tree = repo.work_tree_path().to_owned();
+        for (path, blob_id) in blob_ids {
+            claims
+                .entry(work_tree.join(path))
+                .or_default()
+                .push((name.clone(), blob_id));
+        }
+        opened.insert(name, repo);
+    }
+
+    let home = dirs.home_dir_path()?;
+    let mut shared = Vec::new();
+    for (on_disk_path, claims) in claims {
+        if claims.len() < 2 {
+            continue;
+        }
+        let path = on_disk_path
+            .strip_prefix(&home)
+            .map_or_else(|_| on_disk_path.clone(), ToOwned::to_owned);
+        let on_disk = if on_disk_path.is_file() {
+            // Hash with one of the claimants, so that its Git attributes (i.e., line ending
+            // conversion) are applied the same way as they were on checkout.
+            let (hasher, _) = &claims[0];
+            let on_disk_id = opened[hasher]
+                .hash_files(&[on_disk_path])?
+                .pop()
+                .with_context(|| anyhow!("no blob ID computed for {:?}", path))?;
+            OnDisk::MatchesRepos(
+                claims
+                    .iter()
+                    .filter(|(_, blob_id)| *blob_id == on_disk_id)
+                    .map(|(name, _)| name.clone())
+                    .collect(),
+            )
+        } else {
+            OnDisk::Missing
+        };
+        shared.push(SharedPath {
+            path,
+            claimants: claims.into_iter().map(|(name, _)| name).collect(),
+            on_disk,
+        });
+    }
+    Ok(shared)
+}
@@ -0,0 +1,127 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{dirs::Directories, gc, repo_db::SyncRecord};
+use anyhow::{anyhow, Context};
+use format::lazy_format;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+/// A stable identity for the machine this tool runs on, stored at
+/// [`Directories::machine_identity_path`].
+///
+/// The ID is generated once, so that it survives hostname changes, and distinguishes machines
+/// that happen to share a hostname.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct MachineIdentity {
+    id: Uuid,
+    /// The hostname when this identity was last loaded, for display.
+    hostname: String,
+}
+
+impl MachineIdentity {
+    /// Loads this machine's identity, creating it if there's none yet. In read-only mode, a
+    /// missing identity is created in memory only.
+    pub fn load_or_create(dirs: &Directories) -> anyhow::Result<Self> {
+        let path = dirs.machine_identity_path();
+        let hostname = current_hostname();
+        let existing = Self::read(&path)?;
+        let up_to_date = existing
+            .as_ref()
+            .is_some_and(|identity| identity.hostname == hostname);
+        let identity = Self {
+            id: existing.map_or_else(Uuid::new_v4, |identity| identity.id),
+            hostname,
+        };
+        if !up_to_date && !dirs.is_read_only() {
+            identity.write(&path)?;
+        }
+        Ok(identity)
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| anyhow!("failed to read machine identity {:?}", path))
+            }
+        };
+        serde_json::from_str(&json)
+            .with_context(|| anyhow!("failed to deserialize machine identity {:?}", path))
+            .map(Some)
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("failed to serialize machine identity")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+        }
+        fs::write(path, json).with_context(|| anyhow!("failed to write {:?}", path))
+    }
+
+    /// Whether `record` was made by this machine.
+    pub fn made(&self, record: &SyncRecord<'_>) -> bool {
+        record.machine == self.id.to_string()
+    }
+
+    /// A record of this machine syncing a repo just now.
+    pub fn sync_record(&self) -> SyncRecord<'static> {
+        let Self { id, hostname } = self;
+        SyncRecord {
+            machine: id.to_string().into(),
+            hostname: hostname.clone().into(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+        }
+    }
+}
+
+/// Formats when and where `record` was made, i.e., `3 days ago on laptop (this machine)`.
+/// `machine` is the identity of this machine, if it's known.
+pub(crate) fn display_sync_record<'a>(
+    record: &'a SyncRecord<'_>,
+    machine: Option<&'a MachineIdentity>,
+) -> impl Display + 'a {
+    lazy_format!(move |f| {
+        let SyncRecord {
+            machine: _,
+            hostname,
+            time,
+        } = record;
+        write!(
+            f,
+            "{} on {}",
+            gc::display_age(UNIX_EPOCH.checked_add(Duration::from_secs(*time))),
+            hostname
+        )?;
+        if machine.is_some_and(|machine| machine.made(record)) {
+            write!(f, " (this machine)")?;
+        }
+        Ok(())
+    })
+}
+
+fn current_hostname() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
@@ -0,0 +1,265 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Opt-in records of how long each command, and the operations within it, take.
+//!
+//! With `metrics = true` in the configuration file, a line of JSON describing each command run is
+//! appended to [`Directories::metrics_path`], for `metrics show` to summarize. Nothing is sent
+//! anywhere. Operations are timed by [`MetricsLayer`] from the `tracing` spans this tool already
+//! emits, so recording them doesn't need any plumbing through the code that performs them.
+
+use crate::runner::dirs::Directories;
+use anyhow::{anyhow, Context as _};
+use format::lazy_format;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{
+    span::{Attributes, Id},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// The names of the spans timed as operations, and the names they're recorded under.
+const OPERATIONS: &[(&str, &str)] = &[
+    ("clone_new", "clone"),
+    ("restore", "checkout"),
+    ("sync", "sync"),
+    ("for_each", "for-each"),
+];
+
+/// How many times an operation was performed, and how long it took altogether.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct OperationStats {
+    pub count: u64,
+    pub total_ms: u64,
+}
+
+impl OperationStats {
+    fn add(&mut self, other: Self) {
+        let Self { count, total_ms } = other;
+        self.count += count;
+        self.total_ms += total_ms;
+    }
+}
+
+/// A record of a single command run.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct RunRecord {
+    /// The version of this tool that ran the command.
+    pub version: String,
+    /// The subcommand run, i.e., `overlay clone`.
+    pub command: String,
+    pub hostname: String,
+    /// When the command started, in seconds since the Unix epoch.
+    pub started: u64,
+    pub duration_ms: u64,
+    /// The exit code of the command; `0` if it succeeded.
+    pub exit_code: i32,
+    /// How many errors were logged along the way, i.e., for each repo that failed to sync.
+    pub errors: u64,
+    #[serde(default)]
+    pub operations: BTreeMap<String, OperationStats>,
+}
+
+#[derive(Debug, Default)]
+struct Collected {
+    errors: u64,
+    operations: BTreeMap<&'static str, OperationStats>,
+}
+
+static COLLECTED: Mutex<Collected> = Mutex::new(Collected {
+    errors: 0,
+    operations: BTreeMap::new(),
+});
+
+/// A [`Layer`] that times the spans of operations and counts the errors logged, for
+/// [`RunRecord`]s.
+pub(crate) struct MetricsLayer;
+
+/// When a timed span was created.
+struct SpanStart(Instant);
+
+fn operation_name(span_name: &str) -> Option<&'static str> {
+    OPERATIONS
+        .iter()
+        .find(|(name, _operation)| *name == span_name)
+        .map(|(_name, operation)| *operation)
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if operation_name(attrs.metadata().name()).is_none() {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let (operation, start) = match (
+            operation_name(span.name()),
+            span.extensions().get::<SpanStart>(),
+        ) {
+            (Some(operation), Some(SpanStart(start))) => (operation, *start),
+            _ => return,
+        };
+        let elapsed = OperationStats {
+            count: 1,
+            total_ms: duration_ms(start.elapsed()),
+        };
+        let mut collected = COLLECTED.lock().unwrap();
+        collected
+            .operations
+            .entry(operation)
+            .or_default()
+            .add(elapsed);
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() == Level::ERROR {
+            COLLECTED.lock().unwrap().errors += 1;
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+impl RunRecord {
+    /// A record of the command that's running, with what [`MetricsLayer`] has collected so far.
+    pub fn new(command: String, started: u64, duration: Duration, exit_code: i32) -> Self {
+        let Collected { errors, operations } = &*COLLECTED.lock().unwrap();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            command,
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            started,
+            duration_ms: duration_ms(duration),
+            exit_code,
+            errors: *errors,
+            operations: operations
+                .iter()
+                .map(|(operation, stats)| ((*operation).to_owned(), *stats))
+                .collect(),
+        }
+    }
+
+    /// Appends this record to [`Directories::metrics_path`].
+    pub fn append(&self, dirs: &Directories) -> anyhow::Result<()> {
+        let path = dirs.metrics_path();
+        let mut line = serde_json::to_string(self).context("failed to serialize run record")?;
+        line.push('\n');
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| anyhow!("failed to append to {:?}", path))
+    }
+}
+
+/// Reads the records at [`Directories::metrics_path`], oldest first. Lines that can't be parsed,
+/// i.e., from a write cut short, are skipped.
+pub(crate) fn read_records(dirs: &Directories) -> anyhow::Result<Vec<RunRecord>> {
+    let path = dirs.metrics_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| anyhow!("failed to read {:?}", path)),
+    };
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_idx, line)| !line.trim().is_empty())
+        .filter_map(|(idx, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::warn!("skipping malformed line {} of {:?}: {}", idx + 1, path, e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Runs of one command by one version of this tool, summarized.
+#[derive(Debug, Default)]
+pub(crate) struct CommandSummary {
+    pub runs: u64,
+    pub failed: u64,
+    pub total_ms: u64,
+    pub last_ms: u64,
+    pub operations: BTreeMap<String, OperationStats>,
+}
+
+/// Summarizes `records` by command, then by version.
+pub(crate) fn summarize(records: &[RunRecord]) -> BTreeMap<(&str, &str), CommandSummary> {
+    let mut summaries = BTreeMap::<_, CommandSummary>::new();
+    for record in records {
+        let RunRecord {
+            version,
+            command,
+            hostname: _,
+            started: _,
+            duration_ms,
+            exit_code,
+            errors: _,
+            operations,
+        } = record;
+        let summary = summaries
+            .entry((command.as_str(), version.as_str()))
+            .or_default();
+        summary.runs += 1;
+        if *exit_code != 0 {
+            summary.failed += 1;
+        }
+        summary.total_ms += duration_ms;
+        summary.last_ms = *duration_ms;
+        for (operation, stats) in operations {
+            summary
+                .operations
+                .entry(operation.clone())
+                .or_default()
+                .add(*stats);
+        }
+    }
+    summaries
+}
+
+/// Formats a duration in milliseconds for people, i.e., `850ms`, `3.2s`, or `2m05s`.
+pub(crate) fn display_duration(ms: u64) -> impl Display {
+    lazy_format!(move |f| match ms {
+        0..=999 => write!(f, "{}ms", ms),
+        1_000..=59_999 => write!(f, "{:.1}s", ms as f64 / 1000.),
+        _ => write!(f, "{}m{:02}s", ms / 60_000, ms % 60_000 / 1000),
+    })
+}
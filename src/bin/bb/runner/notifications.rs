@@ -0,0 +1,108 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Desktop notifications for commands that run against many repos, so that users can switch to
+//! something else while a long `sync` runs.
+//!
+//! Notifications are shown with the `notifications` feature, which is on by default. Without it,
+//! they're only logged.
+
+use crate::units::HumanDuration;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct NotificationConfig {
+    /// Show a desktop notification when `sync`, `starter import`, or `for-each` takes at least
+    /// this long, i.e., `"1m"`. Notifications are off unless this is set.
+    after: Option<HumanDuration>,
+}
+
+/// Tracks how long a command runs against repos, and how many it succeeds or fails for.
+#[derive(Debug)]
+pub(crate) struct Tally {
+    /// The command, as the user would type it, i.e., `sync`.
+    command: &'static str,
+    started: Instant,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+impl Tally {
+    pub fn start(command: &'static str) -> Self {
+        Self {
+            command,
+            started: Instant::now(),
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+
+    /// Notifies the user that the command finished, if it took at least as long as `config`
+    /// says it must. Failing to notify is only logged.
+    pub fn finish(self, config: &NotificationConfig) {
+        let Self {
+            command,
+            started,
+            succeeded,
+            failed,
+        } = self;
+        let NotificationConfig { after } = config;
+        let elapsed = started.elapsed();
+        if !after.is_some_and(|HumanDuration(after)| elapsed >= after) {
+            return;
+        }
+        let summary = format!(
+            "`{} {}` {}",
+            env!("CARGO_BIN_NAME"),
+            command,
+            if failed == 0 { "finished" } else { "failed" }
+        );
+        let body = format!(
+            "{} succeeded, {} failed, in {}",
+            succeeded,
+            failed,
+            display_elapsed(elapsed)
+        );
+        tracing::debug!("notifying: {}: {}", summary, body);
+        if let Err(e) = show(&summary, &body) {
+            tracing::warn!("{:?}", e.context("failed to show desktop notification"));
+        }
+    }
+}
+
+/// Formats `elapsed` to the second, i.e., `2m 5s`.
+fn display_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _s) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn show(summary: &str, body: &str) -> anyhow::Result<()> {
+    notify_rust::Notification::new()
+        .appname(env!("CARGO_PKG_NAME"))
+        .summary(summary)
+        .body(body)
+        .show()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "notifications"))]
+fn show(_summary: &str, _body: &str) -> anyhow::Result<()> {
+    anyhow::bail!("this build doesn't support them; rebuild with the `notifications` feature")
+}
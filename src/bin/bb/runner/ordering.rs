@@ -0,0 +1,75 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::repo_db::{RepoEntry, RepoName};
+use anyhow::bail;
+use std::collections::BTreeSet;
+
+/// Reorders `repos` so that each comes after the repos it depends on (see
+/// [`RepoEntry::depends_on`]), keeping the given order otherwise. Fails if dependencies form a
+/// cycle.
+///
+/// Dependencies on repos that aren't in `repos` are ignored, so that commands operating on a
+/// subset of repos don't need to handle the rest.
+pub(crate) fn dependency_order<'a>(
+    repos: Vec<(RepoName<'a>, RepoEntry<'a>)>,
+) -> anyhow::Result<Vec<(RepoName<'a>, RepoEntry<'a>)>> {
+    let included = repos
+        .iter()
+        .map(|(name, _repo)| name.to_string())
+        .collect::<BTreeSet<_>>();
+    let mut done = BTreeSet::new();
+    let mut remaining = repos;
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|(_name, repo)| {
+            repo.depends_on()
+                .iter()
+                .all(|dep| done.contains(&*dep) || !included.contains(&*dep))
+        });
+        match ready {
+            Some(idx) => {
+                let (name, repo) = remaining.remove(idx);
+                done.insert(name.to_string());
+                ordered.push((name, repo));
+            }
+            None => bail!(
+                "repo dependencies form a cycle: {}",
+                describe_cycle(&remaining)
+            ),
+        }
+    }
+    Ok(ordered)
+}
+
+/// Follows dependencies among `stuck` repos, each of which depends on another, until one repeats,
+/// and formats the cycle found, i.e., `a -> b -> a`.
+fn describe_cycle(stuck: &[(RepoName<'_>, RepoEntry<'_>)]) -> String {
+    let next = |name: &str| {
+        let (_name, repo) = stuck.iter().find(|(n, _repo)| &**n == name)?;
+        repo.depends_on()
+            .iter()
+            .find(|dep| stuck.iter().any(|(n, _repo)| *n == *dep))
+            .map(|dep| dep.to_string())
+    };
+    let mut path = vec![stuck[0].0.to_string()];
+    while let Some(dep) = next(path.last().unwrap()) {
+        if let Some(start) = path.iter().position(|name| *name == dep) {
+            path.drain(..start);
+            path.push(dep);
+            break;
+        }
+        path.push(dep);
+    }
+    path.join(" -> ")
+}
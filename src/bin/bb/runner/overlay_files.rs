@@ -0,0 +1,208 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Cleaning up what this tool sets up for an overlay repo outside of its Git directory, for
+//! `--prune-config`, and following it along when the repo's directory is renamed by hand, for
+//! `doctor`.
+//!
+//! Every overlay repo gets excludes files (see
+//! [`refresh_overlay_excludes`](crate::runner::excludes::refresh_overlay_excludes)) and an
+//! attributes file, and its Git configuration points at those and at an empty hooks directory.
+//! None of that goes away with the repo's entry unless asked to, and all of it is named after the
+//! repo.
+
+use crate::runner::{
+    dirs::Directories,
+    excludes,
+    git::{
+        DynGitRepo, GitRepoTrait, ATTRIBUTES_FILE_CONFIG_PATH, EXCLUDES_FILE_CONFIG_PATH,
+        HOOKS_PATH_CONFIG_PATH,
+    },
+    repo_db::RepoDb,
+};
+use anyhow::{anyhow, Context};
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Deletes the excludes and attributes files of the overlay repo `name`, and the directories
+/// holding them if that leaves them empty.
+///
+/// Note that `~/.gitignore.d/<NAME>` is deleted too, even though it's usually written by hand.
+/// Only the host-specific excludes file of this machine is deleted.
+pub(crate) fn prune(dirs: &Directories, name: &str) -> anyhow::Result<()> {
+    let home = dirs.home_dir_path()?;
+    let mut files = vec![
+        dirs.overlay_excludes_file_path(name),
+        excludes::shared_excludes_file_path(&home, name),
+        dirs.overlay_attributes_file_path(name),
+    ];
+    if let Ok(hostname) = gethostname::gethostname().into_string() {
+        files.push(excludes::host_excludes_file_path(&home, name, &hostname));
+    }
+    for file in &files {
+        match fs::remove_file(file) {
+            Ok(()) => tracing::info!("removed {}", file.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e).with_context(|| anyhow!("failed to remove {:?}", file)),
+        }
+    }
+    let mut parents = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .collect::<Vec<_>>();
+    parents.dedup();
+    for dir in parents {
+        // Other repos' files may still be in there.
+        if fs::remove_dir(dir).is_ok() {
+            tracing::debug!("removed empty directory {}", dir.display());
+        }
+    }
+    Ok(())
+}
+
+/// Unsets the Git configuration of the overlay repo `name` that points at files set up by this
+/// tool, for repos whose Git directory outlives their entry. Settings that were changed to point
+/// elsewhere are left alone.
+pub(crate) fn unset_config(
+    dirs: &Directories,
+    repo: &mut DynGitRepo,
+    name: &str,
+) -> anyhow::Result<()> {
+    let home = dirs.home_dir_path()?;
+    let ours: [(&str, Vec<PathBuf>); 3] = [
+        (
+            EXCLUDES_FILE_CONFIG_PATH,
+            vec![
+                dirs.overlay_excludes_file_path(name),
+                // Older versions pointed at the shared file directly.
+                excludes::shared_excludes_file_path(&home, name),
+            ],
+        ),
+        (
+            ATTRIBUTES_FILE_CONFIG_PATH,
+            vec![dirs.overlay_attributes_file_path(name)],
+        ),
+        (HOOKS_PATH_CONFIG_PATH, vec![dirs.empty_hooks_dir_path()]),
+    ];
+    for (key, paths) in ours {
+        let value = repo.get_config(key)?;
+        let is_ours = value.as_deref().map(Path::new).is_some_and(|value| {
+            paths
+                .iter()
+                .any(|path| value.components().eq(path.components()))
+        });
+        if is_ours {
+            repo.set_config(key, None)?;
+            tracing::info!("unset `{}` of {:?}", key, name);
+        }
+    }
+    Ok(())
+}
+
+/// Finds the name that the overlay repo `name` was set up under, if its Git configuration points
+/// at excludes or attributes files of another name, i.e., because its directory in the overlay
+/// repos directory was renamed by hand.
+pub(crate) fn previous_name(
+    dirs: &Directories,
+    repo: &DynGitRepo,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    let home = dirs.home_dir_path()?;
+    let ours: [(&str, Vec<PathBuf>); 2] = [
+        (
+            EXCLUDES_FILE_CONFIG_PATH,
+            vec![
+                dirs.overlay_excludes_file_path(name),
+                excludes::shared_excludes_file_path(&home, name),
+            ],
+        ),
+        (
+            ATTRIBUTES_FILE_CONFIG_PATH,
+            vec![dirs.overlay_attributes_file_path(name)],
+        ),
+    ];
+    for (key, paths) in ours {
+        let Some(value) = repo.get_config(key)? else {
+            continue;
+        };
+        let value = Path::new(&value);
+        let file_name = value.file_name().and_then(OsStr::to_str);
+        let in_our_dir = paths.iter().any(|path| value.parent() == path.parent());
+        match file_name {
+            Some(file_name) if in_our_dir && file_name != name => {
+                return Ok(Some(file_name.to_owned()))
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+/// Moves the files set up for the overlay repo `name` under its previous name, `old_name`, to
+/// where they belong now, and points its Git configuration at them. Files that would overwrite
+/// others are left where they are, with a warning.
+pub(crate) fn follow_rename(
+    dirs: &Directories,
+    repo: &mut DynGitRepo,
+    old_name: &str,
+    name: &str,
+) -> anyhow::Result<()> {
+    let home = dirs.home_dir_path()?;
+    let mut moves = vec![
+        (
+            excludes::shared_excludes_file_path(&home, old_name),
+            excludes::shared_excludes_file_path(&home, name),
+        ),
+        (
+            dirs.overlay_attributes_file_path(old_name),
+            dirs.overlay_attributes_file_path(name),
+        ),
+    ];
+    if let Ok(hostname) = gethostname::gethostname().into_string() {
+        moves.push((
+            excludes::host_excludes_file_path(&home, old_name, &hostname),
+            excludes::host_excludes_file_path(&home, name, &hostname),
+        ));
+    }
+    for (from, to) in moves {
+        if from.symlink_metadata().is_err() {
+            continue;
+        }
+        if to.symlink_metadata().is_ok() {
+            tracing::warn!(
+                "not moving {} to {}, which already exists",
+                from.display(),
+                to.display()
+            );
+            continue;
+        }
+        fs::rename(&from, &to).with_context(|| anyhow!("failed to move {:?} to {:?}", from, to))?;
+        tracing::info!("moved {} to {}", from.display(), to.display());
+    }
+
+    // The generated excludes file is regenerated under the new name.
+    let old_generated = dirs.overlay_excludes_file_path(old_name);
+    match fs::remove_file(&old_generated) {
+        Ok(()) => tracing::debug!("removed {}", old_generated.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e).with_context(|| anyhow!("failed to remove {:?}", old_generated)),
+    }
+    repo.set_excludes_file(Some(&dirs.overlay_excludes_file_path(name)))?;
+    excludes::refresh_overlay_excludes(dirs, repo, name)?;
+    let attributes_path = RepoDb::ensure_overlay_attributes_file(dirs, name)?;
+    repo.set_attributes_file(Some(&attributes_path))?;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Skeletons that new overlay repos can start from, with `overlay init --from-template`.
+//!
+//! A template is a directory, or a Git repo containing one, whose files are copied into the home
+//! directory and committed as the new repo's first commit. It may also contain a manifest at
+//! [`MANIFEST_FILE_NAME`], which isn't copied; see [`TemplateManifest`].
+
+use crate::runner::{
+    dirs::Directories,
+    error::Error,
+    excludes,
+    git::{
+        DynGit, DynGitRepo, GitRepoKind, GitRepoTrait, GitTrait, RepoSource, HOOKS_PATH_CONFIG_PATH,
+    },
+};
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
+
+/// The name of the file, at the root of a template, that [`TemplateManifest`] is read from.
+pub(crate) const MANIFEST_FILE_NAME: &str = ".bellboy-template.toml";
+
+const DEFAULT_COMMIT_MESSAGE: &str = "Initial commit from template";
+
+/// Settings a template declares for repos created from it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TemplateManifest {
+    /// Git configuration to set in the new repo, i.e., `core.hooksPath` for hook scripts in the
+    /// template. Set after the first commit, so that hooks don't run for it. `core.hooksPath` is
+    /// ignored unless overlay repos are allowed to run hooks.
+    #[serde(default)]
+    git_config: BTreeMap<String, String>,
+    /// Patterns to seed the repo's shared excludes file, `~/.gitignore.d/<NAME>`, with.
+    #[serde(default)]
+    excludes: Vec<String>,
+    /// The message of the first commit.
+    #[serde(default)]
+    commit_message: Option<String>,
+}
+
+/// A template that has been read from disk, and fetched first if it's remote.
+#[derive(Debug)]
+pub(crate) struct OverlayTemplate {
+    dir: PathBuf,
+    /// Whether `dir` is a clone made just for this, to be deleted when dropped.
+    cloned: bool,
+    manifest: TemplateManifest,
+    /// The files to copy, relative to `dir`.
+    files: Vec<PathBuf>,
+}
+
+impl OverlayTemplate {
+    /// Reads the template at `location`, which is either a local directory or a source to clone
+    /// it from.
+    pub fn fetch(git: &DynGit, location: &str) -> anyhow::Result<Self> {
+        if Path::new(location).is_dir() {
+            return Self::read(PathBuf::from(location), false);
+        }
+        let source = RepoSource::from_str(location).with_context(|| {
+            anyhow!(
+                "template {:?} is neither a directory nor a source",
+                location
+            )
+        })?;
+        let dir = env::temp_dir().join(format!("bellboy-template-{}", Uuid::new_v4()));
+        tracing::info!("cloning template from {:?}", location);
+        git.clone(&dir, source, GitRepoKind::Normal, NonZeroU32::new(1))
+            .with_context(|| anyhow!("failed to clone template from {:?}", location))?;
+        Self::read(dir, true)
+    }
+
+    fn read(dir: PathBuf, cloned: bool) -> anyhow::Result<Self> {
+        let mut template = Self {
+            dir,
+            cloned,
+            manifest: TemplateManifest::default(),
+            files: Vec::new(),
+        };
+        let manifest_path = template.dir.join(MANIFEST_FILE_NAME);
+        match fs::read_to_string(&manifest_path) {
+            Ok(toml) => {
+                template.manifest = toml::from_str(&toml)
+                    .with_context(|| anyhow!("{:?} is invalid", manifest_path))?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => {
+                return Err(
+                    anyhow::Error::new(e).context(anyhow!("failed to read {:?}", manifest_path))
+                )
+            }
+        }
+        let mut files = Vec::new();
+        list_files(&template.dir, Path::new(""), &mut files)
+            .with_context(|| anyhow!("failed to list files of template {:?}", template.dir))?;
+        files.retain(|path| path != Path::new(MANIFEST_FILE_NAME));
+        template.files = files;
+        Ok(template)
+    }
+
+    /// Fails if copying this template into `home` would overwrite anything, including the shared
+    /// excludes file it would seed for the repo `name`.
+    pub fn ensure_no_conflicts(&self, home: &Path, name: &str) -> Result<(), Error> {
+        let mut conflicts = self
+            .files
+            .iter()
+            .map(|path| home.join(path))
+            .filter(|path| path.symlink_metadata().is_ok())
+            .collect::<Vec<_>>();
+        let excludes_path = excludes::shared_excludes_file_path(home, name);
+        if !self.manifest.excludes.is_empty() && excludes_path.symlink_metadata().is_ok() {
+            conflicts.push(excludes_path);
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Conflict(anyhow!(
+                "template would overwrite existing files: {:?}",
+                conflicts
+            )))
+        }
+    }
+
+    /// Copies this template into the work tree of the new overlay repo `name`, commits it, and
+    /// applies the template's configuration. Hooks are only enabled if `allow_git_hooks` is set.
+    pub fn apply(
+        &self,
+        dirs: &Directories,
+        repo: &mut DynGitRepo,
+        name: &str,
+        allow_git_hooks: bool,
+    ) -> anyhow::Result<()> {
+        let Self {
+            dir,
+            cloned: _,
+            manifest:
+                TemplateManifest {
+                    git_config,
+                    excludes,
+                    commit_message,
+                },
+            files,
+        } = self;
+        let home = dirs.home_dir_path()?;
+        let mut committed = files.clone();
+        for file in files {
+            let (from, to) = (dir.join(file), home.join(file));
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+            }
+            fs::copy(&from, &to)
+                .with_context(|| anyhow!("failed to copy {:?} to {:?}", from, to))?;
+        }
+        if !excludes.is_empty() {
+            let path = excludes::shared_excludes_file_path(&home, name);
+            fs::create_dir_all(path.parent().unwrap())
+                .with_context(|| anyhow!("failed to create directory {:?}", path.parent()))?;
+            let mut contents = excludes.join("\n");
+            contents.push('\n');
+            fs::write(&path, contents).with_context(|| anyhow!("failed to write {:?}", path))?;
+            excludes::refresh_overlay_excludes(dirs, repo, name)?;
+            committed.push(path.strip_prefix(&home).unwrap().to_owned());
+        }
+        if !committed.is_empty() {
+            repo.add(&committed)?;
+            repo.commit(commit_message.as_deref().unwrap_or(DEFAULT_COMMIT_MESSAGE))?;
+        }
+        for (key, value) in git_config {
+            // Keys are case-insensitive, apart from subsection names.
+            if !allow_git_hooks && key.eq_ignore_ascii_case(HOOKS_PATH_CONFIG_PATH) {
+                tracing::warn!(
+                    "not setting `{}` from template, since overlay repos may not run Git hooks; \
+                    set `overlay.allow_git_hooks = true` in the configuration to allow it",
+                    key
+                );
+                continue;
+            }
+            repo.set_config(key, Some(value.as_ref()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OverlayTemplate {
+    fn drop(&mut self) {
+        if self.cloned {
+            if let Err(e) = remove_dir_all::remove_dir_all(&self.dir) {
+                tracing::warn!("failed to delete template clone {:?}: {}", self.dir, e);
+            }
+        }
+    }
+}
+
+/// Appends the paths of the files under `root.join(rel)`, relative to `root`, to `files`. Git
+/// metadata is skipped, as are symlinks, which may point outside of the template.
+fn list_files(root: &Path, rel: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(rel))?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = rel.join(entry.file_name());
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        } else {
+            tracing::warn!("skipping {:?} in template, since it isn't a file", path);
+        }
+    }
+    Ok(())
+}
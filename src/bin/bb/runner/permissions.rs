@@ -0,0 +1,267 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{
+    canonicalize_path,
+    config::RepoConfig,
+    dirs::Directories,
+    git::{DynGitRepo, GitRepoTrait},
+};
+use anyhow::{anyhow, Context};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error as ThisError;
+
+/// Unix-style permission bits for a file, i.e., `0600`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct FileMode(u32);
+
+impl FileMode {
+    const MAX: u32 = 0o7777;
+
+    /// The mode enforced on directories holding this tool's state by `harden_permissions`.
+    const PRIVATE_DIR: Self = Self(0o700);
+    /// The mode enforced on files holding this tool's state by `harden_permissions`.
+    const PRIVATE_FILE: Self = Self(0o600);
+
+    fn is_world_readable(self) -> bool {
+        let Self(mode) = self;
+        mode & 0o004 != 0
+    }
+}
+
+#[derive(Debug, ThisError)]
+pub(crate) enum InvalidFileModeError {
+    #[error("{actual:?} is not an octal number")]
+    NotOctal { actual: String },
+    #[error("{actual:o} is larger than the maximum file mode, {:o}", FileMode::MAX)]
+    TooBig { actual: u32 },
+}
+
+impl FromStr for FileMode {
+    type Err = InvalidFileModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0o").unwrap_or(s);
+        let mode = u32::from_str_radix(digits, 8).map_err(|_e| InvalidFileModeError::NotOctal {
+            actual: s.to_owned(),
+        })?;
+        if mode > Self::MAX {
+            return Err(InvalidFileModeError::TooBig { actual: mode });
+        }
+        Ok(Self(mode))
+    }
+}
+
+impl Display for FileMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self(mode) = self;
+        write!(f, "{:04o}", mode)
+    }
+}
+
+impl<'de> Deserialize<'de> for FileMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// A file in a repo's work tree whose mode doesn't match what's configured for it.
+#[derive(Debug)]
+pub(crate) struct FileModeMismatch {
+    pub path: PathBuf,
+    pub expected: FileMode,
+    pub actual: FileMode,
+}
+
+/// Finds files tracked by `repo` whose modes don't match the overrides in `config`.
+pub(crate) fn check_file_modes(
+    repo: &DynGitRepo,
+    config: &RepoConfig,
+) -> anyhow::Result<Vec<FileModeMismatch>> {
+    let mut mismatches = Vec::new();
+    for_each_overridden_file(repo, config, |path, expected| {
+        let actual =
+            file_mode(path).with_context(|| anyhow!("failed to get mode of {}", path.display()))?;
+        if actual != expected {
+            mismatches.push(FileModeMismatch {
+                path: path.to_owned(),
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    })?;
+    Ok(mismatches)
+}
+
+/// Sets the modes of files tracked by `repo` according to the overrides in `config`.
+pub(crate) fn apply_file_modes(repo: &DynGitRepo, config: &RepoConfig) -> anyhow::Result<()> {
+    for_each_overridden_file(repo, config, |path, mode| {
+        tracing::debug!("setting mode of {} to {}", path.display(), mode);
+        set_file_mode(path, mode)
+            .with_context(|| anyhow!("failed to set mode of {} to {}", path.display(), mode))
+    })
+}
+
+fn for_each_overridden_file(
+    repo: &DynGitRepo,
+    config: &RepoConfig,
+    mut f: impl FnMut(&Path, FileMode) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if !config.has_file_modes() {
+        return Ok(());
+    }
+    let work_tree_path = canonicalize_path(repo.work_tree_path())?;
+    for path in repo.list_files()? {
+        // Changing the mode of a tracked symlink would change that of its target, which may not
+        // be ours to change.
+        if path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            continue;
+        }
+        let relative_path = match path.strip_prefix(&work_tree_path) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+        if let Some(mode) = config.file_mode_for(relative_path) {
+            f(&path, mode)?;
+        }
+    }
+    Ok(())
+}
+
+/// A file or directory holding this tool's state whose mode is too permissive.
+#[derive(Debug)]
+pub(crate) struct ExposedStateFile {
+    pub path: PathBuf,
+    pub actual: FileMode,
+}
+
+/// Lists the files and directories holding this tool's state that exist, along with the modes
+/// that `harden_permissions` enforces on them.
+///
+/// This covers the data directory, the repo DBs and status cache, overlay repos' Git directories,
+/// and quarantined directories. The contents of Git directories aren't included, since Git
+/// manages their modes itself.
+fn state_paths(dirs: &Directories) -> anyhow::Result<Vec<(PathBuf, FileMode)>> {
+    let mut paths = vec![
+        (dirs.data_dir_path(), FileMode::PRIVATE_DIR),
+        (dirs.state_dir_path(), FileMode::PRIVATE_DIR),
+    ];
+    for file in [
+        dirs.standalone_repo_db_path()?,
+        dirs.overlay_repo_db_path(),
+        dirs.status_cache_path(),
+    ] {
+        paths.push((file, FileMode::PRIVATE_FILE));
+    }
+    for dir in [dirs.overlay_repos_dir_path()?, dirs.quarantine_dir_path()] {
+        paths.push((dir.clone(), FileMode::PRIVATE_DIR));
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(anyhow!("failed to read {:?}", dir)))
+            }
+        };
+        for entry in entries {
+            let entry = entry.with_context(|| anyhow!("failed to read entry of {:?}", dir))?;
+            if entry.path().is_dir() {
+                paths.push((entry.path(), FileMode::PRIVATE_DIR));
+            }
+        }
+    }
+    paths.retain(|(path, _mode)| path.exists());
+    Ok(paths)
+}
+
+/// Finds files and directories holding this tool's state that other users can read.
+pub(crate) fn find_exposed_state(dirs: &Directories) -> anyhow::Result<Vec<ExposedStateFile>> {
+    if !cfg!(unix) {
+        return Ok(Vec::new());
+    }
+    let mut exposed = Vec::new();
+    for (path, _mode) in state_paths(dirs)? {
+        let actual = file_mode(&path)
+            .with_context(|| anyhow!("failed to get mode of {}", path.display()))?;
+        if actual.is_world_readable() {
+            exposed.push(ExposedStateFile { path, actual });
+        }
+    }
+    Ok(exposed)
+}
+
+/// Restricts files and directories holding this tool's state to the current user.
+pub(crate) fn harden_state(dirs: &Directories) -> anyhow::Result<()> {
+    if !cfg!(unix) {
+        return Ok(());
+    }
+    for (path, mode) in state_paths(dirs)? {
+        let actual = file_mode(&path)
+            .with_context(|| anyhow!("failed to get mode of {}", path.display()))?;
+        if actual != mode {
+            tracing::debug!("hardening mode of {} to {}", path.display(), mode);
+            set_file_mode(&path, mode)
+                .with_context(|| anyhow!("failed to set mode of {} to {}", path.display(), mode))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> io::Result<FileMode> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    Ok(FileMode(
+        fs::metadata(path)?.permissions().mode() & FileMode::MAX,
+    ))
+}
+
+#[cfg(unix)]
+fn set_file_mode(path: &Path, mode: FileMode) -> io::Result<()> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let FileMode(mode) = mode;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> io::Result<FileMode> {
+    Err(unsupported_platform())
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_path: &Path, _mode: FileMode) -> io::Result<()> {
+    Err(unsupported_platform())
+}
+
+#[cfg(not(unix))]
+fn unsupported_platform() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "file modes are only supported on Unix-like platforms",
+    )
+}
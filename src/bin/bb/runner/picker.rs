@@ -0,0 +1,228 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! An interactive, fuzzy-searchable picker for choosing one of several items on the terminal,
+//! i.e., the repo for `run` when none is given.
+//!
+//! With the `tui` feature, this is a full-screen list that narrows as the user types. Otherwise,
+//! items are numbered, and the user answers a prompt with a number or a filter.
+
+use std::io::{self, IsTerminal};
+
+/// An item that can be picked, shown as its `label` followed by a dimmer `detail`.
+#[derive(Debug)]
+pub(crate) struct Item {
+    pub label: String,
+    pub detail: String,
+}
+
+/// Whether there's a terminal to show a picker on, and read the user's choice from.
+pub(crate) fn is_available() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Scores how well `query` fuzzily matches `candidate`, or returns `None` if the characters of
+/// `query` don't all appear in order in `candidate`. Matching ignores case, and higher scores go
+/// to matches that are consecutive or start at the beginning of `candidate` or of a word.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut prev_match = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let idx = next + candidate[next..].iter().position(|&c| c == q)?;
+        score += 1;
+        if prev_match.is_some_and(|prev| prev + 1 == idx) {
+            score += 5;
+        }
+        if idx == 0 || !candidate[idx - 1].is_alphanumeric() {
+            score += 3;
+        }
+        prev_match = Some(idx);
+        next = idx + 1;
+    }
+    // Prefer shorter candidates among equally good matches.
+    Some(score * 100 - candidate.len() as i64)
+}
+
+/// The indices of the items in `items` that match `query`, best match first. Ties keep the
+/// order of `items`.
+fn matches(items: &[Item], query: &str) -> Vec<usize> {
+    let mut scored = items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, item)| {
+            let haystack = format!("{} {}", item.label, item.detail);
+            fuzzy_score(query, &item.label)
+                .map(|score| score + 1_000)
+                .or_else(|| fuzzy_score(query, &haystack))
+                .map(|score| (idx, score))
+        })
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|&(idx, score)| (-score, idx));
+    scored.into_iter().map(|(idx, _score)| idx).collect()
+}
+
+/// Asks the user to pick one of `items` under the heading `title`, returning its index, or
+/// `None` if they cancelled.
+#[cfg(feature = "tui")]
+pub(crate) fn pick(title: &str, items: &[Item]) -> anyhow::Result<Option<usize>> {
+    tui::pick(title, items)
+}
+
+/// Asks the user to pick one of `items` under the heading `title`, returning its index, or
+/// `None` if they cancelled.
+#[cfg(not(feature = "tui"))]
+pub(crate) fn pick(title: &str, items: &[Item]) -> anyhow::Result<Option<usize>> {
+    use crate::runner::prompt;
+
+    let mut shown = (0..items.len()).collect::<Vec<_>>();
+    loop {
+        eprintln!("{}:", title);
+        for (num, &idx) in shown.iter().enumerate() {
+            let Item { label, detail } = &items[idx];
+            eprintln!("  {:>3}) {}  {}", num + 1, label, detail);
+        }
+        let answer = prompt("number, or text to filter by (empty to cancel):")?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(num) = answer.parse::<usize>() {
+            match num.checked_sub(1).and_then(|num| shown.get(num)) {
+                Some(&idx) => return Ok(Some(idx)),
+                None => eprintln!("no item numbered {}", num),
+            }
+            continue;
+        }
+        match matches(items, &answer).as_slice() {
+            [] => eprintln!("nothing matches {:?}", answer),
+            [only] => return Ok(Some(*only)),
+            found => shown = found.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+mod tui {
+    use super::{matches, Item};
+    use anyhow::Context;
+    use crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    };
+    use ratatui::{
+        backend::CrosstermBackend,
+        layout::{Constraint, Direction, Layout},
+        style::{Modifier, Style},
+        text::{Line, Span},
+        widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+        Terminal,
+    };
+    use std::io::{self, Stderr};
+
+    type Term = Terminal<CrosstermBackend<Stderr>>;
+
+    pub(super) fn pick(title: &str, items: &[Item]) -> anyhow::Result<Option<usize>> {
+        let mut terminal = enter().context("failed to set up terminal")?;
+        let res = run(&mut terminal, title, items);
+        leave(&mut terminal).context("failed to restore terminal")?;
+        res
+    }
+
+    // The picker is drawn on `stderr`, so that it works when `stdout` is redirected.
+    fn enter() -> io::Result<Term> {
+        enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+        Terminal::new(CrosstermBackend::new(io::stderr()))
+    }
+
+    fn leave(terminal: &mut Term) -> io::Result<()> {
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()
+    }
+
+    fn run(terminal: &mut Term, title: &str, items: &[Item]) -> anyhow::Result<Option<usize>> {
+        let mut query = String::new();
+        let mut shown = matches(items, &query);
+        let mut selected = ListState::default();
+        selected.select(Some(0));
+        loop {
+            terminal
+                .draw(|frame| {
+                    let chunks = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Length(1), Constraint::Min(1)])
+                        .split(frame.size());
+                    frame.render_widget(Paragraph::new(format!("> {}", query)), chunks[0]);
+                    let list_items = shown
+                        .iter()
+                        .map(|&idx| {
+                            let Item { label, detail } = &items[idx];
+                            ListItem::new(Line::from(vec![
+                                Span::raw(label.as_str()),
+                                Span::raw("  "),
+                                Span::styled(
+                                    detail.as_str(),
+                                    Style::default().add_modifier(Modifier::DIM),
+                                ),
+                            ]))
+                        })
+                        .collect::<Vec<_>>();
+                    let list = List::new(list_items)
+                        .block(Block::default().borders(Borders::ALL).title(format!(
+                            "{} ({}/{})  ↑/↓ select  enter pick  esc cancel",
+                            title,
+                            shown.len(),
+                            items.len()
+                        )))
+                        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                    frame.render_stateful_widget(list, chunks[1], &mut selected);
+                })
+                .context("failed to draw picker")?;
+
+            let key = match event::read().context("failed to read terminal event")? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            let current = selected.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(None)
+                }
+                KeyCode::Enter => {
+                    if let Some(&idx) = shown.get(current) {
+                        return Ok(Some(idx));
+                    }
+                }
+                KeyCode::Down => {
+                    selected.select(Some((current + 1).min(shown.len().saturating_sub(1))))
+                }
+                KeyCode::Up => selected.select(Some(current.saturating_sub(1))),
+                KeyCode::Backspace => {
+                    query.pop();
+                    shown = matches(items, &query);
+                    selected.select(Some(0));
+                }
+                KeyCode::Char(c) => {
+                    query.push(c);
+                    shown = matches(items, &query);
+                    selected.select(Some(0));
+                }
+                _ => (),
+            }
+        }
+    }
+}
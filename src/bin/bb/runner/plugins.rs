@@ -0,0 +1,134 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Dispatch of unknown subcommands to external executables, like Git and Cargo do.
+//!
+//! Running `bb foo args...` runs `bb-foo args...` from `PATH`. Plugins get the context they need
+//! to cooperate with this tool through environment variables:
+//!
+//! * `BELLBOY_BIN`: the path of this tool's executable, for calling back into it (i.e., `list
+//!   --format json`).
+//! * `BELLBOY_REPO`: the name of the repo at the current working directory, if any.
+//! * `BELLBOY_READ_ONLY`: `1` if this tool is in read-only mode, which plugins should honor.
+//! * `BELLBOY_CONTEXT_JSON`: all of the above and more as a JSON object; see [`PluginContext`].
+//!   Its `protocol` field is bumped whenever the object changes incompatibly.
+
+use crate::runner::dirs::Directories;
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+/// The version of [`PluginContext`], bumped whenever it changes incompatibly.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// What a plugin is told about the invocation of this tool that's running it.
+#[derive(Debug, Serialize)]
+pub(crate) struct PluginContext {
+    protocol: u32,
+    /// The version of this tool.
+    version: &'static str,
+    bin: PathBuf,
+    config_file: PathBuf,
+    data_dir: PathBuf,
+    state_dir: PathBuf,
+    read_only: bool,
+    repo: Option<String>,
+}
+
+impl PluginContext {
+    pub fn new(dirs: &Directories, repo: Option<String>) -> anyhow::Result<Self> {
+        Ok(Self {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+            bin: env::current_exe().context("failed to find path of this executable")?,
+            config_file: dirs.config_file_path(),
+            data_dir: dirs.data_dir_path(),
+            state_dir: dirs.state_dir_path(),
+            read_only: dirs.is_read_only(),
+            repo,
+        })
+    }
+}
+
+/// The name of the executable implementing the subcommand `name`.
+fn executable_name(name: &str) -> String {
+    format!(
+        "{}-{}{}",
+        env!("CARGO_BIN_NAME"),
+        name,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+/// Finds the executable implementing the subcommand `name` on `PATH`.
+pub(crate) fn find(name: &str) -> Option<PathBuf> {
+    // Don't let names like `../foo` escape the directories on `PATH`.
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return None;
+    }
+    let file_name = executable_name(name);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs the plugin at `path` with `args`, waiting for it to exit.
+pub(crate) fn run(
+    path: &Path,
+    args: &[OsString],
+    context: &PluginContext,
+) -> anyhow::Result<ExitStatus> {
+    let PluginContext {
+        protocol: _,
+        version: _,
+        bin,
+        config_file: _,
+        data_dir: _,
+        state_dir: _,
+        read_only,
+        repo,
+    } = context;
+    let mut cmd = Command::new(path);
+    cmd.args(args)
+        .env("BELLBOY_BIN", bin)
+        .env("BELLBOY_READ_ONLY", if *read_only { "1" } else { "0" })
+        .env(
+            "BELLBOY_CONTEXT_JSON",
+            serde_json::to_string(context).context("failed to serialize plugin context")?,
+        );
+    match repo {
+        Some(repo) => cmd.env("BELLBOY_REPO", repo),
+        None => cmd.env_remove("BELLBOY_REPO"),
+    };
+    tracing::debug!("running plugin {:?}", path);
+    cmd.status()
+        .with_context(|| anyhow!("failed to run plugin {:?}", path))
+}
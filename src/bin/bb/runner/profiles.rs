@@ -0,0 +1,200 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Named profiles, each an isolated set of repo DBs, overlay repos, state, and configuration.
+//!
+//! The default profile keeps its files where this tool always has, directly in its data, state,
+//! and configuration directories. Other profiles keep theirs in a `profiles/<NAME>` subdirectory
+//! of each; see [`Directories`]. Which profile is used is chosen with `--profile`, falling back to
+//! the one last chosen with `profile switch`.
+
+use crate::{
+    runner::{dirs::Directories, error::Error},
+    suggest,
+};
+use anyhow::{anyhow, Context};
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    str::FromStr,
+};
+use thiserror::Error as ThisError;
+
+/// The name under which the default profile is listed and can be chosen.
+pub(crate) const DEFAULT_PROFILE: &str = "default";
+
+/// The name of a profile, which doubles as the name of its directories.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) struct ProfileName(String);
+
+#[derive(Debug, ThisError)]
+#[error(
+    "expected profile name to be non-empty and only contain hyphens (\"-\"), underscores \
+    (\"_\"), or alphanumeric characters; got {actual:?}"
+)]
+pub struct InvalidProfileNameError {
+    actual: String,
+}
+
+impl FromStr for ProfileName {
+    type Err = InvalidProfileNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = !s.is_empty()
+            && s.len() <= 100
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'));
+        if valid {
+            Ok(Self(s.to_owned()))
+        } else {
+            Err(InvalidProfileNameError {
+                actual: s.to_owned(),
+            })
+        }
+    }
+}
+
+impl Display for ProfileName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self(inner) = self;
+        f.write_str(inner)
+    }
+}
+
+impl ProfileName {
+    pub fn as_str(&self) -> &str {
+        let Self(inner) = self;
+        inner
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.as_str() == DEFAULT_PROFILE
+    }
+}
+
+/// Lists the names of the profiles that exist, starting with the default profile.
+pub(crate) fn list(dirs: &Directories) -> anyhow::Result<Vec<ProfileName>> {
+    let profiles_dir = dirs.profiles_dir_path();
+    let mut names = Vec::new();
+    let entries = match fs::read_dir(&profiles_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(vec![ProfileName(DEFAULT_PROFILE.to_owned())])
+        }
+        Err(e) => {
+            return Err(e).with_context(|| anyhow!("failed to read directory {:?}", profiles_dir))
+        }
+    };
+    for entry in entries {
+        let entry =
+            entry.with_context(|| anyhow!("failed to read directory {:?}", profiles_dir))?;
+        let name = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| ProfileName::from_str(name).ok());
+        match name {
+            Some(name) if !name.is_default() && entry.path().is_dir() => names.push(name),
+            _ => tracing::debug!("ignoring {:?}, which isn't a profile", entry.path()),
+        }
+    }
+    names.sort();
+    names.insert(0, ProfileName(DEFAULT_PROFILE.to_owned()));
+    Ok(names)
+}
+
+fn exists(dirs: &Directories, name: &ProfileName) -> anyhow::Result<bool> {
+    Ok(name.is_default() || dirs.profiles_dir_path().join(name.as_str()).is_dir())
+}
+
+fn not_found(dirs: &Directories, name: &ProfileName) -> Error {
+    let existing = list(dirs).unwrap_or_default();
+    Error::NotFound(anyhow!(
+        "no profile named {:?} exists{}",
+        name.as_str(),
+        suggest::did_you_mean(name.as_str(), existing.iter().map(ProfileName::as_str))
+    ))
+}
+
+/// Makes `dirs` point at the profile named `requested`, or the active one if it's `None`.
+/// Fails if `requested` doesn't exist. An active profile that no longer exists is reported, and
+/// the default profile used instead.
+pub(crate) fn select(dirs: &mut Directories, requested: Option<ProfileName>) -> Result<(), Error> {
+    let name = match requested {
+        Some(name) => {
+            if !exists(dirs, &name)? {
+                return Err(not_found(dirs, &name));
+            }
+            name
+        }
+        None => match active(dirs)? {
+            Some(name) if exists(dirs, &name)? => name,
+            Some(name) => {
+                tracing::warn!(
+                    "active profile {:?} no longer exists; using the default profile",
+                    name.as_str()
+                );
+                return Ok(());
+            }
+            None => return Ok(()),
+        },
+    };
+    if !name.is_default() {
+        dirs.set_profile(name);
+    }
+    Ok(())
+}
+
+/// The profile last chosen with [`switch`], if it isn't the default.
+fn active(dirs: &Directories) -> anyhow::Result<Option<ProfileName>> {
+    let path = dirs.active_profile_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| anyhow!("failed to read {:?}", path)),
+    };
+    let name =
+        ProfileName::from_str(contents.trim()).with_context(|| anyhow!("{:?} is invalid", path))?;
+    Ok((!name.is_default()).then_some(name))
+}
+
+/// Creates the profile named `name`, failing if it already exists.
+pub(crate) fn create(dirs: &Directories, name: &ProfileName) -> Result<(), Error> {
+    if exists(dirs, name)? {
+        return Err(Error::Conflict(anyhow!(
+            "profile {:?} already exists",
+            name.as_str()
+        )));
+    }
+    let dir = dirs.profiles_dir_path().join(name.as_str());
+    fs::create_dir_all(&dir)
+        .with_context(|| anyhow!("failed to create directory {:?}", dir))
+        .map_err(Error::from)
+}
+
+/// Makes `name` the profile used when `--profile` isn't given.
+pub(crate) fn switch(dirs: &Directories, name: &ProfileName) -> Result<(), Error> {
+    if !exists(dirs, name)? {
+        return Err(not_found(dirs, name));
+    }
+    let path = dirs.active_profile_path();
+    let res = if name.is_default() {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    } else {
+        fs::write(&path, format!("{}\n", name))
+    };
+    res.with_context(|| anyhow!("failed to write {:?}", path))
+        .map_err(Error::from)
+}
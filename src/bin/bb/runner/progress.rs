@@ -0,0 +1,85 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! A progress bar for long-running operations, drawn on `stderr` when it's a terminal.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+/// How often the bar is redrawn at most, so that drawing doesn't slow down what it measures.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+const BAR_WIDTH: usize = 30;
+
+/// Progress through `total` steps of an operation described by `label`.
+#[derive(Debug)]
+pub(crate) struct ProgressBar {
+    label: &'static str,
+    total: usize,
+    done: usize,
+    /// When the bar was last drawn, or `None` if it isn't drawn at all.
+    last_drawn: Option<Instant>,
+}
+
+impl ProgressBar {
+    pub fn new(label: &'static str, total: usize) -> Self {
+        let visible = io::stderr().is_terminal() && total > 0;
+        Self {
+            label,
+            total,
+            done: 0,
+            last_drawn: visible.then(|| Instant::now() - REDRAW_INTERVAL),
+        }
+    }
+
+    /// Marks one more step as done.
+    pub fn inc(&mut self) {
+        self.done = (self.done + 1).min(self.total);
+        if let Some(last_drawn) = self.last_drawn {
+            if self.done == self.total || last_drawn.elapsed() >= REDRAW_INTERVAL {
+                self.draw();
+            }
+        }
+    }
+
+    fn draw(&mut self) {
+        let Self {
+            label,
+            total,
+            done,
+            last_drawn,
+        } = self;
+        let filled = BAR_WIDTH * *done / *total;
+        let _ = write!(
+            io::stderr(),
+            "\r{} [{}{}] {}/{}",
+            label,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            done,
+            total
+        );
+        *last_drawn = Some(Instant::now());
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.last_drawn.is_some() {
+            // Clear the bar, so that whatever is printed next starts on a clean line.
+            let _ = write!(io::stderr(), "\r\x1b[2K");
+        }
+    }
+}
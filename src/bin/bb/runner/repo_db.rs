@@ -11,43 +11,109 @@
 //
 // You should have received a copy of the GNU General Public License along with Bellboy.  If not,
 // see <https://www.gnu.org/licenses/>.
-use self::conflict::{RepoConflictHandler, RepoConflictSearcher};
+use self::conflict::{NameConflictResolution, RepoConflictHandler, RepoConflictSearcher};
+pub(crate) use self::scan::ScanPolicy;
+use self::scan::ScanProblem;
+pub use self::{field::RepoField, record::RepoRecord};
 use crate::{
-    cli::CliRepoKind,
+    i18n::msg,
     runner::{
         canonicalize_path,
-        dirs::Directories,
-        git::{DynGit, DynGitRepo, GitRepoTrait, GitTrait, OpenRepoOptions, RepoSource},
+        config::OverlayConfig,
+        created_dirs,
+        dirs::{contract_home_path, expand_home_path, Directories},
+        error::Error,
+        excludes,
+        git::{
+            default_remote_url, DynGit, DynGitRepo, GitRepoTrait, GitTrait, OpenRepoOptions,
+            RepoSource,
+        },
+        kind::RepoKind,
+        overlay_files,
+        progress::ProgressBar,
         repo_db::conflict::{normalization::NormalizedEqOutcome, RepoConflictCheck},
+        sync::SyncStrategy,
     },
+    suggest,
 };
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use format::lazy_format;
 use lifetime::{IntoStatic, ToBorrowed};
-use path_dsl::path;
 use remove_dir_all::remove_dir_all;
 use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use std::{
-    borrow::Cow,
-    collections::BTreeMap,
+    borrow::{Borrow, Cow},
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
     fmt::{self, Debug, Display, Formatter},
     fs::{self, create_dir, remove_file, OpenOptions},
     io::{self, BufReader, Read},
-    mem::transmute,
+    num::NonZeroU32,
     ops::Deref,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
 };
 use thiserror::Error as ThisError;
 
 pub mod conflict;
-
+mod field;
+mod record;
+mod scan;
+mod schema;
+
+/// The repo entries known to this tool, which may be shared between threads.
+///
+/// Queries go through a [`RepoDbSnapshot`], which keeps seeing the entries as they were when it
+/// was taken. Changes go through a [`RepoDbTransaction`], and only become visible to new
+/// snapshots once it's committed. Committed changes are written to disk by [`Self::flush`].
 #[derive(Debug)]
 pub(super) struct RepoDb {
+    committed: RwLock<Arc<RepoDbSnapshot>>,
+    /// Held by the open transaction, so that transactions are applied one at a time.
+    writer: Mutex<()>,
+    /// Whether committed changes haven't been written to disk yet.
+    needs_persist: AtomicBool,
+}
+
+/// A read-only view of the repo entries. See [`RepoDb::snapshot`].
+#[derive(Debug)]
+pub(super) struct RepoDbSnapshot {
     repos: BTreeMap<RepoName<'static>, RepoEntry<'static>>,
+    includes: Includes,
+    /// Overlay repos left out of `repos` because of a name conflict that [`ScanPolicy`] let
+    /// through. They're written back unchanged, so that resolving the conflict brings them back.
+    hidden: BTreeMap<RepoName<'static>, RepoEntry<'static>>,
+}
+
+/// Changes to the repo entries, made against a private copy of them. See [`RepoDb::transaction`].
+///
+/// Queries against a transaction see the changes made in it so far. Dropping a transaction without
+/// calling [`Self::commit`] discards its changes to the entries, but not to anything on disk, i.e.,
+/// a repo it cloned.
+#[derive(Debug)]
+pub(super) struct RepoDbTransaction<'db> {
+    db: &'db RepoDb,
+    _writer: MutexGuard<'db, ()>,
+    working: RepoDbSnapshot,
+    /// Whether this transaction changed anything that needs to be written to disk.
     needs_persist: bool,
 }
 
+/// Standalone repo entries merged in from the files listed in the standalone repos DB's `include`
+/// field, i.e., a set of repos managed by an employer. Included entries are read-only: they're
+/// never written back, and can't be changed or removed through this tool.
+#[derive(Clone, Debug, Default)]
+struct Includes {
+    /// The `include` field as it was written, so that it can be written back unchanged.
+    paths: Vec<PathBuf>,
+    /// The resolved path of the file each included repo came from.
+    origins: BTreeMap<RepoName<'static>, PathBuf>,
+}
+
 /// A name given to a repository
 #[derive(Clone, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize, ToBorrowed)]
 pub struct RepoName<'a>(#[serde(borrow)] Cow<'a, str>);
@@ -61,6 +127,14 @@ impl Deref for RepoName<'_> {
     }
 }
 
+// `RepoName`'s ordering and equality are those of its inner string, so maps keyed by names can be
+// looked up with any `&str`, without allocating a `'static` key.
+impl Borrow<str> for RepoName<'_> {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
 impl Debug for RepoName<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let Self(inner) = self;
@@ -148,6 +222,19 @@ impl<'a, 'de: 'a> Deserialize<'de> for RepoName<'a> {
 #[derive(Debug, IntoStatic, ToBorrowed)]
 pub struct RepoEntry<'a> {
     kind: RepoEntryKind<'a>,
+    /// Whether this repo is skipped by bulk operations unless they're asked to include pinned
+    /// repos.
+    pinned: bool,
+    /// Whether commands that would change this repo (i.e., `run` and `remove`) refuse to, unless
+    /// told to go ahead anyway.
+    read_only: bool,
+    /// What `sync` does when this repo has diverged from upstream, unless told otherwise.
+    sync_strategy: Option<SyncStrategy>,
+    info: RepoInfo<'a>,
+    /// Which machine last synced this repo with `sync`, and when.
+    last_sync: Option<SyncRecord<'a>>,
+    /// Repos that `for-each` and `sync` must handle before this one.
+    depends_on: Dependencies<'a>,
 }
 
 impl<'a> RepoEntry<'a> {}
@@ -158,25 +245,52 @@ impl RepoEntry<'_> {
         dirs: &Directories,
         name: RepoName<'_>,
     ) -> anyhow::Result<Cow<'_, Path>> {
-        let Self { kind } = self;
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
         kind.path(dirs, name)
     }
 
     pub(crate) fn work_tree_path(&self, dirs: &Directories) -> anyhow::Result<Cow<'_, Path>> {
-        let Self { kind } = self;
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
         kind.work_tree_path(dirs)
     }
 
     pub(crate) fn short_desc(&self) -> impl Display + '_ {
-        let Self { kind } = self;
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
         lazy_format!(move |f| {
+            f.write_str(kind.kind().human_name())?;
             match kind {
-                RepoEntryKind::Standalone { app_info: _, path } => {
-                    write!(f, "standalone repo at {}", path.display())
-                }
-                RepoEntryKind::Overlay {} => {
-                    write!(f, "overlay repo")
+                RepoEntryKind::Standalone { path, .. } | RepoEntryKind::Unmanaged { path } => {
+                    write!(f, " at {}", path.display())
                 }
+                RepoEntryKind::Overlay {
+                    git_dir: Some(git_dir),
+                    ..
+                } => write!(f, " with Git directory {}", git_dir.display()),
+                RepoEntryKind::Overlay { git_dir: None, .. } => Ok(()),
             }
         })
     }
@@ -187,11 +301,22 @@ impl RepoEntry<'_> {
         dirs: &Directories,
         name: RepoName<'_>,
     ) -> anyhow::Result<DynGitRepo> {
-        let Self { kind } = self;
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
 
         let repo_path = kind.path(dirs, name.to_borrowed())?;
         let work_tree_path;
         let options = match kind {
+            RepoEntryKind::Unmanaged { .. } => {
+                bail!("{:?} is an unmanaged directory, not a Git repo", name)
+            }
             RepoEntryKind::Standalone { .. } => OpenRepoOptions::Normal {
                 work_tree_path: &repo_path,
             },
@@ -203,39 +328,370 @@ impl RepoEntry<'_> {
                 }
             }
         };
-        git.open_repo(options)
-            .with_context(|| anyhow!("failed to open {:?} repo", name))
+        let mut repo = git
+            .open_repo(options)
+            .with_context(|| anyhow!("failed to open {:?} repo", name))?;
+        if let RepoEntryKind::Overlay { .. } = kind {
+            if let Err(e) = excludes::refresh_overlay_excludes(dirs, &mut repo, &name) {
+                tracing::warn!(
+                    "{:?}",
+                    e.context(anyhow!("failed to refresh excludes of {:?}", name))
+                );
+            }
+        }
+        Ok(repo)
     }
 
-    pub fn kind(&self) -> CliRepoKind {
-        let Self { kind } = self;
+    pub fn kind(&self) -> RepoKind {
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
         kind.kind()
     }
+
+    pub fn pinned(&self) -> bool {
+        let Self {
+            kind: _,
+            pinned,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        *pinned
+    }
+
+    pub fn read_only(&self) -> bool {
+        let Self {
+            kind: _,
+            pinned: _,
+            read_only,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        *read_only
+    }
+
+    /// Fails unless this repo may be changed, i.e., it isn't marked read-only or `allow` is set.
+    /// `what` describes the refused operation, i.e., "remove".
+    pub(crate) fn ensure_writable(
+        &self,
+        name: &RepoName<'_>,
+        what: impl Display,
+        allow: bool,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            allow || !self.read_only(),
+            "refusing to {} {:?}, since it's marked read-only; pass `--allow-read-only` to do so \
+            anyway, or run `{} unprotect {}`",
+            what,
+            name,
+            env!("CARGO_BIN_NAME"),
+            name
+        );
+        Ok(())
+    }
+
+    pub fn last_sync(&self) -> Option<&SyncRecord<'_>> {
+        let Self {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync,
+            depends_on: _,
+        } = self;
+        last_sync.as_ref()
+    }
+
+    pub fn depends_on(&self) -> &Dependencies<'_> {
+        let Self {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on,
+        } = self;
+        depends_on
+    }
+
+    pub fn info(&self) -> &RepoInfo<'_> {
+        let Self {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        info
+    }
+
+    pub fn sync_strategy(&self) -> Option<SyncStrategy> {
+        let Self {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        *sync_strategy
+    }
+
+    /// The upstream source recorded for this repo, if any.
+    pub(crate) fn source(&self) -> Option<SourceInfo<'_>> {
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        match kind {
+            RepoEntryKind::Overlay { source, .. } | RepoEntryKind::Standalone { source, .. } => {
+                source.to_borrowed()
+            }
+            RepoEntryKind::Unmanaged { .. } => None,
+        }
+    }
+
+    /// What standalone repos were registered as an application's data with, if anything. Always
+    /// `None` for other kinds of entries.
+    pub(crate) fn app_info(&self) -> Option<&AppInfo<'_>> {
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        match kind {
+            RepoEntryKind::Overlay { .. } | RepoEntryKind::Unmanaged { .. } => None,
+            RepoEntryKind::Standalone { app_info, .. } => app_info.as_ref(),
+        }
+    }
+
+    /// The upstream source that the remotes and branch of this repo, open as `repo`, say it has.
+    ///
+    /// A detached `HEAD` keeps the recorded branch, since checking out a commit doesn't change
+    /// where the repo comes from.
+    fn current_source(&self, repo: &DynGitRepo) -> anyhow::Result<Option<SourceInfo<'static>>> {
+        let remotes = repo.remotes()?;
+        let url = match default_remote_url(&remotes) {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let branch = match repo.current_branch()? {
+            Some(branch) => Some(branch.into()),
+            None => self
+                .source()
+                .and_then(|SourceInfo { url: _, branch }| branch)
+                .map(|branch| branch.into_owned().into()),
+        };
+        Ok(Some(SourceInfo {
+            url: url.to_owned().into(),
+            branch,
+        }))
+    }
+
+    /// Updates the recorded upstream source of this repo to match its current remotes and branch,
+    /// returning whether anything changed. Unmanaged directories have no source to record.
+    fn record_source(
+        &mut self,
+        git: &DynGit,
+        dirs: &Directories,
+        name: RepoName<'_>,
+    ) -> anyhow::Result<bool> {
+        if !self.kind().is_git_repo() {
+            return Ok(false);
+        }
+        let repo = self.open(git, dirs, name.to_borrowed())?;
+        let current = self.current_source(&repo)?;
+        if self.source() == current {
+            return Ok(false);
+        }
+
+        let Self {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = self;
+        match kind {
+            RepoEntryKind::Overlay { source, .. } | RepoEntryKind::Standalone { source, .. } => {
+                *source = current
+            }
+            RepoEntryKind::Unmanaged { .. } => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Details about a repo entered by the user with `describe`, for their own reference.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    IntoStatic,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+    ToBorrowed,
+)]
+pub struct RepoInfo<'a> {
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Cow<'a, str>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<Cow<'a, str>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Cow<'a, str>>,
+}
+
+impl RepoInfo<'_> {
+    fn is_empty(&self) -> bool {
+        let Self {
+            description,
+            homepage,
+            notes,
+        } = self;
+        description.is_none() && homepage.is_none() && notes.is_none()
+    }
+}
+
+/// Where a repo was cloned from, recorded when it's added, and brought up to date with its remotes
+/// by `doctor --fix`.
+#[derive(
+    Clone, Debug, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize, ToBorrowed,
+)]
+pub struct SourceInfo<'a> {
+    /// The URL of the repo's default remote; see [`default_remote_url`].
+    #[serde(borrow)]
+    pub url: Cow<'a, str>,
+    /// The branch checked out when this was recorded, if any.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<Cow<'a, str>>,
+}
+
+/// A machine syncing a repo; see [`MachineIdentity`](crate::runner::machine::MachineIdentity).
+#[derive(
+    Clone, Debug, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize, ToBorrowed,
+)]
+pub struct SyncRecord<'a> {
+    /// The ID of the machine.
+    #[serde(borrow)]
+    pub machine: Cow<'a, str>,
+    /// The machine's hostname at the time.
+    #[serde(borrow)]
+    pub hostname: Cow<'a, str>,
+    /// When the sync finished, in seconds since the Unix epoch.
+    pub time: u64,
+}
+
+/// The names of the repos that a repo depends on, in the order they were given.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct Dependencies<'a>(#[serde(borrow)] Vec<RepoName<'a>>);
+
+impl<'a> Dependencies<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = RepoName<'_>> {
+        self.0.iter().map(|name| name.to_borrowed())
+    }
+}
+
+impl<'a> FromIterator<RepoName<'a>> for Dependencies<'a> {
+    fn from_iter<I: IntoIterator<Item = RepoName<'a>>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoStatic for Dependencies<'_> {
+    type Static = Dependencies<'static>;
+
+    fn into_static(self) -> Dependencies<'static> {
+        self.0.into_iter().map(IntoStatic::into_static).collect()
+    }
+}
+
+impl<'b> ToBorrowed for &'b Dependencies<'_> {
+    type Borrowed = Dependencies<'b>;
+
+    fn to_borrowed(self) -> Dependencies<'b> {
+        self.iter().collect()
+    }
 }
 
 #[derive(Debug, IntoStatic, ToBorrowed)]
 enum RepoEntryKind<'a> {
     /// A bare Git repository with a work tree in the user's home directory, set up by this tool.
-    Overlay {},
+    Overlay {
+        source: Option<SourceInfo<'a>>,
+        /// A work tree to use instead of the home directory, set with `overlay set-worktree`.
+        work_tree: Option<Cow<'a, Path>>,
+        /// A Git directory to use instead of one in the overlay repos directory, i.e., an
+        /// existing `~/.cfg` registered with `overlay register --git-dir`.
+        git_dir: Option<Cow<'a, Path>>,
+    },
     /// A whole (non-bare) Git repository located at `repo_path`.
     Standalone {
         path: Cow<'a, Path>,
         app_info: Option<AppInfo<'a>>,
+        source: Option<SourceInfo<'a>>,
     },
+    /// A directory at `path` that isn't a Git repo, registered with `unmanaged register`.
+    Unmanaged { path: Cow<'a, Path> },
 }
 
 impl RepoEntryKind<'_> {
     pub fn path(&self, dirs: &Directories, name: RepoName<'_>) -> anyhow::Result<Cow<'_, Path>> {
         Ok(match self {
-            Self::Overlay {} => Self::overlay_path(dirs, name)?.into(),
-            Self::Standalone { app_info: _, path } => path.to_borrowed(),
+            Self::Overlay {
+                git_dir: Some(git_dir),
+                ..
+            } => git_dir.to_borrowed(),
+            Self::Overlay { git_dir: None, .. } => Self::overlay_path(dirs, name)?.into(),
+            Self::Standalone { path, .. } | Self::Unmanaged { path } => path.to_borrowed(),
         })
     }
 
     pub fn work_tree_path(&self, dirs: &Directories) -> anyhow::Result<Cow<'_, Path>> {
         match self {
-            Self::Overlay {} => dirs.home_dir_path().map(Into::into),
-            Self::Standalone { app_info: _, path } => Ok(path.to_borrowed()),
+            Self::Overlay {
+                work_tree: Some(work_tree),
+                ..
+            } => Ok(work_tree.to_borrowed()),
+            Self::Overlay {
+                work_tree: None, ..
+            } => dirs.home_dir_path().map(Into::into),
+            Self::Standalone { path, .. } | Self::Unmanaged { path } => Ok(path.to_borrowed()),
         }
     }
 
@@ -245,206 +701,956 @@ impl RepoEntryKind<'_> {
         Ok(path)
     }
 
-    pub fn kind(&self) -> CliRepoKind {
+    pub fn kind(&self) -> RepoKind {
         match self {
-            Self::Standalone { .. } => CliRepoKind::Standalone,
-            Self::Overlay { .. } => CliRepoKind::Overlay,
+            Self::Standalone { .. } => RepoKind::Standalone,
+            Self::Overlay { .. } => RepoKind::Overlay,
+            Self::Unmanaged { .. } => RepoKind::Unmanaged,
         }
     }
 }
 
 impl RepoDb {
-    pub fn new(dirs: &Directories) -> anyhow::Result<Self> {
-        let mut repos = {
-            StandaloneRepoDb::from_toml_on_disk(dirs)?
-                .into_runner_repos()
-                .collect::<BTreeMap<_, _>>()
+    /// Loads the repo DBs, reporting problems with them as `policy` says to.
+    pub fn new(dirs: &Directories, policy: &ScanPolicy) -> anyhow::Result<Self> {
+        let mut standalone_db = StandaloneRepoDb::from_toml_on_disk(dirs)?;
+        // Rewrite any paths stored as absolute paths into the home directory by older versions.
+        let mut needs_persist = standalone_db.expand_home_paths(&dirs.home_dir_path()?);
+        let include_paths = standalone_db.include.clone();
+        let mut repos = BTreeMap::new();
+        for (name, repo) in standalone_db.into_runner_repos() {
+            if let Some(first_repo) = repos.get(&name) {
+                return Err(Error::Corruption(anyhow!(
+                    "repo name {:?} is listed as both {} and {} in the standalone repos DB",
+                    name,
+                    RepoEntry::short_desc(first_repo),
+                    repo.short_desc(),
+                ))
+                .into());
+            }
+            repos.insert(name, repo);
+        }
+        let includes = Self::merge_includes(dirs, include_paths, &mut repos, policy)?;
+
+        let scanned_overlay_names = Self::scan_overlay_repos_dir(dirs, policy)?;
+        let overlay_repos = match OverlayRepoDb::from_toml_on_disk(dirs)? {
+            Some(db) => {
+                // The directory scan is only used to check that the DB is consistent with what's
+                // on disk.
+                for name in &scanned_overlay_names {
+                    if !db.overlay_repos.contains_key(name) {
+                        policy.report(
+                            ScanProblem::UnregisteredOverlayDir,
+                            anyhow!(
+                                "overlay repo directory {:?} has no entry in the overlay repos \
+                                DB, ignoring it; if it was renamed by hand, `{} doctor --fix` \
+                                can follow the rename",
+                                name,
+                                env!("CARGO_BIN_NAME")
+                            ),
+                        )?;
+                    }
+                }
+                // Repos with a Git directory elsewhere aren't expected to be in the scan.
+                for (name, entry) in &db.overlay_repos {
+                    if entry.git_dir.is_none() && !scanned_overlay_names.contains(name) {
+                        policy.report(
+                            ScanProblem::MissingOverlayDir,
+                            anyhow!(
+                                "overlay repo {:?} is in the overlay repos DB, but its repo \
+                                directory is missing",
+                                name
+                            ),
+                        )?;
+                    }
+                }
+                db
+            }
+            None => {
+                tracing::info!(
+                    "no overlay repos DB found, creating one from {} overlay repo \
+                    director(y/ies)",
+                    scanned_overlay_names.len()
+                );
+                needs_persist = true;
+                OverlayRepoDb::backfill(scanned_overlay_names)
+            }
         };
 
-        let overlay_repos_dir_path = dirs.overlay_repos_dir_path()?;
-        log::trace!("overlay repos path: {}", overlay_repos_dir_path.display());
-        match overlay_repos_dir_path.read_dir().with_context(|| {
-            anyhow!(
-                "failed to read overlay repo dirs from {}",
-                overlay_repos_dir_path.display(),
-            )
-        }) {
-            Ok(entries) => {
-                entries.filter_map(|ent| {
-                        (|| -> anyhow::Result<_> {
-                            let ent = ent.with_context(|| anyhow!("failed to read a dir entry in overlay repo path"))?;
-
-                            let file_name = ent.file_name();
-                            let file_name = file_name.to_str().context("file name is not convertible to UTF-8")
-                                .and_then(|finm| -> Result<RepoName<'static>> {
-                                    finm.parse().map_err(anyhow::Error::new)
-                                })
-                                .with_context(|| anyhow!("file name {:?} is not a valid repo name", file_name))?;
-
-                            if !ent.path().is_dir() {
-                                log::warn!(
-                                    "skipping overlay repo dir item {:?}, which does not appear to be a directory",
-                                    file_name,
-                                );
-                                return Ok(None);
-                            }
-
-                            Ok(Some(file_name))
-                        })().transpose()
-                    }).try_for_each(|ent| {
-                        match ent {
-                            Ok(repo_name) => {
-                                let repo = RepoEntry { kind: RepoEntryKind::Overlay {} };
-                                log::trace!("found overlay repo {:?}", repo_name);
-                                if let Some(first_repo) = repos.get(&repo_name) {
-                                    bail!(
-                                        "repo name conflict: repo name {:?} found as both:\n1. {}\n2. {}",
-                                        repo_name,
-                                        first_repo.short_desc(),
-                                        repo.short_desc(),
-                                    );
-                                }
-                                repos.insert(repo_name, repo);
-                            }
-                            Err(e) => log::warn!("{}", e),
-                        };
-                        Ok(())
-                    })?;
+        let mut hidden = BTreeMap::new();
+        for (repo_name, repo) in overlay_repos.into_runner_repos(dirs.home_dir_path()?) {
+            tracing::trace!("found overlay repo {:?}", repo_name);
+            if let Some(first_repo) = repos.get(&repo_name) {
+                policy.report(
+                    ScanProblem::NameConflict,
+                    anyhow!(
+                        "repo name conflict: repo name {:?} found as both:\n1. {}\n2. {}\nhiding \
+                        the latter",
+                        repo_name,
+                        first_repo.short_desc(),
+                        repo.short_desc(),
+                    ),
+                )?;
+                hidden.insert(repo_name, repo);
+                continue;
             }
-            Err(e) => log::warn!("{}", e),
+            repos.insert(repo_name, repo);
         }
 
         Ok(Self {
-            repos,
-            needs_persist: false,
+            committed: RwLock::new(Arc::new(RepoDbSnapshot {
+                repos,
+                includes,
+                hidden,
+            })),
+            writer: Mutex::new(()),
+            needs_persist: AtomicBool::new(needs_persist),
         })
     }
 
-    /// # Panics
+    /// Takes a snapshot of the committed repo entries. It isn't affected by later commits.
+    pub fn snapshot(&self) -> Arc<RepoDbSnapshot> {
+        Arc::clone(&self.committed.read().unwrap())
+    }
+
+    /// Starts a transaction against the committed repo entries, waiting for any other open
+    /// transaction to finish first.
     ///
-    /// You should call [`Self::validate_no_add_conflicts`] first!
-    fn insert(
-        &mut self,
-        name: RepoName<'static>,
-        repo: RepoEntry<'static>,
-    ) -> (RepoName<'_>, RepoEntry<'_>) {
-        let Self {
-            repos,
-            needs_persist,
-        } = self;
-        assert!(repos.insert(name.clone(), repo).is_none());
-        *needs_persist = true;
+    /// Don't start a transaction on a thread that already has one open; it would wait forever.
+    pub fn transaction(&self) -> RepoDbTransaction<'_> {
+        let writer = self.writer.lock().unwrap();
+        let working = RepoDbSnapshot::clone(&self.snapshot());
+        RepoDbTransaction {
+            db: self,
+            _writer: writer,
+            working,
+            needs_persist: false,
+        }
+    }
 
-        let (name, repo) = repos.get_key_value(&name).unwrap();
-        (name.to_borrowed(), repo.to_borrowed())
+    /// Reads the standalone repo DB fragments at `paths`, and adds their entries to `repos`.
+    /// Fragments that can't be read and entries that conflict with others are reported and
+    /// skipped, so that a broken shared file doesn't lock the user out of their own repos.
+    fn merge_includes(
+        dirs: &Directories,
+        paths: Vec<PathBuf>,
+        repos: &mut BTreeMap<RepoName<'static>, RepoEntry<'static>>,
+        policy: &ScanPolicy,
+    ) -> anyhow::Result<Includes> {
+        let home = dirs.home_dir_path()?;
+        let db_dir = dirs.data_dir_path();
+        let mut origins = BTreeMap::new();
+        for path in &paths {
+            let resolved = expand_home_path(path, &home).unwrap_or_else(|| db_dir.join(path));
+            let fragment = match StandaloneRepoDb::from_include_file(&resolved, &home) {
+                Ok(fragment) => fragment,
+                Err(e) => {
+                    policy.report(
+                        ScanProblem::BrokenInclude,
+                        e.context("ignoring included repo DB"),
+                    )?;
+                    continue;
+                }
+            };
+            for (name, repo) in fragment.into_runner_repos() {
+                let path = repo.path(dirs, name.to_borrowed())?.into_owned();
+                let conflict = repos.iter().find(|(existing_name, existing)| {
+                    **existing_name == name
+                        || matches!(
+                            existing.path(dirs, existing_name.to_borrowed()),
+                            Ok(existing_path) if existing_path == path
+                        )
+                });
+                if let Some((existing_name, existing)) = conflict {
+                    let existing_origin = lazy_format!(|f| match origins.get(existing_name) {
+                        Some(origin) => write!(f, "included from {:?}", origin),
+                        None => write!(f, "in the standalone repos DB"),
+                    });
+                    policy.report(
+                        ScanProblem::IncludeConflict,
+                        anyhow!(
+                            "ignoring repo {:?} included from {:?}: it conflicts with repo {:?} \
+                            ({}) {}",
+                            name,
+                            resolved,
+                            existing_name,
+                            existing.short_desc(),
+                            existing_origin,
+                        ),
+                    )?;
+                    continue;
+                }
+                origins.insert(name.clone(), resolved.clone());
+                repos.insert(name, repo);
+            }
+        }
+        Ok(Includes { paths, origins })
     }
 
-    pub fn new_overlay(
-        &mut self,
+    /// Lists the names of the directories in the overlay repos directory, skipping anything else
+    /// in it as `policy` allows.
+    fn scan_overlay_repos_dir(
         dirs: &Directories,
-        git: &DynGit,
-        name: RepoName<'_>,
-        options: NewOverlayOptions<'_>,
-        conflict_handler: &mut dyn RepoConflictHandler,
-    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
-        let repo = RepoEntry {
-            kind: RepoEntryKind::Overlay {},
-        };
-        self.validate_no_add_conflicts(
-            dirs,
-            name.to_borrowed(),
-            repo.to_borrowed(),
-            conflict_handler,
-        )?;
-        // // TODO: improve diagnostic for repo already existing
-        // create_dir(&repo.path(dirs, name.to_borrowed())?) // TODO: revert creating this if something fails
-        //     .context("failed to make clone target directory")?;
-        let (name, repo) = match options {
-            NewOverlayOptions::Clone {
-                source,
-                no_checkout,
-            } => {
-                let (name, repo) = self.clone_new(
-                    dirs,
-                    git,
-                    name.into_static(),
-                    repo,
-                    source.into_static(),
-                    conflict_handler,
-                )?;
-                match repo
-                    .open(git, dirs, name.to_borrowed())
-                    .and_then(|mut repo| {
-                        repo.reset()
-                            .context("failed to execute reset staged changes")?;
-                        if !no_checkout {
-                            // TODO: check out files
-                            repo.restore().context("failed to populate work tree")?;
-                        }
-                        Ok(())
-                    }) {
-                    Ok(()) => (),
-                    Err(e) => log::warn!("{}", e),
-                };
-                (name, repo)
+        policy: &ScanPolicy,
+    ) -> Result<BTreeSet<RepoName<'static>>, Error> {
+        let overlay_repos_dir_path = match dirs.overlay_repos_dir_path() {
+            Ok(path) => path,
+            Err(e) => {
+                policy.report(ScanProblem::InvalidOverlayDirEntry, e)?;
+                return Ok(Default::default());
             }
-            NewOverlayOptions::Init => {
-                self.init_new(dirs, git, name.into_static(), repo, conflict_handler)?
+        };
+        tracing::trace!("overlay repos path: {}", overlay_repos_dir_path.display());
+        let entries = match overlay_repos_dir_path.read_dir().with_context(|| {
+            anyhow!(
+                "failed to read overlay repo dirs from {}",
+                overlay_repos_dir_path.display(),
+            )
+        }) {
+            Ok(entries) => entries,
+            Err(e) => {
+                policy.report(ScanProblem::InvalidOverlayDirEntry, e)?;
+                return Ok(Default::default());
             }
         };
 
-        // Tweak bare repo for overlay
-        {
-            let mut repo = repo.open(git, dirs, name.to_borrowed())?;
-            let name: &str = name.as_ref();
-            let home = dirs.home_dir_path()?;
-            let repo_specific_special_path = |segment| path!(home | segment | name);
-            if let Err(e) = repo
-                .set_excludes_file(Some(&repo_specific_special_path(".gitignore.d")))
-                .context("failed to set Git excludes file")
-            {
-                log::warn!("{}", e);
+        let mut names = BTreeSet::new();
+        for ent in entries {
+            let name = (|| -> anyhow::Result<_> {
+                let ent = ent.context("failed to read a dir entry in overlay repo path")?;
+
+                let file_name = ent.file_name();
+                let file_name = file_name
+                    .to_str()
+                    .context("file name is not convertible to UTF-8")
+                    .and_then(|finm| -> Result<RepoName<'static>> {
+                        finm.parse().map_err(anyhow::Error::new)
+                    })
+                    .with_context(|| {
+                        anyhow!("file name {:?} is not a valid repo name", file_name)
+                    })?;
+
+                ensure!(
+                    ent.path().is_dir(),
+                    "skipping overlay repo dir item {:?}, which does not appear to be a directory",
+                    file_name,
+                );
+                Ok(file_name)
+            })();
+            match name {
+                Ok(name) => {
+                    names.insert(name);
+                }
+                Err(e) => policy.report(ScanProblem::InvalidOverlayDirEntry, e)?,
             }
-            // // TODO: set attributes file
-            // if let Err(e) = repo.set_attributes_file(todo!()) {
-            //     log::error!("{}", e);
-            // }
-            // TODO: Looks like we need to set the remote, boo!
         }
-
-        Ok((name, repo))
+        Ok(names)
     }
 
-    pub fn new_standalone(
-        &mut self,
+    /// Creates the attributes file for the overlay repo named `name` if it doesn't exist yet, and
+    /// returns its path.
+    pub fn ensure_overlay_attributes_file(
         dirs: &Directories,
-        git: &DynGit,
-        options: NewStandaloneOptions<'_>,
-        conflict_handler: &mut dyn RepoConflictHandler,
-    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
-        let NewStandaloneOptions {
-            name,
-            path,
-            app_info,
-            method,
-        } = options;
-        let repo = |path: &Path| -> anyhow::Result<_> {
-            // Git doesn't understand UNC paths, which is what
-            // `std::fs::canonicalize` converts paths to on Windows.
-            // There's [reasons] for `std` to do this, but in our
-            // context, this is undesirable. Try to avoid this using
-            // `dunce` if at all possible.
-            //
-            // [reasons]: https://docs.microsoft.com/en-us/windows/win32/fileio/maximum-file-path-limitation?tabs=cmd
-            let path = canonicalize_path(path)?.into();
+        name: &str,
+    ) -> anyhow::Result<PathBuf> {
+        let path = dirs.overlay_attributes_file_path(name);
+        let parent = path.parent().unwrap();
+        fs::create_dir_all(parent)
+            .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| anyhow!("failed to create attributes file {:?}", path))?;
+        Ok(path)
+    }
 
-            // TODO: Check that repo path isn't inside our data dir
+    /// Writes committed changes to disk, if there are any.
+    pub fn flush(&self, dirs: &Directories) -> anyhow::Result<()> {
+        let Self {
+            committed: _,
+            writer,
+            needs_persist,
+        } = self;
+        // Keep transactions from committing while the DB is being written.
+        let _writer = writer.lock().unwrap();
+        if !needs_persist.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let snapshot = self.snapshot();
+        let RepoDbSnapshot {
+            repos,
+            includes,
+            hidden,
+        } = &*snapshot;
+
+        let home = dirs.home_dir_path()?;
+        let standalone_repos = repos
+            .iter()
+            .filter(|(name, _entry)| !includes.origins.contains_key(*name))
+            .filter_map(|(name, entry)| {
+                let RepoEntry {
+                    kind,
+                    pinned,
+                    read_only,
+                    sync_strategy,
+                    info,
+                    last_sync,
+                    depends_on,
+                } = entry;
+                match kind {
+                    RepoEntryKind::Standalone {
+                        app_info,
+                        path,
+                        source,
+                    } => Some((
+                        name.to_borrowed(),
+                        StandaloneRepoEntry {
+                            path: contract_home_path(path, &home)
+                                .map_or_else(|| path.to_borrowed(), Cow::Owned),
+                            app_info: app_info.to_borrowed(),
+                            source: source.to_borrowed(),
+                            pinned: *pinned,
+                            read_only: *read_only,
+                            sync_strategy: *sync_strategy,
+                            info: info.to_borrowed(),
+                            last_sync: last_sync.to_borrowed(),
+                            depends_on: depends_on.to_borrowed(),
+                        },
+                    )),
+                    RepoEntryKind::Overlay { .. } | RepoEntryKind::Unmanaged { .. } => None,
+                }
+            })
+            .collect();
+        let unmanaged = repos
+            .iter()
+            .filter(|(name, _entry)| !includes.origins.contains_key(*name))
+            .filter_map(|(name, entry)| {
+                let RepoEntry {
+                    kind,
+                    pinned,
+                    read_only,
+                    sync_strategy: _,
+                    info,
+                    last_sync: _,
+                    depends_on,
+                } = entry;
+                match kind {
+                    RepoEntryKind::Unmanaged { path } => Some((
+                        name.to_borrowed(),
+                        UnmanagedEntry {
+                            path: contract_home_path(path, &home)
+                                .map_or_else(|| path.to_borrowed(), Cow::Owned),
+                            pinned: *pinned,
+                            read_only: *read_only,
+                            info: info.to_borrowed(),
+                            depends_on: depends_on.to_borrowed(),
+                        },
+                    )),
+                    RepoEntryKind::Standalone { .. } | RepoEntryKind::Overlay { .. } => None,
+                }
+            })
+            .collect();
+        // Hidden repos come first, so that entries of the same name made since win.
+        let overlay_repos = hidden
+            .iter()
+            .chain(repos)
+            .filter_map(|(name, entry)| {
+                let RepoEntry {
+                    kind,
+                    pinned,
+                    read_only,
+                    sync_strategy,
+                    info,
+                    last_sync,
+                    depends_on,
+                } = entry;
+                match kind {
+                    RepoEntryKind::Overlay {
+                        source,
+                        work_tree,
+                        git_dir,
+                    } => Some((
+                        name.to_borrowed(),
+                        OverlayRepoEntry {
+                            work_tree: work_tree.as_ref().map(|work_tree| {
+                                contract_home_path(work_tree, &home)
+                                    .map_or_else(|| work_tree.to_borrowed(), Cow::Owned)
+                            }),
+                            git_dir: git_dir.as_ref().map(|git_dir| {
+                                contract_home_path(git_dir, &home)
+                                    .map_or_else(|| git_dir.to_borrowed(), Cow::Owned)
+                            }),
+                            source: source.to_borrowed(),
+                            pinned: *pinned,
+                            read_only: *read_only,
+                            sync_strategy: *sync_strategy,
+                            info: info.to_borrowed(),
+                            last_sync: last_sync.to_borrowed(),
+                            depends_on: depends_on.to_borrowed(),
+                        },
+                    )),
+                    RepoEntryKind::Standalone { .. } | RepoEntryKind::Unmanaged { .. } => None,
+                }
+            })
+            .collect();
+
+        let standalone_repos_db = StandaloneRepoDb {
+            include: includes.paths.clone(),
+            standalone_repos,
+            unmanaged,
+        };
+        let overlay_repos_db = OverlayRepoDb { overlay_repos };
+
+        let toml = toml::to_string(&standalone_repos_db)
+            .expect("failed to serialize standalone repos DB as TOML");
+        fs::write(dirs.standalone_repo_db_path()?, schema::with_version(toml))
+            .context("failed to write standalone repos DB")?;
+        let toml = toml::to_string(&overlay_repos_db)
+            .expect("failed to serialize overlay repos DB as TOML");
+        fs::write(dirs.overlay_repo_db_path(), schema::with_version(toml))
+            .context("failed to write overlay repos DB")?;
+        needs_persist.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl RepoDbSnapshot {
+    /// Finds overlay repos whose directory in the overlay repos directory was renamed by hand, as
+    /// pairs of the entry left without a directory and the directory it was renamed to, which has
+    /// no entry. Renames are recognized by what the directory's Git configuration points at; see
+    /// [`overlay_files::previous_name`].
+    pub fn find_renamed_overlays(
+        &self,
+        dirs: &Directories,
+        git: &DynGit,
+    ) -> anyhow::Result<Vec<(RepoName<'static>, RepoName<'static>)>> {
+        let overlay_repos_dir_path = dirs.overlay_repos_dir_path()?;
+        let entries = match overlay_repos_dir_path.read_dir() {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(anyhow!(
+                    "failed to read overlay repo dirs from {}",
+                    overlay_repos_dir_path.display()
+                )))
+            }
+        };
+        let mut renames = Vec::new();
+        for ent in entries {
+            let ent = ent.context("failed to read a dir entry in overlay repo path")?;
+            let Some(new_name) = ent
+                .file_name()
+                .to_str()
+                .and_then(|name| RepoName::from_str(name).ok())
+            else {
+                continue;
+            };
+            if !ent.path().is_dir() || self.repos.contains_key(&new_name) {
+                continue;
+            }
+            let unregistered = RepoEntry {
+                kind: RepoEntryKind::Overlay {
+                    source: None,
+                    work_tree: None,
+                    git_dir: None,
+                },
+                pinned: false,
+                read_only: false,
+                sync_strategy: None,
+                info: RepoInfo::default(),
+                last_sync: None,
+                depends_on: Dependencies::default(),
+            };
+            // Anything else that ended up in there is none of our business.
+            let repo = match unregistered.open(git, dirs, new_name.to_borrowed()) {
+                Ok(repo) => repo,
+                Err(e) => {
+                    tracing::debug!(
+                        "skipping {}, which can't be opened as an overlay repo: {:#}",
+                        ent.path().display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let Some(old_name) = overlay_files::previous_name(dirs, &repo, &new_name)? else {
+                continue;
+            };
+            let Ok(old_name) = RepoName::from_str(&old_name) else {
+                continue;
+            };
+            let was_moved = match self.repos.get(&old_name) {
+                Some(RepoEntry {
+                    kind: RepoEntryKind::Overlay { git_dir: None, .. },
+                    ..
+                }) => !RepoEntryKind::overlay_path(dirs, old_name.to_borrowed())?.exists(),
+                _ => false,
+            };
+            if was_moved {
+                renames.push((old_name, new_name));
+            }
+        }
+        Ok(renames)
+    }
+
+    /// Whether the recorded upstream source of the repo `name`, open as `repo`, no longer matches
+    /// its remotes and branch. Included repos' sources are whatever their include file says, so
+    /// they're never stale.
+    pub fn has_stale_source(&self, name: RepoName<'_>, repo: &DynGitRepo) -> anyhow::Result<bool> {
+        if self.includes.origins.contains_key(&*name) {
+            return Ok(false);
+        }
+        let entry = self.get_by_name(name)?;
+        Ok(entry.source() != entry.current_source(repo)?)
+    }
+
+    /// Fails if the repo `name` was included from another file, and so can't be changed.
+    fn ensure_not_included(&self, name: RepoName<'_>) -> Result<(), Error> {
+        match self.includes.origins.get(&*name) {
+            Some(origin) => Err(Error::Other(anyhow!(
+                "repo {:?} is included from {:?}, and can only be changed there",
+                name,
+                origin
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn validate_no_add_conflicts(
+        &self,
+        dirs: &Directories,
+        name: RepoName<'_>,
+        repo: RepoEntry<'_>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> Result<(), Error> {
+        let mut conflict_occurred = false;
+        let mut conflict_searcher = self
+            .find_add_conflicts(dirs, name.to_borrowed(), repo.to_borrowed())
+            .context("failed to perform search for conflicts in adding repo")?;
+        while let Some(conflict_res) = conflict_searcher.next_conflict() {
+            conflict_occurred = true;
+            match conflict_res {
+                Ok(m) => {
+                    let RepoConflictCheck {
+                        found_name,
+                        name_eq,
+                        entry_match,
+                    } = m;
+                    assert!(name_eq.outcome.matched() || entry_match.outcome.matched());
+                    match name_eq.outcome {
+                        NormalizedEqOutcome::ExactMatch => {
+                            conflict_handler.on_conflict_name(found_name.to_borrowed(), None)
+                        }
+                        NormalizedEqOutcome::MatchAfterNormalization { reason } => conflict_handler
+                            .on_conflict_name(found_name.to_borrowed(), Some(reason)),
+                        NormalizedEqOutcome::NotAMatch => (),
+                    }
+                    match entry_match.outcome {
+                        NormalizedEqOutcome::ExactMatch => {
+                            conflict_handler.on_conflict_name(found_name, None)
+                        }
+                        NormalizedEqOutcome::MatchAfterNormalization { reason } => conflict_handler
+                            .on_conflict_path(
+                                found_name.to_borrowed(),
+                                Some((entry_match.found, reason)),
+                            ),
+                        NormalizedEqOutcome::NotAMatch => (),
+                    }
+                }
+                Err(e) => conflict_handler.on_iteration_err(e),
+            }
+        }
+        if conflict_occurred {
+            return Err(Error::Conflict(anyhow!(msg!("repo-entries-conflict"))));
+        }
+        Ok(())
+    }
+
+    /// The existing repo that adding `repo` as `name` conflicts with, if the name is all that
+    /// conflicts, and only with that repo.
+    fn name_only_conflict(
+        &self,
+        dirs: &Directories,
+        name: RepoName<'_>,
+        repo: RepoEntry<'_>,
+    ) -> anyhow::Result<Option<RepoName<'static>>> {
+        let mut conflict_searcher = self.find_add_conflicts(dirs, name, repo)?;
+        let mut found = None;
+        while let Some(conflict_res) = conflict_searcher.next_conflict() {
+            // Errors are for `validate_no_add_conflicts` to report.
+            let Ok(RepoConflictCheck {
+                found_name,
+                name_eq,
+                entry_match,
+            }) = conflict_res
+            else {
+                return Ok(None);
+            };
+            if found.is_some() || !name_eq.outcome.matched() || entry_match.outcome.matched() {
+                return Ok(None);
+            }
+            found = Some(found_name.into_static());
+        }
+        Ok(found)
+    }
+
+    fn find_add_conflicts<'a, 'this: 'a>(
+        &'this self,
+        dirs: &'a Directories,
+        name: RepoName<'a>,
+        repo: RepoEntry<'a>,
+    ) -> anyhow::Result<RepoConflictSearcher<'a>> {
+        tracing::debug!("searching for existing repos with name and repo information");
+        RepoConflictSearcher::new(name, repo, dirs, self)
+    }
+
+    pub fn get_by_name_opt(&self, name: RepoName<'_>) -> Option<RepoEntry<'_>> {
+        self.repos.get(&*name).map(|e| e.to_borrowed())
+    }
+
+    pub fn get_by_name(&self, name: RepoName<'_>) -> Result<RepoEntry<'_>, Error> {
+        self.get_by_name_opt(name.to_borrowed())
+            .ok_or_else(|| self.name_not_found(name))
+    }
+
+    /// An error for `name` not being configured, suggesting similar names that are.
+    fn name_not_found(&self, name: RepoName<'_>) -> Error {
+        Error::NotFound(anyhow!(
+            "{}{}",
+            msg!("repo-name-not-found", name = format!("{:?}", name)),
+            suggest::did_you_mean(&name, self.repos.keys().map(|name| &**name)),
+        ))
+    }
+
+    pub fn get_by_path(
+        &self,
+        dirs: &Directories,
+        path: &Path,
+    ) -> Result<(RepoName<'_>, RepoEntry<'_>), Error> {
+        // TODO: lint/check for canonicalized paths on init
+        let path = canonicalize_path(path)?;
+        for (name, repo) in self.iter() {
+            let repo_path = repo.path(dirs, name.to_borrowed())?;
+            if path == repo_path {
+                return Ok((name, repo));
+            }
+        }
+        Err(Error::NotFound(anyhow!(msg!(
+            "repo-path-not-found",
+            path = format!("{:?}", path)
+        ))))
+    }
+
+    /// Finds the repo whose work tree contains `path`, preferring the most deeply nested work
+    /// tree.
+    pub fn get_by_work_tree_containing(
+        &self,
+        dirs: &Directories,
+        path: &Path,
+    ) -> Result<(RepoName<'_>, RepoEntry<'_>), Error> {
+        let path = canonicalize_path(path)?;
+        let mut best_depth = 0;
+        let mut candidates = Vec::new();
+        for (name, repo) in self.iter() {
+            let work_tree_path = match repo
+                .work_tree_path(dirs)
+                .and_then(|p| canonicalize_path(&p))
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    tracing::debug!("skipping {:?} while searching for work tree: {:?}", name, e);
+                    continue;
+                }
+            };
+            if !path.starts_with(&work_tree_path) {
+                continue;
+            }
+            let depth = work_tree_path.components().count();
+            if depth > best_depth {
+                best_depth = depth;
+                candidates.clear();
+            }
+            if depth == best_depth {
+                candidates.push((name, repo));
+            }
+        }
+        match candidates.len() {
+            0 => Err(Error::NotFound(anyhow!(msg!(
+                "repo-work-tree-not-found",
+                path = format!("{:?}", path)
+            )))),
+            1 => Ok(candidates.pop().unwrap()),
+            _ => Err(Error::Other(anyhow!(msg!(
+                "repo-work-tree-ambiguous",
+                path = format!("{:?}", path),
+                names = candidates
+                    .iter()
+                    .map(|(name, _repo)| format!("{:?}", name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )))),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RepoName<'_>, RepoEntry<'_>)> {
+        self.repos
+            .iter()
+            .map(|(name, repo)| (name.to_borrowed(), repo.to_borrowed()))
+    }
+}
+
+impl RepoDbTransaction<'_> {
+    /// Makes the changes in this transaction visible to new snapshots, and to [`RepoDb::flush`].
+    pub fn commit(self) {
+        let Self {
+            db,
+            _writer,
+            working,
+            needs_persist,
+        } = self;
+        *db.committed.write().unwrap() = Arc::new(working);
+        if needs_persist {
+            db.needs_persist.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// # Panics
+    ///
+    /// You should call [`RepoDbSnapshot::validate_no_add_conflicts`] first!
+    fn insert(
+        &mut self,
+        name: RepoName<'static>,
+        repo: RepoEntry<'static>,
+    ) -> (RepoName<'_>, RepoEntry<'_>) {
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        assert!(repos.insert(name.clone(), repo).is_none());
+        *needs_persist = true;
+
+        let (name, repo) = repos.get_key_value(&name).unwrap();
+        (name.to_borrowed(), repo.to_borrowed())
+    }
+
+    /// Checks that `repo` can be added as `name`, like
+    /// [`RepoDbSnapshot::validate_no_add_conflicts`], but lets `conflict_handler` resolve a
+    /// conflict over the name alone: by choosing another name, which is checked in turn, or by
+    /// replacing the existing entry. Returns the name to add `repo` as.
+    fn resolve_add_conflicts(
+        &mut self,
+        dirs: &Directories,
+        mut name: RepoName<'static>,
+        repo: RepoEntry<'_>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> anyhow::Result<RepoName<'static>> {
+        while let Some(existing) =
+            self.name_only_conflict(dirs, name.to_borrowed(), repo.to_borrowed())?
+        {
+            match conflict_handler.resolve_name_conflict(existing.to_borrowed())? {
+                NameConflictResolution::Rename(new_name) => name = new_name,
+                NameConflictResolution::Replace => self.remove_replaced(existing)?,
+                NameConflictResolution::Abort => break,
+            }
+        }
+        self.validate_no_add_conflicts(
+            dirs,
+            name.to_borrowed(),
+            repo.to_borrowed(),
+            conflict_handler,
+        )?;
+        Ok(name)
+    }
+
+    /// Removes the entry of the repo `name`, leaving its files in place, so that a new repo can
+    /// take its name.
+    fn remove_replaced(&mut self, name: RepoName<'_>) -> anyhow::Result<()> {
+        let existing = self.get_by_name(name.to_borrowed())?;
+        existing.ensure_writable(&name, "replace", false)?;
+        let repo = match existing.kind() {
+            RepoKind::Standalone => self.deregister_standalone(name.to_borrowed())?,
+            RepoKind::Overlay => self.deregister_overlay(name.to_borrowed())?,
+            RepoKind::Unmanaged => self.deregister_unmanaged(name.to_borrowed())?,
+        };
+        tracing::info!(
+            "replacing {:?}, {}; its files are left in place{}",
+            name,
+            repo.short_desc(),
+            if repo.kind() == RepoKind::Overlay {
+                " (see `gc` for its Git directory)"
+            } else {
+                ""
+            }
+        );
+        Ok(())
+    }
+
+    pub fn new_overlay(
+        &mut self,
+        dirs: &Directories,
+        git: &DynGit,
+        overlay_config: &OverlayConfig,
+        name: RepoName<'_>,
+        options: NewOverlayOptions<'_>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
+        let git_dir = match &options {
+            NewOverlayOptions::Register { git_dir } => {
+                let git_dir = canonicalize_path(git_dir)?;
+                // Don't record the location that would be used anyway.
+                (git_dir != RepoEntryKind::overlay_path(dirs, name.to_borrowed())?)
+                    .then(|| git_dir.into())
+            }
+            NewOverlayOptions::Init | NewOverlayOptions::Clone { .. } => None,
+        };
+        let repo = RepoEntry {
+            kind: RepoEntryKind::Overlay {
+                source: None,
+                work_tree: None,
+                git_dir,
+            },
+            pinned: false,
+            read_only: false,
+            sync_strategy: None,
+            info: RepoInfo::default(),
+            last_sync: None,
+            depends_on: Dependencies::default(),
+        };
+        let name = self.resolve_add_conflicts(
+            dirs,
+            name.into_static(),
+            repo.to_borrowed(),
+            conflict_handler,
+        )?;
+        // Git creates the Git directory, but it's ours to remove if setting it up fails later on.
+        let created = match &options {
+            NewOverlayOptions::Init | NewOverlayOptions::Clone { .. } => {
+                CreatedDir::expect(&repo.path(dirs, name.to_borrowed())?)
+            }
+            NewOverlayOptions::Register { git_dir: _ } => CreatedDir(None),
+        };
+        let (name, repo) = match options {
+            NewOverlayOptions::Clone {
+                source,
+                no_checkout,
+                only,
+                keep_depth,
+            } => {
+                let (name, repo) = self.clone_new(
+                    dirs,
+                    git,
+                    name.into_static(),
+                    repo,
+                    source.into_static(),
+                    keep_depth,
+                    conflict_handler,
+                )?;
+                match repo
+                    .open(git, dirs, name.to_borrowed())
+                    .and_then(|mut repo| {
+                        repo.reset()
+                            .context("failed to execute reset staged changes")?;
+                        if !no_checkout {
+                            // TODO: check out files
+                            tracing::info_span!("restore", repo = ?name)
+                                .in_scope(|| created_dirs::restore(&mut repo, &only))
+                                .context("failed to populate work tree")?;
+                        }
+                        Ok(())
+                    }) {
+                    Ok(()) => (),
+                    Err(e) => tracing::warn!("{}", e),
+                };
+                (name, repo)
+            }
+            NewOverlayOptions::Init => {
+                self.init_new(dirs, git, name.into_static(), repo, conflict_handler)?
+            }
+            NewOverlayOptions::Register { git_dir: _ } => {
+                let mut repo = repo;
+                Self::check_repo_exists(dirs, git, name.to_borrowed(), repo.to_borrowed())?;
+                Self::record_source_logged(git, dirs, name.to_borrowed(), &mut repo);
+                self.insert(name.into_static(), repo)
+            }
+        };
+
+        // Tweak bare repo for overlay. Opening it also sets up its excludes file.
+        {
+            let mut repo = repo.open(git, dirs, name.to_borrowed())?;
+            let name: &str = name.as_ref();
+            let home = dirs.home_dir_path()?;
+            let empty_hooks_dir = dirs.empty_hooks_dir_path();
+            if !overlay_config.allow_git_hooks() {
+                fs::create_dir_all(&empty_hooks_dir)
+                    .with_context(|| anyhow!("failed to create directory {:?}", empty_hooks_dir))?;
+            }
+            for (key, value) in overlay_config.git_config(&home, &empty_hooks_dir) {
+                if let Err(e) = repo.set_config(key, Some(value)) {
+                    tracing::warn!("{}", e);
+                }
+            }
+            if let Err(e) = RepoDb::ensure_overlay_attributes_file(dirs, name).and_then(|path| {
+                repo.set_attributes_file(Some(&path))
+                    .context("failed to set Git attributes file")
+            }) {
+                tracing::warn!("{}", e);
+            }
+            // TODO: Looks like we need to set the remote, boo!
+        }
+
+        created.keep();
+        Ok((name, repo))
+    }
+
+    pub fn new_standalone(
+        &mut self,
+        dirs: &Directories,
+        git: &DynGit,
+        options: NewStandaloneOptions<'_>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
+        let NewStandaloneOptions {
+            name,
+            path,
+            app_info,
+            method,
+        } = options;
+        let repo = |path: &Path| -> anyhow::Result<_> {
+            // Git doesn't understand UNC paths, which is what
+            // `std::fs::canonicalize` converts paths to on Windows.
+            // There's [reasons] for `std` to do this, but in our
+            // context, this is undesirable. Try to avoid this using
+            // `dunce` if at all possible.
+            //
+            // [reasons]: https://docs.microsoft.com/en-us/windows/win32/fileio/maximum-file-path-limitation?tabs=cmd
+            let path = canonicalize_path(path)?.into();
+
+            // TODO: Check that repo path isn't inside our data dir
 
             Ok(RepoEntry {
-                kind: RepoEntryKind::Standalone { path, app_info },
+                kind: RepoEntryKind::Standalone {
+                    path,
+                    app_info,
+                    source: None,
+                },
+                pinned: false,
+                read_only: false,
+                sync_strategy: None,
+                info: RepoInfo::default(),
+                last_sync: None,
+                depends_on: Dependencies::default(),
             })
         };
-        // This could be necessary for canonicalizing stuff later, so do it ourselves.
+        // This could be necessary for canonicalizing stuff later, so do it ourselves. If adding the
+        // repo fails after this, the directory is removed again, unless it already existed.
         let create_dir = |path: &Path| -> anyhow::Result<_> {
             let path_parent_is_dir =
                 path.parent()
@@ -457,156 +1663,203 @@ impl RepoDb {
             if !path_parent_is_dir {
                 bail!("path parent is not a directory")
             }
-            let res = create_dir(path);
-            if matches!(&res, Err(e) if e.kind() != io::ErrorKind::AlreadyExists) {
-                res.context("failed to create target directory")?;
-            }
-            Ok(())
+            CreatedDir::create(path).context("failed to create target directory")
         };
         match method {
             NewStandaloneMethod::Init => {
-                create_dir(&path)?;
+                let created = create_dir(&path)?;
                 let repo = repo(&path)?;
-                Ok(self.init_new(
+                let added = self.init_new(
                     dirs,
                     git,
                     name.into_static(),
                     repo.into_static(),
                     conflict_handler,
-                )?)
+                )?;
+                created.keep();
+                Ok(added)
             }
-            NewStandaloneMethod::Clone { source } => {
-                create_dir(&path)?;
+            NewStandaloneMethod::Clone { source, keep_depth } => {
+                let created = create_dir(&path)?;
                 let repo = repo(&path)?;
-                Ok(self.clone_new(
+                let added = self.clone_new(
                     dirs,
                     git,
                     name.into_static(),
                     repo.into_static(),
                     source.into_static(),
+                    keep_depth,
                     conflict_handler,
-                )?)
+                )?;
+                created.keep();
+                Ok(added)
             }
             NewStandaloneMethod::Register => {
-                let repo = repo(&path)?;
+                let mut repo = repo(&path)?;
                 Self::check_repo_exists(dirs, git, name.to_borrowed(), repo.to_borrowed())?;
-                self.validate_no_add_conflicts(
+                let name = self.resolve_add_conflicts(
                     dirs,
-                    name.to_borrowed(),
+                    name.into_static(),
                     repo.to_borrowed(),
                     conflict_handler,
                 )?;
-                Ok(self.insert(name.into_static(), repo.into_static()))
+                Self::record_source_logged(git, dirs, name.to_borrowed(), &mut repo);
+                Ok(self.insert(name, repo.into_static()))
             }
         }
     }
 
-    fn init_new(
+    /// Registers the directory at `path`, which isn't a Git repo, as the unmanaged entry `name`.
+    /// With `create`, the directory and any missing parents are created first, i.e., for entries
+    /// of a starter file.
+    pub fn new_unmanaged(
         &mut self,
         dirs: &Directories,
-        git: &DynGit,
-        name: RepoName<'static>,
-        repo: RepoEntry<'static>,
+        name: RepoName<'_>,
+        path: &Path,
+        create: bool,
         conflict_handler: &mut dyn RepoConflictHandler,
     ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
-        self.validate_no_add_conflicts(
+        let created = if create {
+            if let Some(parent) = path.parent().filter(|p| p != &Path::new("")) {
+                fs::create_dir_all(parent)
+                    .with_context(|| anyhow!("failed to create directory {:?}", parent))?;
+            }
+            Some(CreatedDir::create(path).context("failed to create target directory")?)
+        } else {
+            None
+        };
+        ensure!(path.is_dir(), "{:?} is not a directory", path);
+        if path.join(".git").exists() {
+            tracing::warn!(
+                "{:?} is a Git repo; register it with `standalone register` instead for commands \
+                like `sync` to work with it",
+                path
+            );
+        }
+
+        let repo = RepoEntry {
+            kind: RepoEntryKind::Unmanaged {
+                path: canonicalize_path(path)?.into(),
+            },
+            pinned: false,
+            read_only: false,
+            sync_strategy: None,
+            info: RepoInfo::default(),
+            last_sync: None,
+            depends_on: Dependencies::default(),
+        };
+        let name = self.resolve_add_conflicts(
             dirs,
-            name.to_borrowed(),
+            name.into_static(),
             repo.to_borrowed(),
             conflict_handler,
         )?;
+        if let Some(created) = created {
+            created.keep();
+        }
+        Ok(self.insert(name, repo))
+    }
+
+    fn init_new(
+        &mut self,
+        dirs: &Directories,
+        git: &DynGit,
+        name: RepoName<'static>,
+        repo: RepoEntry<'static>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
+        let name = self.resolve_add_conflicts(dirs, name, repo.to_borrowed(), conflict_handler)?;
 
         let path = repo.path(dirs, name.to_borrowed())?;
-        git.init(path.as_ref(), repo.kind().into())
+        git.init(path.as_ref(), repo.kind().try_into()?)
             .context("failed to init with Git")?;
 
         Ok(self.insert(name, repo))
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(skip_all, fields(repo = ?name, source = ?source))]
     fn clone_new(
         &mut self,
         dirs: &Directories,
         git: &DynGit,
         name: RepoName<'static>,
-        repo: RepoEntry<'static>,
+        mut repo: RepoEntry<'static>,
         source: RepoSource<'static>,
+        keep_depth: Option<NonZeroU32>,
         conflict_handler: &mut dyn RepoConflictHandler,
     ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
-        self.validate_no_add_conflicts(
-            dirs,
-            name.to_borrowed(),
-            repo.to_borrowed(),
-            conflict_handler,
-        )?;
+        let name = self.resolve_add_conflicts(dirs, name, repo.to_borrowed(), conflict_handler)?;
 
         let path = repo.path(dirs, name.to_borrowed())?;
-        git.clone(path.as_ref(), source, repo.kind().into())
+        git.clone(path.as_ref(), source, repo.kind().try_into()?, keep_depth)
             .context("failed to clone with Git")?;
 
+        if let Some(depth) = keep_depth {
+            if let Err(e) = repo
+                .open(git, dirs, name.to_borrowed())
+                .and_then(|mut repo| Ok(repo.set_keep_depth(Some(depth))?))
+                .context("failed to persist `--keep-depth` setting; history will not be re-trimmed")
+            {
+                tracing::warn!("{:?}", e);
+            }
+        }
+
+        Self::record_source_logged(git, dirs, name.to_borrowed(), &mut repo);
         Ok(self.insert(name, repo))
     }
 
-    pub fn validate_no_add_conflicts(
+    fn record_source_logged(
+        git: &DynGit,
+        dirs: &Directories,
+        name: RepoName<'_>,
+        repo: &mut RepoEntry<'_>,
+    ) {
+        if let Err(e) = repo.record_source(git, dirs, name.to_borrowed()) {
+            tracing::warn!("failed to record source of {:?}: {:?}", name, e);
+        }
+    }
+
+    /// Updates the recorded upstream source of the repo `name` to match its current remotes and
+    /// branch.
+    pub fn sync_source(
         &mut self,
+        git: &DynGit,
         dirs: &Directories,
         name: RepoName<'_>,
-        repo: RepoEntry<'_>,
-        conflict_handler: &mut dyn RepoConflictHandler,
     ) -> anyhow::Result<()> {
-        let mut conflict_occurred = false;
-        let mut conflict_searcher = self
-            .find_add_conflicts(dirs, name.to_borrowed(), repo.to_borrowed())
-            .context("failed to perform search for conflicts in adding repo")?;
-        while let Some(conflict_res) = conflict_searcher.next_conflict() {
-            conflict_occurred = true;
-            match conflict_res {
-                Ok(m) => {
-                    let RepoConflictCheck {
-                        found_name,
-                        name_eq,
-                        entry_match,
-                    } = m;
-                    assert!(name_eq.outcome.matched() || entry_match.outcome.matched());
-                    match name_eq.outcome {
-                        NormalizedEqOutcome::ExactMatch => {
-                            conflict_handler.on_conflict_name(found_name.to_borrowed(), None)
-                        }
-                        NormalizedEqOutcome::MatchAfterNormalization { reason } => conflict_handler
-                            .on_conflict_name(found_name.to_borrowed(), Some(reason)),
-                        NormalizedEqOutcome::NotAMatch => (),
-                    }
-                    match entry_match.outcome {
-                        NormalizedEqOutcome::ExactMatch => {
-                            conflict_handler.on_conflict_name(found_name, None)
-                        }
-                        NormalizedEqOutcome::MatchAfterNormalization { reason } => conflict_handler
-                            .on_conflict_path(
-                                found_name.to_borrowed(),
-                                Some((entry_match.found, reason)),
-                            ),
-                        NormalizedEqOutcome::NotAMatch => (),
-                    }
-                }
-                Err(e) => conflict_handler.on_iteration_err(e),
-            }
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        // Included repos' sources are whatever their include file says.
+        if includes.origins.contains_key(&*name) {
+            return Ok(());
+        }
+        let (_name, repo) = repos
+            .iter_mut()
+            .find(|(n, _repo)| n.to_borrowed() == name)
+            .ok_or_else(|| {
+                Error::NotFound(anyhow!(msg!(
+                    "repo-name-not-found",
+                    name = format!("{:?}", name)
+                )))
+            })?;
+        if repo.record_source(git, dirs, name.to_borrowed())? {
+            tracing::info!("updated recorded source of {:?}", name);
+            *needs_persist = true;
         }
-        ensure!(
-            !conflict_occurred,
-            "one or more existing repo entries conflict"
-        );
         Ok(())
     }
 
-    fn find_add_conflicts<'a, 'this: 'a>(
-        &'this mut self,
-        dirs: &'a Directories,
-        name: RepoName<'a>,
-        repo: RepoEntry<'a>,
-    ) -> anyhow::Result<RepoConflictSearcher<'a>> {
-        log::debug!("searching for existing repos with name and repo information");
-        RepoConflictSearcher::new(name, repo, dirs, self)
-    }
-
     fn check_repo_exists(
         dirs: &Directories,
         git: &DynGit,
@@ -614,100 +1867,279 @@ impl RepoDb {
         repo: RepoEntry<'_>,
     ) -> anyhow::Result<()> {
         let check = repo.path(dirs, name.to_borrowed()).and_then(|path| {
-            git.exists(path.as_ref(), repo.kind().into())
+            git.exists(path.as_ref(), repo.kind().try_into()?)
                 .context("failed trying to check if Git repo is present at path")
         })?;
         check.context("Git repo check failed")?;
-        log::debug!(
+        tracing::debug!(
             "validated that work tree exists as expected for {}",
             repo.short_desc()
         );
         Ok(())
     }
 
-    pub fn get_by_name_opt(&self, name: RepoName<'_>) -> Option<RepoEntry<'_>> {
-        // SAFETY: Safe because we're only using this reference in this call -- no lifetime
-        // escaping here.
-        {
-            let name = &name;
-            let name = unsafe { transmute::<_, &RepoName<'static>>(name) };
-            self.repos.get(name)
+    /// Applies `update` to the details recorded for the repo `name`.
+    pub fn update_info(
+        &mut self,
+        name: RepoName<'_>,
+        update: impl FnOnce(&mut RepoInfo<'static>),
+    ) -> Result<(), Error> {
+        self.get_by_name(name.to_borrowed())?;
+        self.ensure_not_included(name.to_borrowed())?;
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        let RepoEntry {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info,
+            last_sync: _,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        let old = info.clone();
+        update(info);
+        if *info != old {
+            *needs_persist = true;
+        }
+        Ok(())
+    }
+
+    /// Records that the repo `name` was just synced, as described by `record`. Included repos
+    /// are left alone, since they can only be changed in their include file.
+    pub fn record_sync(
+        &mut self,
+        name: RepoName<'_>,
+        record: SyncRecord<'static>,
+    ) -> Result<(), Error> {
+        self.get_by_name(name.to_borrowed())?;
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        if includes.origins.contains_key(&*name) {
+            return Ok(());
+        }
+        let RepoEntry {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        *last_sync = Some(record);
+        *needs_persist = true;
+        Ok(())
+    }
+
+    /// Sets the strategy `sync` uses for the repo `name` when it has diverged from upstream.
+    pub fn set_sync_strategy(
+        &mut self,
+        name: RepoName<'_>,
+        strategy: Option<SyncStrategy>,
+    ) -> Result<(), Error> {
+        if !self.get_by_name(name.to_borrowed())?.kind().is_git_repo() {
+            return Err(Error::Other(anyhow!(
+                "{:?} is an unmanaged directory, which `sync` skips",
+                name
+            )));
         }
-        .map(|e| e.to_borrowed())
-    }
-
-    pub fn get_by_name(&self, name: RepoName<'_>) -> anyhow::Result<RepoEntry<'_>> {
-        self.get_by_name_opt(name.to_borrowed())
-            .with_context(|| anyhow!("{:?} is not a repo name in the current configuration", name))
+        self.ensure_not_included(name.to_borrowed())?;
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        let RepoEntry {
+            kind: _,
+            pinned: _,
+            read_only: _,
+            sync_strategy,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        if *sync_strategy != strategy {
+            *sync_strategy = strategy;
+            *needs_persist = true;
+        }
+        Ok(())
     }
 
-    pub fn get_by_path(
-        &self,
-        dirs: &Directories,
-        path: &Path,
-    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
-        // TODO: lint/check for canonicalized paths on init
-        let path = canonicalize_path(path)?;
-        for (name, repo) in self.iter() {
-            let repo_path = repo.path(dirs, name.to_borrowed())?;
-            if path == repo_path {
-                return Ok((name, repo));
+    /// Points the overlay repo `name` at `work_tree`, or back at the home directory if `None`.
+    pub fn set_overlay_work_tree(
+        &mut self,
+        name: RepoName<'_>,
+        work_tree: Option<PathBuf>,
+    ) -> anyhow::Result<()> {
+        self.get_by_name(name.to_borrowed())?;
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        let RepoEntry {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        match kind {
+            RepoEntryKind::Overlay {
+                source: _,
+                work_tree: entry_work_tree,
+                git_dir: _,
+            } => {
+                *entry_work_tree = work_tree.map(Cow::Owned);
+                *needs_persist = true;
+                Ok(())
+            }
+            RepoEntryKind::Standalone { .. } | RepoEntryKind::Unmanaged { .. } => {
+                bail!("{:?} is not an overlay repo", name)
             }
         }
-        bail!(
-            "{:?} is not a path associated with any repo in the current configuration",
-            path,
-        );
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (RepoName<'_>, RepoEntry<'_>)> {
-        self.repos
-            .iter()
-            .map(|(name, repo)| (name.to_borrowed(), repo.to_borrowed()))
+    /// Sets whether the repo `name` is pinned, returning whether it was pinned before.
+    pub fn set_pinned(&mut self, name: RepoName<'_>, pinned: bool) -> Result<bool, Error> {
+        self.get_by_name(name.to_borrowed())?;
+        self.ensure_not_included(name.to_borrowed())?;
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        let RepoEntry {
+            kind: _,
+            pinned: entry_pinned,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        let was_pinned = *entry_pinned;
+        if was_pinned != pinned {
+            *entry_pinned = pinned;
+            *needs_persist = true;
+        }
+        Ok(was_pinned)
     }
 
-    pub fn flush(&mut self, dirs: &Directories) -> anyhow::Result<()> {
+    /// Sets whether the repo `name` is read-only, returning whether it was read-only before.
+    pub fn set_read_only(&mut self, name: RepoName<'_>, read_only: bool) -> Result<bool, Error> {
+        self.get_by_name(name.to_borrowed())?;
+        self.ensure_not_included(name.to_borrowed())?;
         let Self {
-            repos,
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
             needs_persist,
         } = self;
-
-        if !*needs_persist {
-            return Ok(());
+        let RepoEntry {
+            kind: _,
+            pinned: _,
+            read_only: entry_read_only,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = repos.get_mut(&*name).unwrap();
+        let was_read_only = *entry_read_only;
+        if was_read_only != read_only {
+            *entry_read_only = read_only;
+            *needs_persist = true;
         }
+        Ok(was_read_only)
+    }
 
-        let standalone_repos = repos
-            .iter()
-            .filter_map(|(name, entry)| {
-                let RepoEntry { kind } = entry;
-                match kind {
-                    RepoEntryKind::Standalone { app_info, path } => Some((
-                        name.to_borrowed(),
-                        StandaloneRepoEntry {
-                            path: path.to_borrowed(),
-                            app_info: app_info.to_borrowed(),
-                        },
-                    )),
-                    RepoEntryKind::Overlay {} => None,
-                }
-            })
-            .collect();
-
-        let standalone_repos_db = StandaloneRepoDb { standalone_repos };
-
-        let toml = toml::to_string(&standalone_repos_db)
-            .expect("failed to serialize standalone repos DB as TOML");
-        fs::write(dirs.standalone_repo_db_path()?, toml)
-            .context("failed to write standalone repos DB")
+    /// Renames the standalone repo `name` to `new_name`, and replaces its app info with
+    /// `new_app_info`. If `new_name` conflicts with another entry, the entry is left unchanged.
+    pub fn update_standalone(
+        &mut self,
+        dirs: &Directories,
+        name: RepoName<'_>,
+        new_name: RepoName<'static>,
+        new_app_info: Option<AppInfo<'static>>,
+        conflict_handler: &mut dyn RepoConflictHandler,
+    ) -> anyhow::Result<(RepoName<'_>, RepoEntry<'_>)> {
+        let mut repo = self.deregister_standalone(name.to_borrowed())?;
+        if let Err(e) = self.validate_no_add_conflicts(
+            dirs,
+            new_name.to_borrowed(),
+            repo.to_borrowed(),
+            conflict_handler,
+        ) {
+            self.insert(name.into_static(), repo);
+            return Err(e.into());
+        }
+        let RepoEntry {
+            kind,
+            pinned: _,
+            read_only: _,
+            sync_strategy: _,
+            info: _,
+            last_sync: _,
+            depends_on: _,
+        } = &mut repo;
+        if let RepoEntryKind::Standalone { app_info, .. } = kind {
+            *app_info = new_app_info;
+        }
+        Ok(self.insert(new_name, repo))
     }
 
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
     pub fn remove_overlay_bare_repo(
         &mut self,
         dirs: &Directories,
         name: RepoName<'_>,
     ) -> anyhow::Result<()> {
         ensure!(
-            self.get_by_name(name.to_borrowed())?.kind() == CliRepoKind::Overlay,
+            self.get_by_name(name.to_borrowed())?.kind() == RepoKind::Overlay,
             "repo is not an overlay repo"
         );
 
@@ -719,17 +2151,195 @@ impl RepoDb {
         Ok(())
     }
 
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
     pub fn deregister_standalone(
         &mut self,
         name: RepoName<'_>,
     ) -> anyhow::Result<RepoEntry<'static>> {
         ensure!(
-            self.get_by_name(name.to_borrowed())?.kind() == CliRepoKind::Standalone,
+            self.get_by_name(name.to_borrowed())?.kind() == RepoKind::Standalone,
             "repo is not an standalone repo"
         );
+        self.ensure_not_included(name.to_borrowed())?;
+        Ok(self.remove(name).unwrap())
+    }
+
+    /// Removes the entry for the overlay repo `name`, leaving its Git directory in place.
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
+    pub fn deregister_overlay(&mut self, name: RepoName<'_>) -> anyhow::Result<RepoEntry<'static>> {
+        ensure!(
+            self.get_by_name(name.to_borrowed())?.kind() == RepoKind::Overlay,
+            "repo is not an overlay repo"
+        );
+        Ok(self.remove(name).unwrap())
+    }
+
+    /// Removes the entry for the unmanaged directory `name`, leaving the directory in place.
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
+    pub fn deregister_unmanaged(
+        &mut self,
+        name: RepoName<'_>,
+    ) -> anyhow::Result<RepoEntry<'static>> {
+        ensure!(
+            self.get_by_name(name.to_borrowed())?.kind() == RepoKind::Unmanaged,
+            "repo is not an unmanaged directory"
+        );
+        self.ensure_not_included(name.to_borrowed())?;
         Ok(self.remove(name).unwrap())
     }
 
+    /// Moves the entry of the overlay repo `name` to `new_name`, for a repo whose directory was
+    /// already renamed to match. See [`RepoDbSnapshot::find_renamed_overlays`].
+    pub fn adopt_renamed_overlay(
+        &mut self,
+        name: RepoName<'_>,
+        new_name: RepoName<'static>,
+    ) -> anyhow::Result<()> {
+        if self.get_by_name(new_name.to_borrowed()).is_ok() {
+            return Err(Error::Conflict(anyhow!("repo {:?} already exists", new_name)).into());
+        }
+        let repo = self.deregister_overlay(name)?;
+        self.insert(new_name, repo);
+        Ok(())
+    }
+
+    /// Deletes the files tracked by the repo `name` from its work tree, along with directories
+    /// left empty by that, leaving its Git directory and its entry intact. Files that fail to be
+    /// deleted are logged and skipped.
+    ///
+    /// For overlay repos, whose work tree is shared with everything else, only directories that
+    /// were created by checking out the repo are deleted (see [`created_dirs`]). Others left empty
+    /// are kept, and reported.
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
+    pub fn remove_tracked_files(
+        &self,
+        dirs: &Directories,
+        git: &DynGit,
+        name: RepoName<'_>,
+    ) -> anyhow::Result<()> {
+        let entry = self.get_by_name(name.to_borrowed())?;
+        let repo = entry.open(git, dirs, name)?;
+        let work_tree_path = canonicalize_path(repo.work_tree_path())?;
+        let files = repo
+            .list_files()
+            .context("failed to list files")?
+            .collect::<Vec<_>>();
+        tracing::debug!("removing {} tracked files", files.len());
+
+        let mut failed = Vec::new();
+        let mut parents = BTreeSet::new();
+        let mut progress = ProgressBar::new("removing files", files.len());
+        for file in files {
+            // Only directories inside of the work tree, and not the work tree itself, are ours.
+            let mut parent = file.parent();
+            while let Some(dir) = parent.filter(|dir| *dir != work_tree_path) {
+                if !parents.insert(dir.to_owned()) {
+                    break;
+                }
+                parent = dir.parent();
+            }
+            match remove_file(&file) {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => failed.push((file, e)),
+            }
+            progress.inc();
+        }
+        drop(progress);
+        if !failed.is_empty() {
+            tracing::warn!(
+                "failed to remove {} file(s):{}",
+                failed.len(),
+                lazy_format!(|f| {
+                    for (file, e) in &failed {
+                        write!(f, "\n  {}: {}", file.display(), e)?;
+                    }
+                    Ok(())
+                })
+            );
+        }
+
+        // Standalone repos own their entire work tree.
+        let created = match entry.kind() {
+            RepoKind::Overlay => {
+                Some(created_dirs::read(repo.git_dir_path()).unwrap_or_else(|e| {
+                    tracing::warn!("{:#}", e);
+                    BTreeSet::new()
+                }))
+            }
+            RepoKind::Standalone | RepoKind::Unmanaged => None,
+        };
+
+        // Directories that other things still live in aren't empty, so removing them fails. Go
+        // deepest first, so that directories only containing emptied ones are removed too.
+        let mut parents = parents.into_iter().collect::<Vec<_>>();
+        parents.sort_by_key(|dir| Reverse(dir.components().count()));
+        let mut kept = Vec::new();
+        for dir in parents {
+            let owned = created.as_ref().map_or(true, |created| {
+                dir.strip_prefix(&work_tree_path)
+                    .is_ok_and(|dir| created.contains(dir))
+            });
+            if owned {
+                if fs::remove_dir(&dir).is_ok() {
+                    tracing::debug!("removed empty directory {}", dir.display());
+                }
+            } else if fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none()) {
+                kept.push(dir);
+            }
+        }
+        if !kept.is_empty() {
+            tracing::info!(
+                "kept empty directories that existed before the repo was checked out:{}",
+                lazy_format!(|f| {
+                    for dir in &kept {
+                        write!(f, "\n  {}", dir.display())?;
+                    }
+                    Ok(())
+                })
+            );
+        }
+        Ok(())
+    }
+
+    /// Removes the entry for the repo `name` and deletes its Git directory, leaving the files in
+    /// its work tree intact.
+    ///
+    /// For overlay repos, this is the same as [`Self::remove_overlay_bare_repo`]. For standalone
+    /// repos, this deletes the `.git` directory (or file, for worktrees) of the work tree.
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
+    pub fn remove_git_dir(
+        &mut self,
+        dirs: &Directories,
+        name: RepoName<'_>,
+    ) -> anyhow::Result<RepoEntry<'static>> {
+        self.ensure_not_included(name.to_borrowed())?;
+        let repo = self.get_by_name(name.to_borrowed())?;
+        let git_dir_path = match repo.kind() {
+            RepoKind::Overlay => repo.path(dirs, name.to_borrowed())?.into_owned(),
+            RepoKind::Standalone => repo.path(dirs, name.to_borrowed())?.join(".git"),
+            RepoKind::Unmanaged => bail!("{:?} is an unmanaged directory, not a Git repo", name),
+        };
+        let repo = self.remove(name).unwrap();
+        let res = if git_dir_path.is_dir() {
+            remove_dir_all(&git_dir_path)
+        } else {
+            remove_file(&git_dir_path)
+        };
+        res.with_context(|| {
+            anyhow!(
+                "failed to delete Git directory at {:?}; good luck, you're on your own!",
+                git_dir_path
+            )
+        })?;
+        Ok(repo)
+    }
+
+    /// Removes the entry for the repo `name`, and deletes both its Git directory and the files it
+    /// tracks.
+    ///
+    /// For standalone repos, the entire work tree is deleted, including untracked files.
+    #[tracing::instrument(skip_all, fields(repo = ?name))]
     pub fn try_remove_entire_repo(
         &mut self,
         dirs: &Directories,
@@ -737,66 +2347,131 @@ impl RepoDb {
         name: RepoName<'_>,
         // TODO: have an event consumer getting passed in
     ) -> anyhow::Result<RepoEntry<'static>> {
-        let repo = self
-            .remove(name.to_borrowed())
-            .with_context(|| anyhow!("no repo with the name {:?} is configured", name))?;
-
         // TODO: Seek confirmation. This is dangerous, yo.
 
         // TODO: Check if there are any uncommitted files or branches, if so,
         // seek confirmation.
 
-        match repo.kind() {
-            CliRepoKind::Overlay => {
-                // Try to delete all files associated with this repo
-                match repo
-                    .open(git, dirs, name.to_borrowed())?
-                    .list_files()
-                    .context("failed to list files")
-                {
-                    Ok(files) => {
-                        for file in files {
-                            log::debug!("removing {}", file.display());
-                            match remove_file(&file) {
-                                Ok(()) => (),
-                                Err(e) => {
-                                    log::warn!("failed to remove {:?}: {}", file, e)
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => log::warn!("{}", e),
-                }
+        let kind = self.get_by_name(name.to_borrowed())?.kind();
+        self.ensure_not_included(name.to_borrowed())?;
+        match kind {
+            RepoKind::Overlay => {
+                // Files that fail to be deleted are skipped, but if they can't even be listed
+                // (i.e., because some are outside of the work tree), keep the repo around.
+                self.remove_tracked_files(dirs, git, name.to_borrowed())?;
+                self.remove_git_dir(dirs, name)
+            }
+            RepoKind::Standalone => {
+                // Deleting the work tree removes the Git directory along with it.
+                let repo = self.remove(name.to_borrowed()).unwrap();
+                let repo_path = repo.path(dirs, name)?;
+                remove_dir_all(&repo_path).with_context(|| {
+                    anyhow!(
+                        "failed to delete repo at {:?}; watch out, you're on your own now!",
+                        repo_path
+                    )
+                })?;
+                Ok(repo)
             }
-            CliRepoKind::Standalone => (), // deleting the folder should suffice
+            RepoKind::Unmanaged => bail!(
+                "refusing to delete unmanaged directory {:?}, since nothing could bring it back",
+                name
+            ),
         }
-        let repo_path = repo.path(dirs, name)?;
-        remove_dir_all(&repo_path).with_context(|| {
-            anyhow!(
-                "failed to delete repo at {:?}; watch out, you're on your own now!",
-                repo_path
-            )
-        })?;
-        Ok(repo)
     }
 
     fn remove(&mut self, name: RepoName<'_>) -> Option<RepoEntry<'static>> {
         let Self {
-            repos,
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
             needs_persist,
         } = self;
-        let removed = {
-            // SAFETY: Safe because we're only using this reference in this call -- no lifetime
-            // escaping here.
-            let name = &name;
-            let name = unsafe { transmute::<_, &RepoName<'static>>(name) };
-            repos.remove(name)
-        };
+        let removed = repos.remove(&*name);
         *needs_persist = true;
         removed
     }
 }
 
+impl Clone for RepoDbSnapshot {
+    fn clone(&self) -> Self {
+        let Self {
+            repos,
+            includes,
+            hidden,
+        } = self;
+        let clone_repos = |repos: &BTreeMap<RepoName<'static>, RepoEntry<'static>>| {
+            repos
+                .iter()
+                .map(|(name, repo)| (name.clone(), repo.to_borrowed().into_static()))
+                .collect()
+        };
+        Self {
+            repos: clone_repos(repos),
+            includes: includes.clone(),
+            hidden: clone_repos(hidden),
+        }
+    }
+}
+
+impl Deref for RepoDbTransaction<'_> {
+    type Target = RepoDbSnapshot;
+
+    fn deref(&self) -> &Self::Target {
+        &self.working
+    }
+}
+
+/// A directory created for a new repo, which is removed again when this is dropped unless
+/// [`Self::keep`] is called first, so that failing to add a repo leaves nothing behind. Holds
+/// `None` for directories that already existed, which are never removed.
+#[derive(Debug)]
+struct CreatedDir(Option<PathBuf>);
+
+impl CreatedDir {
+    /// Creates the directory at `path`, unless it already exists.
+    fn create(path: &Path) -> io::Result<Self> {
+        match create_dir(path) {
+            Ok(()) => Ok(Self(Some(path.to_owned()))),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(Self(None)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Tracks the directory at `path`, which is about to be created by something else, i.e.,
+    /// `git clone`, unless it already exists.
+    fn expect(path: &Path) -> Self {
+        Self(path.symlink_metadata().is_err().then(|| path.to_owned()))
+    }
+
+    /// Keeps the directory, now that the repo in it was added.
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CreatedDir {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            match remove_dir_all(&path) {
+                Ok(()) => tracing::info!("removed {} after failing to add it", path.display()),
+                // Git cleans up after itself, sometimes.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => tracing::warn!(
+                    "failed to remove {} after failing to add it: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct NewStandaloneOptions<'a> {
     pub name: RepoName<'a>,
@@ -808,7 +2483,10 @@ pub struct NewStandaloneOptions<'a> {
 #[derive(Debug)]
 pub enum NewStandaloneMethod<'a> {
     Init,
-    Clone { source: RepoSource<'a> },
+    Clone {
+        source: RepoSource<'a>,
+        keep_depth: Option<NonZeroU32>,
+    },
     Register,
 }
 
@@ -818,21 +2496,67 @@ pub enum NewOverlayOptions<'a> {
     Clone {
         source: RepoSource<'a>,
         no_checkout: bool,
+        /// Glob patterns limiting which files are checked out, if non-empty.
+        only: Vec<String>,
+        keep_depth: Option<NonZeroU32>,
+    },
+    /// Registers the existing bare repo at `git_dir`, leaving it where it is.
+    Register {
+        git_dir: Cow<'a, Path>,
     },
 }
 
 #[derive(Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 struct StandaloneRepoDb<'a> {
+    /// Paths of other files in this format whose repos are merged into this one when loaded.
+    /// Relative paths are relative to the directory containing this DB.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<PathBuf>,
     #[serde(borrow)]
     standalone_repos: BTreeMap<RepoName<'a>, StandaloneRepoEntry<'a>>,
+    /// Directories that aren't Git repos, which are kept here since they're located like
+    /// standalone repos.
+    #[serde(borrow, default, skip_serializing_if = "BTreeMap::is_empty")]
+    unmanaged: BTreeMap<RepoName<'a>, UnmanagedEntry<'a>>,
 }
 
 #[derive(Debug, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize)]
 struct StandaloneRepoEntry<'a> {
     #[serde(borrow)]
     path: Cow<'a, Path>,
+    // TOML requires plain values to come before tables.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    read_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sync_strategy: Option<SyncStrategy>,
     #[serde(borrow)]
     app_info: Option<AppInfo<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    source: Option<SourceInfo<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "RepoInfo::is_empty")]
+    info: RepoInfo<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    last_sync: Option<SyncRecord<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Dependencies::is_empty")]
+    depends_on: Dependencies<'a>,
+}
+
+/// An unmanaged directory. Unlike repos, these are never synced, so they have no sync state.
+#[derive(Debug, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize)]
+struct UnmanagedEntry<'a> {
+    /// Stored relative to `~` when inside the home directory, like standalone repo paths.
+    #[serde(borrow)]
+    path: Cow<'a, Path>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    read_only: bool,
+    #[serde(borrow, default, skip_serializing_if = "RepoInfo::is_empty")]
+    info: RepoInfo<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Dependencies::is_empty")]
+    depends_on: Dependencies<'a>,
 }
 
 #[derive(Debug, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize, ToBorrowed)]
@@ -845,68 +2569,352 @@ pub struct AppInfo<'a> {
 impl StandaloneRepoDb<'static> {
     fn from_toml_on_disk(dirs: &Directories) -> anyhow::Result<Self> {
         let standalone_repos_db_path = dirs.standalone_repo_db_path()?;
-        log::trace!(
+        tracing::trace!(
             "reading standalone repos DB at {}",
             standalone_repos_db_path.display()
         );
+        let read_only = dirs.is_read_only();
         let db_toml = {
             let mut buf = String::new();
-            let mut reader = BufReader::new(
-                OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(&standalone_repos_db_path)
+            let file = match OpenOptions::new()
+                .read(true)
+                .write(!read_only)
+                .create(!read_only)
+                .open(&standalone_repos_db_path)
+            {
+                Err(e) if read_only && e.kind() == io::ErrorKind::NotFound => None,
+                res => Some(res.with_context(|| {
+                    anyhow!(
+                        "failed to open standalone repos DB at {}",
+                        standalone_repos_db_path.display(),
+                    )
+                })?),
+            };
+            // In read-only mode, a missing DB is read as an empty one rather than created.
+            if let Some(file) = file {
+                BufReader::new(file)
+                    .read_to_string(&mut buf)
                     .with_context(|| {
                         anyhow!(
-                            "failed to open standalone repos DB at {}",
-                            standalone_repos_db_path.display(),
+                            "failed to read standalone repos DB at {}",
+                            standalone_repos_db_path.display()
                         )
-                    })?,
-            );
-            reader.read_to_string(&mut buf).with_context(|| {
-                anyhow!(
-                    "failed to read standalone repos DB at {}",
-                    standalone_repos_db_path.display()
-                )
-            })?;
+                    })?;
+            }
             buf
         };
-        let parsed = StandaloneRepoDb::from_toml(&db_toml).with_context(|| {
-            anyhow!(
+        let db_toml = schema::upgrade(dirs, &standalone_repos_db_path, db_toml)?;
+        let parsed = StandaloneRepoDb::from_toml(&db_toml).map_err(|e| {
+            Error::Corruption(e.context(anyhow!(
                 "failed to deserialize TOML from standalone repo DB at {}",
                 standalone_repos_db_path.display(),
+            )))
+        })?;
+        Ok(parsed.into_static())
+    }
+}
+
+impl StandaloneRepoDb<'static> {
+    /// Reads a DB fragment listed in another DB's `include` field. Fragments can't include other
+    /// files themselves.
+    fn from_include_file(path: &Path, home: &Path) -> anyhow::Result<Self> {
+        let db_toml = fs::read_to_string(path)
+            .with_context(|| anyhow!("failed to read included repo DB at {}", path.display()))?;
+        let mut parsed = StandaloneRepoDb::from_toml(&db_toml).with_context(|| {
+            anyhow!(
+                "failed to deserialize TOML from included repo DB at {}",
+                path.display()
             )
         })?;
+        if !parsed.include.is_empty() {
+            tracing::warn!(
+                "ignoring `include` in included repo DB at {}; includes can't be nested",
+                path.display()
+            );
+        }
+        parsed.expand_home_paths(home);
         Ok(parsed.into_static())
     }
 }
 
 impl<'a> StandaloneRepoDb<'a> {
+    /// Expands paths stored relative to `home` with a leading `~`. Returns whether any paths were
+    /// stored as absolute paths inside `home` instead, which newer versions no longer do.
+    fn expand_home_paths(&mut self, home: &Path) -> bool {
+        let Self {
+            include: _,
+            standalone_repos,
+            unmanaged,
+        } = self;
+
+        let standalone_paths = standalone_repos.values_mut().map(|entry| {
+            let StandaloneRepoEntry {
+                path,
+                app_info: _,
+                source: _,
+                pinned: _,
+                read_only: _,
+                sync_strategy: _,
+                info: _,
+                last_sync: _,
+                depends_on: _,
+            } = entry;
+            path
+        });
+        let unmanaged_paths = unmanaged.values_mut().map(|entry| {
+            let UnmanagedEntry {
+                path,
+                pinned: _,
+                read_only: _,
+                info: _,
+                depends_on: _,
+            } = entry;
+            path
+        });
+        let mut found_absolute = false;
+        for path in standalone_paths.chain(unmanaged_paths) {
+            match expand_home_path(path, home) {
+                Some(expanded) => *path = Cow::Owned(expanded),
+                None => found_absolute |= path.starts_with(home),
+            }
+        }
+        found_absolute
+    }
+
     fn into_static(self) -> StandaloneRepoDb<'static> {
-        let Self { standalone_repos } = self;
+        let Self {
+            include,
+            standalone_repos,
+            unmanaged,
+        } = self;
 
         StandaloneRepoDb {
+            include,
             standalone_repos: standalone_repos
                 .into_iter()
                 .map(|(name, entry)| (name.into_static(), entry.into_static()))
                 .collect(),
+            unmanaged: unmanaged
+                .into_iter()
+                .map(|(name, entry)| (name.into_static(), entry.into_static()))
+                .collect(),
         }
     }
 
     fn into_runner_repos(self) -> impl Iterator<Item = (RepoName<'a>, RepoEntry<'a>)> {
-        let Self { standalone_repos } = self;
+        let Self {
+            include: _,
+            standalone_repos,
+            unmanaged,
+        } = self;
 
+        let unmanaged = unmanaged.into_iter().map(
+            |(
+                name,
+                UnmanagedEntry {
+                    path,
+                    pinned,
+                    read_only,
+                    info,
+                    depends_on,
+                },
+            )| {
+                (
+                    name,
+                    RepoEntry {
+                        kind: RepoEntryKind::Unmanaged { path },
+                        pinned,
+                        read_only,
+                        sync_strategy: None,
+                        info,
+                        last_sync: None,
+                        depends_on,
+                    },
+                )
+            },
+        );
         standalone_repos
             .into_iter()
-            .map(|(name, StandaloneRepoEntry { app_info, path })| {
+            .map(
+                |(
+                    name,
+                    StandaloneRepoEntry {
+                        app_info,
+                        path,
+                        source,
+                        pinned,
+                        read_only,
+                        sync_strategy,
+                        info,
+                        last_sync,
+                        depends_on,
+                    },
+                )| {
+                    (
+                        name,
+                        RepoEntry {
+                            kind: RepoEntryKind::Standalone {
+                                path,
+                                app_info,
+                                source,
+                            },
+                            pinned,
+                            read_only,
+                            sync_strategy,
+                            info,
+                            last_sync,
+                            depends_on,
+                        },
+                    )
+                },
+            )
+            .chain(unmanaged)
+    }
+}
+
+/// The DB of overlay repos. Overlay repos' Git directories are located in
+/// [`Directories::overlay_repos_dir_path`] unless registered from elsewhere, so this mostly holds
+/// metadata.
+#[derive(Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+struct OverlayRepoDb<'a> {
+    #[serde(borrow, default)]
+    overlay_repos: BTreeMap<RepoName<'a>, OverlayRepoEntry<'a>>,
+}
+
+#[derive(Debug, Default, Deserialize, Eq, IntoStatic, Ord, PartialEq, PartialOrd, Serialize)]
+struct OverlayRepoEntry<'a> {
+    // TOML requires plain values to come before tables.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    read_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sync_strategy: Option<SyncStrategy>,
+    /// Stored relative to `~` when inside the home directory, like standalone repo paths.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    work_tree: Option<Cow<'a, Path>>,
+    /// Set for repos whose Git directory isn't in the overlay repos directory. Stored like
+    /// `work_tree`.
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    git_dir: Option<Cow<'a, Path>>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    source: Option<SourceInfo<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "RepoInfo::is_empty")]
+    info: RepoInfo<'a>,
+    #[serde(borrow, default, skip_serializing_if = "Option::is_none")]
+    last_sync: Option<SyncRecord<'a>>,
+    #[serde(borrow, default, skip_serializing_if = "Dependencies::is_empty")]
+    depends_on: Dependencies<'a>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+impl OverlayRepoDb<'static> {
+    /// Reads the overlay repos DB, or returns `None` if it hasn't been created yet.
+    fn from_toml_on_disk(dirs: &Directories) -> anyhow::Result<Option<Self>> {
+        let overlay_repos_db_path = dirs.overlay_repo_db_path();
+        tracing::trace!(
+            "reading overlay repos DB at {}",
+            overlay_repos_db_path.display()
+        );
+        let db_toml = match fs::read_to_string(&overlay_repos_db_path) {
+            Ok(db_toml) => db_toml,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(anyhow!(
+                    "failed to read overlay repos DB at {}",
+                    overlay_repos_db_path.display()
+                )))
+            }
+        };
+        let db_toml = schema::upgrade(dirs, &overlay_repos_db_path, db_toml)?;
+        let parsed = toml::from_str::<OverlayRepoDb<'_>>(&db_toml).map_err(|e| {
+            Error::Corruption(anyhow::Error::new(e).context(anyhow!(
+                "failed to deserialize TOML from overlay repo DB at {}",
+                overlay_repos_db_path.display(),
+            )))
+        })?;
+        let OverlayRepoDb { overlay_repos } = parsed;
+        Ok(Some(OverlayRepoDb {
+            overlay_repos: overlay_repos
+                .into_iter()
+                .map(|(name, entry)| (name.into_static(), entry.into_static()))
+                .collect(),
+        }))
+    }
+
+    /// Creates a DB from overlay repos found by scanning the overlay repos directory.
+    fn backfill(names: impl IntoIterator<Item = RepoName<'static>>) -> Self {
+        let overlay_repos = names
+            .into_iter()
+            .map(|name| {
                 (
                     name,
-                    RepoEntry {
-                        kind: RepoEntryKind::Standalone { path, app_info },
+                    OverlayRepoEntry {
+                        source: None,
+                        pinned: false,
+                        read_only: false,
+                        sync_strategy: None,
+                        work_tree: None,
+                        git_dir: None,
+                        info: RepoInfo::default(),
+                        last_sync: None,
+                        depends_on: Dependencies::default(),
                     },
                 )
             })
+            .collect();
+        Self { overlay_repos }
+    }
+}
+
+impl<'a> OverlayRepoDb<'a> {
+    fn into_runner_repos(
+        self,
+        home: PathBuf,
+    ) -> impl Iterator<Item = (RepoName<'a>, RepoEntry<'a>)> {
+        let Self { overlay_repos } = self;
+
+        overlay_repos.into_iter().map(
+            move |(
+                name,
+                OverlayRepoEntry {
+                    source,
+                    pinned,
+                    read_only,
+                    sync_strategy,
+                    work_tree,
+                    git_dir,
+                    info,
+                    last_sync,
+                    depends_on,
+                },
+            )| {
+                let work_tree = work_tree.map(|work_tree| {
+                    expand_home_path(&work_tree, &home).map_or(work_tree, Cow::Owned)
+                });
+                let git_dir = git_dir
+                    .map(|git_dir| expand_home_path(&git_dir, &home).map_or(git_dir, Cow::Owned));
+                (
+                    name,
+                    RepoEntry {
+                        kind: RepoEntryKind::Overlay {
+                            source,
+                            work_tree,
+                            git_dir,
+                        },
+                        pinned,
+                        read_only,
+                        sync_strategy,
+                        info,
+                        last_sync,
+                        depends_on,
+                    },
+                )
+            },
+        )
     }
 }
 
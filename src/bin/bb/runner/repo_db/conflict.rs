@@ -1,10 +1,8 @@
 use self::normalization::Normalization;
-use crate::{
-    cli::CliRepoKind,
-    runner::{
-        repo_db::{conflict::normalization::NormalizedEqOutcome, RepoDb, RepoEntry, RepoName},
-        Directories,
-    },
+use crate::runner::{
+    kind::RepoKind,
+    repo_db::{conflict::normalization::NormalizedEqOutcome, RepoDbSnapshot, RepoEntry, RepoName},
+    Directories,
 };
 use anyhow::anyhow;
 use lifetime::{IntoStatic, ToBorrowed};
@@ -32,7 +30,7 @@ impl<'a> RepoConflictSearcher<'a> {
         name: RepoName<'a>,
         entry: RepoEntry<'a>,
         dirs: &'a Directories,
-        repo_db: &'a RepoDb,
+        repo_db: &'a RepoDbSnapshot,
     ) -> anyhow::Result<Self> {
         // TODO: Check for a `standalone` repo path within our local data dir -- don't allow this.
         let search_path = entry.path(dirs, name.to_borrowed())?.into_static();
@@ -73,10 +71,10 @@ impl<'a> RepoConflictSearcher<'a> {
                 // For other errors, bail out. We can't make any guarantees about maintaining
                 // integrity of our configuration if we encounter those errors.
                 let outcome =
-                    if other_repo_path.exists() || matches!(repo.kind(), CliRepoKind::Overlay) {
+                    if other_repo_path.exists() || matches!(repo.kind(), RepoKind::Overlay) {
                         NormalizedRepoPathEq::normalized_eq(search_path, &other_repo_path)?
                     } else {
-                        log::warn!("Git work tree directory of existing {}", repo.short_desc());
+                        tracing::warn!("Git work tree directory of existing {}", repo.short_desc());
                         NormalizedEqOutcome::NotAMatch
                     };
 
@@ -205,6 +203,18 @@ impl<'a> Normalization<Cow<'a, Path>> for NormalizedRepoPathEq {
     }
 }
 
+/// What to do about a new repo's name being taken by an existing entry. See
+/// [`RepoConflictHandler::resolve_name_conflict`].
+#[derive(Debug)]
+pub enum NameConflictResolution {
+    /// Add the new repo under this name instead.
+    Rename(RepoName<'static>),
+    /// Remove the existing entry, leaving its files in place, and add the new repo in its stead.
+    Replace,
+    /// Fail with a conflict.
+    Abort,
+}
+
 pub trait RepoConflictHandler {
     fn on_conflict_path(
         &mut self,
@@ -219,4 +229,11 @@ pub trait RepoConflictHandler {
     );
 
     fn on_iteration_err(&mut self, err: anyhow::Error);
+
+    /// Decides what to do about the existing repo `existing` having the name of a new repo, when
+    /// nothing else about them conflicts.
+    fn resolve_name_conflict(
+        &mut self,
+        existing: RepoName<'_>,
+    ) -> anyhow::Result<NameConflictResolution>;
 }
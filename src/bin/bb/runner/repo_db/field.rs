@@ -0,0 +1,292 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Typed access to individual fields of repo entries, for `db get`, `db set`, and `db unset`.
+
+use super::{
+    Dependencies, RepoDbSnapshot, RepoDbTransaction, RepoEntry, RepoEntryKind, RepoName, SourceInfo,
+};
+use crate::{
+    runner::{error::Error, ordering, sync::SyncStrategy},
+    suggest,
+};
+use anyhow::anyhow;
+use lifetime::{IntoStatic, ToBorrowed};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error as ThisError;
+
+/// A field of a repo entry that can be read and changed from the command line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RepoField {
+    Description,
+    Homepage,
+    Notes,
+    Pinned,
+    ReadOnly,
+    SyncStrategy,
+    /// The URL of the recorded upstream source. Note that this is kept up to date with the repo's
+    /// default remote, so changes are overwritten once that's inspected again.
+    SourceUrl,
+    /// The branch of the recorded upstream source. Requires [`Self::SourceUrl`] to be set.
+    SourceBranch,
+    /// The names of the repos this one depends on, separated by commas.
+    DependsOn,
+}
+
+impl RepoField {
+    const ALL: [Self; 9] = [
+        Self::Description,
+        Self::Homepage,
+        Self::Notes,
+        Self::Pinned,
+        Self::ReadOnly,
+        Self::SyncStrategy,
+        Self::SourceUrl,
+        Self::SourceBranch,
+        Self::DependsOn,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            Self::Description => "description",
+            Self::Homepage => "homepage",
+            Self::Notes => "notes",
+            Self::Pinned => "pinned",
+            Self::ReadOnly => "read_only",
+            Self::SyncStrategy => "sync_strategy",
+            Self::SourceUrl => "source.url",
+            Self::SourceBranch => "source.branch",
+            Self::DependsOn => "depends_on",
+        }
+    }
+}
+
+impl Display for RepoField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+#[derive(Debug, ThisError)]
+#[error(
+    "unrecognized repo field {actual:?}{}",
+    suggest::did_you_mean(.actual, RepoField::ALL.map(RepoField::key).iter())
+)]
+pub struct InvalidRepoFieldError {
+    actual: String,
+}
+
+impl FromStr for RepoField {
+    type Err = InvalidRepoFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.key() == s)
+            .ok_or_else(|| InvalidRepoFieldError {
+                actual: s.to_owned(),
+            })
+    }
+}
+
+impl RepoEntry<'_> {
+    /// The value of `field` in this entry, formatted as `db set` accepts it, or `None` if it's
+    /// unset.
+    pub(crate) fn field(&self, field: RepoField) -> Option<String> {
+        let Self {
+            kind,
+            pinned,
+            read_only,
+            sync_strategy,
+            info,
+            last_sync: _,
+            depends_on,
+        } = self;
+        let source = match kind {
+            RepoEntryKind::Overlay { source, .. } | RepoEntryKind::Standalone { source, .. } => {
+                source.as_ref()
+            }
+            RepoEntryKind::Unmanaged { .. } => None,
+        };
+        match field {
+            RepoField::Description => info.description.as_deref().map(str::to_owned),
+            RepoField::Homepage => info.homepage.as_deref().map(str::to_owned),
+            RepoField::Notes => info.notes.as_deref().map(str::to_owned),
+            RepoField::Pinned => Some(pinned.to_string()),
+            RepoField::ReadOnly => Some(read_only.to_string()),
+            RepoField::SyncStrategy => sync_strategy.map(|strategy| strategy.to_string()),
+            RepoField::SourceUrl => source.map(|source| source.url.clone().into_owned()),
+            RepoField::SourceBranch => source
+                .and_then(|source| source.branch.as_deref())
+                .map(str::to_owned),
+            RepoField::DependsOn => (!depends_on.is_empty()).then(|| {
+                depends_on
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }),
+        }
+    }
+}
+
+impl RepoDbTransaction<'_> {
+    /// Sets `field` of the repo `name` to `value`, after checking that it's valid for the field,
+    /// or resets it to its default if `value` is `None`.
+    pub fn set_field(
+        &mut self,
+        name: RepoName<'_>,
+        field: RepoField,
+        value: Option<&str>,
+    ) -> Result<(), Error> {
+        let is_git_repo = self.get_by_name(name.to_borrowed())?.kind().is_git_repo();
+        self.ensure_not_included(name.to_borrowed())?;
+        let invalid =
+            |e: anyhow::Error| Error::Other(e.context(anyhow!("invalid value for {}", field)));
+        if value == Some("") {
+            return Err(invalid(anyhow!(
+                "value is empty; use `db unset` to clear a field"
+            )));
+        }
+        let is_git_field = matches!(
+            field,
+            RepoField::SyncStrategy | RepoField::SourceUrl | RepoField::SourceBranch
+        );
+        if is_git_field && !is_git_repo && value.is_some() {
+            return Err(invalid(anyhow!(
+                "{:?} is an unmanaged directory, which isn't synced",
+                name
+            )));
+        }
+        let parse_bool = |value: Option<&str>| -> Result<bool, Error> {
+            value.map_or(Ok(false), |value| {
+                value
+                    .parse::<bool>()
+                    .map_err(|_e| invalid(anyhow!("expected `true` or `false`, got {:?}", value)))
+            })
+        };
+        let dependencies = match (field, value) {
+            (RepoField::DependsOn, Some(value)) => self.parse_dependencies(&name, value)?,
+            _ => Dependencies::default(),
+        };
+        let Self {
+            db: _,
+            _writer,
+            working:
+                RepoDbSnapshot {
+                    repos,
+                    includes: _,
+                    hidden: _,
+                },
+            needs_persist,
+        } = self;
+        let RepoEntry {
+            kind,
+            pinned,
+            read_only,
+            sync_strategy,
+            info,
+            last_sync: _,
+            depends_on,
+        } = repos.get_mut(&*name).unwrap();
+        let mut no_source = None;
+        let source = match kind {
+            RepoEntryKind::Overlay { source, .. } | RepoEntryKind::Standalone { source, .. } => {
+                source
+            }
+            RepoEntryKind::Unmanaged { .. } => &mut no_source,
+        };
+        let text = |value: Option<&str>| value.map(|value| Cow::Owned(value.to_owned()));
+        let changed = match field {
+            RepoField::Description => replace(&mut info.description, text(value)),
+            RepoField::Homepage => replace(&mut info.homepage, text(value)),
+            RepoField::Notes => replace(&mut info.notes, text(value)),
+            RepoField::Pinned => replace(pinned, parse_bool(value)?),
+            RepoField::ReadOnly => replace(read_only, parse_bool(value)?),
+            RepoField::SyncStrategy => {
+                let strategy = value
+                    .map(SyncStrategy::from_str)
+                    .transpose()
+                    .map_err(|e| invalid(e.into()))?;
+                replace(sync_strategy, strategy)
+            }
+            RepoField::SourceUrl => match value {
+                Some(url) => {
+                    if url.chars().any(char::is_whitespace) {
+                        return Err(invalid(anyhow!("repo sources can't contain whitespace")));
+                    }
+                    let new = SourceInfo {
+                        url: url.to_owned().into(),
+                        branch: source.as_mut().and_then(|source| source.branch.take()),
+                    };
+                    replace(source, Some(new))
+                }
+                None => replace(source, None),
+            },
+            RepoField::SourceBranch => match source {
+                Some(source) => replace(&mut source.branch, text(value)),
+                None if value.is_none() => false,
+                None => {
+                    return Err(invalid(anyhow!(
+                        "{:?} has no recorded source; set {} first",
+                        name,
+                        RepoField::SourceUrl
+                    )))
+                }
+            },
+            RepoField::DependsOn => replace(depends_on, dependencies),
+        };
+        if changed {
+            *needs_persist = true;
+        }
+        if field == RepoField::DependsOn {
+            let all = self.iter().collect();
+            ordering::dependency_order(all).map_err(invalid)?;
+        }
+        Ok(())
+    }
+
+    /// Parses a comma-separated list of the names of repos that `name` may depend on.
+    fn parse_dependencies(
+        &self,
+        name: &RepoName<'_>,
+        value: &str,
+    ) -> Result<Dependencies<'static>, Error> {
+        let invalid = |e: anyhow::Error| {
+            Error::Other(e.context(anyhow!("invalid value for {}", RepoField::DependsOn)))
+        };
+        let mut dependencies = Vec::new();
+        for dep in value.split(',').map(str::trim) {
+            let dep = RepoName::from_str(dep).map_err(|e| invalid(e.into()))?;
+            if dep == *name {
+                return Err(invalid(anyhow!("{:?} can't depend on itself", name)));
+            }
+            self.get_by_name(dep.to_borrowed())?;
+            if !dependencies.contains(&dep) {
+                dependencies.push(dep.into_static());
+            }
+        }
+        Ok(dependencies.into_iter().collect())
+    }
+}
+
+/// Replaces `old` with `new`, returning whether they differed.
+fn replace<T: PartialEq>(old: &mut T, new: T) -> bool {
+    let changed = *old != new;
+    *old = new;
+    changed
+}
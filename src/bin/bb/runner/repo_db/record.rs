@@ -0,0 +1,89 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! A read model of repo entries, shared by everything that presents them: `list --format json`,
+//! `starter export`, and `ui`.
+//!
+//! Unlike [`RepoEntry`], a [`RepoRecord`] has its paths resolved, owns its data, and can be
+//! serialized as is.
+
+use super::{AppInfo, RepoDbSnapshot, RepoEntry, RepoName, SourceInfo};
+use crate::runner::{dirs::Directories, kind::RepoKind, repo_metadata::RepoMetadata};
+use anyhow::{anyhow, Context};
+use lifetime::{IntoStatic, ToBorrowed};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// What is known about a repo entry, without asking Git.
+#[derive(Debug, Serialize)]
+pub struct RepoRecord {
+    pub name: RepoName<'static>,
+    pub kind: RepoKind,
+    /// The path to the repo's Git directory for overlays, or its work tree for standalone repos.
+    pub path: PathBuf,
+    pub work_tree: PathBuf,
+    pub source: Option<SourceInfo<'static>>,
+    /// The tags a machine must have for this repo to be set up on it, from the repo's
+    /// `.bellboy.toml`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_info: Option<AppInfo<'static>>,
+}
+
+impl RepoEntry<'_> {
+    /// Builds the [`RepoRecord`] of this entry, which is registered as `name`.
+    ///
+    /// A `.bellboy.toml` that can't be read only costs the record its tags, with a warning.
+    pub(crate) fn record(
+        &self,
+        dirs: &Directories,
+        name: RepoName<'_>,
+    ) -> anyhow::Result<RepoRecord> {
+        let work_tree = self.work_tree_path(dirs)?.into_owned();
+        let tags = required_tags(&work_tree).unwrap_or_else(|e| {
+            tracing::warn!("{:?}", e.context(anyhow!("ignoring tags of {:?}", name)));
+            Vec::new()
+        });
+        Ok(RepoRecord {
+            kind: self.kind(),
+            path: self.path(dirs, name.to_borrowed())?.into_owned(),
+            work_tree,
+            source: self.source().map(IntoStatic::into_static),
+            tags,
+            app_info: self
+                .app_info()
+                .map(|app_info| app_info.to_borrowed().into_static()),
+            name: name.into_static(),
+        })
+    }
+}
+
+impl RepoDbSnapshot {
+    /// The [`RepoRecord`]s of all entries, ordered by name.
+    pub fn records<'a>(
+        &'a self,
+        dirs: &'a Directories,
+    ) -> impl Iterator<Item = anyhow::Result<RepoRecord>> + 'a {
+        self.iter().map(move |(name, repo)| repo.record(dirs, name))
+    }
+}
+
+fn required_tags(work_tree: &Path) -> anyhow::Result<Vec<String>> {
+    let Some(toml) = RepoMetadata::read_toml(work_tree)? else {
+        return Ok(Vec::new());
+    };
+    let metadata = RepoMetadata::from_toml(&toml)
+        .with_context(|| anyhow!("{:?} is invalid", RepoMetadata::path(work_tree)))?;
+    Ok(metadata.required_tags)
+}
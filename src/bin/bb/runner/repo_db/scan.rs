@@ -0,0 +1,138 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! How to react to problems found while loading the repo DBs, which happens before every command.
+//!
+//! Each class of problem can be made fatal, reported, or ignored with the `[scan]` table of the
+//! configuration, so that a single stray directory doesn't lock users out of every command, and
+//! so that stricter setups can refuse to run on anything unexpected.
+
+use crate::runner::error::Error;
+use serde::Deserialize;
+
+/// What to do about a problem found while loading the repo DBs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    /// Fail every command, since the repo DBs can't be trusted.
+    Error,
+    /// Log a warning, skip whatever is at fault, and carry on.
+    Warn,
+    /// Skip whatever is at fault, only logging it at the debug level.
+    Ignore,
+}
+
+/// A class of problem found while loading the repo DBs. See [`ScanPolicy`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ScanProblem {
+    /// An overlay repo and a standalone repo have the same name. Unless this is fatal, the
+    /// standalone repo wins, and the overlay repo is hidden until the conflict is resolved.
+    NameConflict,
+    /// A directory in the overlay repos directory has no entry in the overlay repos DB.
+    UnregisteredOverlayDir,
+    /// An entry of the overlay repos DB has no directory in the overlay repos directory.
+    MissingOverlayDir,
+    /// Something in the overlay repos directory isn't a directory named like a repo, or the
+    /// overlay repos directory can't be read at all.
+    InvalidOverlayDirEntry,
+    /// A standalone repo DB listed in `include` can't be read.
+    BrokenInclude,
+    /// A repo from an included standalone repo DB has the name or path of another repo.
+    IncludeConflict,
+}
+
+impl ScanProblem {
+    /// The key of the `[scan]` table that sets the severity of this class of problem.
+    fn config_key(self) -> &'static str {
+        match self {
+            Self::NameConflict => "name_conflicts",
+            Self::UnregisteredOverlayDir => "unregistered_overlay_dirs",
+            Self::MissingOverlayDir => "missing_overlay_dirs",
+            Self::InvalidOverlayDirEntry => "invalid_overlay_dir_entries",
+            Self::BrokenInclude => "broken_includes",
+            Self::IncludeConflict => "include_conflicts",
+        }
+    }
+}
+
+/// The severity of each class of [`ScanProblem`], set with the `[scan]` table of the
+/// configuration. Name conflicts are errors by default; everything else is a warning.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ScanPolicy {
+    name_conflicts: Severity,
+    unregistered_overlay_dirs: Severity,
+    missing_overlay_dirs: Severity,
+    invalid_overlay_dir_entries: Severity,
+    broken_includes: Severity,
+    include_conflicts: Severity,
+}
+
+impl Default for ScanPolicy {
+    fn default() -> Self {
+        Self {
+            name_conflicts: Severity::Error,
+            unregistered_overlay_dirs: Severity::Warn,
+            missing_overlay_dirs: Severity::Warn,
+            invalid_overlay_dir_entries: Severity::Warn,
+            broken_includes: Severity::Warn,
+            include_conflicts: Severity::Warn,
+        }
+    }
+}
+
+impl ScanPolicy {
+    fn severity(&self, problem: ScanProblem) -> Severity {
+        let Self {
+            name_conflicts,
+            unregistered_overlay_dirs,
+            missing_overlay_dirs,
+            invalid_overlay_dir_entries,
+            broken_includes,
+            include_conflicts,
+        } = self;
+        *match problem {
+            ScanProblem::NameConflict => name_conflicts,
+            ScanProblem::UnregisteredOverlayDir => unregistered_overlay_dirs,
+            ScanProblem::MissingOverlayDir => missing_overlay_dirs,
+            ScanProblem::InvalidOverlayDirEntry => invalid_overlay_dir_entries,
+            ScanProblem::BrokenInclude => broken_includes,
+            ScanProblem::IncludeConflict => include_conflicts,
+        }
+    }
+
+    /// Reports `error`, an instance of `problem`, as the configured severity says to. Only fails
+    /// if the severity is [`Severity::Error`], in which case the caller should stop loading.
+    pub(crate) fn report(&self, problem: ScanProblem, error: anyhow::Error) -> Result<(), Error> {
+        let key = problem.config_key();
+        match self.severity(problem) {
+            Severity::Error => Err(Error::Corruption(error.context(format!(
+                "the repo DBs are inconsistent; fix the problem below, or set `scan.{} = \
+                \"warn\"` in the configuration to skip what's at fault",
+                key
+            )))),
+            Severity::Warn => {
+                tracing::warn!(
+                    "{:#} (set `scan.{} = \"ignore\"` in the configuration to silence this)",
+                    error,
+                    key
+                );
+                Ok(())
+            }
+            Severity::Ignore => {
+                tracing::debug!("ignoring: {:#}", error);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{dirs::Directories, error::Error};
+use anyhow::{anyhow, Context};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+use toml::{value::Table, Value};
+
+/// The schema version of the repo DBs written by this version of the tool, recorded in their
+/// top-level `version` field.
+pub(super) const CURRENT_VERSION: u32 = 3;
+
+/// The version of DBs written before their schema was versioned, which have no `version` field.
+const UNVERSIONED: u32 = 1;
+
+/// A step upgrading a DB's TOML from the version before `to_version` to `to_version`.
+struct Migration {
+    to_version: u32,
+    description: &'static str,
+    migrate: fn(&mut Table) -> anyhow::Result<()>,
+}
+
+/// Every migration, in order. Adding a field that older versions can't default sensibly, or
+/// changing the meaning of an existing one, should bump [`CURRENT_VERSION`] and add an entry
+/// here.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to_version: 2,
+        description: "record the schema version",
+        migrate: |_db| Ok(()),
+    },
+    // Older versions would drop unmanaged directory entries when rewriting the DB.
+    Migration {
+        to_version: 3,
+        description: "allow unmanaged directory entries",
+        migrate: |_db| Ok(()),
+    },
+];
+
+/// Upgrades the DB at `path`, whose contents are `toml`, to [`CURRENT_VERSION`], and returns the
+/// upgraded contents.
+///
+/// If any migrations apply, the upgraded DB is written back to `path` right away, and the
+/// original is kept next to it as `<path>.v<VERSION>.bak`. In read-only mode, the DB is only
+/// upgraded in memory.
+pub(super) fn upgrade(dirs: &Directories, path: &Path, toml: String) -> Result<String, Error> {
+    if toml.trim().is_empty() {
+        return Ok(toml);
+    }
+    let mut db = toml::from_str::<Table>(&toml).map_err(|e| {
+        Error::Corruption(anyhow::Error::new(e).context(anyhow!(
+            "failed to deserialize TOML from repo DB at {}",
+            path.display()
+        )))
+    })?;
+    let version = match db.get("version") {
+        None => UNVERSIONED,
+        Some(Value::Integer(version)) => u32::try_from(*version)
+            .ok()
+            .filter(|version| *version >= UNVERSIONED)
+            .ok_or_else(|| {
+                Error::Corruption(anyhow!(
+                    "repo DB at {} has invalid version {}",
+                    path.display(),
+                    version
+                ))
+            })?,
+        Some(version) => {
+            return Err(Error::Corruption(anyhow!(
+                "repo DB at {} has a non-integer version {}",
+                path.display(),
+                version
+            )))
+        }
+    };
+    if version > CURRENT_VERSION {
+        return Err(Error::Other(anyhow!(
+            "repo DB at {} has version {}, but this version of Bellboy only understands versions \
+            up to {}; please upgrade Bellboy",
+            path.display(),
+            version,
+            CURRENT_VERSION
+        )));
+    }
+    if version == CURRENT_VERSION {
+        return Ok(toml);
+    }
+
+    for Migration {
+        to_version,
+        description,
+        migrate,
+    } in MIGRATIONS.iter().filter(|m| m.to_version > version)
+    {
+        tracing::info!(
+            "upgrading repo DB at {} to version {}: {}",
+            path.display(),
+            to_version,
+            description
+        );
+        migrate(&mut db).with_context(|| {
+            anyhow!(
+                "failed to upgrade repo DB at {} to version {}",
+                path.display(),
+                to_version
+            )
+        })?;
+    }
+    db.remove("version");
+    let upgraded = with_version(
+        toml::to_string(&Value::Table(db)).context("failed to serialize upgraded repo DB")?,
+    );
+    if dirs.is_read_only() {
+        tracing::info!(
+            "in read-only mode, not writing upgraded repo DB to {}",
+            path.display()
+        );
+        return Ok(upgraded);
+    }
+
+    let backup_path = {
+        let mut backup_path = OsString::from(path);
+        backup_path.push(format!(".v{}.bak", version));
+        backup_path
+    };
+    // Keep the oldest backup, in case a previous upgrade was interrupted.
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&backup_path)
+    {
+        Ok(mut file) => file.write_all(toml.as_bytes()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => Err(e),
+    }
+    .with_context(|| anyhow!("failed to back up repo DB to {:?}", backup_path))?;
+    fs::write(path, &upgraded)
+        .with_context(|| anyhow!("failed to write upgraded repo DB to {}", path.display()))?;
+    tracing::info!(
+        "upgraded repo DB at {}; the original was backed up to {:?}",
+        path.display(),
+        backup_path
+    );
+    Ok(upgraded)
+}
+
+/// Prefixes a DB serialized as `toml` with the `version` field of the current schema.
+pub(super) fn with_version(toml: String) -> String {
+    format!("version = {}\n\n{}", CURRENT_VERSION, toml)
+}
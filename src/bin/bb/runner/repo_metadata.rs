@@ -0,0 +1,142 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::repo_db::{AppInfo, RepoName};
+use anyhow::{anyhow, ensure, Context};
+use format::lazy_format;
+use serde::Deserialize;
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The name of the file, at the root of a repo's work tree, that [`RepoMetadata`] is read from.
+pub(crate) const FILE_NAME: &str = ".bellboy.toml";
+
+/// Metadata that a repo carries about itself, so that it can describe how it should be set up by
+/// this tool.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RepoMetadata<'a> {
+    /// The name this repo would prefer to be registered under.
+    #[serde(borrow, default)]
+    pub name: Option<RepoName<'a>>,
+    #[serde(borrow, default)]
+    pub app_info: Option<AppInfo<'a>>,
+    /// Commands to run from the root of the work tree after the repo is cloned, each as a program
+    /// followed by its arguments.
+    #[serde(default)]
+    pub post_clone: Vec<Vec<String>>,
+    /// Tags that a machine should have for this repo to be set up on it. See the `tags` setting
+    /// of this tool's configuration file.
+    #[serde(default)]
+    pub required_tags: Vec<String>,
+}
+
+impl<'a> RepoMetadata<'a> {
+    /// The path of the metadata file of the repo whose work tree is at `work_tree`.
+    pub fn path(work_tree: &Path) -> PathBuf {
+        work_tree.join(FILE_NAME)
+    }
+
+    /// Reads the metadata file of the repo whose work tree is at `work_tree`, returning `None` if
+    /// it doesn't have one.
+    pub fn read_toml(work_tree: &Path) -> anyhow::Result<Option<String>> {
+        let path = Self::path(work_tree);
+        match fs::read_to_string(&path) {
+            Ok(toml) => Ok(Some(toml)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow::Error::new(e).context(anyhow!("failed to read {:?}", path))),
+        }
+    }
+
+    pub fn from_toml(toml: &'a str) -> anyhow::Result<Self> {
+        let metadata = toml::from_str::<Self>(toml).context("failed to deserialize TOML")?;
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let Self {
+            name: _,
+            app_info: _,
+            post_clone,
+            required_tags: _,
+        } = self;
+        for cmd in post_clone {
+            ensure!(!cmd.is_empty(), "`post_clone` contains an empty command");
+        }
+        Ok(())
+    }
+
+    /// The tags in `required_tags` that aren't among `machine_tags`.
+    pub fn missing_tags<'t>(&'t self, machine_tags: &'t [String]) -> Vec<&'t str> {
+        self.required_tags
+            .iter()
+            .filter(|tag| !machine_tags.contains(tag))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// A multi-line summary of what applying this metadata would do, for confirmation by the
+    /// user.
+    pub fn describe(&'a self, machine_tags: &'a [String]) -> impl Display + 'a {
+        lazy_format!(move |f| {
+            let Self {
+                name,
+                app_info,
+                post_clone,
+                required_tags,
+            } = self;
+            if let Some(name) = name {
+                writeln!(f, "  name: {}", name)?;
+            }
+            if let Some(app_info) = app_info {
+                writeln!(f, "  app info: {:?}", app_info)?;
+            }
+            for cmd in post_clone {
+                writeln!(f, "  post-clone command: {:?}", cmd)?;
+            }
+            if !required_tags.is_empty() {
+                write!(f, "  required tags: {:?}", required_tags)?;
+                match &*self.missing_tags(machine_tags) {
+                    [] => writeln!(f)?,
+                    missing => writeln!(f, " (this machine is missing {:?})", missing)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Runs each of `post_clone` from `work_tree`, stopping at the first failure.
+    pub fn run_post_clone(&self, work_tree: &Path) -> anyhow::Result<()> {
+        for cmd_and_args in &self.post_clone {
+            let (program, args) = cmd_and_args.split_first().unwrap();
+            let mut cmd = Command::new(program);
+            cmd.args(args).current_dir(work_tree);
+            tracing::info!("running post-clone command {:?}", cmd_and_args);
+            let status = cmd.status().with_context(|| {
+                anyhow!("failed to spawn post-clone command {:?}", cmd_and_args)
+            })?;
+            ensure!(
+                status.success(),
+                "post-clone command {:?} failed: {}",
+                cmd_and_args,
+                status
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Just enough of the OpenSSH client configuration, `~/.ssh/config`, to resolve host aliases, so
+//! that, i.e., `gh:user/repo` is known to refer to the same repo as `git@github.com:user/repo`.
+
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// The host aliases defined in an SSH client configuration.
+#[derive(Debug, Default)]
+pub(crate) struct SshConfig {
+    /// Maps each alias to the host name it stands for.
+    host_names: BTreeMap<String, String>,
+}
+
+impl SshConfig {
+    /// Reads `~/.ssh/config` under `home`. A missing or unreadable file is treated as empty,
+    /// since aliases only refine how sources are compared.
+    pub fn load(home: &Path) -> Self {
+        let path = home.join(".ssh").join("config");
+        match fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    tracing::warn!("ignoring SSH configuration {:?}: {}", path, e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    /// Collects the `HostName`s set in `Host` blocks with literal patterns. As in `ssh`, the
+    /// first value found for a host wins. `Match` blocks, wildcards, and `Include`s are ignored.
+    fn parse(contents: &str) -> Self {
+        let mut host_names = BTreeMap::new();
+        let mut current = Vec::new();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (keyword, args) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+                Some((keyword, args)) => (keyword, args.trim_start_matches([' ', '\t', '='])),
+                None => continue,
+            };
+            if keyword.eq_ignore_ascii_case("host") {
+                current = args
+                    .split_whitespace()
+                    .filter(|pattern| !pattern.contains(['*', '?', '!']))
+                    .map(str::to_owned)
+                    .collect();
+            } else if keyword.eq_ignore_ascii_case("match") {
+                current.clear();
+            } else if keyword.eq_ignore_ascii_case("hostname") {
+                let host_name = args.trim();
+                for alias in &current {
+                    host_names
+                        .entry(alias.clone())
+                        .or_insert_with(|| host_name.replace("%h", alias));
+                }
+            }
+        }
+        Self { host_names }
+    }
+
+    /// The host name that `host` stands for, or `host` itself if it isn't an alias.
+    pub fn host_name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.host_names.get(host).map_or(host, String::as_str)
+    }
+}
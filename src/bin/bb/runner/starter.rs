@@ -0,0 +1,792 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::{
+    cli::{is_template_var_name, TemplateVar},
+    runner::{
+        cmd_failure_err,
+        dirs::expand_home_path,
+        git::{command_log, ParsedRepoSource, RepoSource},
+        kind::RepoKind,
+        repo_db::RepoName,
+    },
+};
+use anyhow::{anyhow, bail, Context};
+use path_clean::PathClean;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    env,
+    fmt::{self, Display, Formatter},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use toml::Spanned;
+use uuid::Uuid;
+
+/// The namespace starter file signatures must be made in, so that signatures made by the same key
+/// for other purposes (i.e., Git commits) can't be passed off as signed starter files.
+const SIGNATURE_NAMESPACE: &str = "bellboy-starter";
+
+/// The schema version of starter files written by this version of the tool, recorded in their
+/// top-level `version` field. Files without one are assumed to be of this version.
+const CURRENT_VERSION: u32 = 1;
+
+/// A manifest of repos to set up on a new machine, imported and exported with the `starter`
+/// subcommand.
+///
+/// Entries may be made conditional with a [`Conditions`] table, so that a single starter file can
+/// describe every machine its owner uses. Their `path`s and `source`s may also refer to
+/// [`TemplateVars`].
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StarterFile<'a> {
+    #[serde(default = "current_version")]
+    pub version: u32,
+    #[serde(borrow, default)]
+    pub repos: Vec<StarterEntry<'a>>,
+    /// Scripts to run once every repo has been cloned, in order, i.e., to install packages.
+    #[serde(borrow, default, skip_serializing_if = "Vec::is_empty")]
+    pub run: Vec<StarterScript<'a>>,
+}
+
+impl Default for StarterFile<'_> {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            repos: Vec::new(),
+            run: Vec::new(),
+        }
+    }
+}
+
+fn current_version() -> u32 {
+    CURRENT_VERSION
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StarterEntry<'a> {
+    #[serde(borrow)]
+    pub name: RepoName<'a>,
+    pub kind: RepoKind,
+    /// The path or URL to clone this repo from. Unmanaged directories aren't cloned, so they have
+    /// none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Cow<'a, str>>,
+    /// Where to clone a standalone repo to, or where to create an unmanaged directory. Relative
+    /// paths are relative to the home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Conditions that must all be met for this entry to be applied.
+    #[serde(default, skip_serializing_if = "Conditions::is_empty")]
+    pub when: Conditions,
+}
+
+/// A script in one of the repos of a starter file, run after all of them have been cloned.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct StarterScript<'a> {
+    /// The repo containing the script. It must be listed in the starter file, or already be
+    /// registered.
+    #[serde(borrow)]
+    pub repo: RepoName<'a>,
+    /// The path of the script, relative to the repo's work tree. It's run from the work tree's
+    /// root.
+    pub script: PathBuf,
+    /// Arguments to pass to the script, which may refer to [`TemplateVars`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Conditions that must all be met for this script to be run.
+    #[serde(default, skip_serializing_if = "Conditions::is_empty")]
+    pub when: Conditions,
+}
+
+/// Conditions under which a [`StarterEntry`] or [`StarterScript`] applies to the current machine.
+///
+/// Every non-empty list must have at least one item matching the current machine.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Conditions {
+    /// Patterns for this machine's hostname. `*` matches any number of characters, and `?`
+    /// matches exactly one. Matching is case-insensitive.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    hostname: Vec<String>,
+    /// Operating systems, as named by Rust's `std::env::consts::OS` (i.e., `linux`, `macos`, or
+    /// `windows`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    os: Vec<String>,
+    /// Names of environment variables, at least one of which must be set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env: Vec<String>,
+}
+
+impl<'a> StarterFile<'a> {
+    pub fn from_toml(toml: &'a str) -> anyhow::Result<Self> {
+        let starter = toml::from_str::<Self>(toml).context("failed to deserialize TOML")?;
+        if let Some(problem) = lint(toml, false)
+            .into_iter()
+            .find(|problem| problem.severity == Severity::Error)
+        {
+            bail!("{}", problem);
+        }
+        Ok(starter)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        toml::to_string(self).map_err(|e| anyhow!("failed to serialize TOML: {}", e))
+    }
+}
+
+/// A problem found in a starter file by [`lint`].
+#[derive(Debug)]
+pub(crate) struct LintProblem {
+    pub severity: Severity,
+    /// The 1-based line and column of the value this problem concerns, if known.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Severity {
+    /// The starter file can't be imported.
+    Error,
+    /// The starter file can be imported, but probably won't work as intended.
+    Warning,
+}
+
+impl Display for LintProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self {
+            severity,
+            position,
+            message,
+        } = self;
+        if let Some((line, column)) = position {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        match severity {
+            Severity::Error => write!(f, "error: {}", message),
+            Severity::Warning => write!(f, "warning: {}", message),
+        }
+    }
+}
+
+/// The parts of a starter file that [`lint`] reports problems with, along with where they are.
+#[derive(Deserialize)]
+struct SpannedStarterFile {
+    version: Option<Spanned<i64>>,
+    #[serde(default)]
+    repos: Vec<SpannedStarterEntry>,
+    #[serde(default)]
+    run: Vec<SpannedStarterScript>,
+}
+
+#[derive(Deserialize)]
+struct SpannedStarterEntry {
+    name: Spanned<String>,
+    kind: Spanned<RepoKind>,
+    source: Option<Spanned<String>>,
+    path: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+struct SpannedStarterScript {
+    repo: Spanned<String>,
+    script: Spanned<String>,
+}
+
+/// Checks the starter file `toml` for problems without cloning anything: syntax and schema
+/// errors, unsupported versions, invalid names, entries whose `kind` disagrees with their `path`
+/// or `source`, duplicate names or paths, and scripts outside of their repos.
+///
+/// With `check_sources`, `source`s are also checked to look like something that could be cloned
+/// from. Those checks can't be sure, so they only produce warnings.
+pub(crate) fn lint(toml: &str, check_sources: bool) -> Vec<LintProblem> {
+    let error = |position, message| LintProblem {
+        severity: Severity::Error,
+        position,
+        message,
+    };
+    let at = |spanned_start| Some(line_col(toml, spanned_start));
+
+    // Problems found below are reported with more precise positions than deserialization errors,
+    // so this is only reported if nothing else explains it.
+    let strict_err = toml::from_str::<StarterFile<'_>>(toml).err();
+    let SpannedStarterFile {
+        version,
+        repos,
+        run,
+    } = match toml::from_str(toml) {
+        Ok(spanned) => spanned,
+        Err(e) => return vec![deserialize_problem(strict_err.unwrap_or(e))],
+    };
+
+    let mut problems = Vec::new();
+    if let Some(version) = version {
+        if !(1..=i64::from(CURRENT_VERSION)).contains(version.get_ref()) {
+            problems.push(error(
+                at(version.start()),
+                format!(
+                    "unsupported version {}; this version of Bellboy only understands versions up \
+                    to {}",
+                    version.get_ref(),
+                    CURRENT_VERSION
+                ),
+            ));
+        }
+    }
+
+    let mut names = BTreeMap::new();
+    let mut paths = BTreeMap::new();
+    for SpannedStarterEntry {
+        name,
+        kind,
+        source,
+        path,
+    } in &repos
+    {
+        if let Err(e) = RepoName::new(name.get_ref().into()) {
+            problems.push(error(
+                at(name.start()),
+                format!("invalid repo name {:?}: {}", name.get_ref(), e),
+            ));
+        }
+        if let Some(first) = names.insert(name.get_ref(), name.start()) {
+            let (line, _column) = line_col(toml, first);
+            problems.push(error(
+                at(name.start()),
+                format!(
+                    "repo {:?} is already listed on line {}",
+                    name.get_ref(),
+                    line
+                ),
+            ));
+        }
+        match (kind.get_ref(), path) {
+            (RepoKind::Standalone | RepoKind::Unmanaged, None) => problems.push(error(
+                at(kind.start()),
+                format!(
+                    "{} {:?} has no `path` specified",
+                    kind.get_ref().human_name(),
+                    name.get_ref()
+                ),
+            )),
+            (RepoKind::Overlay, Some(path)) => problems.push(error(
+                at(path.start()),
+                format!(
+                    "overlay repo {:?} cannot have a `path`; overlays always use the home \
+                    directory as their work tree",
+                    name.get_ref()
+                ),
+            )),
+            (RepoKind::Standalone | RepoKind::Unmanaged, Some(path)) => {
+                let normalized = normalize_entry_path(path.get_ref());
+                if let Some((other, _start)) =
+                    paths.insert(normalized, (name.get_ref(), path.start()))
+                {
+                    problems.push(error(
+                        at(path.start()),
+                        format!(
+                            "repo {:?} has the same path as repo {:?}",
+                            name.get_ref(),
+                            other
+                        ),
+                    ));
+                }
+            }
+            (RepoKind::Overlay, None) => (),
+        }
+        match (kind.get_ref(), source) {
+            (RepoKind::Unmanaged, Some(source)) => problems.push(error(
+                at(source.start()),
+                format!(
+                    "unmanaged directory {:?} cannot have a `source`; it isn't cloned",
+                    name.get_ref()
+                ),
+            )),
+            (RepoKind::Unmanaged, None) => (),
+            (RepoKind::Standalone | RepoKind::Overlay, None) => problems.push(error(
+                at(kind.start()),
+                format!("repo {:?} has no `source` specified", name.get_ref()),
+            )),
+            (RepoKind::Standalone | RepoKind::Overlay, Some(source)) => {
+                if source.get_ref().trim().is_empty() {
+                    problems.push(error(
+                        at(source.start()),
+                        format!("repo {:?} has an empty `source`", name.get_ref()),
+                    ));
+                } else if check_sources {
+                    if let Some(reason) = unreachable_source_reason(source.get_ref()) {
+                        problems.push(LintProblem {
+                            severity: Severity::Warning,
+                            position: at(source.start()),
+                            message: format!("source of repo {:?} {}", name.get_ref(), reason),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    for SpannedStarterScript { repo, script } in &run {
+        let is_inside_repo = Path::new(script.get_ref())
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !is_inside_repo || script.get_ref().is_empty() {
+            problems.push(error(
+                at(script.start()),
+                format!(
+                    "script {:?} must be a relative path inside of repo {:?}",
+                    script.get_ref(),
+                    repo.get_ref()
+                ),
+            ));
+        }
+        if !names.contains_key(repo.get_ref()) {
+            problems.push(LintProblem {
+                severity: Severity::Warning,
+                position: at(repo.start()),
+                message: format!(
+                    "repo {:?} isn't listed in this starter file, so it must already be \
+                    registered for script {:?} to run",
+                    repo.get_ref(),
+                    script.get_ref()
+                ),
+            });
+        }
+    }
+    if let Some(e) = strict_err {
+        if !problems
+            .iter()
+            .any(|problem| problem.severity == Severity::Error)
+        {
+            problems.push(deserialize_problem(e));
+        }
+    }
+    problems
+}
+
+fn deserialize_problem(e: toml::de::Error) -> LintProblem {
+    let message = e.to_string();
+    let position = e.line_col().map(|(line, col)| (line + 1, col + 1));
+    // The position is reported separately.
+    let message = match position {
+        Some((line, column)) => message
+            .strip_suffix(&format!(" at line {} column {}", line, column))
+            .map_or_else(|| message.clone(), str::to_owned),
+        None => message,
+    };
+    LintProblem {
+        severity: Severity::Error,
+        position,
+        message,
+    }
+}
+
+/// Converts a byte offset into `text` to a 1-based line and column.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Normalizes an entry's `path`, so that different spellings of the same location (i.e.,
+/// `~/src/a` and `src/./a`) compare equal. Variables are left as they are.
+fn normalize_entry_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    path.strip_prefix("~").unwrap_or(path).to_owned().clean()
+}
+
+/// Hosts of public forges, whose URLs `starter export --redact-sources` leaves as they are.
+const PUBLIC_HOSTS: [&str; 6] = [
+    "github.com",
+    "gitlab.com",
+    "bitbucket.org",
+    "codeberg.org",
+    "git.sr.ht",
+    "sr.ht",
+];
+
+/// Returns a placeholder for `source` if it may reveal something private, for sharing starter
+/// files: local paths, hosts other than [`PUBLIC_HOSTS`], and URLs with credentials in them.
+///
+/// The placeholder is a variable named after the repo `name`, i.e., `{source_dots}`, so that the
+/// real source can be given back with `starter import --var`.
+pub(crate) fn redact_source(name: &RepoName<'_>, source: &str) -> Option<String> {
+    let is_public = RepoSource::from_str(source).is_ok_and(|parsed| {
+        parsed.host().is_some_and(|host| {
+            PUBLIC_HOSTS
+                .iter()
+                .any(|public| host.eq_ignore_ascii_case(public))
+        })
+    });
+    let has_credentials = command_log::redact_url_credentials(source) != source;
+    (!is_public || has_credentials).then(|| format!("{{source_{}}}", name.replace('.', "_")))
+}
+
+/// Returns why `source` doesn't look like something Git could clone from, if it doesn't. This
+/// can't be sure without trying, so sources with variables in them are assumed to be fine.
+fn unreachable_source_reason(source: &str) -> Option<String> {
+    if source.contains(['{', '}']) {
+        return None;
+    }
+    let source = match RepoSource::from_str(source) {
+        Ok(source) => source,
+        Err(e) => return Some(format!("is invalid: {}", e)),
+    };
+    match source.parsed() {
+        ParsedRepoSource::Url { .. } | ParsedRepoSource::Scp { .. } => None,
+        ParsedRepoSource::Local(path) => (!path.exists())
+            .then(|| format!("is a local path, but {:?} does not exist", source.as_str())),
+    }
+}
+
+impl StarterEntry<'_> {
+    /// Replaces variables in `source` and `path` with their values in `vars`. The names of any
+    /// undefined variables are added to `unresolved`.
+    pub fn expand_templates(&mut self, vars: &TemplateVars, unresolved: &mut BTreeSet<String>) {
+        let Self {
+            name: _,
+            kind: _,
+            source,
+            path,
+            when: _,
+        } = self;
+        if let Some(source) = source {
+            *source = vars.expand(source, unresolved).into();
+        }
+        if let Some(path) = path {
+            match path.to_str() {
+                Some(template) => *path = vars.expand(template, unresolved).into(),
+                None => tracing::warn!(
+                    "path {:?} is not valid UTF-8, variables in it won't be expanded",
+                    path
+                ),
+            }
+        }
+    }
+
+    /// Resolves `path` against `home` if it is relative or starts with `~`.
+    pub fn resolved_path(&self, home: &Path) -> Option<PathBuf> {
+        self.path
+            .as_ref()
+            .map(|path| expand_home_path(path, home).unwrap_or_else(|| home.join(path)))
+    }
+}
+
+impl StarterScript<'_> {
+    /// Expands variables in this script's arguments, like [`StarterEntry::expand_templates`].
+    pub fn expand_templates(&mut self, vars: &TemplateVars, unresolved: &mut BTreeSet<String>) {
+        let Self {
+            repo: _,
+            script: _,
+            args,
+            when: _,
+        } = self;
+        for arg in args {
+            *arg = vars.expand(arg, unresolved);
+        }
+    }
+}
+
+/// Checks that `signature_path` holds a valid SSH signature of `contents` made by `key`, which is
+/// either an SSH public key or the path of a file containing one.
+///
+/// `contents` is passed to `ssh-keygen` directly, rather than the path it was read from, so that
+/// what's verified is exactly what gets imported.
+pub(crate) fn verify_signature(
+    contents: &[u8],
+    signature_path: &Path,
+    key: &str,
+) -> anyhow::Result<()> {
+    let public_key = read_public_key(key)?;
+    // Each verification gets its own allowed signers file, so concurrent imports can't verify
+    // against each other's keys.
+    let allowed_signers_path =
+        env::temp_dir().join(format!("bellboy-allowed-signers-{}", Uuid::new_v4()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&allowed_signers_path)
+        .and_then(|mut file| {
+            writeln!(
+                file,
+                "{} namespaces=\"{}\" {}",
+                SIGNATURE_NAMESPACE, SIGNATURE_NAMESPACE, public_key
+            )
+        })
+        .with_context(|| {
+            anyhow!(
+                "failed to write allowed signers file {:?}",
+                allowed_signers_path
+            )
+        })?;
+
+    let res = run_ssh_keygen_verify(&allowed_signers_path, contents, signature_path);
+    if let Err(e) = fs::remove_file(&allowed_signers_path) {
+        tracing::warn!(
+            "failed to delete allowed signers file {:?}: {}",
+            allowed_signers_path,
+            e
+        );
+    }
+    res
+}
+
+fn run_ssh_keygen_verify(
+    allowed_signers_path: &Path,
+    contents: &[u8],
+    signature_path: &Path,
+) -> anyhow::Result<()> {
+    let mut child = Command::new("ssh-keygen")
+        .args([
+            "-Y",
+            "verify",
+            "-n",
+            SIGNATURE_NAMESPACE,
+            "-I",
+            SIGNATURE_NAMESPACE,
+            "-f",
+        ])
+        .arg(allowed_signers_path)
+        .arg("-s")
+        .arg(signature_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to spawn `ssh-keygen`")?;
+    let write_res = child.stdin.take().unwrap().write_all(contents);
+    let output = child
+        .wait_with_output()
+        .context("failed to wait for `ssh-keygen`")?;
+    if let Some(err_msg) = cmd_failure_err(output.status) {
+        bail!(
+            "signature {:?} is missing, invalid, or not made by the given key ({}): {}",
+            signature_path,
+            err_msg,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    match write_res {
+        // `ssh-keygen` doesn't read its input if it fails early, but that's reported above.
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            Err(e).context("failed to pass starter file to `ssh-keygen`")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads `key` as an SSH public key, or as the path of a file containing one, and returns it in
+/// the `<TYPE> <BASE64>` form `ssh-keygen` expects.
+fn read_public_key(key: &str) -> anyhow::Result<String> {
+    let path = Path::new(key);
+    let contents = if path.is_file() {
+        fs::read_to_string(path)
+            .with_context(|| anyhow!("failed to read public key file {:?}", path))?
+    } else {
+        key.to_owned()
+    };
+    let line = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .with_context(|| anyhow!("no public key found in {:?}", key))?;
+    let mut fields = line.split_whitespace();
+    match (fields.next(), fields.next()) {
+        (Some(key_type), Some(blob)) => Ok(format!("{} {}", key_type, blob)),
+        _ => bail!(
+            "{:?} is neither an SSH public key nor a file containing one",
+            key
+        ),
+    }
+}
+
+/// Values for the `{NAME}` variables that starter file entries' `path`s and `source`s may refer
+/// to. `{{` and `}}` stand for literal braces.
+#[derive(Debug)]
+pub(crate) struct TemplateVars {
+    vars: BTreeMap<String, String>,
+}
+
+impl TemplateVars {
+    /// Defines `home`, `hostname`, and `user` from the current machine, overridden by `custom`.
+    pub fn new(machine: &Machine, home: &Path, custom: Vec<TemplateVar>) -> Self {
+        let Machine { hostname, user } = machine;
+        let mut vars = BTreeMap::new();
+        match home.to_str() {
+            Some(home) => {
+                vars.insert("home".to_owned(), home.to_owned());
+            }
+            None => tracing::warn!(
+                "home directory {:?} is not valid UTF-8, so `{{home}}` is undefined",
+                home
+            ),
+        }
+        let builtins = [("hostname", hostname), ("user", user)];
+        for (name, value) in builtins {
+            if let Some(value) = value {
+                vars.insert(name.to_owned(), value.clone());
+            }
+        }
+        vars.extend(
+            custom
+                .into_iter()
+                .map(|TemplateVar { name, value }| (name, value)),
+        );
+        Self { vars }
+    }
+
+    /// Replaces `{NAME}`s in `template` with their values. Braces that don't enclose a valid
+    /// variable name are left as they are. Names of undefined variables are added to
+    /// `unresolved`, and left unexpanded.
+    pub fn expand(&self, template: &str, unresolved: &mut BTreeSet<String>) -> String {
+        let Self { vars } = self;
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(idx) = rest.find(['{', '}']) {
+            let (before, after) = rest.split_at(idx);
+            expanded.push_str(before);
+            if after.starts_with("{{") || after.starts_with("}}") {
+                expanded.push_str(&after[..1]);
+                rest = &after[2..];
+                continue;
+            }
+            let var = after
+                .strip_prefix('{')
+                .and_then(|after| after.split_once('}'))
+                .filter(|(name, _rest)| is_template_var_name(name));
+            match var {
+                Some((name, after_var)) => {
+                    match vars.get(name) {
+                        Some(value) => expanded.push_str(value),
+                        None => {
+                            unresolved.insert(name.to_owned());
+                            expanded.push_str(&after[..name.len() + 2]);
+                        }
+                    }
+                    rest = after_var;
+                }
+                None => {
+                    expanded.push_str(&after[..1]);
+                    rest = &after[1..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+/// A description of the machine that [`Conditions`] are evaluated against.
+#[derive(Debug)]
+pub(crate) struct Machine {
+    hostname: Option<String>,
+    user: Option<String>,
+}
+
+impl Machine {
+    pub fn current() -> Self {
+        let hostname = gethostname::gethostname().into_string().map_or_else(
+            |hostname| {
+                tracing::warn!(
+                    "hostname {:?} is not valid UTF-8, `hostname` conditions will never match",
+                    hostname
+                );
+                None
+            },
+            Some,
+        );
+        let user = ["USER", "USERNAME"]
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|user| !user.is_empty()));
+        Self { hostname, user }
+    }
+}
+
+impl Conditions {
+    pub fn is_empty(&self) -> bool {
+        let Self { hostname, os, env } = self;
+        hostname.is_empty() && os.is_empty() && env.is_empty()
+    }
+
+    /// Returns a human-readable reason that these conditions don't match `machine`, if they don't.
+    pub fn unmet_reason(&self, machine: &Machine) -> Option<String> {
+        let Self { hostname, os, env } = self;
+        let Machine {
+            hostname: current_hostname,
+            user: _,
+        } = machine;
+
+        if !hostname.is_empty()
+            && !current_hostname
+                .as_deref()
+                .is_some_and(|h| hostname.iter().any(|p| wildcard_match(p, h)))
+        {
+            return Some(format!(
+                "hostname {:?} matches none of {:?}",
+                current_hostname.as_deref().unwrap_or_default(),
+                hostname
+            ));
+        }
+        if !os.is_empty() && !os.iter().any(|os| os == env::consts::OS) {
+            return Some(format!("OS {:?} is none of {:?}", env::consts::OS, os));
+        }
+        if !env.is_empty() && !env.iter().any(|var| env::var_os(var).is_some()) {
+            return Some(format!(
+                "none of the environment variables {:?} are set",
+                env
+            ));
+        }
+        None
+    }
+}
+
+/// Matches `text` against `pattern` case-insensitively, where `*` matches any sequence of
+/// characters and `?` matches exactly one character.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_lowercase().chars().collect::<Vec<_>>();
+
+    // Iterative matching with backtracking to the most recent `*`.
+    let (mut p, mut t) = (0, 0);
+    let mut last_star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                last_star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match last_star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    last_star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
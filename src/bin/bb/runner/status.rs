@@ -0,0 +1,349 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{
+    dirs::Directories,
+    gc,
+    git::{CommitInfo, DynGit, DynGitRepo, GitRepoTrait, RepoStatus},
+    repo_db::RepoEntry,
+    RepoName,
+};
+use anyhow::{anyhow, Context};
+use format::lazy_format;
+use lifetime::{IntoStatic, ToBorrowed};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// How long a cached status may be reused for, even if its repo's refs and index haven't changed.
+///
+/// Edits to work tree files don't touch anything under the Git directory, so this bounds how
+/// stale a cached count of changed files can be.
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// The most statuses gathered concurrently. Each one runs a `git` process.
+const MAX_JOBS: usize = 8;
+
+/// How long `prompt-info` should take at most, since it runs on every shell prompt. Exceeding it
+/// is only logged.
+pub(crate) const PROMPT_INFO_BUDGET: Duration = Duration::from_millis(10);
+
+impl Display for RepoStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self {
+            current_branch,
+            detached_head: _,
+            upstream,
+            ahead,
+            behind,
+            staged,
+            unstaged,
+            untracked,
+        } = self;
+        write!(f, "{}", current_branch.as_deref().unwrap_or("(detached)"))?;
+        if let Some(upstream) = upstream {
+            write!(f, "...{}", upstream)?;
+        }
+        match (ahead, behind) {
+            (0, 0) => (),
+            (ahead, 0) => write!(f, " [ahead {}]", ahead)?,
+            (0, behind) => write!(f, " [behind {}]", behind)?,
+            (ahead, behind) => write!(f, " [ahead {}, behind {}]", ahead, behind)?,
+        }
+        if !self.has_uncommitted_changes() && *untracked == 0 {
+            return write!(f, ", clean");
+        }
+        for (count, what) in [
+            (staged, "staged"),
+            (unstaged, "unstaged"),
+            (untracked, "untracked"),
+        ] {
+            if *count > 0 {
+                write!(f, ", {} {}", count, what)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for CommitInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Self {
+            hash: _,
+            author,
+            time,
+            subject,
+        } = self;
+        write!(
+            f,
+            "{} {} ({}, {})",
+            self.short_hash(),
+            subject,
+            author,
+            gc::display_age(Some(*time))
+        )
+    }
+}
+
+/// Formats the parts of `status` that fit in a shell prompt: `*` if tracked files have
+/// uncommitted changes, then how many commits the branch is ahead (`↑`) or behind (`↓`) its
+/// upstream, i.e., `*↑1↓2`. Empty if there's nothing to point out.
+pub(crate) fn display_for_prompt(status: &RepoStatus) -> impl Display + '_ {
+    lazy_format!(move |f| {
+        if status.has_uncommitted_changes() {
+            f.write_str("*")?;
+        }
+        if status.ahead > 0 {
+            write!(f, "↑{}", status.ahead)?;
+        }
+        if status.behind > 0 {
+            write!(f, "↓{}", status.behind)?;
+        }
+        Ok(())
+    })
+}
+
+/// Gathers the statuses of `repos` in parallel, reusing statuses cached on disk by previous calls
+/// where the repo hasn't changed since.
+pub(crate) fn gather<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: impl IntoIterator<Item = (RepoName<'a>, RepoEntry<'a>)>,
+) -> BTreeMap<RepoName<'static>, anyhow::Result<RepoStatus>> {
+    gather_with(dirs, git, repos, true)
+}
+
+/// Like [`gather`], but computes every status afresh, still updating the cache with them.
+pub(crate) fn gather_fresh<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: impl IntoIterator<Item = (RepoName<'a>, RepoEntry<'a>)>,
+) -> BTreeMap<RepoName<'static>, anyhow::Result<RepoStatus>> {
+    gather_with(dirs, git, repos, false)
+}
+
+fn gather_with<'a>(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: impl IntoIterator<Item = (RepoName<'a>, RepoEntry<'a>)>,
+    use_cache: bool,
+) -> BTreeMap<RepoName<'static>, anyhow::Result<RepoStatus>> {
+    let start = Instant::now();
+    let cache_path = dirs.status_cache_path();
+    let cache = StatusCache::read(&cache_path).unwrap_or_else(|e| {
+        tracing::debug!("not using status cache: {:?}", e);
+        StatusCache::default()
+    });
+
+    let mut statuses = BTreeMap::new();
+    let mut jobs = Vec::new();
+    for (name, repo) in repos {
+        // Unmanaged directories have no status, so they're left out.
+        if !repo.kind().is_git_repo() {
+            continue;
+        }
+        let name = name.to_borrowed().into_static();
+        let job = repo.open(git, dirs, name.to_borrowed()).and_then(|repo| {
+            let fingerprint = Fingerprint::of(repo.git_dir_path())?;
+            Ok((repo, fingerprint))
+        });
+        match job {
+            Ok((repo, fingerprint)) => {
+                match cache.get(&name, &repo, &fingerprint).filter(|_| use_cache) {
+                    Some(status) => {
+                        statuses.insert(name, Ok(status.clone()));
+                    }
+                    None => jobs.push((name, repo, fingerprint)),
+                }
+            }
+            Err(e) => {
+                statuses.insert(name, Err(e));
+            }
+        }
+    }
+    let num_cached = statuses.len();
+
+    let next_job = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(jobs.len()));
+    thread::scope(|s| {
+        let num_workers = jobs.len().min(MAX_JOBS);
+        for _ in 0..num_workers {
+            s.spawn(|| {
+                while let Some((name, repo, _fingerprint)) =
+                    jobs.get(next_job.fetch_add(1, Ordering::SeqCst))
+                {
+                    let status = repo
+                        .status()
+                        .with_context(|| anyhow!("failed to get status of {:?}", name));
+                    results.lock().unwrap().push((name.clone(), status));
+                }
+            });
+        }
+    });
+
+    let mut cache = cache;
+    let fingerprints = jobs
+        .into_iter()
+        .map(|(name, repo, fingerprint)| (name, (repo, fingerprint)))
+        .collect::<BTreeMap<_, _>>();
+    for (name, status) in results.into_inner().unwrap() {
+        if let (Ok(status), Some((repo, fingerprint))) = (&status, fingerprints.get(&name)) {
+            cache.put(&name, repo, fingerprint.clone(), status.clone());
+        }
+        statuses.insert(name, status);
+    }
+    if !dirs.is_read_only() {
+        if let Err(e) = cache.write(&cache_path) {
+            tracing::warn!("failed to write status cache: {:?}", e);
+        }
+    }
+
+    tracing::debug!(
+        "gathered status of {} repo(s) ({} cached) in {:.2?}",
+        statuses.len(),
+        num_cached,
+        start.elapsed()
+    );
+    statuses
+}
+
+/// The modification times of the files in a Git directory that change when commits are made,
+/// refs are updated, or files are staged.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct Fingerprint(Vec<Option<Duration>>);
+
+impl Fingerprint {
+    fn of(git_dir: &Path) -> anyhow::Result<Self> {
+        fn mtime(path: &Path) -> io::Result<Option<Duration>> {
+            match fs::metadata(path) {
+                Ok(metadata) => Ok(metadata.modified()?.duration_since(UNIX_EPOCH).ok()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        fn newest_in(dir: &Path) -> io::Result<Option<Duration>> {
+            let mut newest = mtime(dir)?;
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let entry_newest = if entry.file_type()?.is_dir() {
+                    newest_in(&entry.path())?
+                } else {
+                    mtime(&entry.path())?
+                };
+                newest = newest.max(entry_newest);
+            }
+            Ok(newest)
+        }
+
+        (|| -> io::Result<_> {
+            Ok(Self(vec![
+                mtime(&git_dir.join("HEAD"))?,
+                mtime(&git_dir.join("index"))?,
+                mtime(&git_dir.join("packed-refs"))?,
+                newest_in(&git_dir.join("refs"))?,
+            ]))
+        })()
+        .with_context(|| anyhow!("failed to fingerprint Git directory {:?}", git_dir))
+    }
+}
+
+/// Repo statuses from previous runs, stored at [`Directories::status_cache_path`].
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct StatusCache {
+    entries: BTreeMap<String, CachedStatus>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedStatus {
+    /// The Git directory this status was computed for, in case a repo entry is replaced by another
+    /// of the same name.
+    git_dir: PathBuf,
+    fingerprint: Fingerprint,
+    recorded_at: Duration,
+    status: RepoStatus,
+}
+
+impl StatusCache {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(anyhow::Error::new(e).context("failed to read status cache")),
+        };
+        serde_json::from_str(&json).context("failed to deserialize status cache")
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string(self).context("failed to serialize status cache")?;
+        fs::write(path, json).with_context(|| anyhow!("failed to write {:?}", path))
+    }
+
+    fn get(
+        &self,
+        name: &RepoName<'_>,
+        repo: &DynGitRepo,
+        fingerprint: &Fingerprint,
+    ) -> Option<&RepoStatus> {
+        let Self { entries } = self;
+        let CachedStatus {
+            git_dir,
+            fingerprint: cached_fingerprint,
+            recorded_at,
+            status,
+        } = entries.get(&name.to_string())?;
+        let age = now().checked_sub(*recorded_at)?;
+        (git_dir == repo.git_dir_path() && cached_fingerprint == fingerprint && age < CACHE_TTL)
+            .then_some(status)
+    }
+
+    fn put(
+        &mut self,
+        name: &RepoName<'_>,
+        repo: &DynGitRepo,
+        fingerprint: Fingerprint,
+        status: RepoStatus,
+    ) {
+        let Self { entries } = self;
+        entries.insert(
+            name.to_string(),
+            CachedStatus {
+                git_dir: repo.git_dir_path().to_owned(),
+                fingerprint,
+                recorded_at: now(),
+                status,
+            },
+        );
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
@@ -0,0 +1,199 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::{
+    runner::git::{DynGitRepo, GitRepoTrait, Integration, TransferStats},
+    suggest,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+use thiserror::Error as ThisError;
+
+/// What `sync` does with a repo whose branch has diverged from its upstream branch.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStrategy {
+    /// Leave the repo alone, and report it as skipped.
+    #[default]
+    Skip,
+    Rebase,
+    Merge,
+    /// Stash uncommitted changes, rebase, and restore the stashed changes.
+    StashAndRebase,
+}
+
+impl Display for SyncStrategy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Skip => "skip",
+            Self::Rebase => "rebase",
+            Self::Merge => "merge",
+            Self::StashAndRebase => "stash-and-rebase",
+        })
+    }
+}
+
+impl SyncStrategy {
+    const ALL: [Self; 4] = [Self::Skip, Self::Rebase, Self::Merge, Self::StashAndRebase];
+}
+
+#[derive(Debug, ThisError)]
+#[error(
+    "unrecognized sync strategy {actual:?}{}",
+    suggest::did_you_mean(
+        .actual,
+        SyncStrategy::ALL.map(|strategy| strategy.to_string()).iter(),
+    )
+)]
+pub struct InvalidSyncStrategyError {
+    actual: String,
+}
+
+impl FromStr for SyncStrategy {
+    type Err = InvalidSyncStrategyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.to_string() == s)
+            .ok_or_else(|| InvalidSyncStrategyError {
+                actual: s.to_owned(),
+            })
+    }
+}
+
+/// What `sync` did with a repo.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum SyncOutcome {
+    /// The repo's branch has no upstream, or `HEAD` is detached.
+    NoUpstream,
+    /// There was nothing to bring in from upstream.
+    UpToDate,
+    FastForwarded,
+    /// The branch had diverged from upstream, and `strategy` was used to reconcile them.
+    Reconciled {
+        strategy: SyncStrategy,
+    },
+    /// The branch had diverged from upstream, and was left alone.
+    Skipped {
+        ahead: u32,
+        behind: u32,
+    },
+}
+
+impl Display for SyncOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoUpstream => write!(f, "no upstream branch"),
+            Self::UpToDate => write!(f, "up to date"),
+            Self::FastForwarded => write!(f, "fast-forwarded"),
+            Self::Reconciled { strategy } => write!(f, "diverged, reconciled with {}", strategy),
+            Self::Skipped { ahead, behind } => {
+                write!(f, "diverged ({} ahead, {} behind), skipped", ahead, behind)
+            }
+        }
+    }
+}
+
+/// The message of stashes made by `sync --autostash`, so that they can be told apart in `git stash
+/// list` if restoring them fails.
+const AUTOSTASH_MESSAGE: &str = concat!(env!("CARGO_BIN_NAME"), " sync --autostash");
+
+/// Fetches `repo`'s default remote, then brings in upstream changes to the branch checked out,
+/// using `strategy` if the branch has diverged from its upstream. Also returns what the fetch
+/// transferred.
+///
+/// Repos configured with `--keep-depth` have their history trimmed back to that depth afterwards,
+/// since fetching deepens it.
+///
+/// If `autostash` is set, uncommitted changes are stashed before bringing in upstream changes,
+/// and restored afterwards, even if that failed. Restoring them may conflict with upstream
+/// changes, which fails with the conflicting paths, leaving the changes in the stash.
+pub(crate) fn sync_repo(
+    repo: &mut DynGitRepo,
+    strategy: SyncStrategy,
+    autostash: bool,
+) -> anyhow::Result<(SyncOutcome, TransferStats)> {
+    let transfer = repo.fetch()?;
+    let outcome = integrate(repo, strategy, autostash)?;
+    if let Some(depth) = repo.keep_depth()? {
+        repo.reshallow(depth)?;
+        tracing::debug!("trimmed local history to {} commit(s)", depth);
+    }
+    Ok((outcome, transfer))
+}
+
+fn integrate(
+    repo: &mut DynGitRepo,
+    strategy: SyncStrategy,
+    autostash: bool,
+) -> anyhow::Result<SyncOutcome> {
+    let (ahead, behind) = match repo.upstream_divergence()? {
+        Some(divergence) => divergence,
+        None => return Ok(SyncOutcome::NoUpstream),
+    };
+    if behind == 0 {
+        return Ok(SyncOutcome::UpToDate);
+    }
+    let (integration, outcome) = if ahead == 0 {
+        (Integration::FastForward, SyncOutcome::FastForwarded)
+    } else {
+        let integration = match strategy {
+            SyncStrategy::Skip => return Ok(SyncOutcome::Skipped { ahead, behind }),
+            SyncStrategy::Rebase => Integration::Rebase { autostash: false },
+            SyncStrategy::Merge => Integration::Merge,
+            SyncStrategy::StashAndRebase => Integration::Rebase { autostash: true },
+        };
+        (integration, SyncOutcome::Reconciled { strategy })
+    };
+
+    let dirty = repo.status()?.has_uncommitted_changes();
+    if !(autostash && dirty) {
+        return match repo.integrate_upstream(integration) {
+            Ok(()) => Ok(outcome),
+            Err(e) if dirty && integration != (Integration::Rebase { autostash: true }) => {
+                Err(anyhow::Error::new(e).context(
+                    "the work tree has uncommitted changes; pass `--autostash` to set them aside \
+                while syncing",
+                ))
+            }
+            Err(e) => Err(e.into()),
+        };
+    }
+
+    if !repo.stash(AUTOSTASH_MESSAGE)? {
+        // Popping now would restore someone else's stash.
+        tracing::debug!("found nothing to stash");
+        return Ok(repo.integrate_upstream(integration).map(|()| outcome)?);
+    }
+    tracing::debug!("stashed uncommitted changes");
+    // Whatever happened upstream, the changes belong back in the work tree.
+    let integrated = repo.integrate_upstream(integration);
+    let restored = repo.stash_pop();
+    match (integrated, restored) {
+        (Ok(()), Ok(())) => {
+            tracing::debug!("restored stashed changes");
+            Ok(outcome)
+        }
+        (Err(e), Ok(())) => Err(e.into()),
+        (Ok(()), Err(e)) => Err(e.into()),
+        (Err(e), Err(restore_err)) => {
+            Err(anyhow::Error::new(e).context(anyhow!("{:#}", restore_err)))
+        }
+    }
+}
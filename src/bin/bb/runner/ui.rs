@@ -0,0 +1,443 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::runner::{
+    dirs::Directories,
+    git::{DynGit, GitRepoTrait},
+    hooks::{run_hooks, Event, HookConfig},
+    kind::RepoKind,
+    machine::MachineIdentity,
+    repo_db::{RepoDb, RepoRecord},
+    status,
+    sync::{self, SyncOutcome},
+};
+use anyhow::{anyhow, Context};
+use crossterm::{
+    event::{self, Event as TermEvent, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use lifetime::ToBorrowed;
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+use std::{
+    io::{self, BufRead, Stdout, Write},
+    process::Command,
+};
+
+const HELP: &str = "↑/↓ select  enter diff  s sync  c commit  x remove  r refresh  q quit";
+
+type Term = Terminal<CrosstermBackend<Stdout>>;
+
+/// Runs the dashboard until the user quits.
+pub(crate) fn run(
+    dirs: &Directories,
+    git: &DynGit,
+    repos: &RepoDb,
+    hooks: &[HookConfig],
+) -> anyhow::Result<()> {
+    let mut terminal = enter().context("failed to set up terminal")?;
+    let res = App::new(dirs, git, repos).run(&mut terminal, dirs, git, repos, hooks);
+    leave(&mut terminal).context("failed to restore terminal")?;
+    res
+}
+
+fn enter() -> io::Result<Term> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    Terminal::new(CrosstermBackend::new(io::stdout()))
+}
+
+fn leave(terminal: &mut Term) -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()
+}
+
+struct App {
+    entries: Vec<Entry>,
+    selected: ListState,
+    view: View,
+    /// Feedback from the last action, shown at the bottom of the screen.
+    message: String,
+}
+
+struct Entry {
+    record: RepoRecord,
+    desc: String,
+    status: String,
+    /// The most recent commit, or why it couldn't be found.
+    last_commit: String,
+}
+
+enum View {
+    List,
+    Diff { text: String, scroll: u16 },
+    ConfirmRemove,
+}
+
+impl App {
+    fn new(dirs: &Directories, git: &DynGit, repos: &RepoDb) -> Self {
+        let mut this = Self {
+            entries: Vec::new(),
+            selected: ListState::default(),
+            view: View::List,
+            message: HELP.to_owned(),
+        };
+        this.refresh(dirs, git, repos);
+        this
+    }
+
+    fn refresh(&mut self, dirs: &Directories, git: &DynGit, repos: &RepoDb) {
+        let Self {
+            entries,
+            selected,
+            view: _,
+            message,
+        } = self;
+        let repos = repos.snapshot();
+        let mut statuses = status::gather(dirs, git, repos.iter());
+        *entries = repos
+            .iter()
+            .filter_map(|(name, repo)| {
+                let record = match repo.record(dirs, name.to_borrowed()) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        *message = format!("skipped {:?}: {:#}", name, e);
+                        return None;
+                    }
+                };
+                Some((name, repo, record))
+            })
+            .map(|(name, repo, record)| Entry {
+                desc: repo.short_desc().to_string(),
+                status: match statuses.remove(&*name) {
+                    Some(Ok(status)) => status.to_string(),
+                    Some(Err(e)) => format!("error: {:#}", e),
+                    None if !repo.kind().is_git_repo() => "not a Git repo".to_owned(),
+                    None => "unknown".to_owned(),
+                },
+                last_commit: if repo.kind().is_git_repo() {
+                    match repo
+                        .open(git, dirs, name.to_borrowed())
+                        .and_then(|opened| Ok(opened.last_commit()?))
+                    {
+                        Ok(Some(commit)) => commit.to_string(),
+                        Ok(None) => "no commits".to_owned(),
+                        Err(e) => format!("error: {:#}", e),
+                    }
+                } else {
+                    "none".to_owned()
+                },
+                record,
+            })
+            .collect();
+        let max = entries.len().checked_sub(1);
+        selected.select(match (selected.selected(), max) {
+            (_, None) => None,
+            (Some(idx), Some(max)) => Some(idx.min(max)),
+            (None, Some(_)) => Some(0),
+        });
+    }
+
+    fn current(&self) -> Option<&Entry> {
+        self.selected
+            .selected()
+            .and_then(|idx| self.entries.get(idx))
+    }
+
+    fn run(
+        &mut self,
+        terminal: &mut Term,
+        dirs: &Directories,
+        git: &DynGit,
+        repos: &RepoDb,
+        hooks: &[HookConfig],
+    ) -> anyhow::Result<()> {
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .context("failed to draw")?;
+
+            let key = match event::read().context("failed to read terminal event")? {
+                TermEvent::Key(key) if key.kind == KeyEventKind::Press => key.code,
+                _ => continue,
+            };
+
+            match &mut self.view {
+                View::Diff { scroll, .. } => match key {
+                    KeyCode::Down | KeyCode::Char('j') => *scroll = scroll.saturating_add(1),
+                    KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+                    KeyCode::PageDown => *scroll = scroll.saturating_add(20),
+                    KeyCode::PageUp => *scroll = scroll.saturating_sub(20),
+                    KeyCode::Esc | KeyCode::Char('q') => self.view = View::List,
+                    _ => (),
+                },
+                View::ConfirmRemove => {
+                    self.view = View::List;
+                    if key == KeyCode::Char('y') {
+                        self.message = match self.remove(dirs, repos, hooks) {
+                            Ok(message) => message,
+                            Err(e) => format!("{:#}", e),
+                        };
+                        self.refresh(dirs, git, repos);
+                    } else {
+                        self.message = "removal cancelled".to_owned();
+                    }
+                }
+                View::List => match key {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                    KeyCode::Char('r') => {
+                        self.refresh(dirs, git, repos);
+                        self.message = "refreshed".to_owned();
+                    }
+                    KeyCode::Enter | KeyCode::Char('d') => {
+                        if let Some(entry) = self.current() {
+                            self.view = View::Diff {
+                                text: repos
+                                    .snapshot()
+                                    .get_by_name(entry.record.name.to_borrowed())
+                                    .map_err(anyhow::Error::from)
+                                    .and_then(|repo| {
+                                        repo.open(git, dirs, entry.record.name.to_borrowed())
+                                    })
+                                    .and_then(|repo| Ok(repo.diff()?))
+                                    .unwrap_or_else(|e| format!("{:?}", e)),
+                                scroll: 0,
+                            };
+                        }
+                    }
+                    KeyCode::Char('s') => match self.ensure_writable(repos, "sync") {
+                        Ok(()) => self.sync(terminal, dirs, git, repos)?,
+                        Err(e) => self.message = format!("{:#}", e),
+                    },
+                    KeyCode::Char('c') => match self.ensure_writable(repos, "commit to") {
+                        Ok(()) => self.run_git(terminal, dirs, git, repos, &["commit", "--all"])?,
+                        Err(e) => self.message = format!("{:#}", e),
+                    },
+                    KeyCode::Char('x') => {
+                        if let Some(entry) = self.current() {
+                            self.message = format!(
+                                "remove {:?} ({})? files will be left intact [y/N]",
+                                entry.record.name, entry.desc
+                            );
+                            self.view = View::ConfirmRemove;
+                        }
+                    }
+                    _ => (),
+                },
+            }
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = self.entries.len();
+        if len == 0 {
+            return;
+        }
+        let current = self.selected.selected().unwrap_or(0) as isize;
+        let next = (current + delta).rem_euclid(len as isize) as usize;
+        self.selected.select(Some(next));
+    }
+
+    /// Runs `git` with `args` against the selected repo, with the terminal handed back to the user
+    /// so that Git can prompt for input or open an editor.
+    fn run_git(
+        &mut self,
+        terminal: &mut Term,
+        dirs: &Directories,
+        git: &DynGit,
+        repos: &RepoDb,
+        args: &[&str],
+    ) -> anyhow::Result<()> {
+        let name = match self.current() {
+            Some(entry) => entry.record.name.clone(),
+            None => return Ok(()),
+        };
+        let repo = repos.snapshot().get_by_name(name.to_borrowed())?.open(
+            git,
+            dirs,
+            name.to_borrowed(),
+        )?;
+
+        leave(terminal).context("failed to restore terminal")?;
+        println!("==> git {} ({})", args.join(" "), name);
+        let mut cmd = Command::new("git");
+        cmd.args(args);
+        let res = repo.run_cmd(cmd, |mut cmd| cmd.status());
+        print!("press enter to return to the dashboard...");
+        io::stdout().flush().ok();
+        io::stdin().lock().read_line(&mut String::new()).ok();
+        *terminal = enter().context("failed to set up terminal")?;
+
+        self.message = match res {
+            Ok(status) if status.success() => format!("`git {}` succeeded", args.join(" ")),
+            Ok(status) => format!("`git {}` failed: {}", args.join(" "), status),
+            Err(e) => format!("failed to run `git {}`: {}", args.join(" "), e),
+        };
+        self.refresh(dirs, git, repos);
+        Ok(())
+    }
+
+    /// Syncs the selected repo like `sync` does, with the repo's configured strategy.
+    fn sync(
+        &mut self,
+        terminal: &mut Term,
+        dirs: &Directories,
+        git: &DynGit,
+        repos: &RepoDb,
+    ) -> anyhow::Result<()> {
+        let name = match self.current() {
+            Some(entry) => entry.record.name.clone(),
+            None => return Ok(()),
+        };
+        let (strategy, mut repo) = {
+            let snapshot = repos.snapshot();
+            let entry = snapshot.get_by_name(name.to_borrowed())?;
+            (
+                entry.sync_strategy().unwrap_or_default(),
+                entry.open(git, dirs, name.to_borrowed())?,
+            )
+        };
+
+        leave(terminal).context("failed to restore terminal")?;
+        println!("==> sync ({})", name);
+        let res = sync::sync_repo(&mut repo, strategy, false);
+        print!("press enter to return to the dashboard...");
+        io::stdout().flush().ok();
+        io::stdin().lock().read_line(&mut String::new()).ok();
+        *terminal = enter().context("failed to set up terminal")?;
+
+        self.message = match res {
+            Ok((outcome, _transfer)) => {
+                if !matches!(outcome, SyncOutcome::Skipped { .. }) {
+                    let machine = MachineIdentity::load_or_create(dirs)?;
+                    let mut repos = repos.transaction();
+                    repos.record_sync(name.to_borrowed(), machine.sync_record())?;
+                    repos.commit();
+                }
+                format!("{}: {}", name, outcome)
+            }
+            Err(e) => format!("failed to sync {:?}: {:#}", name, e),
+        };
+        self.refresh(dirs, git, repos);
+        Ok(())
+    }
+
+    /// Fails if the selected repo is marked read-only. `what` describes the refused operation.
+    fn ensure_writable(&self, repos: &RepoDb, what: &str) -> anyhow::Result<()> {
+        let name = match self.current() {
+            Some(entry) => &entry.record.name,
+            None => return Ok(()),
+        };
+        repos
+            .snapshot()
+            .get_by_name(name.to_borrowed())?
+            .ensure_writable(name, what, false)
+    }
+
+    /// Removes the selected repo entry, leaving its work tree files intact.
+    fn remove(
+        &mut self,
+        dirs: &Directories,
+        repos: &RepoDb,
+        hooks: &[HookConfig],
+    ) -> anyhow::Result<String> {
+        let (name, kind) = self
+            .current()
+            .map(|entry| (entry.record.name.clone(), entry.record.kind))
+            .ok_or_else(|| anyhow!("no repo selected"))?;
+        self.ensure_writable(repos, "remove")?;
+        let mut repos = repos.transaction();
+        match kind {
+            RepoKind::Overlay => {
+                repos.remove_overlay_bare_repo(dirs, name.to_borrowed())?;
+                run_hooks(
+                    hooks,
+                    &Event::RepoRemoved {
+                        name: name.clone(),
+                        files_deleted: false,
+                    },
+                );
+            }
+            RepoKind::Standalone => {
+                repos.deregister_standalone(name.to_borrowed())?;
+                run_hooks(hooks, &Event::RepoDeregistered { name: name.clone() });
+            }
+            RepoKind::Unmanaged => {
+                repos.deregister_unmanaged(name.to_borrowed())?;
+                run_hooks(hooks, &Event::RepoDeregistered { name: name.clone() });
+            }
+        }
+        repos.commit();
+        Ok(format!(
+            "removed {:?}; work tree files were left intact",
+            name
+        ))
+    }
+
+    fn draw(&mut self, frame: &mut Frame<'_>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.size());
+
+        let Self {
+            entries,
+            selected,
+            view,
+            message,
+        } = self;
+        match view {
+            View::Diff { text, scroll } => {
+                let diff = Paragraph::new(text.as_str())
+                    .block(Block::default().borders(Borders::ALL).title("diff"))
+                    .scroll((*scroll, 0));
+                frame.render_widget(diff, chunks[0]);
+            }
+            View::List | View::ConfirmRemove => {
+                let width = entries
+                    .iter()
+                    .map(|entry| entry.record.name.to_string().len())
+                    .max()
+                    .unwrap_or(0);
+                let items = entries
+                    .iter()
+                    .map(|entry| {
+                        ListItem::new(format!(
+                            "{:width$}  {:10}  {}  {}",
+                            entry.record.name.to_string(),
+                            entry.record.kind.to_string(),
+                            entry.status,
+                            entry.last_commit,
+                            width = width,
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title("repos"))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                frame.render_stateful_widget(list, chunks[0], selected);
+            }
+        }
+        frame.render_widget(Paragraph::new(Line::from(message.as_str())), chunks[1]);
+    }
+}
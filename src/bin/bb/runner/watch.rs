@@ -0,0 +1,168 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Watching a repo's tracked files for changes, for `watch`.
+//!
+//! Files are polled rather than watched with OS notifications, which keeps this portable and
+//! cheap enough for the few hundred files a dotfiles repo usually has.
+
+use crate::runner::{git::GitRepoTrait, repo_db::RepoName};
+use anyhow::{anyhow, Context};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write as _,
+    io,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often tracked files are checked at most. Shorter debounce periods check more often.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What to do once tracked files have stopped changing.
+#[derive(Debug)]
+pub(crate) enum OnChange<'a> {
+    /// Print the files with uncommitted changes.
+    Report,
+    /// Commit all changes to tracked files, with a message rendered from this template. See
+    /// [`render_message`].
+    Commit(&'a str),
+}
+
+/// The size and modification time of each tracked file, or `None` for files that don't exist.
+type Snapshot = BTreeMap<PathBuf, Option<(u64, SystemTime)>>;
+
+fn snapshot(repo: &impl GitRepoTrait) -> anyhow::Result<Snapshot> {
+    let files = repo.list_files().context("failed to list tracked files")?;
+    files
+        .map(|path| {
+            let stat = match path.symlink_metadata() {
+                Ok(metadata) => Some((metadata.len(), metadata.modified()?)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e).with_context(|| anyhow!("failed to inspect {:?}", path)),
+            };
+            Ok((path, stat))
+        })
+        .collect()
+}
+
+/// Watches the files tracked by `repo`, acting on changes once files have gone unchanged for
+/// `debounce`. Only returns on failure.
+pub(crate) fn watch(
+    repo: &mut impl GitRepoTrait,
+    name: &RepoName<'_>,
+    debounce: Duration,
+    on_change: OnChange<'_>,
+) -> anyhow::Result<()> {
+    let poll_interval = debounce.min(MAX_POLL_INTERVAL);
+    let mut last = snapshot(repo)?;
+    let mut changed_at = None;
+    tracing::info!("watching {:?} for changes; press Ctrl-C to stop", name);
+    loop {
+        thread::sleep(poll_interval);
+        // The set of tracked files changes with commits made elsewhere, too.
+        let current = snapshot(repo)?;
+        if current != last {
+            last = current;
+            changed_at = Some(Instant::now());
+            continue;
+        }
+        if !changed_at.is_some_and(|at: Instant| at.elapsed() >= debounce) {
+            continue;
+        }
+        changed_at = None;
+        match on_change {
+            OnChange::Report => {
+                let status = repo
+                    .status()
+                    .with_context(|| anyhow!("failed to get status of {:?}", name))?;
+                println!("{:?}: {}", name, status);
+            }
+            OnChange::Commit(template) => {
+                let files = repo
+                    .stage_tracked_changes()
+                    .with_context(|| anyhow!("failed to stage changes in {:?}", name))?;
+                if files.is_empty() {
+                    continue;
+                }
+                let message = render_message(template, name, &files);
+                repo.commit(&message)
+                    .with_context(|| anyhow!("failed to commit changes in {:?}", name))?;
+                tracing::info!("committed changes to {} file(s) in {:?}", files.len(), name);
+                // Committing touches nothing in the work tree, but take the snapshot again in
+                // case files changed while committing.
+                last = snapshot(repo)?;
+            }
+        }
+    }
+}
+
+/// Renders a commit message from `template`, replacing `{repo}` with the repo's name,
+/// `{hostname}` with this machine's hostname, `{count}` with the number of changed files, and
+/// `{files}` with a short list of them.
+pub(crate) fn render_message(template: &str, name: &RepoName<'_>, files: &[PathBuf]) -> String {
+    /// Lists beyond this many files are cut short.
+    const MAX_LISTED: usize = 3;
+
+    let mut listed = String::new();
+    for (idx, file) in files.iter().take(MAX_LISTED).enumerate() {
+        if idx > 0 {
+            listed.push_str(", ");
+        }
+        let _ = write!(listed, "{}", file.display());
+    }
+    if files.len() > MAX_LISTED {
+        let _ = write!(listed, " and {} more", files.len() - MAX_LISTED);
+    }
+    template
+        .replace("{repo}", name)
+        .replace("{hostname}", &gethostname::gethostname().to_string_lossy())
+        .replace("{count}", &files.len().to_string())
+        .replace("{files}", &listed)
+}
+
+/// A systemd user service that runs this executable with `args`, restarting it if it fails.
+pub(crate) fn systemd_service(name: &RepoName<'_>, args: &[String]) -> anyhow::Result<String> {
+    let exe = env::current_exe().context("failed to find path of this executable")?;
+    // Quote every word, and escape what systemd would otherwise expand in them.
+    let quote = |word: &str| {
+        let escaped = word
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+            .replace('$', "$$")
+            .replace('\n', "\\n");
+        format!("\"{}\"", escaped)
+    };
+    let mut exec_start = quote(&exe.to_string_lossy());
+    for arg in args {
+        exec_start.push(' ');
+        exec_start.push_str(&quote(arg));
+    }
+    Ok(format!(
+        "[Unit]\n\
+        Description={} watch {}\n\
+        \n\
+        [Service]\n\
+        ExecStart={}\n\
+        Restart=on-failure\n\
+        \n\
+        [Install]\n\
+        WantedBy=default.target\n",
+        env!("CARGO_BIN_NAME"),
+        name,
+        exec_start
+    ))
+}
@@ -0,0 +1,86 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+use crate::i18n::msg;
+use std::fmt::Debug;
+
+/// The most suggestions to offer at once.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Returns the `candidates` close enough to `actual` to plausibly be what was meant, closest
+/// first.
+pub(crate) fn closest<'a, T>(
+    actual: &str,
+    candidates: impl IntoIterator<Item = &'a T>,
+) -> Vec<&'a T>
+where
+    T: AsRef<str> + ?Sized + 'a,
+{
+    // Allow roughly one typo for every three characters, so short names don't match everything.
+    let max_distance = (actual.chars().count() / 3).max(1);
+    let mut close = candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(actual, candidate.as_ref()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    close.sort_by_key(|(distance, _)| *distance);
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// Formats a "did you mean" hint for an error message about `actual`, or an empty string if no
+/// candidate is close. The hint starts with a `; ` separator, so it can be appended directly.
+pub(crate) fn did_you_mean<'a, T>(
+    actual: &str,
+    candidates: impl IntoIterator<Item = &'a T>,
+) -> String
+where
+    T: AsRef<str> + Debug + ?Sized + 'a,
+{
+    match closest(actual, candidates).as_slice() {
+        [] => String::new(),
+        [only] => msg!("did-you-mean", candidates = format!("{:?}", only)),
+        [rest @ .., last] => {
+            let rest = rest
+                .iter()
+                .map(|candidate| format!("{:?}", candidate))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let candidates = format!("{} {} {:?}", rest, msg!("did-you-mean-or"), last);
+            msg!("did-you-mean", candidates = candidates)
+        }
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`, ignoring case.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+
+    let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+    let mut row = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution_cost = if a_char == b_char { 0 } else { 1 };
+            row[j + 1] = (prev_row[j] + substitution_cost)
+                .min(prev_row[j + 1] + 1)
+                .min(row[j] + 1);
+        }
+        std::mem::swap(&mut prev_row, &mut row);
+    }
+    prev_row[b.len()]
+}
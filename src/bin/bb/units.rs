@@ -0,0 +1,188 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+//! Quantities written the way people write them, i.e., `30s` or `10MB`, for command-line flags
+//! and the configuration file.
+//!
+//! In the configuration file, a bare integer is also accepted wherever a string is, and means the
+//! same as it does on the command line: seconds for durations, and bytes for sizes.
+
+use crate::suggest;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// A span of time, written as a whole number followed by a unit: `ms`, `s`, `m`, `h`, or `d`. A
+/// bare number is interpreted as seconds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HumanDuration(pub Duration);
+
+impl HumanDuration {
+    /// Units and how many milliseconds they stand for.
+    const UNITS: [(&'static str, u64); 5] = [
+        ("ms", 1),
+        ("s", 1_000),
+        ("m", 60 * 1_000),
+        ("h", 60 * 60 * 1_000),
+        ("d", 24 * 60 * 60 * 1_000),
+    ];
+}
+
+impl FromStr for HumanDuration {
+    type Err = InvalidQuantityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse("duration", s, &Self::UNITS, "s").map(|millis| Self(Duration::from_millis(millis)))
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match RawQuantity::deserialize(deserializer)? {
+            RawQuantity::Integer(secs) => Ok(Self(Duration::from_secs(secs))),
+            RawQuantity::String(s) => s.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+/// A number of bytes, written as a whole number optionally followed by a unit: `B`, a decimal
+/// multiple (`KB`, `MB`, `GB`, or `TB`), or a binary one (`KiB`, `MiB`, `GiB`, or `TiB`). Units
+/// are case-insensitive.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    /// Units and how many bytes they stand for.
+    const UNITS: [(&'static str, u64); 9] = [
+        ("B", 1),
+        ("KB", 1_000),
+        ("MB", 1_000_000),
+        ("GB", 1_000_000_000),
+        ("TB", 1_000_000_000_000),
+        ("KiB", 1 << 10),
+        ("MiB", 1 << 20),
+        ("GiB", 1 << 30),
+        ("TiB", 1 << 40),
+    ];
+}
+
+impl FromStr for ByteSize {
+    type Err = InvalidQuantityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse("size", s, &Self::UNITS, "B").map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match RawQuantity::deserialize(deserializer)? {
+            RawQuantity::Integer(bytes) => Ok(Self(bytes)),
+            RawQuantity::String(s) => s.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+/// A quantity as written in the configuration file, before it's parsed.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQuantity {
+    Integer(u64),
+    String(String),
+}
+
+#[derive(Debug, ThisError)]
+#[error("invalid {what} {actual:?}: {problem}")]
+pub struct InvalidQuantityError {
+    what: &'static str,
+    actual: String,
+    problem: QuantityProblem,
+}
+
+#[derive(Debug)]
+enum QuantityProblem {
+    MissingAmount,
+    UnknownUnit {
+        unit: String,
+        units: &'static [(&'static str, u64)],
+    },
+    TooLarge,
+}
+
+impl Display for QuantityProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingAmount => f.write_str("expected a whole number, i.e., `30`"),
+            Self::UnknownUnit { unit, units } => {
+                let names = units.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                write!(
+                    f,
+                    "unknown unit {:?}; expected one of `{}`{}",
+                    unit,
+                    names.join("`, `"),
+                    suggest::did_you_mean(unit, names.iter())
+                )
+            }
+            Self::TooLarge => f.write_str("too large"),
+        }
+    }
+}
+
+/// Parses `s` as a whole number followed by one of `units`, or by nothing for `default_unit`, and
+/// returns it in terms of the unit worth `1`. `what` names the kind of quantity, for errors.
+fn parse(
+    what: &'static str,
+    s: &str,
+    units: &'static [(&'static str, u64)],
+    default_unit: &str,
+) -> Result<u64, InvalidQuantityError> {
+    let err = |problem| InvalidQuantityError {
+        what,
+        actual: s.to_owned(),
+        problem,
+    };
+    let trimmed = s.trim();
+    let unit_idx = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (amount, unit) = trimmed.split_at(unit_idx);
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_e| err(QuantityProblem::MissingAmount))?;
+    let unit = match unit.trim_start() {
+        "" => default_unit,
+        unit => unit,
+    };
+    let (_name, factor) = units
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+        .ok_or_else(|| {
+            err(QuantityProblem::UnknownUnit {
+                unit: unit.to_owned(),
+                units,
+            })
+        })?;
+    amount
+        .checked_mul(*factor)
+        .ok_or_else(|| err(QuantityProblem::TooLarge))
+}
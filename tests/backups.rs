@@ -0,0 +1,122 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{fs, path::Path, process::Command};
+
+fn touch(path: &Path, stamp: &str) {
+    let status = Command::new("touch")
+        .args(["-t", stamp])
+        .arg(path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn retention_limits_prune_oldest_backups() {
+    let env = TestEnv::new("backups-retention");
+    fs::create_dir_all(env.data_dir()).unwrap();
+    let db_backup = env.data_dir().join("standalone_repos.toml.v1.bak");
+    fs::write(&db_backup, "").unwrap();
+    touch(&db_backup, "202001010000");
+    let quarantine = env.state_dir().join("quarantine");
+    let older = quarantine.join("old.1600000000");
+    let newer = quarantine.join("new.1700000000");
+    for dir in [&older, &newer] {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("config"), "contents").unwrap();
+    }
+    touch(&older, "202101010000");
+
+    env.write_config("[backups]\nmax_count = 2\n");
+    let listed = env.run_ok(&["prune-backups", "--dry-run"]);
+    assert!(
+        listed.contains("standalone_repos.toml.v1.bak"),
+        "{}",
+        listed
+    );
+    assert!(!listed.contains("old."), "{}", listed);
+    assert!(db_backup.exists());
+
+    // Any command that may make changes enforces the limits afterwards.
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    assert!(!db_backup.exists());
+    assert!(older.exists() && newer.exists());
+
+    env.write_config("[backups]\nmax_age_days = 30\n");
+    env.run_ok(&["prune-backups"]);
+    assert!(!older.exists());
+    assert!(newer.exists());
+}
+
+#[test]
+fn retention_limits_accept_units() {
+    let env = TestEnv::new("backups-units");
+    let quarantine = env.state_dir().join("quarantine");
+    let older = quarantine.join("old.1600000000");
+    let newer = quarantine.join("new.1700000000");
+    for dir in [&older, &newer] {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("config"), "x".repeat(600)).unwrap();
+    }
+    touch(&older, "202101010000");
+
+    env.write_config("[backups]\nmax_total_size = \"1KB\"\n");
+    env.run_ok(&["prune-backups"]);
+    assert!(!older.exists());
+    assert!(newer.exists());
+
+    touch(&newer, "202101010000");
+    env.write_config("[backups]\nmax_age = \"30d\"\n");
+    env.run_ok(&["prune-backups"]);
+    assert!(!newer.exists());
+
+    env.write_config("[backups]\nmax_age = \"30 days\"\n");
+    let err = env.run_err(&["prune-backups"], 1);
+    assert!(err.contains("unknown unit \"days\""), "{}", err);
+}
+
+#[test]
+fn state_left_in_data_dir_moves_to_state_dir() {
+    let env = TestEnv::new("backups-migrate-state");
+    let quarantined = env.data_dir().join("quarantine").join("old.1600000000");
+    fs::create_dir_all(&quarantined).unwrap();
+    fs::write(quarantined.join("config"), "contents").unwrap();
+    fs::write(env.data_dir().join("status_cache.json"), "{}").unwrap();
+    fs::write(env.data_dir().join("failed_repos.json"), "{}").unwrap();
+
+    // Nothing is moved in read-only mode.
+    env.run_ok(&["--read-only", "list"]);
+    assert!(quarantined.is_dir());
+    assert!(!env.state_dir().join("quarantine").exists());
+
+    env.run_ok(&["list"]);
+    assert!(!env.data_dir().join("quarantine").exists());
+    assert!(!env.data_dir().join("status_cache.json").exists());
+    assert!(!env.data_dir().join("failed_repos.json").exists());
+    assert_eq!(
+        fs::read_to_string(
+            env.state_dir()
+                .join("quarantine")
+                .join("old.1600000000")
+                .join("config")
+        )
+        .unwrap(),
+        "contents"
+    );
+    assert!(env.state_dir().join("failed_repos.json").is_file());
+}
@@ -0,0 +1,99 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{fs, io::Write, process::Stdio};
+
+#[test]
+fn lists_and_prunes_branches() {
+    let env = TestEnv::new("branches");
+    let source = env.make_source_repo("notes", &[("todo.md", "- write tests\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    env.git(&path, &["branch", "merged"]);
+    env.git(&path, &["checkout", "--quiet", "-b", "feature"]);
+    fs::write(path.join("todo.md"), "- write more tests\n").unwrap();
+    env.git(&path, &["commit", "--quiet", "--all", "--message", "more"]);
+    env.git(&path, &["checkout", "--quiet", "master"]);
+    fs::write(source.join("todo.md"), "- done\n").unwrap();
+    env.git(
+        &source,
+        &["commit", "--quiet", "--all", "--message", "done"],
+    );
+    env.git(&path, &["fetch", "--quiet"]);
+
+    let listed = env.run_ok(&["branches", "notes"]);
+    let names = listed
+        .lines()
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["feature", "*", "merged", "origin/master"],
+        "{}",
+        listed
+    );
+    assert!(listed.contains("* master "), "{}", listed);
+    assert!(listed.contains("origin/master, behind 1"), "{}", listed);
+    assert!(listed.contains(" ago\n"), "{}", listed);
+
+    let prune = |args: &[&str], answer: &str| {
+        let mut child = env
+            .bb(args)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answer.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stderr).unwrap()
+    };
+    let branches = || env.git(&path, &["branch", "--format=%(refname:short)"]);
+
+    let stderr = prune(&["branches", "prune", "notes"], "n\n");
+    assert!(stderr.contains("\n  merged\n"), "{}", stderr);
+    assert!(!stderr.contains("feature"), "{}", stderr);
+    assert_eq!(branches(), "feature\nmaster\nmerged\n");
+
+    prune(&["branches", "prune", "notes", "--yes"], "");
+    assert_eq!(branches(), "feature\nmaster\n");
+}
+
+#[test]
+fn prune_respects_protect() {
+    let env = TestEnv::new("branches-protect");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.run_ok(&["protect", "project"]);
+
+    let stderr = env.run_err(&["branches", "prune", "project", "--yes"], 1);
+    assert!(stderr.contains("read-only"), "{}", stderr);
+    // Nothing has been committed, so there's nothing to prune.
+    env.run_ok(&["branches", "prune", "project", "--yes", "--allow-read-only"]);
+    assert_eq!(env.run_ok(&["branches", "project"]), "");
+}
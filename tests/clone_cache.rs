@@ -0,0 +1,110 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn clones_of_remote_sources_borrow_from_mirrors() {
+    let env = TestEnv::new("clone-cache");
+    env.write_config("[clone_cache]\nenabled = true\n");
+    let source = env.make_source_repo("notes", &[("todo.md", "- write tests\n")]);
+    let url = format!("file://{}", source.display());
+
+    for name in ["first", "second"] {
+        let path = env.home().join(name);
+        env.run_ok(&[
+            "standalone",
+            "clone",
+            &url,
+            path.to_str().unwrap(),
+            "--name",
+            name,
+        ]);
+        assert_eq!(
+            fs::read_to_string(path.join("todo.md")).unwrap(),
+            "- write tests\n"
+        );
+        // Clones don't depend on the mirror.
+        assert!(!path.join(".git/objects/info/alternates").exists());
+    }
+
+    let mirrors = fs::read_dir(env.data_dir().join("clone_cache"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(mirrors.len(), 1, "{:?}", mirrors);
+    assert!(mirrors[0].ends_with("notes.git"), "{:?}", mirrors);
+
+    // Local paths are cloned without a mirror.
+    let path = env.home().join("third");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "third",
+    ]);
+    assert_eq!(
+        fs::read_dir(env.data_dir().join("clone_cache"))
+            .unwrap()
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn disabled_by_default() {
+    let env = TestEnv::new("clone-cache-disabled");
+    let source = env.make_source_repo("notes", &[("todo.md", "- write tests\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        &format!("file://{}", source.display()),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    assert!(!env.data_dir().join("clone_cache").exists());
+}
+
+#[test]
+fn max_size_deletes_least_recently_updated_mirrors() {
+    let env = TestEnv::new("clone-cache-max-size");
+    env.write_config("[clone_cache]\nenabled = true\nmax_size = \"1B\"\n");
+    for name in ["notes", "recipes"] {
+        let source = env.make_source_repo(name, &[("README.md", "hello\n")]);
+        let path = env.home().join(name);
+        env.run_ok(&[
+            "standalone",
+            "clone",
+            &format!("file://{}", source.display()),
+            path.to_str().unwrap(),
+            "--name",
+            name,
+        ]);
+        assert!(path.join("README.md").is_file());
+    }
+
+    // The mirror just used is kept even though it's over the limit on its own.
+    let mirrors = fs::read_dir(env.data_dir().join("clone_cache"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(mirrors.len(), 1, "{:?}", mirrors);
+    assert!(mirrors[0].ends_with("recipes.git"), "{:?}", mirrors);
+}
@@ -0,0 +1,204 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+// Shared scaffolding for end-to-end tests that drive the compiled `bb` binary.
+
+#![allow(dead_code)] // Not every test binary uses every helper.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory containing an isolated `HOME` and XDG base directories, removed on drop.
+///
+/// Every command run through a `TestEnv` sees only these directories, so tests never touch the
+/// real user's repos, config, or Git settings.
+pub struct TestEnv {
+    root: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new(name: &str) -> Self {
+        let root = env::temp_dir().join(format!(
+            "bb-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        ));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        for dir in ["home", "config", "data", "state", "cache", "sources"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        // Resolve symlinks (e.g., `/tmp` on macOS) so paths match what `bb` records.
+        let root = dunce::canonicalize(root).unwrap();
+        Self { root }
+    }
+
+    pub fn home(&self) -> PathBuf {
+        self.root.join("home")
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.root.join("data").join("bellboy")
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.root.join("state").join("bellboy")
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        self.root.join("config").join("bellboy")
+    }
+
+    /// Writes this tool's configuration file.
+    pub fn write_config(&self, contents: &str) {
+        let dir = self.config_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), contents).unwrap();
+    }
+
+    pub fn sources_dir(&self) -> PathBuf {
+        self.root.join("sources")
+    }
+
+    fn isolate(&self, cmd: &mut Command) {
+        cmd.current_dir(self.home())
+            .env("HOME", self.home())
+            .env("XDG_CONFIG_HOME", self.root.join("config"))
+            .env("XDG_DATA_HOME", self.root.join("data"))
+            .env("XDG_STATE_HOME", self.root.join("state"))
+            .env("XDG_CACHE_HOME", self.root.join("cache"))
+            // `XDG_*` only count on Linux.
+            .env("BELLBOY_CONFIG_DIR", self.config_dir())
+            .env("BELLBOY_DATA_DIR", self.data_dir())
+            .env("BELLBOY_STATE_DIR", self.state_dir())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Bellboy Tests")
+            .env("GIT_AUTHOR_EMAIL", "tests@bellboy.invalid")
+            .env("GIT_COMMITTER_NAME", "Bellboy Tests")
+            .env("GIT_COMMITTER_EMAIL", "tests@bellboy.invalid")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE")
+            .env_remove("BELLBOY_HOME")
+            .env_remove("RUST_LOG")
+            // Messages are asserted on in English.
+            .env_remove("LC_ALL")
+            .env_remove("LC_MESSAGES")
+            .env("LANG", "C");
+    }
+
+    /// Builds a `bb` invocation inside this environment.
+    pub fn bb(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_bb"));
+        self.isolate(&mut cmd);
+        cmd.args(args);
+        cmd
+    }
+
+    /// Runs `bb` and panics unless it succeeds, returning its `stdout`.
+    pub fn run_ok(&self, args: &[&str]) -> String {
+        let output = self.bb(args).output().unwrap();
+        if !output.status.success() {
+            panic!("`bb {}` failed:\n{}", args.join(" "), describe(&output));
+        }
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs `bb` and panics unless it fails with `exit_code`, returning its `stderr`.
+    pub fn run_err(&self, args: &[&str], exit_code: i32) -> String {
+        let output = self.bb(args).output().unwrap();
+        if output.status.success() {
+            panic!(
+                "`bb {}` unexpectedly succeeded:\n{}",
+                args.join(" "),
+                describe(&output)
+            );
+        }
+        assert_eq!(
+            output.status.code(),
+            Some(exit_code),
+            "{}",
+            describe(&output)
+        );
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    /// Runs `git` in `dir` and panics unless it succeeds, returning its `stdout`.
+    pub fn git(&self, dir: &Path, args: &[&str]) -> String {
+        let mut cmd = Command::new("git");
+        self.isolate(&mut cmd);
+        let output = cmd.current_dir(dir).args(args).output().unwrap();
+        if !output.status.success() {
+            panic!("`git {}` failed:\n{}", args.join(" "), describe(&output));
+        }
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Creates a repo under [`Self::sources_dir`] with a single commit containing `files`, suitable
+    /// as a clone source.
+    pub fn make_source_repo(&self, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path = self.sources_dir().join(name);
+        fs::create_dir_all(&path).unwrap();
+        self.git(&path, &["init", "--quiet"]);
+        for (file, contents) in files {
+            let file_path = path.join(file);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(file_path, contents).unwrap();
+        }
+        self.git(&path, &["add", "--all"]);
+        self.git(&path, &["commit", "--quiet", "--message", "initial commit"]);
+        path
+    }
+
+    pub fn standalone_db(&self) -> toml::value::Table {
+        self.read_db("standalone_repos.toml")
+    }
+
+    pub fn overlay_db(&self) -> toml::value::Table {
+        self.read_db("overlay_repos.toml")
+    }
+
+    /// Reads one of `bb`'s repo DBs, treating a missing file as empty.
+    fn read_db(&self, file_name: &str) -> toml::value::Table {
+        match fs::read_to_string(self.data_dir().join(file_name)) {
+            Ok(contents) => toml::from_str(&contents).unwrap(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(e) => panic!("failed to read {:?}: {}", file_name, e),
+        }
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        // Keep the environment around for inspection if the test failed.
+        if !std::thread::panicking() {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+fn describe(output: &Output) -> String {
+    format!(
+        "status: {}\n--- stdout ---\n{}\n--- stderr ---\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    )
+}
@@ -0,0 +1,89 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn get_set_and_unset_validate_fields() {
+    let env = TestEnv::new("db-fields");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+
+    env.run_ok(&["db", "set", "project", "description", "My project"]);
+    env.run_ok(&["db", "set", "project", "pinned", "true"]);
+    env.run_ok(&["db", "set", "project", "sync_strategy", "stash-and-rebase"]);
+    assert_eq!(
+        env.run_ok(&["db", "get", "project", "description"]),
+        "My project\n"
+    );
+    let db = env.standalone_db();
+    let entry = &db["standalone_repos"]["project"];
+    assert_eq!(entry["pinned"].as_bool(), Some(true));
+    assert_eq!(entry["sync_strategy"].as_str(), Some("stash-and-rebase"));
+
+    let db_before = env.standalone_db();
+    let stderr = env.run_err(&["db", "set", "project", "pinned", "yes"], 1);
+    assert!(stderr.contains("expected `true` or `false`"), "{}", stderr);
+    let stderr = env.run_err(&["db", "set", "project", "sync_strategy", "rebas"], 1);
+    assert!(stderr.contains("did you mean \"rebase\"?"), "{}", stderr);
+    let stderr = env.run_err(&["db", "get", "project", "descripton"], 2);
+    assert!(
+        stderr.contains("did you mean \"description\"?"),
+        "{}",
+        stderr
+    );
+    env.run_err(&["db", "set", "project", "source.branch", "main"], 1);
+    assert_eq!(env.standalone_db(), db_before);
+
+    env.run_ok(&["db", "unset", "project", "description"]);
+    env.run_ok(&["db", "unset", "project", "pinned"]);
+    env.run_err(&["db", "get", "project", "description"], 1);
+    assert_eq!(env.run_ok(&["db", "get", "project", "pinned"]), "false\n");
+}
+
+#[test]
+fn dependencies_order_for_each_and_reject_cycles() {
+    let env = TestEnv::new("db-depends-on");
+    for name in ["a-plugins", "b-base", "c-other"] {
+        let path = env.home().join(name);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    }
+    env.run_ok(&["db", "set", "a-plugins", "depends_on", "b-base"]);
+    assert_eq!(
+        env.run_ok(&["db", "get", "a-plugins", "depends_on"]),
+        "b-base\n"
+    );
+
+    let order = env.run_ok(&[
+        "for-each",
+        "--no-cd-root",
+        "--",
+        "sh",
+        "-c",
+        "basename $GIT_WORK_TREE",
+    ]);
+    assert_eq!(order, "b-base\na-plugins\nc-other\n");
+
+    let stderr = env.run_err(&["db", "set", "b-base", "depends_on", "a-plugins"], 1);
+    assert!(
+        stderr.contains("cycle: a-plugins -> b-base -> a-plugins"),
+        "{}",
+        stderr
+    );
+    env.run_err(&["db", "set", "c-other", "depends_on", "missing"], 3);
+    assert!(env.standalone_db()["standalone_repos"]["b-base"]
+        .get("depends_on")
+        .is_none());
+}
@@ -0,0 +1,40 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn failed_reruns_only_repos_that_failed() {
+    let env = TestEnv::new("for-each-failed");
+    for name in ["a", "b", "c"] {
+        let path = env.home().join(name);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    }
+    fs::write(env.home().join("b/broken"), "").unwrap();
+
+    // Non-zero exit codes are only reported, but still count as failures to retry.
+    env.run_ok(&["for-each", "--", "sh", "-c", "test ! -f broken"]);
+
+    fs::remove_file(env.home().join("b/broken")).unwrap();
+    env.run_ok(&["for-each", "--failed", "--", "touch", "retried"]);
+    assert!(!env.home().join("a/retried").exists());
+    assert!(env.home().join("b/retried").exists());
+    assert!(!env.home().join("c/retried").exists());
+
+    // Nothing failed the last time, so there's nothing to retry.
+    env.run_ok(&["for-each", "--failed", "--", "touch", "retried-again"]);
+    assert!(!env.home().join("b/retried-again").exists());
+}
@@ -0,0 +1,72 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn prompts_and_errors_follow_locale() {
+    let env = TestEnv::new("i18n");
+    let output = env
+        .bb(&["show", "nope"])
+        .env("LANG", "fr_FR.UTF-8")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("\"nope\" n'est pas un nom de dépôt de la configuration actuelle"),
+        "{}",
+        stderr
+    );
+
+    // `LC_ALL` takes precedence over `LANG`.
+    let output = env
+        .bb(&["show", "nope"])
+        .env("LANG", "fr_FR.UTF-8")
+        .env("LC_ALL", "C")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("\"nope\" is not a repo name in the current configuration"),
+        "{}",
+        stderr
+    );
+
+    let source = env.make_source_repo("dotfiles", &[(".profile", "theirs\n")]);
+    fs::write(env.home().join(".profile"), "mine\n").unwrap();
+    let output = env
+        .bb(&[
+            "overlay",
+            "clone",
+            source.to_str().unwrap(),
+            "--name",
+            "dots",
+        ])
+        .env("LANG", "fr_CA.UTF-8")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("extraire \"dots\" écraserait des fichiers existants :"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("les écraser ? [o/N]"), "{}", stderr);
+    // Logs stay in English.
+    assert!(stderr.contains("registered \"dots\""), "{}", stderr);
+}
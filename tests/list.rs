@@ -0,0 +1,156 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+
+fn listed_names(listing: &str) -> Vec<&str> {
+    listing
+        .lines()
+        .map(|line| line.split(':').next().unwrap().trim_matches('"'))
+        .collect()
+}
+
+#[test]
+fn sort_by_path() {
+    let env = TestEnv::new("list-sort-path");
+    for (name, dir) in [("a", "zeta"), ("b", "alpha"), ("c", "mu")] {
+        let path = env.home().join(dir);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap(), "--name", name]);
+    }
+
+    assert_eq!(listed_names(&env.run_ok(&["list"])), ["a", "b", "c"]);
+    assert_eq!(
+        listed_names(&env.run_ok(&["list", "--sort", "path"])),
+        ["b", "c", "a"]
+    );
+}
+
+#[test]
+fn sort_by_recent_activity_puts_repos_without_commits_last() {
+    let env = TestEnv::new("list-sort-recent");
+    // Named so that sorting by name would put it first.
+    let empty = env.home().join("empty");
+    env.run_ok(&[
+        "standalone",
+        "init",
+        empty.to_str().unwrap(),
+        "--name",
+        "aardvark",
+    ]);
+    let source = env.make_source_repo("busy", &[("file", "contents\n")]);
+    let busy = env.home().join("busy");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        busy.to_str().unwrap(),
+        "--name",
+        "busy",
+    ]);
+
+    assert_eq!(
+        listed_names(&env.run_ok(&["list", "--sort", "recent-activity"])),
+        ["busy", "aardvark"]
+    );
+}
+
+#[test]
+fn unknown_sort_is_a_usage_error() {
+    let env = TestEnv::new("list-sort-unknown");
+    env.run_err(&["list", "--sort", "size"], 2);
+}
+
+#[test]
+fn filter_and_group_by_kind() {
+    let env = TestEnv::new("list-kind");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.run_ok(&["overlay", "init", "dots"]);
+
+    assert_eq!(
+        listed_names(&env.run_ok(&["list", "--kind", "overlay"])),
+        ["dots"]
+    );
+    assert_eq!(
+        listed_names(&env.run_ok(&["list", "--kind", "standalone"])),
+        ["project"]
+    );
+    let grouped = env.run_ok(&["list", "--format", "group-by-kind"]);
+    assert!(
+        grouped.starts_with("standalone repos\n  project: "),
+        "{}",
+        grouped
+    );
+    assert!(grouped.contains("overlay repos\n  dots\n"), "{}", grouped);
+
+    let stderr = env.run_err(&["list", "--kind", "overlays"], 2);
+    assert!(stderr.contains("overlay"), "{}", stderr);
+}
+
+#[test]
+fn names_only_and_paths_only_print_one_per_line() {
+    let env = TestEnv::new("list-only");
+    for (name, dir) in [("a", "zeta"), ("b", "alpha")] {
+        let path = env.home().join(dir);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap(), "--name", name]);
+    }
+    env.run_ok(&["overlay", "init", "dots"]);
+
+    assert_eq!(env.run_ok(&["list", "--names-only"]), "a\nb\ndots\n");
+    assert_eq!(
+        env.run_ok(&[
+            "list",
+            "--paths-only",
+            "--kind",
+            "standalone",
+            "--sort",
+            "path"
+        ]),
+        format!(
+            "{}\n{}\n",
+            env.home().join("alpha").display(),
+            env.home().join("zeta").display()
+        )
+    );
+    assert_eq!(
+        env.run_ok(&["list", "--paths-only", "--kind", "overlay"]),
+        format!("{}\n", env.home().display())
+    );
+
+    env.run_err(&["list", "--names-only", "--paths-only"], 2);
+    env.run_err(&["list", "--names-only", "--format", "json"], 2);
+}
+
+#[test]
+fn json_includes_required_tags() {
+    let env = TestEnv::new("list-json");
+    let path = env.home().join("work-notes");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    std::fs::write(path.join(".bellboy.toml"), "required_tags = [\"work\"]\n").unwrap();
+
+    let listed: serde_json::Value =
+        serde_json::from_str(&env.run_ok(&["list", "--format", "json"])).unwrap();
+    assert_eq!(
+        listed,
+        serde_json::json!([{
+            "name": "work-notes",
+            "kind": "standalone",
+            "path": path,
+            "work_tree": path,
+            "source": null,
+            "tags": ["work"],
+        }])
+    );
+}
@@ -0,0 +1,59 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn runs_are_recorded_only_when_enabled() {
+    let env = TestEnv::new("metrics-disabled");
+    let source = env.make_source_repo("dotfiles", &[(".profile", "export A=1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+    assert!(!env.state_dir().join("metrics.jsonl").exists());
+    assert_eq!(env.run_ok(&["metrics", "show"]), "");
+}
+
+#[test]
+fn show_summarizes_recorded_runs() {
+    let env = TestEnv::new("metrics-show");
+    env.write_config("metrics = true\n");
+    let source = env.make_source_repo("dotfiles", &[(".profile", "export A=1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+    env.run_err(&["remove", "nonexistent"], 3);
+    env.run_ok(&["list"]);
+
+    let shown = env.run_ok(&["metrics", "show"]);
+    assert!(shown.contains("overlay clone (version "), "{}", shown);
+    assert!(shown.contains("1 run(s), 0 failed"), "{}", shown);
+    assert!(shown.contains("  clone: 1 in "), "{}", shown);
+    assert!(shown.contains("  checkout: 1 in "), "{}", shown);
+    assert!(shown.contains("1 run(s), 1 failed"), "{}", shown);
+    assert!(!shown.contains("metrics show"), "{}", shown);
+
+    let shown = env.run_ok(&["metrics", "show", "--command", "list"]);
+    assert!(shown.starts_with("list (version "), "{}", shown);
+    assert_eq!(shown.lines().count(), 1, "{}", shown);
+}
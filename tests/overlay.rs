@@ -0,0 +1,840 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn clone_checks_out_into_home() {
+    let env = TestEnv::new("overlay-clone");
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    assert_eq!(
+        fs::read_to_string(env.home().join(".config/app/app.toml")).unwrap(),
+        "theme = 1\n"
+    );
+    let db = env.overlay_db();
+    assert!(db["overlay_repos"].get("dots").is_some());
+    assert!(env.data_dir().join("overlay_repos").join("dots").is_dir());
+
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("dots"), "{}", listed);
+}
+
+#[test]
+fn init_then_remove() {
+    let env = TestEnv::new("overlay-remove");
+    env.run_ok(&["overlay", "init", "empty"]);
+    assert!(env.overlay_db()["overlay_repos"].get("empty").is_some());
+
+    env.run_ok(&["remove", "empty"]);
+    assert!(env
+        .overlay_db()
+        .get("overlay_repos")
+        .and_then(|repos| repos.get("empty"))
+        .is_none());
+    assert!(!env.data_dir().join("overlay_repos").join("empty").exists());
+}
+
+#[test]
+fn name_shared_with_standalone_repo_is_a_conflict() {
+    let env = TestEnv::new("overlay-name-conflict");
+    let path = env.home().join("shared");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.run_err(&["overlay", "init", "shared"], 4);
+
+    assert!(env
+        .overlay_db()
+        .get("overlay_repos")
+        .and_then(|repos| repos.get("shared"))
+        .is_none());
+}
+
+#[test]
+fn conflicts_reports_shared_paths() {
+    let env = TestEnv::new("overlay-conflicts");
+    let first = env.make_source_repo("first", &[(".profile", "first\n")]);
+    let second = env.make_source_repo("second", &[(".profile", "second\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        first.to_str().unwrap(),
+        "--name",
+        "first",
+    ]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        second.to_str().unwrap(),
+        "--name",
+        "second",
+        "--no-checkout",
+    ]);
+
+    let report = env.run_ok(&["overlay", "conflicts"]);
+    assert!(report.contains(".profile"), "{}", report);
+}
+
+#[test]
+fn excludes_combine_shared_and_host_specific_files() {
+    let env = TestEnv::new("overlay-excludes");
+    let hostname = gethostname::gethostname().into_string().unwrap();
+    let excludes_dir = env.home().join(".gitignore.d");
+    fs::create_dir_all(&excludes_dir).unwrap();
+    fs::write(excludes_dir.join("dots"), "shared.log\n").unwrap();
+    fs::write(
+        excludes_dir.join(format!("dots.{}", hostname)),
+        "host.log\n",
+    )
+    .unwrap();
+    env.run_ok(&["overlay", "init", "dots"]);
+
+    let git_dir = env.data_dir().join("overlay_repos").join("dots");
+    let ignored = env.git(
+        &env.home(),
+        &[
+            &format!("--git-dir={}", git_dir.display()),
+            &format!("--work-tree={}", env.home().display()),
+            "check-ignore",
+            "shared.log",
+            "host.log",
+        ],
+    );
+    assert_eq!(ignored, "shared.log\nhost.log\n");
+}
+
+#[test]
+fn init_from_template_commits_its_files() {
+    let env = TestEnv::new("overlay-template");
+    env.write_config("[overlay]\nallow_git_hooks = true\n");
+    let template = env.make_source_repo(
+        "template",
+        &[
+            ("README.md", "# Dotfiles\n"),
+            (".config/git/hooks/pre-commit", "#!/bin/sh\n"),
+            (
+                ".bellboy-template.toml",
+                "excludes = [\"*.log\"]\n\
+                commit_message = \"Start from template\"\n\
+                [git_config]\n\
+                \"core.hooksPath\" = \".config/git/hooks\"\n",
+            ),
+        ],
+    );
+    env.run_ok(&[
+        "overlay",
+        "init",
+        "dots",
+        "--from-template",
+        template.to_str().unwrap(),
+    ]);
+
+    assert_eq!(
+        fs::read_to_string(env.home().join("README.md")).unwrap(),
+        "# Dotfiles\n"
+    );
+    assert!(!env.home().join(".bellboy-template.toml").exists());
+    let git_dir = env.data_dir().join("overlay_repos").join("dots");
+    let git = |args: &[&str]| {
+        let mut full_args = vec![
+            format!("--git-dir={}", git_dir.display()),
+            format!("--work-tree={}", env.home().display()),
+        ];
+        full_args.extend(args.iter().map(|arg| arg.to_string()));
+        env.git(
+            &env.home(),
+            &full_args.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(git(&["log", "--format=%s"]), "Start from template\n");
+    assert_eq!(
+        git(&["ls-files"]),
+        ".config/git/hooks/pre-commit\n.gitignore.d/dots\nREADME.md\n"
+    );
+    assert_eq!(git(&["config", "core.hooksPath"]), ".config/git/hooks\n");
+    assert_eq!(git(&["check-ignore", "debug.log"]), "debug.log\n");
+}
+
+#[test]
+fn init_from_template_refuses_to_overwrite_files() {
+    let env = TestEnv::new("overlay-template-conflict");
+    let template = env.make_source_repo("template", &[("README.md", "# Dotfiles\n")]);
+    fs::write(env.home().join("README.md"), "mine\n").unwrap();
+    env.run_err(
+        &[
+            "overlay",
+            "init",
+            "dots",
+            "--from-template",
+            template.to_str().unwrap(),
+        ],
+        4,
+    );
+
+    assert_eq!(
+        fs::read_to_string(env.home().join("README.md")).unwrap(),
+        "mine\n"
+    );
+    assert!(env
+        .overlay_db()
+        .get("overlay_repos")
+        .and_then(|repos| repos.get("dots"))
+        .is_none());
+}
+
+#[test]
+fn adopt_file_stages_and_commits_existing_files() {
+    let env = TestEnv::new("overlay-adopt-file");
+    env.run_ok(&["overlay", "init", "dots"]);
+    fs::write(env.home().join(".inputrc"), "set bell-style none\n").unwrap();
+    fs::create_dir_all(env.home().join(".config").join("tool")).unwrap();
+    fs::write(env.home().join(".config").join("tool").join("rc"), "").unwrap();
+    let git_dir = env.data_dir().join("overlay_repos").join("dots");
+    let git_dir_arg = format!("--git-dir={}", git_dir.display());
+    let work_tree_arg = format!("--work-tree={}", env.home().display());
+
+    let output = env
+        .bb(&["overlay", "adopt-file", "dots", ".inputrc"])
+        .current_dir(env.home())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        env.git(
+            &env.home(),
+            &[
+                &git_dir_arg,
+                &work_tree_arg,
+                "diff",
+                "--cached",
+                "--name-only"
+            ]
+        ),
+        ".inputrc\n"
+    );
+
+    let output = env
+        .bb(&["overlay", "adopt-file", "dots", "tool/rc", "--commit"])
+        .current_dir(env.home().join(".config"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        env.git(
+            &env.home(),
+            &[&git_dir_arg, &work_tree_arg, "log", "--format=%s"]
+        ),
+        "Adopt .config/tool/rc\n"
+    );
+    assert_eq!(
+        env.git(&env.home(), &[&git_dir_arg, &work_tree_arg, "ls-files"]),
+        ".config/tool/rc\n.inputrc\n"
+    );
+
+    let outside = env.sources_dir().join("elsewhere");
+    fs::create_dir_all(&outside).unwrap();
+    let stderr = env.run_err(
+        &["overlay", "adopt-file", "dots", outside.to_str().unwrap()],
+        1,
+    );
+    assert!(
+        stderr.contains("outside of \"dots\"'s work tree"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn clone_asks_before_overwriting_existing_files() {
+    let env = TestEnv::new("overlay-clone-overwrite");
+    let source = env.make_source_repo(
+        "dotfiles",
+        &[(".profile", "theirs\n"), (".bashrc", "alias ll='ls -l'\n")],
+    );
+    fs::write(env.home().join(".profile"), "mine\n").unwrap();
+
+    // Without a terminal to answer on, the prompt is declined.
+    let output = env
+        .bb(&[
+            "overlay",
+            "clone",
+            source.to_str().unwrap(),
+            "--name",
+            "dots",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("would overwrite existing files"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(".profile | 2 +-"), "{}", stderr);
+    assert!(!stderr.contains(".bashrc"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(env.home().join(".profile")).unwrap(),
+        "mine\n"
+    );
+    assert!(!env.home().join(".bashrc").exists());
+}
+
+#[test]
+fn clone_overwrites_existing_files_when_told_to() {
+    let env = TestEnv::new("overlay-clone-overwrite-forced");
+    let source = env.make_source_repo("dotfiles", &[(".profile", "theirs\n")]);
+    fs::write(env.home().join(".profile"), "mine\n").unwrap();
+
+    let output = env
+        .bb(&[
+            "overlay",
+            "clone",
+            source.to_str().unwrap(),
+            "--name",
+            "dots",
+            "--overwrite",
+            "--show-diff",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("-mine\n+theirs\n"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(env.home().join(".profile")).unwrap(),
+        "theirs\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn remove_refuses_files_outside_of_home() {
+    let env = TestEnv::new("overlay-remove-outside-home");
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    // Move `~/.config` elsewhere, leaving a symlink to it behind.
+    let elsewhere = env.sources_dir().join("config");
+    fs::rename(env.home().join(".config"), &elsewhere).unwrap();
+    std::os::unix::fs::symlink(&elsewhere, env.home().join(".config")).unwrap();
+
+    let stderr = env.run_err(&["remove", "dots", "--force"], 5);
+    assert!(stderr.contains("outside of the work tree"), "{}", stderr);
+    assert!(stderr.contains(".config/app/app.toml ->"), "{}", stderr);
+    assert!(elsewhere.join("app/app.toml").is_file());
+    assert!(env.overlay_db()["overlay_repos"].get("dots").is_some());
+}
+
+#[test]
+fn remove_deletes_directories_left_empty() {
+    let env = TestEnv::new("overlay-remove-empty-dirs");
+    let source = env.make_source_repo(
+        "dotfiles",
+        &[
+            (".config/app/app.toml", "theme = 1\n"),
+            (".config/app/themes/dark.toml", "bg = 0\n"),
+            (".local/bin/tool", "#!/bin/sh\n"),
+        ],
+    );
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+    fs::write(env.home().join(".local/notes.txt"), "mine\n").unwrap();
+
+    env.run_ok(&["remove", "dots", "--force"]);
+    assert!(!env.home().join(".config").exists());
+    assert!(!env.home().join(".local/bin").exists());
+    assert_eq!(
+        fs::read_to_string(env.home().join(".local/notes.txt")).unwrap(),
+        "mine\n"
+    );
+    assert!(env.home().is_dir());
+}
+
+#[test]
+fn remove_keeps_directories_that_existed_before_checkout() {
+    let env = TestEnv::new("overlay-remove-existing-dirs");
+    let source = env.make_source_repo(
+        "dotfiles",
+        &[(".ssh/config", "Host *\n"), (".vim/vimrc", "set nu\n")],
+    );
+    fs::create_dir(env.home().join(".ssh")).unwrap();
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    let output = env.bb(&["remove", "dots", "--force"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!env.home().join(".ssh/config").exists());
+    assert!(env.home().join(".ssh").is_dir());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(".ssh"), "{}", stderr);
+    assert!(!env.home().join(".vim").exists());
+}
+
+#[test]
+fn remove_keep_files_leaves_work_tree_intact() {
+    let env = TestEnv::new("overlay-remove-keep-files");
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    env.run_ok(&["remove", "dots", "--keep-files"]);
+    assert!(env
+        .overlay_db()
+        .get("overlay_repos")
+        .and_then(|repos| repos.get("dots"))
+        .is_none());
+    assert!(!env.data_dir().join("overlay_repos").join("dots").exists());
+    assert_eq!(
+        fs::read_to_string(env.home().join(".config/app/app.toml")).unwrap(),
+        "theme = 1\n"
+    );
+}
+
+#[test]
+fn remove_files_only_keeps_entry_and_git_dir() {
+    let env = TestEnv::new("overlay-remove-files-only");
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    env.run_ok(&["remove", "dots", "--files-only"]);
+    assert!(env.overlay_db()["overlay_repos"].get("dots").is_some());
+    assert!(env.data_dir().join("overlay_repos").join("dots").is_dir());
+    assert!(!env.home().join(".config/app/app.toml").exists());
+}
+
+#[test]
+fn remove_files_only_refuses_uncommitted_changes_without_force() {
+    let env = TestEnv::new("overlay-remove-files-only-dirty");
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+    let tracked = env.home().join(".config/app/app.toml");
+    fs::write(&tracked, "theme = 2\n").unwrap();
+
+    env.run_err(&["remove", "dots", "--files-only"], 1);
+    assert_eq!(fs::read_to_string(&tracked).unwrap(), "theme = 2\n");
+
+    env.run_ok(&["remove", "dots", "--files-only", "--force"]);
+    assert!(!tracked.exists());
+    assert!(env.overlay_db()["overlay_repos"].get("dots").is_some());
+}
+
+#[test]
+fn hooks_are_isolated_by_default() {
+    let env = TestEnv::new("overlay-hooks-isolated");
+    // Otherwise, `doctor` finds the test's state directories exposed.
+    env.write_config("harden_permissions = true\n");
+    let template = env.make_source_repo(
+        "template",
+        &[
+            (".config/git/hooks/pre-commit", "#!/bin/sh\n"),
+            (
+                ".bellboy-template.toml",
+                "[git_config]\n\"core.hooksPath\" = \".config/git/hooks\"\n",
+            ),
+        ],
+    );
+    let output = env
+        .bb(&[
+            "overlay",
+            "init",
+            "dots",
+            "--from-template",
+            template.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("may not run Git hooks"), "{}", stderr);
+
+    let git_dir = env.data_dir().join("overlay_repos").join("dots");
+    let hooks_path = env.git(
+        &env.home(),
+        &[
+            &format!("--git-dir={}", git_dir.display()),
+            "config",
+            "core.hooksPath",
+        ],
+    );
+    let empty_hooks_dir = env.data_dir().join("empty_hooks");
+    assert_eq!(std::path::Path::new(hooks_path.trim_end()), empty_hooks_dir);
+    assert_eq!(fs::read_dir(&empty_hooks_dir).unwrap().count(), 0);
+    env.run_ok(&["doctor"]);
+
+    env.git(
+        &env.home(),
+        &[
+            &format!("--git-dir={}", git_dir.display()),
+            "config",
+            "core.hooksPath",
+            ".config/git/hooks",
+        ],
+    );
+    let stderr = env.run_err(&["doctor"], 1);
+    assert!(stderr.contains("\"dots\" may run Git hooks"), "{}", stderr);
+}
+
+#[test]
+fn register_keeps_git_dir_where_it_is() {
+    let env = TestEnv::new("overlay-register-git-dir");
+    let git_dir = env.home().join(".cfg");
+    env.git(&env.home(), &["init", "--bare", "--quiet", ".cfg"]);
+    fs::write(env.home().join(".vimrc"), "set nocompatible\n").unwrap();
+    env.git(
+        &env.home(),
+        &["--git-dir", ".cfg", "--work-tree", ".", "add", ".vimrc"],
+    );
+    env.git(
+        &env.home(),
+        &[
+            "--git-dir",
+            ".cfg",
+            "--work-tree",
+            ".",
+            "commit",
+            "-m",
+            "vimrc",
+        ],
+    );
+
+    env.run_ok(&[
+        "overlay",
+        "register",
+        "cfg",
+        "--git-dir",
+        git_dir.to_str().unwrap(),
+    ]);
+    let db = env.overlay_db();
+    assert_eq!(
+        db["overlay_repos"]["cfg"]["git_dir"].as_str(),
+        Some("~/.cfg"),
+        "{:?}",
+        db
+    );
+    assert!(git_dir.is_dir());
+    assert!(!env.data_dir().join("overlay_repos").join("cfg").exists());
+
+    let paths = env.run_ok(&["paths"]);
+    assert!(
+        paths.contains(&format!("GIT_DIR: {}", git_dir.display())),
+        "{}",
+        paths
+    );
+    let files = env.run_ok(&["run", "cfg", "--", "git", "ls-files"]);
+    assert_eq!(files.trim(), ".vimrc");
+
+    // Registering it twice is a conflict, under any name.
+    env.run_err(
+        &[
+            "overlay",
+            "register",
+            "other",
+            "--git-dir",
+            git_dir.to_str().unwrap(),
+        ],
+        4,
+    );
+}
+
+#[test]
+fn remove_prunes_config_when_asked_to() {
+    let env = TestEnv::new("overlay-remove-prune-config");
+    env.run_ok(&["overlay", "init", "dots"]);
+    fs::create_dir_all(env.home().join(".gitignore.d")).unwrap();
+    fs::write(env.home().join(".gitignore.d/dots"), "*.swp\n").unwrap();
+    fs::write(env.home().join(".gitignore.d/other"), "*.bak\n").unwrap();
+    // Opening the repo generates its excludes file.
+    env.run_ok(&["status"]);
+    let generated = env.state_dir().join("excludes/dots");
+    assert!(generated.is_file());
+    assert!(env.home().join(".gitattributes.d/dots").is_file());
+
+    env.run_ok(&["remove", "dots", "--prune-config"]);
+    assert!(!generated.exists());
+    assert!(!env.home().join(".gitignore.d/dots").exists());
+    assert!(env.home().join(".gitignore.d/other").is_file());
+    assert!(!env.home().join(".gitattributes.d").exists());
+}
+
+#[test]
+fn deregister_leaves_git_dir_and_prunes_config() {
+    let env = TestEnv::new("overlay-deregister");
+    let git_dir = env.home().join(".cfg");
+    env.git(&env.home(), &["init", "--bare", "--quiet", ".cfg"]);
+    env.run_ok(&[
+        "overlay",
+        "register",
+        "cfg",
+        "--git-dir",
+        git_dir.to_str().unwrap(),
+    ]);
+    env.git(
+        &env.home(),
+        &[
+            "--git-dir",
+            ".cfg",
+            "config",
+            "core.excludesFile",
+            "/elsewhere",
+        ],
+    );
+
+    env.run_ok(&["overlay", "deregister", "cfg", "--prune-config"]);
+    assert!(env
+        .overlay_db()
+        .get("overlay_repos")
+        .and_then(|repos| repos.get("cfg"))
+        .is_none());
+    assert!(git_dir.join("HEAD").is_file());
+    assert!(!env.home().join(".gitattributes.d/cfg").exists());
+    let config = env.git(&env.home(), &["--git-dir", ".cfg", "config", "--list"]);
+    assert!(!config.contains("core.attributesfile"), "{}", config);
+    assert!(!config.contains("core.hookspath"), "{}", config);
+    // Settings pointing elsewhere aren't this tool's to remove.
+    assert!(
+        config.contains("core.excludesfile=/elsewhere"),
+        "{}",
+        config
+    );
+}
+
+#[test]
+fn home_dir_can_be_overridden_for_sandboxes() {
+    let env = TestEnv::new("overlay-home-override");
+    let sandbox = env.home().with_file_name("sandbox");
+    fs::create_dir_all(&sandbox).unwrap();
+    env.write_config(&format!("home_dir = {:?}\n", sandbox));
+    env.run_ok(&["overlay", "init", "dots"]);
+
+    let shown = env.run_ok(&["show", "dots"]);
+    assert!(
+        shown.contains(&format!("  GIT_WORK_TREE: {}\n", sandbox.display())),
+        "{}",
+        shown
+    );
+    assert!(sandbox.join(".gitattributes.d").join("dots").is_file());
+    assert!(!env.home().join(".gitattributes.d").exists());
+
+    // The environment variable wins over the configuration.
+    let other = env.home().with_file_name("other-sandbox");
+    fs::create_dir_all(&other).unwrap();
+    let output = env
+        .bb(&["show", "dots"])
+        .env("BELLBOY_HOME", &other)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let shown = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        shown.contains(&format!("  GIT_WORK_TREE: {}\n", other.display())),
+        "{}",
+        shown
+    );
+
+    env.write_config("home_dir = \"sandbox\"\n");
+    let stderr = env.run_err(&["list"], 1);
+    assert!(stderr.contains("must be an absolute path"), "{}", stderr);
+}
+
+#[test]
+fn data_dir_can_be_overridden() {
+    let env = TestEnv::new("overlay-data-dir-override");
+    let other = env.sources_dir().join("data");
+    let output = env
+        .bb(&["overlay", "init", "dots"])
+        .env("BELLBOY_DATA_DIR", &other)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(other.join("overlay_repos").join("dots").is_dir());
+    assert!(!env.data_dir().join("overlay_repos").join("dots").exists());
+
+    let output = env
+        .bb(&["list"])
+        .env("BELLBOY_DATA_DIR", "data")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("must be an absolute path"), "{}", stderr);
+}
+
+#[test]
+fn scan_policy_decides_what_fails_loading() {
+    let env = TestEnv::new("overlay-scan-policy");
+    env.run_ok(&["overlay", "init", "dots"]);
+    let path = env.home().join("dots");
+    env.run_ok(&[
+        "standalone",
+        "init",
+        path.to_str().unwrap(),
+        "--name",
+        "other",
+    ]);
+    let db_path = env.data_dir().join("standalone_repos.toml");
+    let db = fs::read_to_string(&db_path).unwrap();
+    fs::write(
+        &db_path,
+        db.replace("[standalone_repos.other]", "[standalone_repos.dots]"),
+    )
+    .unwrap();
+
+    let stderr = env.run_err(&["list"], 7);
+    assert!(stderr.contains("scan.name_conflicts"), "{}", stderr);
+
+    env.write_config("[scan]\nname_conflicts = \"warn\"\n");
+    let output = env.bb(&["list"]).output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("hiding the latter"), "{}", stderr);
+    let listed = String::from_utf8(output.stdout).unwrap();
+    assert!(listed.contains("\"dots\": standalone repo"), "{}", listed);
+    // The hidden overlay repo survives changes to the DBs.
+    env.run_ok(&["overlay", "init", "more"]);
+    assert!(env.overlay_db()["overlay_repos"].get("dots").is_some());
+
+    fs::write(env.data_dir().join("overlay_repos").join("stray"), "").unwrap();
+    env.write_config(
+        "[scan]\nname_conflicts = \"ignore\"\ninvalid_overlay_dir_entries = \"error\"\n",
+    );
+    let stderr = env.run_err(&["list"], 7);
+    assert!(stderr.contains("\"stray\""), "{}", stderr);
+}
+
+#[test]
+fn doctor_follows_directories_renamed_by_hand() {
+    let env = TestEnv::new("overlay-renamed-dir");
+    env.write_config("harden_permissions = true\n");
+    let excludes_dir = env.home().join(".gitignore.d");
+    fs::create_dir_all(&excludes_dir).unwrap();
+    fs::write(excludes_dir.join("dots"), "*.log\n").unwrap();
+    env.run_ok(&["overlay", "init", "dots"]);
+    let overlay_repos_dir = env.data_dir().join("overlay_repos");
+    fs::rename(
+        overlay_repos_dir.join("dots"),
+        overlay_repos_dir.join("home"),
+    )
+    .unwrap();
+
+    let stderr = env.run_err(&["doctor"], 1);
+    assert!(
+        stderr.contains("overlay repo \"dots\" was renamed to \"home\" by hand"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("pass `--fix`"), "{}", stderr);
+
+    env.run_ok(&["doctor", "--fix"]);
+    let db = env.overlay_db();
+    let overlay_repos = db["overlay_repos"].as_table().unwrap();
+    assert!(overlay_repos.contains_key("home"), "{:?}", overlay_repos);
+    assert!(!overlay_repos.contains_key("dots"), "{:?}", overlay_repos);
+    assert!(excludes_dir.join("home").is_file());
+    assert!(!excludes_dir.join("dots").exists());
+    let excludes_file = env.git(
+        &env.home(),
+        &[
+            &format!("--git-dir={}", overlay_repos_dir.join("home").display()),
+            "config",
+            "core.excludesFile",
+        ],
+    );
+    assert_eq!(
+        std::path::Path::new(excludes_file.trim_end()),
+        env.state_dir().join("excludes").join("home")
+    );
+    env.run_ok(&["doctor"]);
+}
+
+#[test]
+fn doctor_skips_stray_directories_among_overlay_repos() {
+    let env = TestEnv::new("overlay-stray-dir");
+    env.write_config("harden_permissions = true\n");
+    env.run_ok(&["overlay", "init", "dots"]);
+    let junk = env.data_dir().join("overlay_repos").join("junk");
+    fs::create_dir(&junk).unwrap();
+    fs::write(junk.join("notes.txt"), "not a repo\n").unwrap();
+
+    env.run_ok(&["doctor"]);
+    assert!(junk.join("notes.txt").is_file());
+}
+
+#[test]
+fn removing_bare_repo_reports_files_kept_to_hooks() {
+    let env = TestEnv::new("overlay-remove-bare-repo-hooks");
+    let payload = env.sources_dir().join("payload.json");
+    env.write_config(&format!(
+        "[[hooks]]\nevents = [\"repo-removed\"]\ncommand = [\"sh\", \"-c\", \"cat > '{}'\"]\n",
+        payload.display()
+    ));
+    let source = env.make_source_repo("dotfiles", &[(".config/app/app.toml", "theme = 1\n")]);
+    env.run_ok(&[
+        "overlay",
+        "clone",
+        source.to_str().unwrap(),
+        "--name",
+        "dots",
+    ]);
+
+    env.run_ok(&["overlay", "remove-bare-repo", "dots"]);
+
+    assert!(env.home().join(".config/app/app.toml").is_file());
+    let payload: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&payload).unwrap()).unwrap();
+    assert_eq!(payload["event"], "repo-removed");
+    assert_eq!(payload["name"], "dots");
+    assert_eq!(payload["files_deleted"], false);
+}
@@ -0,0 +1,61 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{env, fs};
+
+#[cfg(unix)]
+#[test]
+fn unknown_subcommands_run_plugins_from_path() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::new("plugins");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    let plugin_dir = env.sources_dir().join("bin");
+    fs::create_dir_all(&plugin_dir).unwrap();
+    let plugin = plugin_dir.join("bb-hello");
+    fs::write(
+        &plugin,
+        "#!/bin/sh\necho \"args: $*\"\necho \"repo: $BELLBOY_REPO\"\necho \"$BELLBOY_CONTEXT_JSON\"\n\
+        exit 3\n",
+    )
+    .unwrap();
+    fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+    let path_var = env::join_paths(
+        [plugin_dir]
+            .into_iter()
+            .chain(env::split_paths(&env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+
+    let output = env
+        .bb(&["hello", "--flag", "value"])
+        .current_dir(&path)
+        .env("PATH", &path_var)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("args: --flag value\n"), "{}", stdout);
+    assert!(stdout.contains("repo: project\n"), "{}", stdout);
+    assert!(stdout.contains("\"protocol\":1"), "{}", stdout);
+
+    let output = env.bb(&["stat"]).env("PATH", &path_var).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.contains("no `bb-stat` plugin"), "{}", stderr);
+    assert!(stderr.contains("did you mean \"stats\"?"), "{}", stderr);
+}
@@ -0,0 +1,65 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn profiles_keep_repos_apart() {
+    let env = TestEnv::new("profiles");
+    let personal = env.home().join("personal");
+    let work = env.home().join("work");
+    env.run_ok(&["standalone", "init", personal.to_str().unwrap()]);
+
+    env.run_ok(&["profile", "create", "work"]);
+    assert_eq!(env.run_ok(&["profile", "list"]), "* default\n  work\n");
+    env.run_ok(&[
+        "--profile",
+        "work",
+        "standalone",
+        "init",
+        work.to_str().unwrap(),
+    ]);
+    assert!(env
+        .data_dir()
+        .join("profiles/work/standalone_repos.toml")
+        .is_file());
+
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("personal"), "{}", listed);
+    assert!(!listed.contains("work"), "{}", listed);
+
+    env.run_ok(&["profile", "switch", "work"]);
+    assert_eq!(env.run_ok(&["profile", "list"]), "  default\n* work\n");
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("work"), "{}", listed);
+    assert!(!listed.contains("personal"), "{}", listed);
+    let listed = env.run_ok(&["--profile", "default", "list"]);
+    assert!(listed.contains("personal"), "{}", listed);
+
+    env.run_ok(&["profile", "switch", "default"]);
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("personal"), "{}", listed);
+}
+
+#[test]
+fn unknown_profiles_are_rejected() {
+    let env = TestEnv::new("profiles-unknown");
+    env.run_ok(&["profile", "create", "work"]);
+    env.run_err(&["profile", "create", "work"], 4);
+    let stderr = env.run_err(&["--profile", "works", "list"], 3);
+    assert!(stderr.contains("did you mean \"work\"?"), "{}", stderr);
+    env.run_err(&["profile", "switch", "home"], 3);
+    env.run_err(&["profile", "create", "../escape"], 2);
+}
@@ -0,0 +1,119 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn flag_allows_reports_but_refuses_changes() {
+    let env = TestEnv::new("read-only-flag");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    let db_before = env.standalone_db();
+
+    let listed = env.run_ok(&["--read-only", "list"]);
+    assert!(listed.contains("project"), "{}", listed);
+    env.run_ok(&["--read-only", "show", "project"]);
+
+    let other = env.home().join("other");
+    env.run_err(
+        &["--read-only", "standalone", "init", other.to_str().unwrap()],
+        8,
+    );
+    assert!(!other.exists());
+    env.run_err(&["pin", "project", "--read-only"], 8);
+    assert_eq!(env.standalone_db(), db_before);
+}
+
+#[test]
+fn config_option_enables_read_only_mode() {
+    let env = TestEnv::new("read-only-config");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.write_config("read_only = true\n");
+
+    env.run_ok(&["list"]);
+    env.run_err(&["remove", "project"], 8);
+    assert!(path.join(".git").is_dir());
+}
+
+#[test]
+fn protected_repo_refuses_changes_unless_allowed() {
+    let env = TestEnv::new("read-only-protect");
+    let path = env.home().join("vendored");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.run_ok(&["protect", "vendored"]);
+    assert_eq!(
+        env.standalone_db()["standalone_repos"]["vendored"]["read_only"].as_bool(),
+        Some(true)
+    );
+    let shown = env.run_ok(&["show", "vendored"]);
+    assert!(shown.contains("read-only"), "{}", shown);
+
+    let stderr = env.run_err(&["run", "vendored", "--", "git", "status"], 1);
+    assert!(stderr.contains("marked read-only"), "{}", stderr);
+    env.run_err(&["remove", "vendored"], 1);
+    assert!(path.join(".git").is_dir());
+    let output = env.run_ok(&["for-each", "--", "git", "status"]);
+    assert!(!output.contains("vendored"), "{}", output);
+
+    env.run_ok(&[
+        "run",
+        "vendored",
+        "--allow-read-only",
+        "--",
+        "git",
+        "status",
+    ]);
+    env.run_ok(&["unprotect", "vendored"]);
+    env.run_ok(&["remove", "vendored"]);
+    assert!(!path.exists());
+}
+
+#[test]
+fn sync_skips_protected_repos_unless_allowed() {
+    let env = TestEnv::new("read-only-sync");
+    let source = env.make_source_repo("vendored", &[("README.md", "v1\n")]);
+    let path = env.home().join("vendored");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "vendored",
+    ]);
+    env.run_ok(&["protect", "vendored"]);
+    fs::write(source.join("README.md"), "v2\n").unwrap();
+    env.git(
+        &source,
+        &["commit", "--quiet", "--all", "--message", "upstream"],
+    );
+
+    let output = env.bb(&["sync"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("skipping read-only repo \"vendored\""),
+        "{}",
+        stderr
+    );
+    let stderr = env.run_err(&["sync", "vendored"], 1);
+    assert!(stderr.contains("marked read-only"), "{}", stderr);
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "v1\n");
+
+    env.run_ok(&["sync", "--allow-read-only"]);
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "v2\n");
+}
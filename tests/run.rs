@@ -0,0 +1,78 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn print_env_shows_repo_environment_without_running() {
+    let env = TestEnv::new("run-print-env");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+
+    let marker = env.home().join("ran");
+    let printed = env.run_ok(&[
+        "run",
+        "project",
+        "--print-env",
+        "--",
+        "touch",
+        marker.to_str().unwrap(),
+    ]);
+    assert!(!marker.exists());
+    assert!(
+        printed.contains(&format!("working directory: {}\n", path.display())),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains(&format!("  GIT_DIR={}\n", path.join(".git").display())),
+        "{}",
+        printed
+    );
+    assert!(
+        printed.contains(&format!("  GIT_WORK_TREE={}\n", path.display())),
+        "{}",
+        printed
+    );
+}
+
+#[test]
+fn outside_repos_fails_without_a_terminal() {
+    let env = TestEnv::new("run-outside");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+
+    // There's no terminal to ask on, so both fail the same way instead of waiting for an answer.
+    let stderr = env.run_err(&["run", "--", "git", "status"], 3);
+    assert_eq!(
+        env.run_err(&["run", "--no-interactive", "--", "git", "status"], 3),
+        stderr
+    );
+}
+
+#[test]
+fn timeout_must_have_a_known_unit() {
+    let env = TestEnv::new("run-timeout-unit");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+
+    let stderr = env.run_err(&["run", "project", "--timeout", "5min", "--", "true"], 2);
+    assert!(stderr.contains("unknown unit \"min\""), "{}", stderr);
+    assert!(
+        stderr.contains("expected one of `ms`, `s`, `m`"),
+        "{}",
+        stderr
+    );
+}
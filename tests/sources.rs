@@ -0,0 +1,146 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn malformed_sources_are_rejected_with_hints() {
+    let env = TestEnv::new("sources-malformed");
+    let stderr = env.run_err(&["standalone", "clone", "htps://example.com/me/repo"], 2);
+    assert!(stderr.contains("did you mean \"https\""), "{}", stderr);
+    let stderr = env.run_err(&["standalone", "clone", "https://example.com"], 2);
+    assert!(stderr.contains("has no repo path"), "{}", stderr);
+    let stderr = env.run_err(&["standalone", "clone", "git@exa mple.com:me/repo"], 2);
+    assert!(
+        stderr.contains("invalid host \"exa mple.com\""),
+        "{}",
+        stderr
+    );
+
+    let stderr = env.run_err(
+        &[
+            "standalone",
+            "clone",
+            "example.com/me/repo",
+            "repo",
+            "--name",
+            "repo",
+        ],
+        5,
+    );
+    assert!(
+        stderr.contains("did you mean \"https://example.com/me/repo\"?"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn overlay_clone_infers_name_from_source() {
+    let env = TestEnv::new("sources-overlay-name");
+    let source = env.make_source_repo("dotfiles.git", &[(".profile", "export A=1\n")]);
+    env.run_ok(&["overlay", "clone", source.to_str().unwrap()]);
+    assert!(env.overlay_db()["overlay_repos"].get("dotfiles").is_some());
+}
+
+#[test]
+fn duplicates_resolve_ssh_host_aliases() {
+    let env = TestEnv::new("sources-ssh-alias");
+    let ssh_dir = env.home().join(".ssh");
+    fs::create_dir_all(&ssh_dir).unwrap();
+    fs::write(
+        ssh_dir.join("config"),
+        "Host gh\n  HostName github.com\n  User git\n",
+    )
+    .unwrap();
+    for (name, url) in [("a", "gh:me/dots.git"), ("b", "https://github.com/me/dots")] {
+        let path = env.home().join(name);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+        env.git(&path, &["remote", "add", "origin", url]);
+    }
+
+    let listed = env.run_ok(&["list", "--duplicates"]);
+    assert!(listed.starts_with("github.com/me/dots\n"), "{}", listed);
+}
+
+#[test]
+fn duplicates_tell_apart_non_default_ports() {
+    let env = TestEnv::new("sources-ports");
+    for (name, url) in [
+        ("a", "ssh://git@git.example.com/me/dots.git"),
+        ("b", "ssh://git@git.example.com:22/me/dots"),
+        ("c", "ssh://git@git.example.com:2222/me/dots"),
+        ("d", "https://git.example.com:8443/me/dots"),
+    ] {
+        let path = env.home().join(name);
+        env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+        env.git(&path, &["remote", "add", "origin", url]);
+    }
+
+    let listed = env.run_ok(&["list", "--duplicates"]);
+    assert_eq!(
+        listed, "git.example.com/me/dots\n  a (origin)\n  b (origin)\n",
+        "{}",
+        listed
+    );
+}
+
+#[test]
+fn recorded_sources_change_only_with_doctor_fix() {
+    let env = TestEnv::new("sources-recorded");
+    env.write_config("harden_permissions = true\n");
+    let source = env.make_source_repo("proj", &[("README.md", "v1\n")]);
+    let path = env.home().join("proj");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+    ]);
+    let recorded =
+        |env: &TestEnv| env.standalone_db()["standalone_repos"]["proj"]["source"].clone();
+    let original = recorded(&env);
+    assert!(original.get("branch").is_some(), "{}", original);
+
+    env.run_ok(&[
+        "run", "proj", "--", "git", "checkout", "--quiet", "--detach",
+    ]);
+    let moved = env.sources_dir().join("moved");
+    env.run_ok(&[
+        "run",
+        "proj",
+        "--",
+        "git",
+        "remote",
+        "set-url",
+        "origin",
+        moved.to_str().unwrap(),
+    ]);
+    env.run_ok(&["for-each", "--", "git", "status"]);
+    assert_eq!(recorded(&env), original);
+
+    let stderr = env.run_err(&["doctor"], 1);
+    assert!(
+        stderr.contains("out-of-date recorded sources"),
+        "{}",
+        stderr
+    );
+    env.run_ok(&["doctor", "--fix"]);
+    let fixed = recorded(&env);
+    assert_eq!(fixed["url"].as_str(), moved.to_str());
+    assert_eq!(fixed["branch"], original["branch"]);
+    env.run_ok(&["doctor"]);
+}
@@ -0,0 +1,430 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{
+    fs,
+    io::Write,
+    process::{Output, Stdio},
+};
+
+#[test]
+fn init_registers_repo_relative_to_home() {
+    let env = TestEnv::new("standalone-init");
+    let path = env.home().join("project");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+
+    assert!(path.join(".git").is_dir());
+    let db = env.standalone_db();
+    let entry = &db["standalone_repos"]["project"];
+    assert_eq!(entry["path"].as_str(), Some("~/project"));
+
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("project"), "{}", listed);
+}
+
+#[test]
+fn clone_checks_out_and_records_source() {
+    let env = TestEnv::new("standalone-clone");
+    let source = env.make_source_repo("notes", &[("README.md", "hello\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+
+    assert_eq!(
+        fs::read_to_string(path.join("README.md")).unwrap(),
+        "hello\n"
+    );
+    let db = env.standalone_db();
+    let entry = &db["standalone_repos"]["notes"];
+    assert_eq!(entry["path"].as_str(), Some("~/notes"));
+    assert_eq!(
+        entry["source"]["url"].as_str(),
+        Some(source.to_str().unwrap())
+    );
+}
+
+#[test]
+fn duplicate_name_is_a_conflict() {
+    let env = TestEnv::new("standalone-conflict");
+    let first = env.home().join("first");
+    let second = env.home().join("second");
+    env.run_ok(&[
+        "standalone",
+        "init",
+        first.to_str().unwrap(),
+        "--name",
+        "dup",
+    ]);
+    env.run_err(
+        &[
+            "standalone",
+            "init",
+            second.to_str().unwrap(),
+            "--name",
+            "dup",
+        ],
+        4,
+    );
+
+    let db = env.standalone_db();
+    let repos = db["standalone_repos"].as_table().unwrap();
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos["dup"]["path"].as_str(), Some("~/first"));
+}
+
+#[test]
+fn name_conflict_can_be_resolved_interactively() {
+    let env = TestEnv::new("standalone-conflict-prompt");
+    let init = |path: &str, name: &str, answers: &str| -> Output {
+        let path = env.home().join(path);
+        let mut child = env
+            .bb(&["standalone", "init", path.to_str().unwrap(), "--name", name])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answers.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let path_of = |name: &str| {
+        env.standalone_db()["standalone_repos"][name]["path"]
+            .as_str()
+            .map(str::to_owned)
+    };
+    assert!(init("first", "dup", "").status.success());
+
+    // Invalid names are asked for again.
+    let output = init("second", "dup", "r\nnot/valid\nsecond\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(path_of("second").as_deref(), Some("~/second"));
+
+    let output = init("third", "dup", "p\n");
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(path_of("dup").as_deref(), Some("~/third"));
+    assert!(env.home().join("first/.git").is_dir());
+
+    let output = init("fourth", "dup", "a\n");
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    assert!(!env.home().join("fourth").exists());
+
+    // Conflicts over more than the name aren't up for resolution.
+    let output = init("third", "other", "p\n");
+    assert_eq!(output.status.code(), Some(4), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("[r]ename"), "{}", stderr);
+    assert_eq!(path_of("dup").as_deref(), Some("~/third"));
+}
+
+#[test]
+fn failed_init_removes_only_directories_it_created() {
+    let env = TestEnv::new("standalone-init-rollback");
+    let first = env.home().join("first");
+    let created = env.home().join("created");
+    let existing = env.home().join("existing");
+    fs::create_dir(&existing).unwrap();
+    env.run_ok(&[
+        "standalone",
+        "init",
+        first.to_str().unwrap(),
+        "--name",
+        "dup",
+    ]);
+
+    for path in [&created, &existing] {
+        env.run_err(
+            &[
+                "standalone",
+                "init",
+                path.to_str().unwrap(),
+                "--name",
+                "dup",
+            ],
+            4,
+        );
+    }
+    assert!(!created.exists());
+    assert!(existing.is_dir());
+    assert!(!existing.join(".git").exists());
+}
+
+#[test]
+fn failed_clone_leaves_nothing_behind() {
+    let env = TestEnv::new("standalone-clone-rollback");
+    let source = env.sources_dir().join("nonexistent");
+    let path = env.home().join("notes");
+    env.run_err(
+        &[
+            "standalone",
+            "clone",
+            source.to_str().unwrap(),
+            path.to_str().unwrap(),
+            "--name",
+            "notes",
+        ],
+        5,
+    );
+
+    assert!(!path.exists());
+    assert!(env
+        .standalone_db()
+        .get("standalone_repos")
+        .and_then(|repos| repos.get("notes"))
+        .is_none());
+}
+
+#[test]
+fn remove_deletes_entry() {
+    let env = TestEnv::new("standalone-remove");
+    let path = env.home().join("scratch");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    env.run_ok(&["remove", "scratch"]);
+
+    let db = env.standalone_db();
+    assert!(db
+        .get("standalone_repos")
+        .and_then(|repos| repos.get("scratch"))
+        .is_none());
+    assert!(!env.run_ok(&["list"]).contains("scratch"));
+}
+
+#[test]
+fn remove_refuses_uncommitted_changes_without_force() {
+    let env = TestEnv::new("standalone-remove-dirty");
+    let path = env.home().join("wip");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    fs::write(path.join("draft.txt"), "unsaved\n").unwrap();
+
+    env.run_err(&["remove", "wip"], 1);
+    assert!(env.standalone_db()["standalone_repos"].get("wip").is_some());
+
+    env.run_ok(&["remove", "wip", "--force"]);
+    assert!(env
+        .standalone_db()
+        .get("standalone_repos")
+        .and_then(|repos| repos.get("wip"))
+        .is_none());
+}
+
+#[test]
+fn remove_keep_files_deletes_only_entry_and_git_dir() {
+    let env = TestEnv::new("standalone-remove-keep-files");
+    let path = env.home().join("notes");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    fs::write(path.join("todo.txt"), "write tests\n").unwrap();
+
+    // Untracked files are never deleted with `--keep-files`, so there's nothing to refuse over.
+    env.run_ok(&["remove", "notes", "--keep-files"]);
+    assert!(env
+        .standalone_db()
+        .get("standalone_repos")
+        .and_then(|repos| repos.get("notes"))
+        .is_none());
+    assert!(!path.join(".git").exists());
+    assert_eq!(
+        fs::read_to_string(path.join("todo.txt")).unwrap(),
+        "write tests\n"
+    );
+}
+
+#[test]
+fn remove_files_only_deletes_only_tracked_files() {
+    let env = TestEnv::new("standalone-remove-files-only");
+    let path = env.home().join("notes");
+    env.run_ok(&["standalone", "init", path.to_str().unwrap()]);
+    fs::write(path.join("todo.txt"), "write tests\n").unwrap();
+    env.git(&path, &["add", "todo.txt"]);
+    env.git(&path, &["commit", "--quiet", "-m", "Add todo"]);
+    fs::write(path.join("scratch.txt"), "untracked\n").unwrap();
+
+    env.run_ok(&["remove", "notes", "--files-only"]);
+    assert!(env.standalone_db()["standalone_repos"]
+        .get("notes")
+        .is_some());
+    assert!(path.join(".git").is_dir());
+    assert!(!path.join("todo.txt").exists());
+    assert!(path.join("scratch.txt").is_file());
+}
+
+#[test]
+fn register_refuses_directory_that_only_looks_like_a_repo() {
+    let env = TestEnv::new("standalone-register-not-repo");
+    let path = env.home().join("notrepo");
+    fs::create_dir(&path).unwrap();
+    fs::write(path.join("HEAD"), "").unwrap();
+
+    let stderr = env.run_err(&["standalone", "register", path.to_str().unwrap()], 1);
+    assert!(stderr.contains("expected Normal, got None"), "{}", stderr);
+}
+
+#[test]
+fn removing_unknown_repo_is_not_found() {
+    let env = TestEnv::new("standalone-remove-unknown");
+    env.run_err(&["remove", "nonexistent"], 3);
+}
+
+#[test]
+fn directory_flag_changes_default_path() {
+    let env = TestEnv::new("standalone-directory-flag");
+    let path = env.home().join("elsewhere");
+    fs::create_dir(&path).unwrap();
+    env.run_ok(&["-C", path.to_str().unwrap(), "standalone", "init"]);
+
+    assert!(path.join(".git").is_dir());
+    let db = env.standalone_db();
+    assert_eq!(
+        db["standalone_repos"]["elsewhere"]["path"].as_str(),
+        Some("~/elsewhere")
+    );
+}
+
+#[test]
+fn unversioned_db_is_upgraded_with_backup() {
+    let env = TestEnv::new("standalone-db-upgrade");
+    let path = env.home().join("old");
+    env.git(&env.home(), &["init", "--quiet", "old"]);
+    let original = format!(
+        "[standalone_repos.old]\npath = {:?}\n",
+        path.to_str().unwrap()
+    );
+    fs::create_dir_all(env.data_dir()).unwrap();
+    let db_path = env.data_dir().join("standalone_repos.toml");
+    fs::write(&db_path, &original).unwrap();
+
+    assert!(env.run_ok(&["list"]).contains("old"));
+
+    let db = env.standalone_db();
+    assert_eq!(db["version"].as_integer(), Some(3));
+    assert!(db["standalone_repos"].get("old").is_some());
+    let backup = env.data_dir().join("standalone_repos.toml.v1.bak");
+    assert_eq!(fs::read_to_string(backup).unwrap(), original);
+}
+
+#[test]
+fn newer_db_version_is_refused() {
+    let env = TestEnv::new("standalone-db-too-new");
+    fs::create_dir_all(env.data_dir()).unwrap();
+    let db_path = env.data_dir().join("standalone_repos.toml");
+    let contents = "version = 999\n\n[standalone_repos]\n";
+    fs::write(&db_path, contents).unwrap();
+
+    let stderr = env.run_err(&["list"], 1);
+    assert!(stderr.contains("version 999"), "{}", stderr);
+    assert_eq!(fs::read_to_string(db_path).unwrap(), contents);
+}
+
+#[test]
+fn included_repos_are_merged_read_only() {
+    let env = TestEnv::new("standalone-include");
+    env.git(&env.home(), &["init", "--quiet", "work"]);
+    env.git(&env.home(), &["init", "--quiet", "clash"]);
+    let personal = env.home().join("personal");
+    env.run_ok(&["standalone", "init", personal.to_str().unwrap()]);
+    fs::write(
+        env.data_dir().join("work_repos.toml"),
+        "[standalone_repos.work]\n\
+        path = \"~/work\"\n\
+        [standalone_repos.personal]\n\
+        path = \"~/clash\"\n",
+    )
+    .unwrap();
+    let db_path = env.data_dir().join("standalone_repos.toml");
+    let db = fs::read_to_string(&db_path).unwrap();
+    fs::write(
+        &db_path,
+        db.replacen("\n\n", "\n\ninclude = [\"work_repos.toml\"]\n\n", 1),
+    )
+    .unwrap();
+
+    let listed = env.run_ok(&["list"]);
+    assert!(listed.contains("\"work\""), "{}", listed);
+    // The personal entry wins over the conflicting included one.
+    assert!(!listed.contains("clash"), "{}", listed);
+
+    let stderr = env.run_err(&["remove", "work", "--force"], 1);
+    assert!(stderr.contains("work_repos.toml"), "{}", stderr);
+    assert!(env.home().join("work").is_dir());
+
+    // Changing the DB writes back only its own entries.
+    env.run_ok(&["remove", "personal"]);
+    let db = env.standalone_db();
+    assert_eq!(
+        db["include"].as_array().unwrap()[0].as_str(),
+        Some("work_repos.toml")
+    );
+    assert!(db["standalone_repos"].as_table().unwrap().is_empty());
+    assert!(env.run_ok(&["list"]).contains("\"work\""));
+}
+
+#[test]
+fn clone_without_path_uses_projects_dir() {
+    let env = TestEnv::new("standalone-projects-dir");
+    env.write_config("projects_dir = \"~/code/personal\"\n");
+    let source = env.make_source_repo("notes.git", &[("README.md", "hello\n")]);
+    env.run_ok(&["standalone", "clone", source.to_str().unwrap()]);
+
+    let path = env.home().join("code").join("personal").join("notes");
+    assert_eq!(
+        fs::read_to_string(path.join("README.md")).unwrap(),
+        "hello\n"
+    );
+    let db = env.standalone_db();
+    assert_eq!(
+        db["standalone_repos"]["notes"]["path"].as_str(),
+        Some("~/code/personal/notes")
+    );
+}
+
+#[test]
+fn register_follows_git_file_redirects() {
+    let env = TestEnv::new("standalone-gitdir");
+    let source = env.make_source_repo("main", &[("README.md", "hello\n")]);
+    let linked = env.home().join("linked");
+    env.git(
+        &source,
+        &[
+            "worktree",
+            "add",
+            "--quiet",
+            "-b",
+            "linked",
+            linked.to_str().unwrap(),
+        ],
+    );
+    assert!(linked.join(".git").is_file());
+
+    env.run_ok(&["standalone", "register", linked.to_str().unwrap()]);
+    let paths = env.run_ok(&["paths"]);
+    let git_dir = source.join(".git").join("worktrees").join("linked");
+    assert!(
+        paths.contains(&format!("GIT_DIR: {}", git_dir.display())),
+        "{}",
+        paths
+    );
+    let branch = env.run_ok(&["run", "linked", "--", "git", "branch", "--show-current"]);
+    assert_eq!(branch.trim(), "linked");
+}
@@ -0,0 +1,119 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{fs, process::Stdio, thread, time::Duration};
+
+fn clone_notes(env: &TestEnv) -> std::path::PathBuf {
+    let source = env.make_source_repo("notes", &[("todo.md", "- write tests\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    path
+}
+
+#[test]
+fn prints_status_of_each_repo() {
+    let env = TestEnv::new("status");
+    let path = clone_notes(&env);
+
+    let output = env.run_ok(&["status"]);
+    assert!(
+        output.starts_with("\"notes\" (standalone repo): master...origin/master"),
+        "{}",
+        output
+    );
+    assert!(output.contains(", clean"), "{}", output);
+
+    // Changes show up right away, without waiting for cached statuses to expire.
+    fs::write(path.join("todo.md"), "- write more tests\n").unwrap();
+    let output = env.run_ok(&["status"]);
+    assert!(output.contains(", 1 unstaged"), "{}", output);
+
+    let output = env.run_ok(&["status", "--kind", "overlay"]);
+    assert_eq!(output, "");
+}
+
+#[test]
+fn show_describes_last_commit() {
+    let env = TestEnv::new("status-last-commit");
+    let path = clone_notes(&env);
+    let hash = env.git(&path, &["rev-parse", "--short=7", "HEAD"]);
+
+    let shown = env.run_ok(&["show", "notes"]);
+    assert!(
+        shown.contains(&format!(
+            "  last commit: {} initial commit (Bellboy Tests, ",
+            hash.trim()
+        )),
+        "{}",
+        shown
+    );
+
+    let empty = env.home().join("empty");
+    env.run_ok(&["standalone", "init", empty.to_str().unwrap()]);
+    let shown = env.run_ok(&["show", "empty"]);
+    assert!(shown.contains("  last commit: none yet\n"), "{}", shown);
+}
+
+#[test]
+fn watch_redraws_until_interrupted() {
+    let env = TestEnv::new("status-watch");
+    clone_notes(&env);
+
+    let mut child = env
+        .bb(&["status", "--watch", "--interval", "100ms"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(1000));
+    child.kill().unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.matches("Every 100ms; press Ctrl-C to stop.").count() >= 2,
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("\"notes\" (standalone repo)"), "{}", stdout);
+}
+
+#[test]
+fn prompt_info_summarizes_repo_containing_path() {
+    let env = TestEnv::new("status-prompt-info");
+    let path = clone_notes(&env);
+    fs::create_dir(path.join("drafts")).unwrap();
+    let nested = path.join("drafts");
+
+    let info = env.run_ok(&["prompt-info", nested.to_str().unwrap()]);
+    assert_eq!(info, "notes (standalone)\n");
+
+    fs::write(path.join("todo.md"), "- write more tests\n").unwrap();
+    env.git(&path, &["commit", "-am", "more tests"]);
+    fs::write(path.join("todo.md"), "- write even more tests\n").unwrap();
+    env.git(&path, &["add", "todo.md"]);
+    let info = env.run_ok(&["prompt-info", path.to_str().unwrap()]);
+    assert_eq!(info, "notes (standalone) *↑1\n");
+
+    // Prompts are shown outside of repos too.
+    let info = env.run_ok(&["prompt-info", env.home().to_str().unwrap()]);
+    assert_eq!(info, "");
+}
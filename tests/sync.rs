@@ -0,0 +1,304 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn sync_fast_forwards_and_reports_transfers() {
+    let env = TestEnv::new("sync-fast-forward");
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    fs::write(source.join("README.md"), "v2\n").unwrap();
+    env.git(&source, &["commit", "--quiet", "--all", "--message", "v2"]);
+
+    let summary = env.run_ok(&["sync"]);
+
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "v2\n");
+    assert!(summary.contains("fetched:\n"), "{}", summary);
+    assert!(summary.contains("  notes  "), "{}", summary);
+}
+
+#[test]
+fn sync_records_which_machine_synced() {
+    let env = TestEnv::new("sync-machine");
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    let shown = env.run_ok(&["show", "notes"]);
+    assert!(!shown.contains("last synced"), "{}", shown);
+
+    env.run_ok(&["sync"]);
+
+    let db = env.standalone_db();
+    let last_sync = db["standalone_repos"]["notes"]["last_sync"]
+        .as_table()
+        .unwrap();
+    let identity: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(env.state_dir().join("machine.json")).unwrap())
+            .unwrap();
+    assert_eq!(last_sync["machine"].as_str(), identity["id"].as_str());
+    let shown = env.run_ok(&["show", "notes"]);
+    assert!(shown.contains("(this machine)"), "{}", shown);
+    let listed = env.run_ok(&["list", "--status"]);
+    assert!(listed.contains("(synced "), "{}", listed);
+}
+
+#[test]
+fn sync_notifies_when_slow() {
+    let env = TestEnv::new("sync-notify");
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    let notified = |config: &str| {
+        env.write_config(config);
+        let output = env
+            .bb(&["sync"])
+            .env("RUST_LOG", "debug")
+            // Keep notifications off of the desktop of whoever runs the tests.
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .env_remove("XDG_RUNTIME_DIR")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        stderr
+            .contains("notifying: `bb sync` finished: 1 succeeded, 0 failed, in 0s")
+            .then_some(())
+            .ok_or(stderr)
+    };
+
+    notified("").unwrap_err();
+    notified("[notifications]\nafter = \"1h\"\n").unwrap_err();
+    notified("[notifications]\nafter = \"0s\"\n").unwrap();
+}
+
+#[test]
+fn sync_autostash_sets_uncommitted_changes_aside() {
+    let env = TestEnv::new("sync-autostash");
+    let source = env.make_source_repo("notes", &[("README.md", "a\nb\nc\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    fs::write(source.join("README.md"), "A\nb\nc\n").unwrap();
+    env.git(
+        &source,
+        &["commit", "--quiet", "--all", "--message", "upstream"],
+    );
+    fs::write(path.join("README.md"), "a\nb\nC\n").unwrap();
+
+    let stderr = env.run_err(&["sync"], 1);
+    assert!(stderr.contains("pass `--autostash`"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(path.join("README.md")).unwrap(),
+        "a\nb\nC\n"
+    );
+
+    env.run_ok(&["sync", "--autostash"]);
+    assert_eq!(
+        fs::read_to_string(path.join("README.md")).unwrap(),
+        "A\nb\nC\n"
+    );
+    assert_eq!(env.git(&path, &["stash", "list"]), "");
+}
+
+#[test]
+fn sync_autostash_leaves_older_stashes_alone_when_nothing_is_stashed() {
+    let env = TestEnv::new("sync-autostash-nothing-stashed");
+    let lib = env.make_source_repo("lib", &[("lib.sh", "true\n")]);
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n"), ("todo.txt", "nothing\n")]);
+    let allow_file = ["-c", "protocol.file.allow=always"];
+    let git_allowing_file =
+        |dir: &std::path::Path, args: &[&str]| env.git(dir, &[&allow_file[..], args].concat());
+    git_allowing_file(
+        &source,
+        &["submodule", "add", "--quiet", lib.to_str().unwrap(), "lib"],
+    );
+    env.git(&source, &["commit", "--quiet", "--message", "add lib"]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    git_allowing_file(&path, &["submodule", "update", "--quiet", "--init"]);
+    fs::write(path.join("todo.txt"), "mine\n").unwrap();
+    env.git(&path, &["stash", "push", "--quiet", "--message", "mine"]);
+    // Git can't stash a submodule that moved to another commit.
+    env.git(
+        &path.join("lib"),
+        &["commit", "--quiet", "--allow-empty", "--message", "local"],
+    );
+    fs::write(source.join("README.md"), "v2\n").unwrap();
+    env.git(
+        &source,
+        &["commit", "--quiet", "--all", "--message", "upstream"],
+    );
+
+    env.run_ok(&["sync", "--autostash"]);
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "v2\n");
+    assert_eq!(
+        fs::read_to_string(path.join("todo.txt")).unwrap(),
+        "nothing\n"
+    );
+    let stashes = env.git(&path, &["stash", "list"]);
+    assert!(stashes.contains("mine"), "{}", stashes);
+}
+
+#[test]
+fn sync_autostash_reports_conflicts_and_keeps_stash() {
+    let env = TestEnv::new("sync-autostash-conflict");
+    let source = env.make_source_repo("notes", &[("README.md", "a\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+    fs::write(source.join("README.md"), "upstream\n").unwrap();
+    env.git(
+        &source,
+        &["commit", "--quiet", "--all", "--message", "upstream"],
+    );
+    fs::write(path.join("README.md"), "local\n").unwrap();
+
+    let stderr = env.run_err(&["sync", "--autostash"], 1);
+    assert!(
+        stderr.contains("restoring stashed changes conflicted in README.md"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        env.git(&path, &["rev-parse", "HEAD"]),
+        env.git(&source, &["rev-parse", "HEAD"])
+    );
+    let stashes = env.git(&path, &["stash", "list"]);
+    assert!(stashes.contains("bb sync --autostash"), "{}", stashes);
+}
+
+#[test]
+fn sync_runs_hooks_when_finished() {
+    let env = TestEnv::new("sync-hooks");
+    let payload = env.sources_dir().join("payload.json");
+    env.write_config(&format!(
+        "[[hooks]]\nevents = [\"sync-finished\"]\ncommand = [\"sh\", \"-c\", \"cat > '{}'\"]\n",
+        payload.display()
+    ));
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        env.home().join("notes").to_str().unwrap(),
+    ]);
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        env.home().join("gone").to_str().unwrap(),
+    ]);
+    fs::write(source.join("README.md"), "v2\n").unwrap();
+    env.git(&source, &["commit", "--quiet", "--all", "--message", "v2"]);
+    fs::remove_dir_all(env.home().join("gone")).unwrap();
+
+    env.run_err(&["sync"], 1);
+
+    let payload: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&payload).unwrap()).unwrap();
+    assert_eq!(payload["event"], "sync-finished");
+    assert_eq!(payload["synced"][0]["name"], "notes");
+    assert_eq!(payload["synced"][0]["outcome"]["kind"], "fast-forwarded");
+    assert_eq!(payload["failed"][0]["name"], "gone");
+    assert!(payload["failed"][0]["error"].is_string(), "{}", payload);
+}
+
+#[test]
+fn sync_keeps_history_shallow() {
+    let env = TestEnv::new("sync-keep-depth");
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    let commit = |version: usize| {
+        fs::write(source.join("README.md"), format!("v{}\n", version)).unwrap();
+        env.git(
+            &source,
+            &[
+                "commit",
+                "--quiet",
+                "--all",
+                "--message",
+                &version.to_string(),
+            ],
+        );
+    };
+    for version in 2..=4 {
+        commit(version);
+    }
+    let path = env.home().join("notes");
+    // Local clones ignore `--depth` unless they go through a URL.
+    let url = format!("file://{}", source.display());
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        &url,
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+        "--keep-depth",
+        "2",
+    ]);
+    let depth = || env.git(&path, &["rev-list", "--count", "HEAD"]);
+    assert_eq!(depth().trim(), "2");
+
+    for version in 5..=7 {
+        commit(version);
+    }
+    env.run_ok(&["sync"]);
+
+    assert_eq!(fs::read_to_string(path.join("README.md")).unwrap(), "v7\n");
+    assert_eq!(depth().trim(), "2");
+}
@@ -0,0 +1,37 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn trace_file_is_written() {
+    let env = TestEnv::new("tracing-written");
+    let trace_file = env.home().join("trace.json");
+
+    env.run_ok(&["--trace-file", trace_file.to_str().unwrap(), "list"]);
+    let trace = fs::read_to_string(&trace_file).unwrap();
+    assert!(trace.trim_start().starts_with('['), "{}", trace);
+}
+
+#[test]
+fn uncreatable_trace_file_is_an_error() {
+    let env = TestEnv::new("tracing-uncreatable");
+    let trace_file = env.home().join("nonexistent").join("trace.json");
+
+    let stderr = env.run_err(&["--trace-file", trace_file.to_str().unwrap(), "list"], 6);
+    assert!(stderr.contains("failed to create trace file"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
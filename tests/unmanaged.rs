@@ -0,0 +1,124 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::fs;
+
+#[test]
+fn unmanaged_directories_are_listed_and_only_deregistered_by_remove() {
+    let env = TestEnv::new("unmanaged-register");
+    let path = env.home().join(".config").join("app");
+    fs::create_dir_all(&path).unwrap();
+    fs::write(path.join("settings.ini"), "a = 1\n").unwrap();
+
+    env.run_ok(&["unmanaged", "register", path.to_str().unwrap()]);
+
+    let db = env.standalone_db();
+    assert_eq!(
+        db["unmanaged"]["app"]["path"].as_str(),
+        Some("~/.config/app")
+    );
+    assert!(db["standalone_repos"].get("app").is_none());
+    let listed = env.run_ok(&["list", "--format", "group-by-kind"]);
+    assert!(
+        listed.contains("unmanaged directories\n  app: "),
+        "{}",
+        listed
+    );
+    let json = env.run_ok(&["list", "--format", "json"]);
+    assert!(json.contains("\"kind\": \"unmanaged\""), "{}", json);
+
+    let stderr = env.run_err(&["branches", "app"], 1);
+    assert!(stderr.contains("not a Git repo"), "{}", stderr);
+
+    env.run_ok(&["remove", "app"]);
+    assert!(path.join("settings.ini").is_file());
+    assert!(env.run_ok(&["list"]).is_empty());
+}
+
+#[test]
+fn git_commands_skip_unmanaged_directories() {
+    let env = TestEnv::new("unmanaged-skip");
+    let source = env.make_source_repo("notes", &[("README.md", "v1\n")]);
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        env.home().join("notes").to_str().unwrap(),
+    ]);
+    let path = env.home().join("cache");
+    fs::create_dir(&path).unwrap();
+    env.run_ok(&["unmanaged", "register", path.to_str().unwrap()]);
+
+    let output = env.bb(&["sync"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("skipping unmanaged directory \"cache\""),
+        "{}",
+        stderr
+    );
+
+    let ran = env.run_ok(&["for-each", "--", "pwd"]);
+    assert!(!ran.contains("cache"), "{}", ran);
+    let ran = env.run_ok(&["for-each", "--kind", "unmanaged", "--", "pwd"]);
+    assert!(ran.contains("cache"), "{}", ran);
+}
+
+#[test]
+fn starter_files_recreate_unmanaged_directories() {
+    let env = TestEnv::new("unmanaged-starter");
+    let path = env.home().join(".local").join("share").join("app");
+    fs::create_dir_all(&path).unwrap();
+    env.run_ok(&[
+        "unmanaged",
+        "register",
+        path.to_str().unwrap(),
+        "--name",
+        "app-data",
+    ]);
+    let starter = env.sources_dir().join("starter.toml");
+    env.run_ok(&["starter", "export", starter.to_str().unwrap()]);
+    let exported = fs::read_to_string(&starter).unwrap();
+    assert!(exported.contains("kind = \"unmanaged\""), "{}", exported);
+    assert!(!exported.contains("source"), "{}", exported);
+
+    env.run_ok(&["unmanaged", "deregister", "app-data"]);
+    fs::remove_dir_all(env.home().join(".local")).unwrap();
+    env.run_ok(&["starter", "import", starter.to_str().unwrap()]);
+
+    assert!(path.is_dir());
+    assert!(env.run_ok(&["list"]).contains("app-data"));
+}
+
+#[test]
+fn starter_lint_checks_unmanaged_entries() {
+    let env = TestEnv::new("unmanaged-starter-lint");
+    let starter = env.sources_dir().join("starter.toml");
+    fs::write(
+        &starter,
+        "[[repos]]\nname = \"app\"\nkind = \"unmanaged\"\nsource = \"https://example.com/app\"\n",
+    )
+    .unwrap();
+
+    let output = env
+        .bb(&["starter", "verify", starter.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("has no `path` specified"), "{}", stdout);
+    assert!(stdout.contains("cannot have a `source`"), "{}", stdout);
+}
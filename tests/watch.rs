@@ -0,0 +1,74 @@
+// Copyright 2021, Bellboy maintainers.
+// This file is part of the [Bellboy project](https://github.com/bellboy-dotfiles/bellboy).
+//
+// Bellboy is free software: you can redistribute it and/or modify it under the terms of the GNU
+// General Public License as published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// Bellboy is distributed in the hope that it will be useful, but WITHOUT ANY WARRANTY; without
+// even the implied warranty of MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+// General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with Bellboy.  If not,
+// see <https://www.gnu.org/licenses/>.
+mod common;
+
+use common::TestEnv;
+use std::{fs, thread, time::Duration};
+
+#[test]
+fn auto_commit_commits_changes_once_files_settle() {
+    let env = TestEnv::new("watch-auto-commit");
+    let source = env.make_source_repo("notes", &[("todo.md", "- write tests\n")]);
+    let path = env.home().join("notes");
+    env.run_ok(&[
+        "standalone",
+        "clone",
+        source.to_str().unwrap(),
+        path.to_str().unwrap(),
+        "--name",
+        "notes",
+    ]);
+
+    let mut child = env
+        .bb(&[
+            "watch",
+            "notes",
+            "--auto-commit",
+            "--debounce",
+            "200ms",
+            "--message",
+            "Auto: {count} file(s) in {repo}: {files}",
+        ])
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+    fs::write(path.join("todo.md"), "- write more tests\n").unwrap();
+    thread::sleep(Duration::from_millis(1500));
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(
+        env.git(&path, &["log", "-1", "--format=%s"]),
+        "Auto: 1 file(s) in notes: todo.md\n"
+    );
+    assert_eq!(env.git(&path, &["status", "--porcelain"]), "");
+}
+
+#[test]
+fn print_service_runs_the_same_command() {
+    let env = TestEnv::new("watch-service");
+    env.run_ok(&["overlay", "init", "dots"]);
+
+    let unit = env.run_ok(&["watch", "dots", "--auto-commit", "--print-service"]);
+    assert!(unit.starts_with("[Unit]\n"), "{}", unit);
+    assert!(
+        unit.contains(
+            "\" \"watch\" \"dots\" \"--debounce\" \"5000ms\" \"--auto-commit\" \"--message\" \
+            \"Update {files} on {hostname}\"\n"
+        ),
+        "{}",
+        unit
+    );
+    assert!(unit.contains("WantedBy=default.target\n"), "{}", unit);
+}